@@ -0,0 +1,2455 @@
+use anyhow::{Result, anyhow};
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::path::Path;
+use std::process::Command;
+use tokio::time::sleep;
+use tokio_tungstenite::{connect_async_with_config, tungstenite::Message};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tracing::{error, info, warn, debug, Instrument};
+
+mod privileges;
+
+use yggman_proto::protocol::{AgentMessage, ErrorCode, PeerCounters, ServerErrorReport, ServerMessage, PROTOCOL_VERSION};
+use yggman_proto::redact::Secret;
+use yggman_proto::{sealing, signing};
+
+/// Exit status of --oneshot when the Yggdrasil config was changed
+const ONESHOT_CHANGED: i32 = 2;
+
+/// Errors that retrying cannot fix. They stop the agent, even one that
+/// reconnects on everything else, with an exit status of their own for
+/// supervisors to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fatal {
+    /// The arguments or the Yggdrasil config path are wrong
+    Config,
+    /// The server refused the agent token or enrollment token
+    Unauthorized,
+    /// The server no longer supports this agent version
+    Incompatible,
+}
+
+impl Fatal {
+    fn exit_code(self) -> i32 {
+        match self {
+            Fatal::Config => 3,
+            Fatal::Unauthorized => 4,
+            Fatal::Incompatible => 5,
+        }
+    }
+
+    fn error(self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(FatalError { kind: self, message: message.into() })
+    }
+
+    /// The kind of `error`, `None` for transient errors
+    fn of(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref::<FatalError>().map(|fatal| fatal.kind)
+    }
+}
+
+#[derive(Debug)]
+struct FatalError {
+    kind: Fatal,
+    message: String,
+}
+
+impl std::fmt::Display for FatalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for FatalError {}
+
+/// How long a control plane gets to answer before the next one is tried
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the control plane gets to answer a ping, unless it says otherwise
+const DEFAULT_PONG_TIMEOUT: Duration = Duration::from_secs(yggman_proto::protocol::DEFAULT_PONG_TIMEOUT);
+
+/// Public address the server last saw this agent connect from, with
+/// --public-address-from-server
+static ECHOED_ADDRESS: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// Whether messages to the control plane are sent as CBOR, with --cbor
+static WIRE_CBOR: AtomicBool = AtomicBool::new(false);
+
+/// Marks an OpenWrt system, where Yggdrasil runs under procd
+const OPENWRT_RELEASE: &str = "/etc/openwrt_release";
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "yggman-agent",
+    about = "Yggdrasil network agent for automatic node configuration",
+    after_help = "Exit status: 0 on success, 1 on other errors, 2 when --oneshot changed the \
+Yggdrasil config, 3 for invalid arguments or Yggdrasil config path, 4 when the server \
+refused the agent or enrollment token, 5 when the server does not support this agent version."
+)]
+struct Args {
+    /// Control plane server URL (e.g., ws://localhost:8080/ws/agent, or wss:// with TLS).
+    /// Repeat or separate with commas to add standby servers, tried in
+    /// order on every (re)connect; standbys must share the signing key.
+    #[arg(short, long, env = "YGGMAN_AGENT_SERVER", required = true, value_delimiter = ',')]
+    server: Vec<String>,
+
+    /// Node name (optional, will use hostname if not provided)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Network to join (optional, the server's default network if not provided)
+    #[arg(long)]
+    network: Option<String>,
+
+    /// Tag to attach to this node, selects tag-specific listen templates (repeatable)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+
+    /// Hex encoded server signing key; when set, unsigned or forged server messages are rejected
+    #[arg(long)]
+    server_pubkey: Option<String>,
+
+    /// File recording the last applied config revision across restarts
+    #[arg(long, default_value = "/var/lib/yggman-agent/state.json")]
+    state_file: String,
+
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(long, default_value = "info")]
+    log_level: String,
+
+    /// Reconnect interval in seconds
+    #[arg(long, default_value = "5")]
+    reconnect_interval: u64,
+    
+    /// Skip automatic Yggdrasil service restart after config changes
+    #[arg(long)]
+    no_restart: bool,
+    
+    /// Custom command to restart Yggdrasil service (overrides platform detection);
+    /// `{instance}` is replaced with the instance name
+    #[arg(long)]
+    restart_command: Option<String>,
+
+    /// How Yggdrasil runs: as a system service, in a Docker or Podman
+    /// container restarted through the runtime's API socket, or in a
+    /// sidecar container of the same pod, sent SIGHUP through --pid-file
+    #[arg(long, env = "YGGMAN_AGENT_MODE", value_enum, default_value = "service")]
+    yggdrasil_mode: YggdrasilMode,
+
+    /// Yggdrasil config to manage, e.g. the host side of a container's
+    /// bind mount; looked for in /etc when not given. It is replaced rather
+    /// than rewritten, so bind mount its directory, not the file itself.
+    /// Repeat as NAME=PATH to manage several Yggdrasil instances on this
+    /// host, each registered as node <name>-NAME with its own state file
+    /// and restarted as the systemd unit yggdrasil@NAME.
+    #[arg(long, env = "YGGMAN_AGENT_CONFIG_PATH")]
+    yggdrasil_config: Vec<String>,
+
+    /// Name or ID of the Yggdrasil container, with --yggdrasil-mode docker or podman;
+    /// `{instance}` is replaced with the instance name
+    #[arg(long, default_value = "yggdrasil")]
+    container: String,
+
+    /// The Yggdrasil instance this copy of the arguments manages, set per
+    /// NAME=PATH --yggdrasil-config
+    #[arg(skip)]
+    instance: Option<String>,
+
+    /// API socket of the container runtime, when not the usual one of
+    /// Docker or Podman
+    #[arg(long)]
+    container_socket: Option<String>,
+
+    /// File holding the PID of Yggdrasil, with --yggdrasil-mode sidecar;
+    /// written by the Yggdrasil container on the volume shared with the
+    /// agent, whose pod must share the process namespace. `{instance}` is
+    /// replaced with the instance name.
+    #[arg(long, env = "YGGMAN_AGENT_PID_FILE")]
+    pid_file: Option<String>,
+    
+    /// Open the configured listen ports in the host firewall and close the
+    /// ones removed later. The nftables backend expects an `inet filter`
+    /// table with an `input` chain.
+    #[arg(long, value_enum)]
+    manage_firewall: Option<FirewallBackend>,
+
+    /// What to do when the managed sections of the Yggdrasil config were
+    /// edited by hand: report it to the server, or also restore the
+    /// server's config
+    #[arg(long, value_enum, default_value = "report")]
+    on_drift: DriftPolicy,
+
+    /// Install agent releases the server offers and restart into them.
+    /// Requires --server-pubkey, since the offer names the binary to trust.
+    #[arg(long)]
+    auto_update: bool,
+
+    /// Ask the server which address this agent connects from and report it
+    /// as well, and use it for {public_ip} when no interface has a public
+    /// address. For nodes behind NAT; the address is only as reliable as
+    /// the server's trusted_proxies setting.
+    #[arg(long)]
+    public_address_from_server: bool,
+
+    /// Register, print how the configuration from the control plane would
+    /// change the Yggdrasil config and exit, without writing it or
+    /// restarting anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Fetch and apply the configuration once, then exit: 0 when the
+    /// Yggdrasil config was up to date, 2 when it was changed, 1 on
+    /// errors. For cron jobs and configuration management runs.
+    #[arg(long, conflicts_with = "dry_run")]
+    oneshot: bool,
+
+    /// Poll the node's configuration over HTTP every this many seconds
+    /// instead of keeping a WebSocket open, for networks that do not allow
+    /// long-lived connections. Needs --node-id and --token.
+    #[arg(long, requires_all = ["node_id", "token"], conflicts_with_all = ["dry_run", "oneshot"])]
+    pull_interval: Option<u64>,
+
+    /// ID of this node, needed for --pull-interval; configs for other
+    /// nodes are rejected. Not for several Yggdrasil instances.
+    #[arg(long)]
+    node_id: Option<String>,
+
+    /// Agent token of this node, for --pull-interval and for registering
+    /// as an existing node on servers requiring enrollment; an admin gets
+    /// it from GET /api/nodes/<id>/agent-token. Agents keep the one sent
+    /// with their config in the state file, which several Yggdrasil
+    /// instances rely on alone.
+    #[arg(long, env = "YGGMAN_AGENT_TOKEN", hide_env_values = true)]
+    token: Option<Secret<String>>,
+
+    /// Enrollment token from POST /api/tokens, needed to register a new
+    /// node when the server requires enrollment; ignored once the node is
+    /// known
+    #[arg(long, env = "YGGMAN_ENROLLMENT_TOKEN", hide_env_values = true)]
+    enrollment_token: Option<Secret<String>>,
+
+    /// Exchange messages with the control plane as CBOR instead of JSON,
+    /// which is smaller on the wire and cheaper to parse on routers
+    #[arg(long)]
+    cbor: bool,
+
+    /// Run on a single thread with small WebSocket buffers, for routers
+    /// with little RAM; on by default on OpenWrt
+    #[arg(long)]
+    low_memory: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum YggdrasilMode {
+    Service,
+    Docker,
+    Podman,
+    Sidecar,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum DriftPolicy {
+    Report,
+    Reapply,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum FirewallBackend {
+    Nftables,
+    Ufw,
+    Firewalld,
+}
+
+impl FirewallBackend {
+    /// As reported to the server
+    fn name(self) -> &'static str {
+        match self {
+            FirewallBackend::Nftables => "nftables",
+            FirewallBackend::Ufw => "ufw",
+            FirewallBackend::Firewalld => "firewalld",
+        }
+    }
+}
+
+/// Messages go out as binary CBOR frames with --cbor, JSON text frames otherwise
+trait ToFrame {
+    fn to_frame(&self) -> Result<Message>;
+}
+
+impl ToFrame for AgentMessage {
+    fn to_frame(&self) -> Result<Message> {
+        if WIRE_CBOR.load(Ordering::Relaxed) {
+            let mut data = Vec::new();
+            ciborium::into_writer(self, &mut data)?;
+            Ok(Message::Binary(data))
+        } else {
+            Ok(Message::Text(serde_json::to_string(self)?))
+        }
+    }
+}
+
+/// Agent state persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AgentState {
+    #[serde(default)]
+    last_revision: u64,
+    /// Server run that numbered `last_revision`, see `stale_reason`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    epoch: String,
+    /// Ports opened by --manage-firewall, as `port/transport`
+    #[serde(default)]
+    opened_ports: Vec<String>,
+    /// Keys written from the server's extra config, removed from the
+    /// Yggdrasil config once the server drops them
+    #[serde(default)]
+    extra_config_keys: Vec<String>,
+    /// Hash of the managed sections as last written, see `managed_sections_hash`
+    #[serde(default)]
+    managed_hash: Option<String>,
+    /// Sent with the node's config, for registering as the node again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    agent_token: Option<Secret<String>>,
+    /// Reported with each status, kept across reconnects since an
+    /// `Unauthorized` error ends the connection it arrived on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_server_error: Option<ServerErrorReport>,
+}
+
+impl AgentState {
+    fn load(path: &str) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable state file {}: {}", path, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save(&self, path: &str) -> Result<()> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Holds the agent token
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        {
+            use std::io::Write;
+            options.open(path)?.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Why a config or update must not be applied: it is older than the
+    /// applied revision of the same server run. Revisions restart with a
+    /// restored or ephemeral server database, so other runs cannot be
+    /// compared; a config answering this agent's registration or pull
+    /// (`answers`) is the current one whatever its revision.
+    fn stale_reason(&self, revision: u64, epoch: &str, answers: bool) -> Option<String> {
+        if answers {
+            return None;
+        }
+        if !epoch.is_empty() && !self.epoch.is_empty() && epoch != self.epoch {
+            return Some(format!("from server run {}, the applied revision {} is from run {}", epoch, self.last_revision, self.epoch));
+        }
+        (revision < self.last_revision).then(|| format!("older than last applied revision {}", self.last_revision))
+    }
+
+    /// Keep the agent token of a config, unless the server sent none
+    fn remember_agent_token(&mut self, token: &Secret<String>) {
+        if !token.is_empty() {
+            self.agent_token = Some(token.clone());
+        }
+    }
+
+    fn record_server_error(&mut self, code: ErrorCode, message: &str, path: &str) {
+        self.last_server_error = Some(ServerErrorReport {
+            code,
+            message: message.to_string(),
+            received_at: signing::unix_millis(),
+        });
+        if let Err(e) = self.save(path) {
+            warn!("Failed to save agent state to {}: {}", path, e);
+        }
+    }
+}
+
+/// Parses server messages, checking signatures against the pinned server
+/// key when one is configured, and opens private keys sealed to this agent.
+struct MessageVerifier {
+    server_key: Option<ed25519_dalek::VerifyingKey>,
+    // Newest accepted timestamp; older messages are replays
+    last_timestamp: u64,
+    key_opener: sealing::KeyOpener,
+    /// --node-id, which every config and update must be for
+    pinned_node_id: Option<String>,
+    /// The node configs and updates are for since the last registration
+    node_id: Option<String>,
+    /// Sent with the last registration or pull, see `AgentState::stale_reason`
+    nonce: String,
+}
+
+impl MessageVerifier {
+    fn new(server_pubkey: Option<&str>, node_id: Option<&str>) -> Result<Self> {
+        let server_key = server_pubkey
+            .map(signing::parse_public_key)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid --server-pubkey: {}", e))?;
+        Ok(Self {
+            server_key,
+            last_timestamp: 0,
+            key_opener: sealing::KeyOpener::generate(),
+            pinned_node_id: node_id.map(str::to_string),
+            node_id: node_id.map(str::to_string),
+            nonce: signing::nonce(),
+        })
+    }
+
+    /// Registering again may reach a node recreated under another id; the
+    /// first config after it names the node, unless --node-id does.
+    /// Returns the nonce for the registration.
+    fn registering(&mut self) -> String {
+        self.node_id = self.pinned_node_id.clone();
+        self.renew_nonce()
+    }
+
+    /// A fresh nonce for a registration or pull, which the config
+    /// answering it echoes
+    fn renew_nonce(&mut self) -> String {
+        self.nonce = signing::nonce();
+        self.nonce.clone()
+    }
+
+    /// Whether `message` is the config answering the last registration or pull
+    fn answers(&self, message: &ServerMessage) -> bool {
+        matches!(message, ServerMessage::Config { nonce: Some(nonce), .. } if *nonce == self.nonce)
+    }
+
+    /// Reject configs and updates signed for another node, which a proxy
+    /// could otherwise replay to this one
+    fn check_recipient(&mut self, message: &ServerMessage) -> Result<()> {
+        let (node_id, is_config) = match message {
+            ServerMessage::Config { node_id, .. } => (node_id, true),
+            ServerMessage::Update { node_id, .. } => (node_id, false),
+            _ => return Ok(()),
+        };
+        // Older servers do not address updates; only accepted unverified
+        if node_id.is_empty() && !is_config && self.server_key.is_none() {
+            return Ok(());
+        }
+        match &self.node_id {
+            Some(expected) if expected != node_id => {
+                Err(anyhow!("message for node {:?}, this is node {}, possible replay", node_id, expected))
+            }
+            Some(_) => Ok(()),
+            None if is_config => {
+                self.node_id = Some(node_id.clone());
+                Ok(())
+            }
+            None => Err(anyhow!("update for node {:?} before any config", node_id)),
+        }
+    }
+
+    fn parse(&mut self, text: &str) -> Result<ServerMessage> {
+        self.verify(serde_json::from_str(text)?)
+    }
+
+    /// A text frame holds JSON, a binary one CBOR
+    fn parse_frame(&mut self, frame: Message) -> Result<ServerMessage> {
+        match frame {
+            Message::Text(text) => self.parse(&text),
+            Message::Binary(data) => self.verify(ciborium::from_reader(data.as_slice())?),
+            _ => Err(anyhow!("not a data frame")),
+        }
+    }
+
+    fn verify(&mut self, value: serde_json::Value) -> Result<ServerMessage> {
+        if let Some(key) = &self.server_key {
+            let timestamp = signing::verify_message(key, &value)?;
+            if timestamp < self.last_timestamp {
+                return Err(anyhow!("message timestamp {} is older than the last accepted one, possible replay", timestamp));
+            }
+            self.last_timestamp = timestamp;
+        }
+
+        let mut message = serde_json::from_value(value)?;
+        self.check_recipient(&message)?;
+        if let ServerMessage::Config { private_key, sealed_private_key: Some(sealed), .. } = &mut message {
+            *private_key = Secret::new(self.key_opener.open(sealed)?);
+        }
+        if let ServerMessage::Config { agent_token, sealed_agent_token: Some(sealed), .. } = &mut message {
+            *agent_token = Secret::new(self.key_opener.open(sealed)?);
+        }
+        Ok(message)
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args = Args::parse();
+
+    // Initialize tracing, on stderr for dry runs which print their diff on stdout
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(args.log_level.parse::<tracing::Level>()?);
+    if args.dry_run {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    info!("Starting yggman-agent v{}", env!("CARGO_PKG_VERSION"));
+    privileges::escalation();
+    if is_openwrt() {
+        info!("Running on OpenWrt");
+        args.low_memory = true;
+    }
+    WIRE_CBOR.store(args.cbor, Ordering::Relaxed);
+
+    // Routers rarely have more than one core or RAM to spare for idle workers
+    let runtime = if args.low_memory {
+        tokio::runtime::Builder::new_current_thread().enable_all().build()?
+    } else {
+        tokio::runtime::Builder::new_multi_thread().enable_all().build()?
+    };
+    let result = runtime.block_on(run(args));
+    if let Some(fatal) = result.as_ref().err().and_then(Fatal::of) {
+        error!("{:#}", result.unwrap_err());
+        std::process::exit(fatal.exit_code());
+    }
+    result
+}
+
+async fn run(args: Args) -> Result<()> {
+    let instances = instances(&args).map_err(|e| Fatal::Config.error(e.to_string()))?;
+    if instances.len() > 1 && args.pull_interval.is_some() {
+        return Err(Fatal::Config.error("--pull-interval manages a single node, it cannot be combined with several Yggdrasil instances"));
+    }
+    // Each instance is its own node, with the token kept in its state file
+    if instances.len() > 1 && (args.token.is_some() || args.node_id.is_some()) {
+        return Err(Fatal::Config.error("--token and --node-id belong to a single node, they cannot be combined with several Yggdrasil instances"));
+    }
+    info!("Control plane: {}", args.server.join(", "));
+
+    // Set to the signal that asked the agent to stop
+    let (shutdown_tx, shutdown) = tokio::sync::watch::channel(None);
+    if args.dry_run || args.oneshot {
+        // One instance after the other, for their output not to interleave
+        let mut changed = false;
+        for instance in &instances {
+            changed |= run_instance(instance, shutdown.clone()).instrument(instance_span(instance)).await?;
+        }
+        if changed {
+            std::process::exit(ONESHOT_CHANGED);
+        }
+        return Ok(());
+    }
+
+    spawn_shutdown_listener(shutdown_tx.clone())?;
+    // A misconfigured instance stops the agent rather than going unnoticed
+    futures_util::future::try_join_all(
+        instances.iter().map(|instance| run_instance(instance, shutdown.clone()).instrument(instance_span(instance))),
+    ).await?;
+    info!("yggman-agent stopped");
+    Ok(())
+}
+
+/// The arguments for each Yggdrasil instance on this host: `args` itself
+/// for a single one, or one copy per NAME=PATH --yggdrasil-config
+fn instances(args: &Args) -> Result<Vec<Args>> {
+    match args.yggdrasil_config.as_slice() {
+        [] => return Ok(vec![args.clone()]),
+        [config] if !config.contains('=') => return Ok(vec![args.clone()]),
+        _ => {}
+    }
+
+    let mut instances: Vec<Args> = Vec::new();
+    for config in &args.yggdrasil_config {
+        let (name, path) = config.split_once('=')
+            .ok_or_else(|| anyhow!("--yggdrasil-config {} needs an instance name (NAME=PATH) when managing several instances", config))?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow!("Invalid instance name \"{}\", use letters, digits, - and _", name));
+        }
+        if instances.iter().any(|instance| instance.instance.as_deref() == Some(name)) {
+            return Err(anyhow!("Yggdrasil instance {} is given twice", name));
+        }
+
+        let state_file = Path::new(&args.state_file);
+        let state_file = state_file.with_file_name(format!(
+            "{}-{}.{}",
+            state_file.file_stem().unwrap_or_default().to_string_lossy(),
+            name,
+            state_file.extension().unwrap_or_default().to_string_lossy()
+        ));
+        instances.push(Args {
+            name: Some(format!("{}-{}", node_name(args), name)),
+            yggdrasil_config: vec![path.to_string()],
+            state_file: state_file.display().to_string(),
+            restart_command: args.restart_command.as_ref().map(|command| command.replace("{instance}", name)),
+            container: args.container.replace("{instance}", name),
+            pid_file: args.pid_file.as_ref().map(|pid_file| pid_file.replace("{instance}", name)),
+            instance: Some(name.to_string()),
+            ..args.clone()
+        });
+    }
+    Ok(instances)
+}
+
+fn instance_span(args: &Args) -> tracing::Span {
+    match &args.instance {
+        Some(name) => tracing::info_span!("instance", name = %name),
+        None => tracing::Span::none(),
+    }
+}
+
+/// Manage one Yggdrasil instance until shutdown; with --oneshot, whether
+/// its config was changed
+async fn run_instance(args: &Args, mut shutdown: tokio::sync::watch::Receiver<Option<&'static str>>) -> Result<bool> {
+    // Check for yggdrasil config file
+    let ygg_config_path = yggdrasil_config_path(args).ok_or_else(|| {
+        Fatal::Config.error("Yggdrasil config file not found. Please ensure yggdrasil.conf exists at /etc/yggdrasil.conf or /etc/yggdrasil/yggdrasil.conf, or pass --yggdrasil-config")
+    })?;
+    info!("Found Yggdrasil config at: {}", ygg_config_path);
+    if is_openwrt() && Path::new("/etc/config/yggdrasil").exists() {
+        warn!("/etc/config/yggdrasil exists; if the init script builds the Yggdrasil config from UCI it will overwrite what the agent writes to {}", ygg_config_path);
+    }
+    match args.yggdrasil_mode {
+        YggdrasilMode::Service => {}
+        YggdrasilMode::Sidecar => match &args.pid_file {
+            Some(pid_file) => info!("Managing Yggdrasil in a sidecar container, its PID read from {}", pid_file),
+            None if args.restart_command.is_some() || args.no_restart => {}
+            None => return Err(Fatal::Config.error("--yggdrasil-mode sidecar needs --pid-file to signal Yggdrasil")),
+        },
+        _ => info!("Managing Yggdrasil in {:?} container {}", args.yggdrasil_mode, args.container),
+    }
+    
+    let mut verifier = MessageVerifier::new(args.server_pubkey.as_deref(), args.node_id.as_deref())
+        .map_err(|e| Fatal::Config.error(e.to_string()))?;
+    if args.auto_update && verifier.server_key.is_none() {
+        return Err(Fatal::Config.error("--auto-update requires --server-pubkey, otherwise anyone able to reach the agent could push a binary"));
+    }
+    let mut state = AgentState::load(&args.state_file);
+    info!("Last applied config revision: {}", state.last_revision);
+    if verifier.server_key.is_none() {
+        warn!("No --server-pubkey given, server messages are not verified");
+    }
+    
+    if args.dry_run {
+        dry_run(args, &ygg_config_path, &mut verifier, &state).await?;
+        return Ok(false);
+    }
+    if args.oneshot {
+        return oneshot(args, &ygg_config_path, &mut verifier, &mut state).await;
+    }
+
+    if let Some(interval) = args.pull_interval {
+        pull_loop(args, &ygg_config_path, &mut verifier, &mut state, interval, shutdown).await?;
+        return Ok(false);
+    }
+
+    // Main loop with reconnection logic
+    let mut reconnect_interval = args.reconnect_interval;
+    loop {
+        match run_agent(args, &ygg_config_path, &mut verifier, &mut state, &mut reconnect_interval, shutdown.clone()).await {
+            Ok(_) => {
+                info!("Agent connection closed normally");
+            }
+            Err(e) if Fatal::of(&e).is_some() => return Err(e),
+            Err(e) => {
+                error!("Agent error: {}", e);
+            }
+        }
+
+        if shutdown.borrow().is_some() {
+            break;
+        }
+        info!(
+            "Reconnecting in {} seconds...",
+            reconnect_interval
+        );
+        tokio::select! {
+            _ = sleep(Duration::from_secs(reconnect_interval)) => {}
+            _ = shutdown.changed() => break,
+        }
+    }
+    Ok(false)
+}
+
+/// Record SIGINT or SIGTERM in `shutdown`, for the agent to say goodbye to
+/// the control plane instead of just dropping the connection
+fn spawn_shutdown_listener(shutdown: tokio::sync::watch::Sender<Option<&'static str>>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        tokio::spawn(async move {
+            let signal = tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = interrupt.recv() => "SIGINT",
+            };
+            // Before logging, which panics once stdout is a closed pipe
+            let _ = shutdown.send(Some(signal));
+            info!("Received {}, shutting down", signal);
+        });
+    }
+    #[cfg(not(unix))]
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = shutdown.send(Some("Ctrl-C"));
+            info!("Received Ctrl-C, shutting down");
+        }
+    });
+    Ok(())
+}
+
+async fn run_agent(
+    args: &Args,
+    ygg_config_path: &str,
+    verifier: &mut MessageVerifier,
+    state: &mut AgentState,
+    reconnect_interval: &mut u64,
+    mut shutdown: tokio::sync::watch::Receiver<Option<&'static str>>,
+) -> Result<()> {
+    let node_name = node_name(args);
+
+    // Connect to WebSocket
+    let (ws_stream, server) = connect(args).await?;
+    info!("Connected to control plane {}", server);
+
+    // Discover network interfaces
+    let whoami_url = args.public_address_from_server.then(|| whoami_url(server));
+    let addresses = scan_addresses(whoami_url.as_deref()).await?;
+    info!("Discovered addresses: {:?}", addresses);
+
+    let (mut write, mut read) = ws_stream.split();
+
+    // Send registration message
+    let register_msg = register_message(args, &node_name, addresses.clone(), verifier, state).await;
+    let frame = register_msg.to_frame()?;
+    write.send(frame).await?;
+    info!("Sent registration for node: {}", node_name);
+
+    // Heartbeats until the server's Config says otherwise
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(30));
+    
+    // Spawn address scanning task, rescheduled when the server changes the period
+    let (address_scan_tx, mut address_scan_rx) = tokio::sync::mpsc::channel(1);
+    let (scan_period_tx, mut scan_period_rx) = tokio::sync::watch::channel(Duration::from_secs(60));
+    let current_addresses = Arc::new(tokio::sync::RwLock::new(addresses.clone()));
+    let current_addresses_clone = current_addresses.clone();
+    
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(*scan_period_rx.borrow());
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                changed = scan_period_rx.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    interval = delayed_interval(*scan_period_rx.borrow());
+                    continue;
+                }
+            }
+            
+            match scan_addresses(whoami_url.as_deref()).await {
+                Ok(new_addresses) => {
+                    let mut current = current_addresses_clone.write().await;
+                    
+                    // Check if addresses have changed
+                    if *current != new_addresses {
+                        info!("Address change detected: {:?} -> {:?}", *current, new_addresses);
+                        *current = new_addresses.clone();
+                        
+                        if address_scan_tx.send(new_addresses).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to scan addresses: {}", e);
+                }
+            }
+        }
+    });
+
+    // Reasons to ask the server for the full config again
+    let (resync_tx, mut resync_rx) = tokio::sync::mpsc::channel::<String>(1);
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        
+        // Sent after restoring the Yggdrasil config by hand
+        let mut hangup = signal(SignalKind::hangup())?;
+        let resync_tx = resync_tx.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if resync_tx.send("SIGHUP received".to_string()).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let mut reported_listen: Option<Vec<String>> = None;
+    // Whether the last status found hand edits, to act on them only once
+    let mut drifted = false;
+
+    // The server pings, or else answers the pings sent with each heartbeat;
+    // hearing nothing for longer than that interval plus the pong timeout
+    // means the connection died without a close, e.g. behind a NAT
+    let mut last_frame = tokio::time::Instant::now();
+    let mut server_ping: Option<Duration> = None;
+    let mut pong_timeout = DEFAULT_PONG_TIMEOUT;
+
+    // Main message loop
+    loop {
+        let silence_limit = server_ping.unwrap_or(heartbeat.period()) + pong_timeout;
+        tokio::select! {
+            msg = read.next() => {
+                if let Some(Ok(_)) = &msg {
+                    last_frame = tokio::time::Instant::now();
+                }
+                match msg {
+                    Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => {
+                        let parsed = verifier.parse_frame(frame);
+                        let stale = parsed.as_ref().ok().and_then(|msg| {
+                            state.stale_reason(msg.revision()?, msg.epoch().unwrap_or_default(), verifier.answers(msg))
+                        });
+                        match parsed {
+                            Ok(server_msg) if stale.is_some() => {
+                                // Never roll back to an older topology, whatever delivered it
+                                let revision = server_msg.revision().unwrap_or_default();
+                                let reason = stale.unwrap_or_default();
+                                warn!("Rejected config revision {}: {}", revision, reason);
+                                let frame = AgentMessage::ConfigRejected { revision, reason }.to_frame()?;
+                                if let Err(e) = write.send(frame).await {
+                                    error!("Failed to report rejected config: {}", e);
+                                    break;
+                                }
+                            }
+                            Ok(ServerMessage::Error { message, code: Some(ErrorCode::UnsupportedVersion), upgrade }) => {
+                                // Registration was refused; reconnecting only helps once the server lowers its minimum
+                                error!("Server refused this agent: {}", message);
+                                if let Some(upgrade) = upgrade {
+                                    error!(
+                                        "Upgrade to agent version {} or newer (protocol {} or newer); this is {} (protocol {})",
+                                        upgrade.min_agent_version.as_deref().unwrap_or("any"),
+                                        upgrade.min_protocol_version,
+                                        env!("CARGO_PKG_VERSION"),
+                                        PROTOCOL_VERSION
+                                    );
+                                }
+                                return Err(Fatal::Incompatible.error("agent version not supported by the server"));
+                            }
+                            Ok(ServerMessage::Error { message, code: Some(ErrorCode::EnrollmentRefused), .. }) => {
+                                // Reconnecting with the same token would be refused again
+                                error!("Server refused to enroll this node: {}", message);
+                                return Err(Fatal::Unauthorized.error("enrollment refused, pass a valid --enrollment-token"));
+                            }
+                            Ok(ServerMessage::Error { message, code: Some(ErrorCode::AgentTokenRefused), .. }) => {
+                                error!("Server refused to hand over this node: {}", message);
+                                return Err(Fatal::Unauthorized.error("agent token refused, pass the node's --token"));
+                            }
+                            Ok(ServerMessage::Error { message, code: Some(ErrorCode::Unauthorized), .. }) => {
+                                // The registration failed or was lost; a new connection registers again
+                                error!("Server dropped a message from this agent: {}", message);
+                                state.record_server_error(ErrorCode::Unauthorized, &message, &args.state_file);
+                                return Err(anyhow!("not registered with the server"));
+                            }
+                            Ok(ServerMessage::Error { message, code: Some(ErrorCode::ParseError), .. }) => {
+                                // E.g. a message type the server is too old to know
+                                warn!("Server could not parse a message from this agent (version {}, protocol {}): {}", env!("CARGO_PKG_VERSION"), PROTOCOL_VERSION, message);
+                                state.record_server_error(ErrorCode::ParseError, &message, &args.state_file);
+                                if let Err(e) = send_status(&mut write, ygg_config_path, state).await {
+                                    error!("Failed to send status: {}", e);
+                                    break;
+                                }
+                            }
+                            Ok(ServerMessage::UpdateAvailable { version, url, sha256 }) => {
+                                if !args.auto_update {
+                                    info!("Agent version {} is available at {}, this is {}", version, url, env!("CARGO_PKG_VERSION"));
+                                    continue;
+                                }
+                                info!("Updating agent to version {} from {}", version, url);
+                                match install_update(&url, &sha256).await {
+                                    Ok(exe) => {
+                                        info!("Installed agent version {}, restarting", version);
+                                        let _ = write.close().await;
+                                        return Err(restart_agent(&exe));
+                                    }
+                                    Err(e) => error!("Failed to update agent to version {}: {}", version, e),
+                                }
+                            }
+                            Ok(server_msg) => {
+                                if let ServerMessage::Config { agent_token, .. } = &server_msg {
+                                    state.remember_agent_token(agent_token);
+                                }
+                                if let ServerMessage::Config { timing: Some(timing), .. } | ServerMessage::Timing { timing } = &server_msg {
+                                    info!("Server timing: heartbeat {}s, address scan {}s", timing.heartbeat_interval, timing.address_scan_interval);
+                                    let period = Duration::from_secs(timing.heartbeat_interval.max(1));
+                                    if heartbeat.period() != period {
+                                        heartbeat = delayed_interval(period);
+                                    }
+                                    let period = Duration::from_secs(timing.address_scan_interval.max(1));
+                                    scan_period_tx.send_if_modified(|current| std::mem::replace(current, period) != period);
+                                    if let Some(interval) = timing.reconnect_interval {
+                                        *reconnect_interval = interval;
+                                    }
+                                    server_ping = (timing.ping_interval > 0).then(|| Duration::from_secs(timing.ping_interval));
+                                    pong_timeout = Duration::from_secs(timing.pong_timeout.max(1));
+                                }
+                                let revision = server_msg.revision();
+                                let epoch = server_msg.epoch().map(str::to_string);
+                                let outcome = handle_server_message(server_msg, ygg_config_path, &state.extra_config_keys, args).await?;
+                                if let Some(keys) = &outcome.extra_config_keys {
+                                    state.extra_config_keys = keys.clone();
+                                }
+                                if outcome.applied_listen.is_some() {
+                                    state.managed_hash = read_yggdrasil_config(ygg_config_path).await
+                                        .map(|config| managed_sections_hash(&config, &state.extra_config_keys));
+                                    drifted = false;
+                                }
+                                
+                                if let Some(revision) = revision {
+                                    state.last_revision = revision;
+                                    state.epoch = epoch.unwrap_or_default();
+                                    if let Err(e) = state.save(&args.state_file) {
+                                        warn!("Failed to save agent state to {}: {}", args.state_file, e);
+                                    }
+                                    
+                                    let applied = AgentMessage::ConfigApplied {
+                                        revision,
+                                        success: outcome.error.is_none(),
+                                        error: outcome.error,
+                                    };
+                                    if let Err(e) = write.send(applied.to_frame()?).await {
+                                        error!("Failed to acknowledge config revision {}: {}", revision, e);
+                                        break;
+                                    }
+                                    if let Err(e) = send_status(&mut write, ygg_config_path, state).await {
+                                        error!("Failed to send status: {}", e);
+                                        break;
+                                    }
+                                }
+                                if let (Some(backend), Some(listen)) = (args.manage_firewall, &outcome.applied_listen) {
+                                    let error = sync_firewall(backend, listen, state).await.err().map(|e| e.to_string());
+                                    if let Err(e) = state.save(&args.state_file) {
+                                        warn!("Failed to save agent state to {}: {}", args.state_file, e);
+                                    }
+                                    let report = AgentMessage::FirewallUpdated {
+                                        backend: backend.name().to_string(),
+                                        open_ports: state.opened_ports.clone(),
+                                        error,
+                                    };
+                                    if let Err(e) = write.send(report.to_frame()?).await {
+                                        error!("Failed to report firewall state: {}", e);
+                                        break;
+                                    }
+                                }
+                                let resolved = outcome.resolved_listen;
+                                
+                                // Report listen templates resolved locally so peers dial the right addresses
+                                if let Some(resolved) = resolved {
+                                    if reported_listen.as_ref() != Some(&resolved) {
+                                        let frame = AgentMessage::ListenResolved { listen: resolved.clone() }.to_frame()?;
+                                        if let Err(e) = write.send(frame).await {
+                                            error!("Failed to report resolved listen endpoints: {}", e);
+                                            break;
+                                        }
+                                        info!("Reported resolved listen endpoints: {:?}", resolved);
+                                        reported_listen = Some(resolved);
+                                    }
+                                }
+                            }
+                            Err(e) => error!("Rejected server message: {}", e),
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        info!("Server closed connection");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    None => {
+                        info!("WebSocket stream ended");
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            _ = heartbeat.tick() => {
+                let heartbeat = AgentMessage::Heartbeat.to_frame()?;
+                if let Err(e) = write.send(heartbeat).await {
+                    error!("Failed to send heartbeat: {}", e);
+                    break;
+                }
+                if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                    error!("Failed to send ping: {}", e);
+                    break;
+                }
+                debug!("Sent heartbeat");
+                let drift = match send_status(&mut write, ygg_config_path, state).await {
+                    Ok(drift) => drift,
+                    Err(e) => {
+                        error!("Failed to send status: {}", e);
+                        break;
+                    }
+                };
+                if drift && !drifted {
+                    warn!("Managed sections of {} were edited by hand", ygg_config_path);
+                    if args.on_drift == DriftPolicy::Reapply {
+                        let _ = resync_tx.try_send(format!("managed sections of {} edited by hand", ygg_config_path));
+                    }
+                }
+                drifted = drift;
+            }
+            Some(new_addresses) = address_scan_rx.recv() => {
+                let update_msg = AgentMessage::UpdateAddresses {
+                    addresses: new_addresses,
+                };
+                let frame = update_msg.to_frame()?;
+                if let Err(e) = write.send(frame).await {
+                    error!("Failed to send address update: {}", e);
+                    break;
+                }
+                info!("Sent address update to control plane");
+            }
+            Some(reason) = resync_rx.recv() => {
+                info!("Requesting full config from control plane: {}", reason);
+                let frame = AgentMessage::RequestFullConfig { reason: Some(reason) }.to_frame()?;
+                if let Err(e) = write.send(frame).await {
+                    error!("Failed to request full config: {}", e);
+                    break;
+                }
+            }
+            _ = tokio::time::sleep_until(last_frame + silence_limit) => {
+                warn!("Nothing heard from the control plane for {}s, reconnecting", silence_limit.as_secs());
+                break;
+            }
+            _ = shutdown.changed() => {
+                let signal = shutdown.borrow().unwrap_or("shutdown");
+                let frame = AgentMessage::Disconnect { reason: Some(format!("{} received", signal)) }.to_frame()?;
+                // A hung connection must not keep the agent from exiting
+                let goodbye = async {
+                    write.send(frame).await?;
+                    write.send(Message::Close(None)).await
+                };
+                match tokio::time::timeout(Duration::from_secs(5), goodbye).await {
+                    Ok(Ok(())) => info!("Told the control plane this node is going offline"),
+                    Ok(Err(e)) => warn!("Failed to announce shutdown: {}", e),
+                    Err(_) => warn!("Timed out announcing shutdown"),
+                }
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Name to register as: --name, or the hostname
+fn node_name(args: &Args) -> String {
+    args.name.clone().unwrap_or_else(|| {
+        hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "unknown".to_string())
+    })
+}
+
+async fn register_message(args: &Args, node_name: &str, addresses: Vec<String>, verifier: &mut MessageVerifier, state: &AgentState) -> AgentMessage {
+    let nonce = verifier.registering();
+    let endpoint = match yggdrasil_config_path(args) {
+        Some(path) => admin_endpoint(&path).await,
+        None => None,
+    };
+    AgentMessage::Register {
+        name: node_name.to_string(),
+        addresses,
+        network: args.network.clone(),
+        tags: args.tags.clone(),
+        agent_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        yggdrasil_version: yggdrasil_version(endpoint.as_deref()).await,
+        protocol_version: PROTOCOL_VERSION,
+        target: Some(format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)),
+        dry_run: args.dry_run,
+        enrollment_token: args.enrollment_token.clone(),
+        agent_token: args.token.clone().or_else(|| state.agent_token.clone()),
+        key_agreement_key: Some(verifier.key_opener.public_key()),
+        nonce: Some(nonce),
+    }
+}
+
+type WsStream = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+type WsWrite = futures_util::stream::SplitSink<WsStream, Message>;
+
+/// Connect to the first reachable control plane of --server, in order
+async fn connect(args: &Args) -> Result<(WsStream, &str)> {
+    // Configs of even large networks stay well below these limits
+    let config = args.low_memory.then(|| WebSocketConfig {
+        write_buffer_size: 8 << 10,
+        max_write_buffer_size: 1 << 20,
+        max_message_size: Some(4 << 20),
+        max_frame_size: Some(4 << 20),
+        ..Default::default()
+    });
+    for server in &args.server {
+        match tokio::time::timeout(CONNECT_TIMEOUT, connect_async_with_config(server.as_str(), config, false)).await {
+            Ok(Ok((stream, _))) => return Ok((stream, server)),
+            Ok(Err(e)) => warn!("Control plane {} is unreachable: {}", server, e),
+            Err(_) => warn!("Control plane {} did not answer within {}s", server, CONNECT_TIMEOUT.as_secs()),
+        }
+    }
+    Err(anyhow!("no control plane reachable"))
+}
+
+/// The configuration received by --dry-run and --oneshot
+struct FetchedConfig {
+    revision: u64,
+    epoch: String,
+    /// Echoed from the registration or pull, see `MessageVerifier::answers`
+    answers: bool,
+    /// The Yggdrasil config as it would be written
+    config: serde_json::Value,
+    listen: ResolvedListen,
+    extra_config_keys: Vec<String>,
+    agent_token: Secret<String>,
+}
+
+impl FetchedConfig {
+    /// `None` for anything but a `Config` message
+    fn from_message(msg: ServerMessage, verifier: &MessageVerifier) -> Option<Self> {
+        let answers = verifier.answers(&msg);
+        let ServerMessage::Config { revision, epoch, private_key, agent_token, listen, peers, allowed_public_keys, interface_peers, if_name, extra_config, .. } = msg else {
+            return None;
+        };
+        debug!("Received configuration revision {}", revision);
+        let listen = resolve_listen_templates(&listen);
+        let extras = ConfigExtras { interface_peers, if_name, extra_config };
+        let config = full_yggdrasil_config(private_key.expose(), &listen.resolved, &peers, &allowed_public_keys, &extras);
+        let extra_config_keys = extras.extra_config.keys().cloned().collect();
+        Some(Self { revision, epoch, answers, config, listen, extra_config_keys, agent_token })
+    }
+}
+
+/// Register and wait for the configuration, for runs that handle a single
+/// config and exit
+async fn fetch_config(args: &Args, verifier: &mut MessageVerifier, state: &AgentState) -> Result<(WsWrite, FetchedConfig)> {
+    let (ws_stream, server) = connect(args).await?;
+    let whoami_url = args.public_address_from_server.then(|| whoami_url(server));
+    let addresses = scan_addresses(whoami_url.as_deref()).await?;
+    let (mut write, mut read) = ws_stream.split();
+    let register_msg = register_message(args, &node_name(args), addresses, verifier, state).await;
+    write.send(register_msg.to_frame()?).await?;
+    
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(30), read.next()).await
+            .map_err(|_| anyhow!("no configuration from the control plane within 30 seconds"))?;
+        match msg {
+            Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => match verifier.parse_frame(frame)? {
+                ServerMessage::Error { message, code, .. } => {
+                    let error = format!("server error: {}", message);
+                    return Err(match code {
+                        Some(ErrorCode::UnsupportedVersion) => Fatal::Incompatible.error(error),
+                        Some(ErrorCode::EnrollmentRefused | ErrorCode::AgentTokenRefused) => Fatal::Unauthorized.error(error),
+                        _ => anyhow!(error),
+                    });
+                }
+                msg => {
+                    if let Some(fetched) = FetchedConfig::from_message(msg, verifier) {
+                        return Ok((write, fetched));
+                    }
+                }
+            },
+            Some(Ok(Message::Close(_))) | None => {
+                return Err(anyhow!("control plane closed the connection before sending a configuration"));
+            }
+            Some(Err(e)) => return Err(e.into()),
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+/// Register, wait for the configuration and print a diff of the Yggdrasil
+/// config against what the agent would write. Nothing is written, restarted
+/// or opened in the firewall, and the revision is not recorded.
+async fn dry_run(args: &Args, ygg_config_path: &str, verifier: &mut MessageVerifier, state: &AgentState) -> Result<()> {
+    let (mut write, fetched) = fetch_config(args, verifier, state).await?;
+    let _ = write.close().await;
+    
+    let current = match tokio::fs::read_to_string(ygg_config_path).await {
+        Ok(current) => current,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", ygg_config_path, e)),
+    };
+    let proposed = serde_json::to_string_pretty(&fetched.config)?;
+    print_diff(ygg_config_path, &redact_private_key(&current), &redact_private_key(&proposed));
+    Ok(())
+}
+
+/// Register, write the configuration if the Yggdrasil config differs from
+/// it, report back like a connected agent would and disconnect. Returns
+/// whether the Yggdrasil config was changed.
+async fn oneshot(args: &Args, ygg_config_path: &str, verifier: &mut MessageVerifier, state: &mut AgentState) -> Result<bool> {
+    let (mut write, fetched) = fetch_config(args, verifier, state).await?;
+    state.remember_agent_token(&fetched.agent_token);
+    let revision = fetched.revision;
+    if let Some(reason) = state.stale_reason(revision, &fetched.epoch, fetched.answers) {
+        // Never roll back to an older topology, whatever delivered it
+        let rejected = AgentMessage::ConfigRejected { revision, reason: reason.clone() };
+        write.send(rejected.to_frame()?).await?;
+        let _ = write.close().await;
+        return Err(anyhow!("Rejected config revision {}: {}", revision, reason));
+    }
+    
+    let (changed, error) = apply_if_changed(args, ygg_config_path, &fetched, state).await;
+    let applied = error.is_none();
+    
+    let report = AgentMessage::ConfigApplied { revision, success: applied, error: error.clone() };
+    write.send(report.to_frame()?).await?;
+    send_status(&mut write, ygg_config_path, state).await?;
+    if let (Some(backend), true) = (args.manage_firewall, applied) {
+        let error = sync_firewall(backend, &fetched.listen.resolved, state).await.err().map(|e| e.to_string());
+        if let Err(e) = state.save(&args.state_file) {
+            warn!("Failed to save agent state to {}: {}", args.state_file, e);
+        }
+        let report = AgentMessage::FirewallUpdated { backend: backend.name().to_string(), open_ports: state.opened_ports.clone(), error };
+        write.send(report.to_frame()?).await?;
+    }
+    if has_listen_templates(&fetched.listen.raw) {
+        let resolved = AgentMessage::ListenResolved { listen: fetched.listen.resolved };
+        write.send(resolved.to_frame()?).await?;
+    }
+    let _ = write.close().await;
+    
+    match error {
+        Some(error) => Err(anyhow!(error)),
+        None => Ok(changed),
+    }
+}
+
+/// Write `fetched` and restart Yggdrasil when the Yggdrasil config differs
+/// from it, and record the revision. Returns whether the config changed and
+/// what failed, if anything.
+async fn apply_if_changed(args: &Args, ygg_config_path: &str, fetched: &FetchedConfig, state: &mut AgentState) -> (bool, Option<String>) {
+    let changed = read_yggdrasil_config(ygg_config_path).await.as_ref() != Some(&fetched.config);
+    let mut error = None;
+    if changed {
+        match write_yggdrasil_config(ygg_config_path, &fetched.config).await {
+            Ok(()) if args.no_restart => info!("Skipping service restart (--no-restart flag set)"),
+            Ok(()) => {
+                if let Err(e) = restart_yggdrasil(args) {
+                    error = Some(format!("Failed to restart Yggdrasil service: {}", e));
+                }
+            }
+            Err(e) => error = Some(format!("Failed to write Yggdrasil config: {}", e)),
+        }
+    } else {
+        debug!("{} is up to date with revision {}", ygg_config_path, fetched.revision);
+    }
+    
+    state.last_revision = fetched.revision;
+    state.epoch = fetched.epoch.clone();
+    if error.is_none() {
+        state.extra_config_keys = fetched.extra_config_keys.clone();
+        state.managed_hash = Some(managed_sections_hash(&fetched.config, &state.extra_config_keys));
+    }
+    if let Err(e) = state.save(&args.state_file) {
+        warn!("Failed to save agent state to {}: {}", args.state_file, e);
+    }
+    (changed, error)
+}
+
+/// Fetch the configuration over HTTP every `interval` seconds and apply it
+/// when it changed, until SIGINT or SIGTERM. Nothing is reported back, so
+/// the server sees no status, drift or resolved listen templates.
+async fn pull_loop(
+    args: &Args,
+    ygg_config_path: &str,
+    verifier: &mut MessageVerifier,
+    state: &mut AgentState,
+    interval: u64,
+    mut shutdown: tokio::sync::watch::Receiver<Option<&'static str>>,
+) -> Result<()> {
+    let (Some(node_id), Some(token)) = (&args.node_id, &args.token) else {
+        return Err(Fatal::Config.error("--pull-interval needs --node-id and --token"));
+    };
+    let path = format!("/api/nodes/{}/config", node_id);
+    let urls: Vec<String> = args.server.iter().map(|server| api_url(server, &path)).collect();
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    info!("Pulling the configuration from {} every {}s", urls.join(", "), interval);
+    
+    loop {
+        match pull_config(&client, &urls, token.expose(), verifier).await {
+            Ok(fetched) => match state.stale_reason(fetched.revision, &fetched.epoch, fetched.answers) {
+                // Never roll back to an older topology, whatever delivered it
+                Some(reason) => warn!("Rejected config revision {}: {}", fetched.revision, reason),
+                None => match apply_if_changed(args, ygg_config_path, &fetched, state).await {
+                    (_, Some(e)) => error!("{}", e),
+                    (_, None) => {
+                        if let Some(backend) = args.manage_firewall {
+                            if let Err(e) = sync_firewall(backend, &fetched.listen.resolved, state).await {
+                                error!("Failed to update firewall: {}", e);
+                            }
+                            if let Err(e) = state.save(&args.state_file) {
+                                warn!("Failed to save agent state to {}: {}", args.state_file, e);
+                            }
+                        }
+                    }
+                },
+            },
+            Err(e) if Fatal::of(&e).is_some() => return Err(e),
+            Err(e) => error!("Failed to pull configuration: {}", e),
+        }
+        
+        tokio::select! {
+            _ = sleep(Duration::from_secs(interval.max(1))) => {}
+            _ = shutdown.changed() => break,
+        }
+    }
+    
+    info!("yggman-agent stopped");
+    Ok(())
+}
+
+/// The configuration from the first of `urls` that serves it
+async fn pull_config(client: &reqwest::Client, urls: &[String], token: &str, verifier: &mut MessageVerifier) -> Result<FetchedConfig> {
+    for url in urls {
+        let response = client.get(url)
+            .bearer_auth(token)
+            .header(sealing::KEY_AGREEMENT_HEADER, verifier.key_opener.public_key())
+            .header(signing::NONCE_HEADER, verifier.renew_nonce())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Some(status @ (reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN)) = response.as_ref().err().and_then(reqwest::Error::status) {
+            return Err(Fatal::Unauthorized.error(format!("{} refused the agent token: {}", url, status)));
+        }
+        let text = match response {
+            Ok(response) => response.text().await?,
+            Err(e) => {
+                warn!("Failed to pull configuration from {}: {}", url, e);
+                continue;
+            }
+        };
+        let message = verifier.parse(&text)?;
+        return FetchedConfig::from_message(message, verifier)
+            .ok_or_else(|| anyhow!("{} answered with something other than a configuration", url));
+    }
+    Err(anyhow!("no control plane reachable"))
+}
+
+/// Replace the value of `PrivateKey` lines, JSON or HJSON, with a short
+/// fingerprint that still tells whether the key would change
+fn redact_private_key(config: &str) -> String {
+    use sha2::{Digest, Sha256};
+    
+    let mut redacted = String::with_capacity(config.len());
+    for line in config.lines() {
+        let trimmed = line.trim_start();
+        let value = trimmed.strip_prefix("\"PrivateKey\"")
+            .or_else(|| trimmed.strip_prefix("PrivateKey"))
+            .and_then(|rest| rest.trim_start().strip_prefix(':'));
+        match value {
+            Some(value) => {
+                let comma = if value.trim_end().ends_with(',') { "," } else { "" };
+                let key = value.trim().trim_end_matches(',').trim_matches('"');
+                let fingerprint = &hex::encode(Sha256::digest(key.as_bytes()))[..12];
+                redacted.push_str(&line[..line.len() - value.len()]);
+                redacted.push_str(&format!(" \"<redacted, sha256 {}>\"{}", fingerprint, comma));
+            }
+            None => redacted.push_str(line),
+        }
+        redacted.push('\n');
+    }
+    redacted
+}
+
+/// Print a unified diff from `current` to `proposed`, colored on terminals
+fn print_diff(path: &str, current: &str, proposed: &str) {
+    use similar::{ChangeTag, TextDiff};
+    use std::io::IsTerminal;
+    
+    if current == proposed {
+        println!("{} is up to date, nothing would change", path);
+        return;
+    }
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let paint = |code: &str, text: &str| {
+        if color { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text.to_string() }
+    };
+    
+    println!("{}", paint("1", &format!("--- {}", path)));
+    println!("{}", paint("1", &format!("+++ {} (from control plane)", path)));
+    let diff = TextDiff::from_lines(current, proposed);
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        println!("{}", paint("36", &hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches('\n');
+            match change.tag() {
+                ChangeTag::Delete => println!("{}", paint("31", &format!("-{}", line))),
+                ChangeTag::Insert => println!("{}", paint("32", &format!("+{}", line))),
+                ChangeTag::Equal => println!(" {}", line),
+            }
+        }
+    }
+}
+
+/// Download the agent release at `url`, check it against `sha256` and put
+/// it in place of the running binary, whose path is returned
+async fn install_update(url: &str, sha256: &str) -> Result<std::path::PathBuf> {
+    use sha2::{Digest, Sha256};
+    
+    let exe = std::env::current_exe()?;
+    let binary = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+    let digest = hex::encode(Sha256::digest(&binary));
+    if !digest.eq_ignore_ascii_case(sha256) {
+        return Err(anyhow!("checksum mismatch: expected {}, downloaded {}", sha256, digest));
+    }
+    
+    // Stage next to the binary so the rename stays on one filesystem
+    let staged = exe.with_extension("new");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o755);
+    }
+    {
+        use std::io::Write;
+        let mut file = options.open(&staged)
+            .map_err(|e| anyhow!("cannot write {}: {}", staged.display(), e))?;
+        file.write_all(&binary)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&staged, &exe)?;
+    Ok(exe)
+}
+
+/// Replace this process with `exe`, keeping the arguments. Only returns
+/// on failure.
+fn restart_agent(exe: &Path) -> anyhow::Error {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let e = Command::new(exe).args(std::env::args_os().skip(1)).exec();
+        anyhow!("failed to restart into {}: {}", exe.display(), e)
+    }
+    #[cfg(not(unix))]
+    {
+        anyhow!("{} was updated, restart the agent to run it", exe.display())
+    }
+}
+
+/// Interval whose first tick is one period away, unlike `tokio::time::interval`
+fn delayed_interval(period: Duration) -> tokio::time::Interval {
+    tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+}
+
+/// Report what the Yggdrasil config file on disk actually contains, so the
+/// server can spot drift from what it pushed. Returns whether the managed
+/// sections were edited since the agent last wrote them.
+async fn send_status<S>(write: &mut S, ygg_config_path: &str, state: &AgentState) -> Result<bool>
+where
+    S: futures_util::Sink<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let Some(config) = read_yggdrasil_config(ygg_config_path).await else {
+        return Ok(false);
+    };
+    let strings = |field: &str| -> Vec<String> {
+        config[field].as_array()
+            .map(|items| items.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+            .unwrap_or_default()
+    };
+    
+    let drift = state.managed_hash.as_ref()
+        .is_some_and(|hash| *hash != managed_sections_hash(&config, &state.extra_config_keys));
+    
+    let endpoint = admin_endpoint(ygg_config_path).await;
+    let peers = query_peers(endpoint.as_deref()).await;
+    let status = AgentMessage::Status {
+        revision: state.last_revision,
+        listen: strings("Listen"),
+        peers: strings("Peers"),
+        allowed_public_keys: strings("AllowedPublicKeys"),
+        established_peers: peers.as_ref().map(|peers| peers.iter().map(|peer| peer.key.clone()).collect()),
+        drift,
+        traffic: peers,
+        last_server_error: state.last_server_error.clone(),
+    };
+    write.send(status.to_frame()?).await?;
+    Ok(drift)
+}
+
+async fn read_yggdrasil_config(ygg_config_path: &str) -> Option<serde_json::Value> {
+    let content = match tokio::fs::read_to_string(ygg_config_path).await {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Cannot read {}: {}", ygg_config_path, e);
+            return None;
+        }
+    };
+    match serde_json::from_str(&content) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            warn!("Cannot parse {}: {}", ygg_config_path, e);
+            None
+        }
+    }
+}
+
+/// Keys of the Yggdrasil config written from the server's config, besides
+/// the extra config keys
+const MANAGED_KEYS: &[&str] = &["PrivateKey", "Listen", "Peers", "AllowedPublicKeys", "InterfacePeers", "IfName"];
+
+/// Hash of the sections the agent manages; formatting and other keys do not count
+fn managed_sections_hash(config: &serde_json::Value, extra_config_keys: &[String]) -> String {
+    use sha2::{Digest, Sha256};
+    
+    let managed: serde_json::Map<String, serde_json::Value> = MANAGED_KEYS.iter().copied()
+        .chain(extra_config_keys.iter().map(String::as_str))
+        .filter_map(|key| config.get(key).map(|value| (key.to_string(), value.clone())))
+        .collect();
+    hex::encode(Sha256::digest(serde_json::Value::Object(managed).to_string()))
+}
+
+/// Version of the running Yggdrasil daemon, or of the installed binary
+/// when the daemon cannot be asked
+async fn yggdrasil_version(endpoint: Option<&str>) -> Option<String> {
+    let from_daemon = yggdrasilctl(endpoint)
+        .args(["-json", "getSelf"])
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| serde_json::from_slice::<serde_json::Value>(&output.stdout).ok())
+        .and_then(|info| info["build_version"].as_str().map(str::to_string));
+    if from_daemon.is_some() {
+        return from_daemon;
+    }
+
+    // `yggdrasil -version` prints "Build name: ..." and "Build version: ..."
+    let output = tokio::process::Command::new("yggdrasil")
+        .arg("-version")
+        .output()
+        .await
+        .map_err(|e| debug!("Cannot run yggdrasil -version: {}", e))
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("Build version:"))
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// Ask the running Yggdrasil daemon which peers are up and how much traffic
+/// each carried, sorted by key
+async fn query_peers(endpoint: Option<&str>) -> Option<Vec<PeerCounters>> {
+    let output = match yggdrasilctl(endpoint)
+        .args(["-json", "getPeers"])
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!("yggdrasilctl getPeers failed: {}", String::from_utf8_lossy(&output.stderr));
+            return None;
+        }
+        Err(e) => {
+            debug!("Cannot run yggdrasilctl: {}", e);
+            return None;
+        }
+    };
+    
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let peer_entries: Vec<&serde_json::Value> = match &response["peers"] {
+        // Yggdrasil 0.5 lists peers, older releases map addresses to peers
+        serde_json::Value::Array(peers) => peers.iter().collect(),
+        serde_json::Value::Object(peers) => peers.values().collect(),
+        _ => return None,
+    };
+    
+    // A peer connected over several links appears once per link
+    let mut peers: std::collections::BTreeMap<String, PeerCounters> = std::collections::BTreeMap::new();
+    for entry in peer_entries.into_iter().filter(|peer| peer["up"].as_bool().unwrap_or(true)) {
+        let Some(key) = entry["key"].as_str() else {
+            continue;
+        };
+        let peer = peers.entry(key.to_string()).or_insert_with(|| PeerCounters {
+            key: key.to_string(),
+            rx_bytes: 0,
+            tx_bytes: 0,
+        });
+        peer.rx_bytes += entry["bytes_recvd"].as_u64().unwrap_or(0);
+        peer.tx_bytes += entry["bytes_sent"].as_u64().unwrap_or(0);
+    }
+    Some(peers.into_values().collect())
+}
+
+/// Result of applying a server message
+#[derive(Default)]
+struct ApplyOutcome {
+    /// Resolved listen endpoints, when the server sent listen templates
+    /// containing placeholders
+    resolved_listen: Option<Vec<String>>,
+    /// Listen endpoints now in the Yggdrasil config, when it was written
+    applied_listen: Option<Vec<String>>,
+    /// Extra config keys now in the Yggdrasil config, when it was written
+    extra_config_keys: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+/// Apply a server message
+async fn handle_server_message(
+    msg: ServerMessage,
+    ygg_config_path: &str,
+    previous_extra_keys: &[String],
+    args: &Args,
+) -> Result<ApplyOutcome> {
+    let mut outcome = ApplyOutcome::default();
+    
+    match msg {
+        ServerMessage::Config {
+            revision,
+            node_id,
+            private_key,
+            listen,
+            peers,
+            allowed_public_keys,
+            interface_peers,
+            if_name,
+            extra_config,
+            sealed_private_key: _,
+            agent_token: _,
+            sealed_agent_token: _,
+            timing: _,
+            epoch: _,
+            nonce: _,
+        } => {
+            info!("Received initial configuration (revision {}):", revision);
+            info!("  Node ID: {}", node_id);
+            info!("  Listen endpoints: {:?}", listen);
+            info!("  Peers: {} configured", peers.len());
+            for peer in &peers {
+                debug!("    - {}", peer);
+            }
+            info!("  Allowed keys: {} configured", allowed_public_keys.len());
+            
+            let listen = resolve_listen_templates(&listen);
+            if has_listen_templates(&listen.raw) {
+                outcome.resolved_listen = Some(listen.resolved.clone());
+            }
+            let listen = listen.resolved;
+            
+            // Apply configuration to Yggdrasil
+            let extras = ConfigExtras { interface_peers, if_name, extra_config };
+            let config = full_yggdrasil_config(private_key.expose(), &listen, &peers, &allowed_public_keys, &extras);
+            match write_yggdrasil_config(ygg_config_path, &config).await {
+                Ok(_) => {
+                    info!("Configuration successfully written to {}", ygg_config_path);
+                    outcome.applied_listen = Some(listen);
+                    outcome.extra_config_keys = Some(extras.extra_config.keys().cloned().collect());
+                    // Restart Yggdrasil service to apply new configuration
+                    if !args.no_restart {
+                        if let Err(e) = restart_yggdrasil(args) {
+                            error!("Failed to restart Yggdrasil service: {}", e);
+                            outcome.error = Some(format!("Failed to restart Yggdrasil service: {}", e));
+                        }
+                    } else {
+                        info!("Skipping service restart (--no-restart flag set)");
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to write Yggdrasil config: {}", e);
+                    outcome.error = Some(format!("Failed to write Yggdrasil config: {}", e));
+                }
+            }
+        }
+        ServerMessage::Update {
+            revision,
+            epoch: _,
+            node_id: _,
+            listen,
+            peers,
+            allowed_public_keys,
+            interface_peers,
+            if_name,
+            extra_config,
+        } => {
+            info!("Received configuration update (revision {}):", revision);
+            info!("  Updated listen endpoints: {:?}", listen);
+            info!("  Updated peers: {} configured", peers.len());
+            for peer in &peers {
+                debug!("    - {}", peer);
+            }
+            info!("  Updated allowed keys: {} configured", allowed_public_keys.len());
+            
+            let listen = resolve_listen_templates(&listen);
+            if has_listen_templates(&listen.raw) {
+                outcome.resolved_listen = Some(listen.resolved.clone());
+            }
+            let listen = listen.resolved;
+            
+            // Apply full configuration update to Yggdrasil 
+            let extras = ConfigExtras { interface_peers, if_name, extra_config };
+            let result = update_yggdrasil_config_full(ygg_config_path, &listen, &peers, &allowed_public_keys, &extras, previous_extra_keys).await;
+            if result.is_ok() {
+                outcome.extra_config_keys = Some(extras.extra_config.keys().cloned().collect());
+            }
+            match result {
+                Ok(true) => {
+                    info!("Configuration update successfully applied to {}", ygg_config_path);
+                    outcome.applied_listen = Some(listen);
+                    // Restart Yggdrasil service to apply updated configuration
+                    if !args.no_restart {
+                        if let Err(e) = restart_yggdrasil(args) {
+                            error!("Failed to restart Yggdrasil service: {}", e);
+                            outcome.error = Some(format!("Failed to restart Yggdrasil service: {}", e));
+                        }
+                    } else {
+                        info!("Skipping service restart (--no-restart flag set)");
+                    }
+                },
+                Ok(false) => {
+                    info!("Configuration unchanged, skipping restart");
+                    outcome.applied_listen = Some(listen);
+                },
+                Err(e) => {
+                    error!("Failed to update Yggdrasil config: {}", e);
+                    outcome.error = Some(format!("Failed to update Yggdrasil config: {}", e));
+                }
+            }
+        }
+        ServerMessage::Timing { .. } => {
+            // Applied by the connection loop, which owns the timers
+        }
+        ServerMessage::UpdateAvailable { .. } => {
+            // Handled by the connection loop, which knows about --auto-update
+        }
+        ServerMessage::Error { message, code, .. } => {
+            match code {
+                Some(code) => error!("Server error ({:?}): {}", code, message),
+                None => error!("Server error: {}", message),
+            }
+        }
+    }
+    
+    Ok(outcome)
+}
+
+/// `port/transport` a listen endpoint needs opened, `None` for unix
+/// sockets and unparseable endpoints
+fn listen_port(endpoint: &str) -> Option<String> {
+    let (scheme, rest) = endpoint.split_once("://")?;
+    let transport = match scheme {
+        "tcp" | "tls" | "ws" | "wss" => "tcp",
+        "quic" => "udp",
+        _ => return None,
+    };
+    let authority = rest.split(['?', '/']).next()?;
+    let (_, port) = authority.rsplit_once(':')?;
+    let port: u16 = port.parse().ok()?;
+    Some(format!("{}/{}", port, transport))
+}
+
+/// Open the ports of `listen` and close the ones opened earlier that are no
+/// longer listened on. Records what ends up open in the agent state.
+async fn sync_firewall(backend: FirewallBackend, listen: &[String], state: &mut AgentState) -> Result<()> {
+    let mut wanted: Vec<String> = listen.iter().filter_map(|l| listen_port(l)).collect();
+    wanted.sort();
+    wanted.dedup();
+    
+    firewall_command(backend, FirewallAction::Setup, "").await?;
+    
+    let mut errors = Vec::new();
+    let previously_opened = std::mem::take(&mut state.opened_ports);
+    for port in previously_opened.iter().filter(|p| !wanted.contains(p)) {
+        match firewall_command(backend, FirewallAction::Close, port).await {
+            Ok(()) => info!("Closed firewall port {}", port),
+            Err(e) => {
+                errors.push(format!("close {}: {}", port, e));
+                state.opened_ports.push(port.clone());
+            }
+        }
+    }
+    for port in &wanted {
+        if previously_opened.contains(port) {
+            state.opened_ports.push(port.clone());
+            continue;
+        }
+        match firewall_command(backend, FirewallAction::Open, port).await {
+            Ok(()) => {
+                info!("Opened firewall port {}", port);
+                state.opened_ports.push(port.clone());
+            }
+            Err(e) => errors.push(format!("open {}: {}", port, e)),
+        }
+    }
+    state.opened_ports.sort();
+    
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Firewall update failed: {}", errors.join("; ")))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FirewallAction {
+    Setup,
+    Open,
+    Close,
+}
+
+/// nftables ports go into sets matched by rules created on first use
+const NFT_SETS: [(&str, &str); 2] = [("yggman_tcp", "tcp"), ("yggman_udp", "udp")];
+
+async fn firewall_command(backend: FirewallBackend, action: FirewallAction, port: &str) -> Result<()> {
+    let (number, transport) = port.split_once('/').unwrap_or((port, "tcp"));
+    let commands: Vec<Vec<String>> = match (backend, action) {
+        (FirewallBackend::Nftables, FirewallAction::Setup) => {
+            let mut commands = Vec::new();
+            for (set, transport) in NFT_SETS {
+                if run_command(&["nft", "list", "set", "inet", "filter", set]).await.is_err() {
+                    commands.push(vec!["nft".into(), "add".into(), "set".into(), "inet".into(), "filter".into(), set.into(), "{ type inet_service; }".into()]);
+                    commands.push(vec![
+                        "nft".into(), "insert".into(), "rule".into(), "inet".into(), "filter".into(), "input".into(),
+                        transport.into(), "dport".into(), format!("@{}", set), "accept".into(),
+                        "comment".into(), "\"yggman-agent\"".into(),
+                    ]);
+                }
+            }
+            commands
+        }
+        (FirewallBackend::Nftables, action) => {
+            let verb = if matches!(action, FirewallAction::Open) { "add" } else { "delete" };
+            let set = if transport == "udp" { "yggman_udp" } else { "yggman_tcp" };
+            vec![vec!["nft".into(), verb.into(), "element".into(), "inet".into(), "filter".into(), set.into(), format!("{{ {} }}", number)]]
+        }
+        (FirewallBackend::Ufw, FirewallAction::Open) => {
+            vec![vec!["ufw".into(), "allow".into(), port.into(), "comment".into(), "yggman-agent".into()]]
+        }
+        (FirewallBackend::Ufw, FirewallAction::Close) => {
+            vec![vec!["ufw".into(), "delete".into(), "allow".into(), port.into()]]
+        }
+        (FirewallBackend::Firewalld, FirewallAction::Open) => vec![
+            vec!["firewall-cmd".into(), format!("--add-port={}", port)],
+            vec!["firewall-cmd".into(), "--permanent".into(), format!("--add-port={}", port)],
+        ],
+        (FirewallBackend::Firewalld, FirewallAction::Close) => vec![
+            vec!["firewall-cmd".into(), format!("--remove-port={}", port)],
+            vec!["firewall-cmd".into(), "--permanent".into(), format!("--remove-port={}", port)],
+        ],
+        (_, FirewallAction::Setup) => Vec::new(),
+    };
+    
+    for command in commands {
+        let args: Vec<&str> = command.iter().map(String::as_str).collect();
+        run_command(&args).await?;
+    }
+    Ok(())
+}
+
+async fn run_command(args: &[&str]) -> Result<()> {
+    let output = tokio::process::Command::new(args[0])
+        .args(&args[1..])
+        .output()
+        .await
+        .map_err(|e| anyhow!("{}: {}", args[0], e))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+struct ResolvedListen {
+    raw: Vec<String>,
+    resolved: Vec<String>,
+}
+
+fn has_listen_templates(listen: &[String]) -> bool {
+    listen.iter().any(|l| l.contains('{'))
+}
+
+/// Substitute `{public_ip}`, `{interface:NAME}` and `{interface6:NAME}`
+/// placeholders in listen endpoints with local addresses. Endpoints whose
+/// placeholders cannot be resolved are dropped.
+fn resolve_listen_templates(listen: &[String]) -> ResolvedListen {
+    let mut resolved = Vec::new();
+    
+    for endpoint in listen {
+        match resolve_listen_template(endpoint) {
+            Ok(value) => resolved.push(value),
+            Err(e) => warn!("Skipping listen endpoint {}: {}", endpoint, e),
+        }
+    }
+    
+    ResolvedListen {
+        raw: listen.to_vec(),
+        resolved,
+    }
+}
+
+fn resolve_listen_template(endpoint: &str) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = endpoint;
+    
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| anyhow!("unterminated placeholder"))? + start;
+        result.push_str(&rest[..start]);
+        
+        let placeholder = &rest[start + 1..end];
+        let ip = match placeholder.split_once(':') {
+            None if placeholder == "public_ip" => discover_addresses()?
+                .into_iter()
+                .find(|a| is_public_address(a))
+                .or_else(|| ECHOED_ADDRESS.lock().unwrap().clone())
+                .ok_or_else(|| anyhow!("no public address found"))?,
+            Some(("interface", name)) => interface_address(name, false)?,
+            Some(("interface6", name)) => interface_address(name, true)?,
+            _ => return Err(anyhow!("unknown placeholder {{{}}}", placeholder)),
+        };
+        
+        // IPv6 literals are bracketed in URIs
+        if ip.contains(':') {
+            result.push_str(&format!("[{}]", ip));
+        } else {
+            result.push_str(&ip);
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    
+    Ok(result)
+}
+
+/// First IPv4 (or global IPv6 when `ipv6` is set) address of an interface
+fn interface_address(name: &str, ipv6: bool) -> Result<String> {
+    let interface = NetworkInterface::show()?
+        .into_iter()
+        .find(|i| i.name == name)
+        .ok_or_else(|| anyhow!("interface {} not found", name))?;
+    
+    interface.addr.iter()
+        .find_map(|addr| match addr {
+            network_interface::Addr::V4(v4) if !ipv6 => Some(v4.ip.to_string()),
+            network_interface::Addr::V6(v6) if ipv6 && !v6.ip.to_string().starts_with("fe80:") => Some(v6.ip.to_string()),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("interface {} has no {} address", name, if ipv6 { "IPv6" } else { "IPv4" }))
+}
+
+fn is_public_address(address: &str) -> bool {
+    match address.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(ip)) => {
+            let octets = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64))
+        }
+        Ok(std::net::IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first & 0xfe00) == 0x0200)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Interface addresses, plus the public address the server sees when
+/// `whoami_url` is given and it is not among them, as behind NAT
+async fn scan_addresses(whoami_url: Option<&str>) -> Result<Vec<String>> {
+    let mut addresses = discover_addresses()?;
+    let Some(url) = whoami_url else {
+        return Ok(addresses);
+    };
+
+    match echoed_address(url).await {
+        Ok(address) if is_public_address(&address) => {
+            if !addresses.contains(&address) {
+                addresses.push(address.clone());
+            }
+            *ECHOED_ADDRESS.lock().unwrap() = Some(address);
+        }
+        Ok(address) => debug!("Server sees this agent at non-public address {}, ignoring it", address),
+        // Keep the last known address through a failed lookup
+        Err(e) => {
+            warn!("Failed to ask the server for this agent's public address: {}", e);
+            if let Some(address) = ECHOED_ADDRESS.lock().unwrap().clone() {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+    }
+    Ok(addresses)
+}
+
+/// The server's /api/whoami next to the agent WebSocket at `server`
+fn whoami_url(server: &str) -> String {
+    api_url(server, "/api/whoami")
+}
+
+/// HTTP URL of `path` on the server whose agent WebSocket is at `server`
+fn api_url(server: &str, path: &str) -> String {
+    let url = server
+        .replacen("wss://", "https://", 1)
+        .replacen("ws://", "http://", 1);
+    let base = match url.strip_suffix("/ws/agent") {
+        Some(base) => base,
+        None => url.trim_end_matches('/'),
+    };
+    format!("{}{}", base, path)
+}
+
+async fn echoed_address(url: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Whoami {
+        ip: String,
+    }
+
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let whoami: Whoami = client.get(url).send().await?.error_for_status()?.json().await?;
+    Ok(whoami.ip)
+}
+
+fn discover_addresses() -> Result<Vec<String>> {
+    let interfaces = NetworkInterface::show()?;
+    let mut addresses = Vec::new();
+
+    for interface in interfaces {
+        // Skip loopback and down interfaces
+        if interface.name.starts_with("lo") {
+            continue;
+        }
+
+        for addr in interface.addr {
+            match addr {
+                network_interface::Addr::V4(v4) => {
+                    let ip = v4.ip.to_string();
+                    // Skip link-local and private addresses for now
+                    // In production, you might want to be more selective
+                    if !ip.starts_with("127.") && !ip.starts_with("169.254.") {
+                        addresses.push(ip);
+                    }
+                }
+                network_interface::Addr::V6(v6) => {
+                    let ip = v6.ip.to_string();
+                    // Skip link-local IPv6
+                    if !ip.starts_with("fe80:") && !ip.starts_with("::1") {
+                        addresses.push(ip);
+                    }
+                }
+            }
+        }
+    }
+
+    // If no addresses found, return empty vec (will use localhost)
+    Ok(addresses)
+}
+
+/// Whether this is an OpenWrt system
+fn is_openwrt() -> bool {
+    Path::new(OPENWRT_RELEASE).exists()
+}
+
+/// --yggdrasil-config, or the config found in /etc
+fn yggdrasil_config_path(args: &Args) -> Option<String> {
+    args.yggdrasil_config.first().cloned().or_else(find_yggdrasil_config)
+}
+
+/// `AdminListen` of the Yggdrasil config at `path`, for yggdrasilctl to
+/// reach that daemon rather than whichever listens on the default socket
+async fn admin_endpoint(path: &str) -> Option<String> {
+    let config = read_yggdrasil_config(path).await?;
+    config["AdminListen"].as_str()
+        .filter(|endpoint| !endpoint.is_empty() && *endpoint != "none")
+        .map(str::to_string)
+}
+
+/// yggdrasilctl, pointed at `endpoint` when given
+fn yggdrasilctl(endpoint: Option<&str>) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("yggdrasilctl");
+    if let Some(endpoint) = endpoint {
+        command.arg(format!("-endpoint={}", endpoint));
+    }
+    command
+}
+
+fn find_yggdrasil_config() -> Option<String> {
+    let possible_paths = vec![
+        "/etc/yggdrasil.conf",
+        "/etc/yggdrasil/yggdrasil.conf",
+    ];
+    
+    for path in possible_paths {
+        if Path::new(path).exists() {
+            return Some(path.to_string());
+        }
+    }
+    
+    None
+}
+
+/// Config sections beyond listen, peers and keys sent by the server
+struct ConfigExtras {
+    interface_peers: HashMap<String, Vec<String>>,
+    /// `None` keeps the name already configured
+    if_name: Option<String>,
+    /// Raw options merged into the config as-is
+    extra_config: HashMap<String, serde_json::Value>,
+}
+
+impl ConfigExtras {
+    /// Write the extras into a config, dropping extra keys applied before
+    /// that the server no longer sends
+    fn apply(&self, config: &mut serde_json::Value, previous_extra_keys: &[String]) {
+        config["InterfacePeers"] = serde_json::json!(self.interface_peers);
+        if let Some(if_name) = &self.if_name {
+            config["IfName"] = serde_json::json!(if_name);
+        }
+        if let Some(config) = config.as_object_mut() {
+            for key in previous_extra_keys.iter().filter(|k| !self.extra_config.contains_key(*k)) {
+                config.remove(key);
+            }
+            config.extend(self.extra_config.clone());
+        }
+    }
+}
+
+/// The config written on an initial `Config` message, replacing the file
+fn full_yggdrasil_config(
+    private_key: &str,
+    listen: &[String],
+    peers: &[String],
+    allowed_public_keys: &[String],
+    extras: &ConfigExtras,
+) -> serde_json::Value {
+    let mut config = serde_json::json!({
+        "PrivateKey": private_key,
+        "Listen": listen,
+        "Peers": peers,
+        "AllowedPublicKeys": allowed_public_keys,
+        "NodeInfo": {},
+        "NodeInfoPrivacy": false
+    });
+    extras.apply(&mut config, &[]);
+    config
+}
+
+async fn write_yggdrasil_config(config_path: &str, config: &serde_json::Value) -> Result<()> {
+    let config_json = serde_json::to_string_pretty(config)?;
+    
+    privileges::apply_privileged_write(config_path, config_json.as_bytes()).await?;
+    info!("Yggdrasil configuration written to {}", config_path);
+    Ok(())
+}
+
+async fn update_yggdrasil_config_full(
+    config_path: &str,
+    listen: &[String],
+    peers: &[String],
+    allowed_public_keys: &[String],
+    extras: &ConfigExtras,
+    previous_extra_keys: &[String],
+) -> Result<bool> {  // Returns true if config was updated
+    // Read current config
+    let current_config = tokio::fs::read_to_string(config_path).await?;
+    let mut config: serde_json::Value = serde_json::from_str(&current_config)?;
+    let original = config.clone();
+    
+    // Update listen, peers, allowed public keys and the extras
+    config["Listen"] = serde_json::json!(listen);
+    config["Peers"] = serde_json::json!(peers);
+    config["AllowedPublicKeys"] = serde_json::json!(allowed_public_keys);
+    extras.apply(&mut config, previous_extra_keys);
+    
+    // Check if config actually changed
+    if config == original {
+        debug!("Configuration unchanged, skipping update");
+        return Ok(false);
+    }
+    
+    // Write updated config back
+    let updated_config = serde_json::to_string_pretty(&config)?;
+    
+    privileges::apply_privileged_write(config_path, updated_config.as_bytes()).await?;
+    info!("Yggdrasil configuration fully updated in {}", config_path);
+    Ok(true)
+}
+
+/// Restart Yggdrasil with --restart-command, through the container runtime,
+/// by signalling its sidecar or as a system service
+fn restart_yggdrasil(args: &Args) -> Result<()> {
+    if args.restart_command.is_some() {
+        return restart_yggdrasil_service(&args.restart_command);
+    }
+    let runtime = match args.yggdrasil_mode {
+        YggdrasilMode::Service => match &args.instance {
+            Some(instance) => return restart_yggdrasil_instance(instance),
+            None => return restart_yggdrasil_service(&None),
+        },
+        YggdrasilMode::Sidecar => return signal_sidecar(args.pid_file.as_deref().unwrap_or_default()),
+        YggdrasilMode::Docker => "Docker",
+        YggdrasilMode::Podman => "Podman",
+    };
+    let socket = args.container_socket.clone().unwrap_or_else(|| default_container_socket(args.yggdrasil_mode));
+    info!("Restarting Yggdrasil container {} through {}...", args.container, socket);
+    restart_container(&socket, &args.container)
+        .map_err(|e| anyhow!("{} container {}: {}", runtime, args.container, e))?;
+    info!("Yggdrasil container restarted successfully");
+    Ok(())
+}
+
+/// The API socket Docker or Podman listens on by default; rootless Podman
+/// keeps it in the user's runtime directory
+fn default_container_socket(mode: YggdrasilMode) -> String {
+    if mode == YggdrasilMode::Podman {
+        let rootless = std::env::var("XDG_RUNTIME_DIR").ok()
+            .map(|dir| format!("{}/podman/podman.sock", dir))
+            .filter(|socket| Path::new(socket).exists());
+        return rootless.unwrap_or_else(|| "/run/podman/podman.sock".to_string());
+    }
+    "/var/run/docker.sock".to_string()
+}
+
+/// Restart a container through the Docker Engine API, which Podman serves
+/// as well
+#[cfg(unix)]
+fn restart_container(socket: &str, container: &str) -> Result<()> {
+    use std::io::{Read, Write};
+    
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)
+        .map_err(|e| anyhow!("cannot connect to {}: {}", socket, e))?;
+    // The runtime answers once the container stopped and started again
+    stream.set_read_timeout(Some(Duration::from_secs(60)))?;
+    write!(
+        stream,
+        "POST /containers/{}/restart HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        container
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("204") => Ok(()),
+        Some("404") => Err(anyhow!("no such container")),
+        _ => Err(anyhow!("unexpected answer \"{}\"", status_line)),
+    }
+}
+
+/// Send SIGHUP to the Yggdrasil of a sidecar container. Yggdrasil exits on
+/// it, and the pod's restart policy starts it again on the new config.
+fn signal_sidecar(pid_file: &str) -> Result<()> {
+    let content = std::fs::read_to_string(pid_file)
+        .map_err(|e| anyhow!("Cannot read Yggdrasil PID file {}: {}", pid_file, e))?;
+    let pid: u32 = content.trim().parse()
+        .ok()
+        .filter(|pid| *pid > 1)
+        .ok_or_else(|| anyhow!("{} holds no valid PID", pid_file))?;
+    info!("Sending SIGHUP to Yggdrasil (PID {})...", pid);
+    let output = Command::new("kill").args(["-HUP", &pid.to_string()]).output()
+        .map_err(|e| anyhow!("Failed to run kill: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to signal Yggdrasil PID {}: {} (does the pod share its process namespace?)",
+            pid,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!("Yggdrasil signalled successfully");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restart_container(_socket: &str, _container: &str) -> Result<()> {
+    Err(anyhow!("container runtimes are only supported on unix"))
+}
+
+/// Restart one of several Yggdrasil instances, as the systemd template unit
+/// yggdrasil@NAME; other service managers have no common convention
+fn restart_yggdrasil_instance(instance: &str) -> Result<()> {
+    if !cfg!(target_os = "linux") || is_openwrt() {
+        return Err(anyhow!("Restarting Yggdrasil instance {} needs --restart-command on this platform", instance));
+    }
+    let unit = format!("yggdrasil@{}", instance);
+    info!("Restarting Yggdrasil service {}...", unit);
+    privileges::restart(&["systemctl", "restart", &unit]).map_err(|e| anyhow!("Failed to restart Yggdrasil service: {}", e))?;
+    info!("Yggdrasil service restarted successfully");
+    Ok(())
+}
+
+fn restart_yggdrasil_service(custom_command: &Option<String>) -> Result<()> {
+    // If custom command is provided, use it
+    if let Some(cmd) = custom_command {
+        info!("Using custom restart command: {}", cmd);
+        let parts: Vec<&str> = cmd.split_whitespace().collect();
+        if parts.is_empty() {
+            return Err(anyhow!("Invalid custom restart command"));
+        }
+        
+        let output = Command::new(parts[0])
+            .args(&parts[1..])
+            .output()?;
+        
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            error!("Custom restart command failed. Stderr: {}", stderr);
+            error!("Stdout: {}", stdout);
+            return Err(anyhow!("Failed to restart Yggdrasil with custom command: {}", stderr));
+        }
+        
+        info!("Yggdrasil service restarted successfully with custom command");
+        return Ok(());
+    }
+    
+    // Detect platform and restart accordingly
+    #[cfg(target_os = "linux")]
+    let command: Option<&[&str]> = if is_openwrt() {
+        // procd has no systemctl; the init script restarts the instance
+        Some(&["/etc/init.d/yggdrasil", "restart"])
+    } else {
+        Some(&["systemctl", "restart", "yggdrasil"])
+    };
+    #[cfg(target_os = "freebsd")]
+    let command: Option<&[&str]> = Some(&["service", "yggdrasil", "restart"]);
+    #[cfg(target_os = "openbsd")]
+    let command: Option<&[&str]> = Some(&["rcctl", "restart", "yggdrasil"]);
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+    let command: Option<&[&str]> = None;
+
+    #[cfg(target_os = "macos")]
+    {
+        info!("Restarting Yggdrasil service on macOS...");
+        
+        // First unload the service
+        if let Err(e) = privileges::restart(&["launchctl", "unload", "/Library/LaunchDaemons/yggdrasil.plist"]) {
+            warn!("Failed to unload Yggdrasil service: {} (continuing anyway)", e);
+        }
+        // Then load it again
+        privileges::restart(&["launchctl", "load", "/Library/LaunchDaemons/yggdrasil.plist"])
+            .map_err(|e| anyhow!("Failed to load Yggdrasil service: {}", e))?;
+        info!("Yggdrasil service restarted successfully");
+    }
+
+    match command {
+        Some(command) => {
+            info!("Restarting Yggdrasil service with {}...", command.join(" "));
+            privileges::restart(command).map_err(|e| anyhow!("Failed to restart Yggdrasil service: {}", e))?;
+            info!("Yggdrasil service restarted successfully");
+        }
+        None if cfg!(target_os = "macos") => {}
+        None => warn!("Platform not supported for automatic service restart. Please restart Yggdrasil manually."),
+    }
+    
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(last_revision: u64, epoch: &str) -> AgentState {
+        AgentState { last_revision, epoch: epoch.to_string(), ..Default::default() }
+    }
+
+    fn update(revision: u64, node_id: &str) -> serde_json::Value {
+        serde_json::to_value(ServerMessage::Update {
+            revision,
+            epoch: "run".to_string(),
+            node_id: node_id.to_string(),
+            listen: Vec::new(),
+            peers: Vec::new(),
+            allowed_public_keys: Vec::new(),
+            interface_peers: HashMap::new(),
+            if_name: None,
+            extra_config: HashMap::new(),
+        }).unwrap()
+    }
+
+    #[test]
+    fn rejects_older_revisions_of_the_same_run() {
+        let state = state(5, "run");
+        assert!(state.stale_reason(4, "run", false).is_some());
+        assert_eq!(state.stale_reason(5, "run", false), None);
+        assert_eq!(state.stale_reason(6, "run", false), None);
+    }
+
+    #[test]
+    fn another_run_needs_an_answer_to_the_nonce() {
+        let state = state(50, "before");
+        assert!(state.stale_reason(51, "after", false).is_some());
+        assert_eq!(state.stale_reason(1, "after", true), None);
+    }
+
+    #[test]
+    fn state_without_epoch_compares_revisions() {
+        // Written by agents older than server epochs, or from older servers
+        let state = state(5, "");
+        assert!(state.stale_reason(4, "run", false).is_some());
+        assert_eq!(state.stale_reason(6, "run", false), None);
+        assert!(self::state(5, "run").stale_reason(4, "", false).is_some());
+    }
+
+    #[test]
+    fn rejects_replayed_signatures() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let server_key = hex::encode(key.verifying_key().to_bytes());
+        let mut verifier = MessageVerifier::new(Some(&server_key), Some("node-a")).unwrap();
+
+        let older = signing::sign_message(&key, update(1, "node-a"));
+        std::thread::sleep(Duration::from_millis(2));
+        let newer = signing::sign_message(&key, update(2, "node-a"));
+        assert!(verifier.verify(newer.clone()).is_ok());
+        assert!(verifier.verify(older).is_err());
+        assert!(verifier.verify(newer).is_ok());
+
+        let elsewhere = signing::sign_message(&key, update(3, "node-b"));
+        assert!(verifier.verify(elsewhere).is_err());
+        let unsigned = update(4, "node-a");
+        assert!(verifier.verify(unsigned).is_err());
+    }
+
+    #[test]
+    fn only_the_config_echoing_the_nonce_answers() {
+        let config = |nonce: &str| -> ServerMessage {
+            serde_json::from_value(serde_json::json!({
+                "type": "Config", "revision": 1, "epoch": "run", "nonce": nonce, "node_id": "node-a",
+                "listen": [], "peers": [], "allowed_public_keys": []
+            })).unwrap()
+        };
+        let mut verifier = MessageVerifier::new(None, None).unwrap();
+        let nonce = verifier.renew_nonce();
+        assert!(verifier.answers(&config(&nonce)));
+        assert!(!verifier.answers(&config("replayed")));
+        assert!(!verifier.answers(&serde_json::from_value(update(1, "node-a")).unwrap()));
+
+        // A new registration asks anew, so the earlier answer is stale
+        verifier.registering();
+        assert!(!verifier.answers(&config(&nonce)));
+    }
+}
@@ -0,0 +1,353 @@
+//! Writing the Yggdrasil config and restarting services from an agent that
+//! may not run as root. How to escalate is worked out once, on first use:
+//! nothing as root, otherwise sudo, doas or pkexec, whichever is installed.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+static ESCALATION: OnceLock<Escalation> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escalation {
+    /// The agent runs as root
+    Root,
+    Sudo,
+    /// The sudo replacement of OpenBSD and some Linux distributions
+    Doas,
+    /// polkit, for systems where neither sudo nor doas is installed
+    Pkexec,
+    /// No way to escalate; privileged steps only work where the agent's
+    /// user is allowed to do them
+    None,
+}
+
+impl Escalation {
+    fn detect() -> Self {
+        if is_root() {
+            return Escalation::Root;
+        }
+        // OpenBSD ships doas and no sudo, elsewhere sudo is the default
+        let preference = if cfg!(target_os = "openbsd") {
+            [Escalation::Doas, Escalation::Sudo, Escalation::Pkexec]
+        } else {
+            [Escalation::Sudo, Escalation::Doas, Escalation::Pkexec]
+        };
+        preference.into_iter()
+            .find(|escalation| escalation.program().is_some_and(in_path))
+            .unwrap_or(Escalation::None)
+    }
+
+    fn program(self) -> Option<&'static str> {
+        match self {
+            Escalation::Sudo => Some("sudo"),
+            Escalation::Doas => Some("doas"),
+            Escalation::Pkexec => Some("pkexec"),
+            Escalation::Root | Escalation::None => None,
+        }
+    }
+
+    /// `command` run through this escalation, never prompting for a password
+    fn wrap(self, command: &[&str]) -> Vec<String> {
+        let prefix: &[&str] = match self {
+            Escalation::Sudo => &["sudo", "-n"],
+            Escalation::Doas => &["doas", "-n"],
+            // Fails without a prompt when no authentication agent runs
+            Escalation::Pkexec => &["pkexec", "--disable-internal-agent"],
+            Escalation::Root | Escalation::None => &[],
+        };
+        prefix.iter().chain(command).map(|arg| arg.to_string()).collect()
+    }
+
+    /// What to configure so that `commands` run without a password
+    fn guidance(self, commands: &[&[&str]]) -> String {
+        let user = std::env::var("USER").unwrap_or_else(|_| "<agent user>".to_string());
+        let rules = |format_rule: &dyn Fn(String, String) -> String| {
+            commands.iter()
+                .map(|command| format_rule(absolute_path(command[0]), command[1..].join(" ")))
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Escalation::Sudo => format!(
+                "add '{} ALL=(ALL) NOPASSWD: {}' to sudoers",
+                user,
+                rules(&|program, args| format!("{} {}", program, args)).join(", ")
+            ),
+            Escalation::Doas => format!(
+                "add {} to /etc/doas.conf",
+                rules(&|program, args| format!("'permit nopass {} cmd {} args {}'", user, program, args)).join(" and ")
+            ),
+            Escalation::Pkexec => format!(
+                "allow org.freedesktop.policykit.exec for {} running {} in a polkit rule",
+                user,
+                rules(&|program, _| program).join(", ")
+            ),
+            Escalation::Root | Escalation::None => {
+                "run the agent as root, or install sudo or doas and allow the agent's user to run it".to_string()
+            }
+        }
+    }
+}
+
+/// How this agent escalates, detected on the first call
+pub fn escalation() -> Escalation {
+    *ESCALATION.get_or_init(|| {
+        let escalation = Escalation::detect();
+        match escalation {
+            Escalation::Root => info!("Running as root"),
+            Escalation::None => warn!("Not running as root and found no sudo, doas or pkexec; writing the Yggdrasil config or restarting it may fail"),
+            _ => info!("Not running as root, escalating privileges with {} when needed", escalation.program().unwrap_or_default()),
+        }
+        escalation
+    })
+}
+
+/// Replace `path` with `contents` atomically: written next to it, synced
+/// and renamed over it, keeping its mode, owner and SELinux label. Through
+/// `cp`, `tee` and `mv` with escalated privileges when the agent may not do
+/// it itself.
+pub async fn apply_privileged_write(path: &str, contents: &[u8]) -> Result<()> {
+    // Replace the file a symlink points to rather than the symlink
+    let path = std::fs::canonicalize(path).map(|path| path.display().to_string()).unwrap_or_else(|_| path.to_string());
+    let selinux = selinux_mode();
+    let label = selinux.and_then(|_| selinux_label(&path));
+
+    replace_file(&path, contents).await.map_err(|e| match apparmor_profile() {
+        Some(profile) => anyhow!(
+            "{}; the agent is confined by AppArmor profile {}, which must allow writing {} and {}.yggman-new",
+            e, profile, path, path
+        ),
+        None => e,
+    })?;
+
+    if let Some(mode) = selinux {
+        relabel(&path, label.as_deref(), mode)?;
+    }
+    Ok(())
+}
+
+async fn replace_file(path: &str, contents: &[u8]) -> Result<()> {
+    let path = path.to_string();
+    let temp = format!("{}.yggman-new", path);
+
+    let direct = {
+        let (path, temp, contents) = (path.clone(), temp.clone(), contents.to_vec());
+        tokio::task::spawn_blocking(move || write_atomically(Path::new(&path), Path::new(&temp), &contents)).await?
+    };
+    match direct {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && escalation() != Escalation::Root => {
+            debug!("Permission denied writing to {}, escalating", path);
+        }
+        Err(e) => return Err(anyhow!("Failed to write {}: {}", path, e)),
+    }
+
+    let escalation = escalation();
+    let copy = ["cp", "-p", path.as_str(), temp.as_str()];
+    let write = ["tee", temp.as_str()];
+    let replace = ["mv", "-f", temp.as_str(), path.as_str()];
+    let guidance = escalation.guidance(&[&copy, &write, &replace]);
+    if escalation == Escalation::None {
+        return Err(anyhow!("Permission denied writing to {}; {}", path, guidance));
+    }
+
+    let escalated = async {
+        // Writing into a copy keeps the original's mode and owner, which a
+        // file created by tee would not have
+        if Path::new(&path).exists() {
+            run_with_input(&escalation.wrap(&copy), Vec::new()).await?;
+        }
+        run_with_input(&escalation.wrap(&write), contents.to_vec()).await?;
+        // Needs no privileges, and takes a file argument on GNU only
+        run_with_input(&["sync".to_string()], Vec::new()).await?;
+        run_with_input(&escalation.wrap(&replace), Vec::new()).await
+    };
+    escalated.await.map_err(|e| anyhow!("Failed to write {} with {}: {}; {}", path, escalation.program().unwrap_or_default(), e, guidance))
+}
+
+/// Write `temp` in full, sync it and rename it over `path`
+fn write_atomically(path: &Path, temp: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let original = std::fs::metadata(path).ok();
+    let result = (|| {
+        let mut file = std::fs::File::create(temp)?;
+        file.write_all(contents)?;
+        if let Some(original) = &original {
+            file.set_permissions(original.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let created = file.metadata()?;
+                if (created.uid(), created.gid()) != (original.uid(), original.gid()) {
+                    // Only root may give files away; others escalate
+                    std::os::unix::fs::fchown(&file, Some(original.uid()), Some(original.gid()))?;
+                }
+            }
+        }
+        file.sync_all()?;
+        std::fs::rename(temp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp);
+    }
+    result?;
+
+    // The rename itself only survives a crash once the directory is synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SelinuxMode {
+    Enforcing,
+    Permissive,
+}
+
+/// The SELinux mode, `None` where SELinux is disabled or absent
+fn selinux_mode() -> Option<SelinuxMode> {
+    match std::fs::read_to_string("/sys/fs/selinux/enforce").ok()?.trim() {
+        "1" => Some(SelinuxMode::Enforcing),
+        _ => Some(SelinuxMode::Permissive),
+    }
+}
+
+/// The SELinux context of `path`, such as `system_u:object_r:etc_t:s0`
+fn selinux_label(path: &str) -> Option<String> {
+    let output = Command::new("stat").args(["-c", "%C", path]).output().ok()?;
+    let label = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && label.contains(':')).then_some(label)
+}
+
+/// Give the replaced `path` the label the original had, or the policy's
+/// default without one; the temporary file got its directory's instead,
+/// which Yggdrasil may not be allowed to read
+fn relabel(path: &str, label: Option<&str>, mode: SelinuxMode) -> Result<()> {
+    let result = match label {
+        Some(label) => run_privileged(&["chcon", label, path]),
+        None => run_privileged(&["restorecon", path]),
+    };
+    match (result, mode) {
+        (Ok(()), _) => Ok(()),
+        (Err(e), SelinuxMode::Enforcing) => Err(anyhow!(
+            "Failed to restore the SELinux label of {}, Yggdrasil may be denied reading it: {}; run 'restorecon -v {}' as root",
+            path, e, path
+        )),
+        (Err(e), SelinuxMode::Permissive) => {
+            warn!("Failed to restore the SELinux label of {}, harmless while SELinux is permissive: {}", path, e);
+            Ok(())
+        }
+    }
+}
+
+/// The AppArmor profile enforced on the agent, `None` when unconfined
+fn apparmor_profile() -> Option<String> {
+    let current = match std::fs::read_to_string("/proc/self/attr/apparmor/current") {
+        Ok(current) => current,
+        // Older kernels only have the generic attribute, which holds the
+        // SELinux context where AppArmor is not loaded
+        Err(_) if Path::new("/sys/kernel/security/apparmor").exists() => {
+            std::fs::read_to_string("/proc/self/attr/current").ok()?
+        }
+        Err(_) => return None,
+    };
+    let profile = current.trim_end_matches(['\0', '\n']).to_string();
+    (profile != "unconfined" && !profile.ends_with("(complain)")).then_some(profile)
+}
+
+/// Run a service manager `command`, such as `systemctl restart yggdrasil`,
+/// as is first, where polkit rules or group membership may allow it, and
+/// with escalated privileges when that fails
+pub fn restart(command: &[&str]) -> Result<()> {
+    run_privileged(command)
+}
+
+fn run_privileged(command: &[&str]) -> Result<()> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .output()
+        .map_err(|e| anyhow!("Failed to execute {}: {}", command[0], e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let escalation = escalation();
+    match escalation {
+        Escalation::Root => return Err(anyhow!("{} failed: {}", command.join(" "), stderr.trim())),
+        Escalation::None => {
+            return Err(anyhow!("{} failed: {}; {}", command.join(" "), stderr.trim(), escalation.guidance(&[command])));
+        }
+        _ => debug!("{} failed: {}", command.join(" "), stderr.trim()),
+    }
+
+    let wrapped = escalation.wrap(command);
+    info!("Retrying with {}...", escalation.program().unwrap_or_default());
+    let output = Command::new(&wrapped[0])
+        .args(&wrapped[1..])
+        .output()
+        .map_err(|e| anyhow!("Failed to execute {}: {}", wrapped[0], e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}; {}",
+            wrapped.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim(),
+            escalation.guidance(&[command])
+        ));
+    }
+    Ok(())
+}
+
+/// Run `command` with `input` on its stdin
+async fn run_with_input(command: &[String], input: Vec<u8>) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut child = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to execute {}: {}", command[0], e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&input).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", command.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "0")
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Where `program` is found in PATH, as sudoers and doas.conf want it
+fn absolute_path(program: &str) -> String {
+    if Path::new(program).is_absolute() {
+        return program.to_string();
+    }
+    std::env::var_os("PATH")
+        .and_then(|paths| std::env::split_paths(&paths).map(|dir| dir.join(program)).find(|path| path.is_file()))
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| program.to_string())
+}
@@ -0,0 +1,8 @@
+//! The domain of yggman without its server: Yggdrasil nodes and configs,
+//! and how the configs of a managed mesh are generated from its nodes.
+
+pub mod mesh;
+pub mod yggdrasil;
+
+/// Network every node belongs to unless told otherwise
+pub const DEFAULT_NETWORK: &str = "default";
@@ -0,0 +1,326 @@
+//! Configs of a network's nodes, generated as a full mesh: every node
+//! dials every other node of its network on each address it is reachable
+//! at and allows all of their keys, plus the extra allowed keys set on the
+//! node for devices outside yggman. Peer schedules and interface peers
+//! narrow down whom a node dials; withdrawn nodes are dialed by nobody.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::yggdrasil::{Node, YggdrasilConfig};
+
+/// What a node under an open peer schedule peers with instead of the mesh
+#[derive(Debug, Clone, Default)]
+pub struct ScheduledPeers {
+    /// Ids of nodes in its network
+    pub nodes: HashSet<String>,
+    pub uris: Vec<String>,
+}
+
+/// Whether `a` and `b` dial each other, which peer schedules may prevent
+fn linked(a: &Node, b: &Node, scheduled: &HashMap<String, ScheduledPeers>) -> bool {
+    scheduled.get(&a.id).is_none_or(|peers| peers.nodes.contains(&b.id))
+        && scheduled.get(&b.id).is_none_or(|peers| peers.nodes.contains(&a.id))
+}
+
+/// Networks up to this size are built on the calling thread; spawning
+/// costs more than it saves
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// Build the configs of one network's nodes as a full mesh. Larger networks
+/// are split across threads, each building its share of the configs from
+/// the same read-only view of the nodes.
+pub fn generate_network_configs(nodes: &[Node]) -> HashMap<String, YggdrasilConfig> {
+    let all: Vec<usize> = (0..nodes.len()).collect();
+    generate_network_configs_for(nodes, &all, &HashSet::new(), &HashMap::new())
+}
+
+/// Build the configs of `nodes[i]` for every `i` in `indices`, still
+/// peering them with all of `nodes` but as `scheduled` says. Nobody dials
+/// the `withdrawn` nodes, though their keys stay allowed so they can rejoin.
+pub fn generate_network_configs_for(
+    nodes: &[Node],
+    indices: &[usize],
+    withdrawn: &HashSet<String>,
+    scheduled: &HashMap<String, ScheduledPeers>,
+) -> HashMap<String, YggdrasilConfig> {
+    let all_public_keys: Vec<String> = nodes
+        .iter()
+        .map(|n| n.public_key.clone())
+        .collect();
+    // URIs other nodes dial each node at, computed once rather than once per peer
+    let dial_uris: Vec<Vec<String>> = nodes.iter()
+        .map(|node| if withdrawn.contains(&node.id) { Vec::new() } else { dial_uris(node) })
+        .collect();
+    let build = |index: usize| build_node_config(nodes, index, &all_public_keys, &dial_uris, scheduled);
+    
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if indices.len() <= PARALLEL_THRESHOLD || threads == 1 {
+        return indices.iter().map(|&i| (nodes[i].id.clone(), build(i))).collect();
+    }
+    
+    let chunk_size = indices.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = indices
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let build = &build;
+                scope.spawn(move || {
+                    chunk.iter()
+                        .map(|&i| (nodes[i].id.clone(), build(i)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("config generation thread panicked"))
+            .collect()
+    })
+}
+
+/// Nodes `nodes[index]` peers with: every other node of its network
+pub fn mesh_peers(nodes: &[Node], index: usize) -> impl Iterator<Item = &Node> {
+    nodes.iter().enumerate().filter(move |(other, _)| *other != index).map(|(_, node)| node)
+}
+
+/// Peer URIs for every advertised listen endpoint and address of `node`
+fn dial_uris(node: &Node) -> Vec<String> {
+    let mut uris = Vec::new();
+    for listen_addr in node.advertised_listen() {
+        for address in &peer_addresses(listen_addr, &node.addresses) {
+            if let Some(peer_addr) = convert_listen_to_peer_with_address(listen_addr, &node.public_key, address) {
+                uris.push(peer_addr);
+            }
+        }
+    }
+    uris
+}
+
+/// The config of `nodes[index]`, peered with every other node it is
+/// `linked` to
+fn build_node_config(
+    nodes: &[Node],
+    index: usize,
+    all_public_keys: &[String],
+    dial_uris: &[Vec<String>],
+    scheduled: &HashMap<String, ScheduledPeers>,
+) -> YggdrasilConfig {
+    let node = &nodes[index];
+    let mut other_keys = all_public_keys.to_vec();
+    other_keys.retain(|k| k != &node.public_key);
+    for key in &node.extra_allowed_keys {
+        let key = key.to_lowercase();
+        if !other_keys.contains(&key) {
+            other_keys.push(key);
+        }
+    }
+    
+    let mut config = YggdrasilConfig {
+        private_key: node.private_key.clone(),
+        listen: node.listen.clone(),
+        allowed_public_keys: other_keys,
+        ..Default::default()
+    };
+    
+    // Build peers from other nodes' listen endpoints
+    let mut peers: Vec<String> = dial_uris.iter()
+        .enumerate()
+        .filter(|(other, _)| *other != index && linked(node, &nodes[*other], scheduled))
+        .flat_map(|(_, uris)| uris.iter().cloned())
+        .collect();
+    peers.extend(node.external_peers.iter().cloned());
+    if let Some(scheduled) = scheduled.get(&node.id) {
+        peers.extend(scheduled.uris.iter().filter(|uri| !peers.contains(uri)).cloned().collect::<Vec<_>>());
+    }
+    
+    // Move peerings pinned to a local interface out of the general list
+    for (interface, entries) in &node.interface_peers {
+        let mut bound = Vec::new();
+        for entry in entries {
+            if entry.contains("://") {
+                peers.retain(|p| p != entry);
+                bound.push(entry.clone());
+                continue;
+            }
+            let Some(target) = nodes.iter().find(|n| n.id != node.id && (n.name == *entry || n.id == *entry)) else {
+                tracing::warn!("Interface peer {} of node {} is not a node in its network", entry, node.name);
+                continue;
+            };
+            let (to_target, rest): (Vec<String>, Vec<String>) = peers.into_iter()
+                .partition(|p| peer_uri_key(p) == Some(target.public_key.as_str()));
+            peers = rest;
+            bound.extend(to_target);
+        }
+        if !bound.is_empty() {
+            config.interface_peers.entry(interface.clone()).or_default().extend(bound);
+        }
+    }
+    config.peers = peers;
+    
+    if let Some(if_name) = &node.if_name {
+        config.if_name = if_name.clone();
+    }
+    
+    if let Some(mtu) = node.mtu {
+        config.if_mtu = mtu;
+    }
+    
+    let mut node_info = node.node_info.clone();
+    node_info.insert("name".to_string(), serde_json::Value::String(node.name.clone()));
+    config.node_info = node_info;
+    config.extra_config = node.extra_config.clone();
+    
+    config
+}
+
+/// Public key pinned by a peer URI's `key` parameter
+pub fn peer_uri_key(uri: &str) -> Option<&str> {
+    let (_, query) = uri.split_once('?')?;
+    query.split('&').find_map(|param| param.strip_prefix("key="))
+}
+
+pub fn ordered_pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+pub fn convert_listen_to_peer_with_address(listen_addr: &str, public_key: &str, address: &str) -> Option<String> {
+    // Parse the listen address and convert to peer format
+    // Listen format: tcp://[::]:1234 or tcp://0.0.0.0:1234
+    // Peer format: tcp://REAL_IP:1234?key=PUBLIC_KEY
+    
+    if listen_addr.contains("unix://") {
+        // Unix sockets are local only, skip
+        return None;
+    }
+    
+    // Extract protocol and port
+    let parts: Vec<&str> = listen_addr.split("://").collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    
+    let protocol = parts[0];
+    let addr_part = parts[1];
+    
+    // Extract port from address
+    let port = if addr_part.contains("]:") {
+        // IPv6 format [::]:port
+        addr_part.split("]:").nth(1)
+    } else {
+        // IPv4 format 0.0.0.0:port
+        addr_part.split(':').nth(1)
+    };
+    
+    let port = port?;
+    
+    // Check for query parameters in the original listen address
+    let (port_clean, params) = if port.contains('?') {
+        let parts: Vec<&str> = port.split('?').collect();
+        (parts[0], Some(parts[1]))
+    } else {
+        (port, None)
+    };
+    
+    // IPv6 literals must be bracketed in peer URIs
+    let host = if address.contains(':') && !address.starts_with('[') {
+        format!("[{}]", address)
+    } else {
+        address.to_string()
+    };
+    
+    // Build the peer address with the specified IP
+    let mut peer_addr = format!("{}://{}:{}?key={}", protocol, host, port_clean, public_key);
+    
+    // Add any additional parameters from the listen address
+    if let Some(params) = params {
+        peer_addr.push('&');
+        peer_addr.push_str(params);
+    }
+    
+    Some(peer_addr)
+}
+
+
+/// Host part of a listen URI, without brackets
+fn listen_host(listen_addr: &str) -> Option<&str> {
+    let (_, rest) = listen_addr.split_once("://")?;
+    if let Some(stripped) = rest.strip_prefix('[') {
+        stripped.split(']').next()
+    } else {
+        rest.split(':').next()
+    }
+}
+
+/// Addresses other nodes should dial for a listen endpoint. Wildcard binds
+/// are reachable on every known address of the node (localhost when none
+/// are known), specific binds only on the bound address. Endpoints with
+/// unresolved placeholders yield nothing.
+pub fn peer_addresses(listen_addr: &str, addresses: &[String]) -> Vec<String> {
+    let Some(host) = listen_host(listen_addr) else {
+        return Vec::new();
+    };
+    
+    if listen_addr.contains('{') {
+        return Vec::new();
+    }
+    
+    let is_wildcard = host.is_empty()
+        || host.parse::<std::net::IpAddr>().map(|ip| ip.is_unspecified()).unwrap_or(false);
+    
+    if !is_wildcard {
+        vec![host.to_string()]
+    } else if addresses.is_empty() {
+        vec!["127.0.0.1".to_string()]
+    } else {
+        addresses.to_vec()
+    }
+}
+
+/// Returns false for listen endpoints bound to loopback or unix sockets,
+/// which can never be reached from outside the host.
+pub fn listen_host_is_public(listen_addr: &str) -> bool {
+    if listen_addr.starts_with("unix://") {
+        return false;
+    }
+    let Some(host) = listen_host(listen_addr) else {
+        return false;
+    };
+    
+    match host.parse::<std::net::IpAddr>() {
+        Ok(ip) => !ip.is_loopback(),
+        Err(_) => host != "localhost",
+    }
+}
+
+/// Whether an underlay address is globally routable. Private, link-local,
+/// CGNAT, documentation and Yggdrasil (200::/7) ranges are excluded.
+pub fn is_public_address(address: &str) -> bool {
+    use std::net::IpAddr;
+    
+    match address.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let octets = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64))
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00 // unique local
+                || (first & 0xffc0) == 0xfe80 // link-local
+                || (first & 0xfe00) == 0x0200 // yggdrasil
+                || first == 0x2001 && ip.segments()[1] == 0x0db8)
+        }
+        // Hostnames are assumed to be resolvable from the outside
+        Err(_) => address != "localhost",
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+
+use yggman_proto::redact::Secret;
+
+/// Top-level yggdrasil.conf keys yggman generates; per-node extra config
+/// may not set them.
+pub const MANAGED_CONFIG_KEYS: &[&str] = &[
+    "PrivateKey",
+    "Peers",
+    "Listen",
+    "AllowedPublicKeys",
+    "InterfacePeers",
+    "IfName",
+    "IfMTU",
+    "NodeInfoPrivacy",
+    "NodeInfo",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct YggdrasilConfig {
+    /// Never serialized, so no API response carries it; agents get the
+    /// node's key in their config message
+    #[serde(rename = "PrivateKey", default, skip_serializing)]
+    pub private_key: Secret<String>,
+    
+    pub peers: Vec<String>,
+    
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub listen: Vec<String>,
+    
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_public_keys: Vec<String>,
+    
+    /// Peers dialled only through a specific local interface
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub interface_peers: HashMap<String, Vec<String>>,
+    
+    #[serde(rename = "IfName")]
+    pub if_name: String,
+    
+    #[serde(rename = "IfMTU")]
+    pub if_mtu: u16,
+    
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_info_privacy: Option<bool>,
+    
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub node_info: HashMap<String, serde_json::Value>,
+    
+    /// Raw options yggman has no first-class support for, merged last
+    #[serde(flatten)]
+    pub extra_config: HashMap<String, serde_json::Value>,
+}
+
+impl Default for YggdrasilConfig {
+    fn default() -> Self {
+        Self {
+            private_key: Secret::default(),
+            peers: Vec::new(),
+            listen: Vec::new(),
+            allowed_public_keys: Vec::new(),
+            interface_peers: HashMap::new(),
+            if_name: "auto".to_string(),
+            if_mtu: 65535,
+            node_info_privacy: Some(false),
+            node_info: HashMap::new(),
+            extra_config: HashMap::new(),
+        }
+    }
+}
+
+/// Whether a node's listen endpoints follow its network's listen templates,
+/// rewritten when agents register and when templates change, or were set
+/// by hand and are left alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenPolicy {
+    #[default]
+    Template,
+    Pinned,
+}
+
+impl ListenPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListenPolicy::Template => "template",
+            ListenPolicy::Pinned => "pinned",
+        }
+    }
+    
+    /// Unknown values fall back to following the templates
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "pinned" => ListenPolicy::Pinned,
+            _ => ListenPolicy::Template,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub id: String,
+    pub name: String,
+    pub public_key: String,
+    /// Never serialized, so no API response carries it; stores keeping
+    /// nodes serialize them through `with_private_key`
+    #[serde(default, skip_serializing)]
+    pub private_key: Secret<String>,
+    pub listen: Vec<String>,
+    #[serde(default)]
+    pub listen_policy: ListenPolicy,
+    pub addresses: Vec<String>, // Real IP addresses of the node
+    #[serde(default)]
+    pub external_peers: Vec<String>, // Peers outside the managed mesh, e.g. public peers
+    #[serde(default = "default_network")]
+    pub network: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub resolved_listen: Vec<String>, // Listen endpoints with placeholders resolved by the agent
+    #[serde(default)]
+    pub mtu: Option<u16>, // Overrides IfMTU when set
+    #[serde(default)]
+    pub node_info: HashMap<String, serde_json::Value>, // Extra NodeInfo entries
+    #[serde(default)]
+    pub if_name: Option<String>, // Fixed TUN interface name, "auto" when unset
+    #[serde(default)]
+    pub interface_peers: HashMap<String, Vec<String>>, // Local interface -> peer URIs or managed node names
+    #[serde(default)]
+    pub extra_allowed_keys: Vec<String>, // Keys of devices outside yggman allowed to peer in, e.g. a phone
+    #[serde(default)]
+    pub extra_config: HashMap<String, serde_json::Value>, // Raw yggdrasil.conf options outside MANAGED_CONFIG_KEYS
+    #[serde(default)]
+    pub agent_version: Option<String>, // Reported by the agent when it registers
+    #[serde(default)]
+    pub yggdrasil_version: Option<String>, // Yggdrasil build found by the agent, when it could tell
+    #[serde(default)]
+    pub description: Option<String>, // Free-form notes for operators
+    #[serde(default)]
+    pub owner: Option<String>, // Person or team running the node
+    #[serde(default)]
+    pub contact: Option<String>, // How to reach the owner, e.g. an email address or chat handle
+    #[serde(default)]
+    pub external_id: Option<String>, // The node in an outside system, e.g. a Kubernetes node or cloud instance id
+    #[serde(default)]
+    pub provider: Option<String>, // System external_id belongs to, e.g. "kubernetes" or "aws"
+}
+
+impl Node {
+    /// The node's address inside the mesh, derived from its public key
+    pub fn yggdrasil_address(&self) -> Option<Ipv6Addr> {
+        address_for_key(&self.public_key)
+    }
+    
+    /// The node as serialized by stores, including its private key
+    pub fn with_private_key(&self) -> NodeWithPrivateKey<'_> {
+        NodeWithPrivateKey { node: self, private_key: &self.private_key }
+    }
+
+    /// Listen endpoints other nodes should dial: the agent-resolved ones
+    /// when listen templates contain placeholders, the configured ones otherwise.
+    pub fn advertised_listen(&self) -> &[String] {
+        if self.resolved_listen.is_empty() {
+            &self.listen
+        } else {
+            &self.resolved_listen
+        }
+    }
+}
+
+/// A node serialized with its private key, which reads back as a `Node`
+#[derive(Serialize)]
+pub struct NodeWithPrivateKey<'a> {
+    #[serde(flatten)]
+    node: &'a Node,
+    private_key: &'a Secret<String>,
+}
+
+/// Derive the 200::/7 address Yggdrasil assigns to a hex encoded public
+/// key: the prefix, the number of leading ones of the inverted key, then
+/// the inverted key bits following the first zero.
+pub fn address_for_key(public_key: &str) -> Option<Ipv6Addr> {
+    let key: [u8; 32] = hex::decode(public_key).ok()?.try_into().ok()?;
+    let inverted: Vec<u8> = key.iter().map(|b| !b).collect();
+    
+    let mut addr = [0u8; 16];
+    addr[0] = 0x02;
+    let mut ones = 0u8;
+    let mut done = false;
+    let mut bits = 0u8;
+    let mut n_bits = 0;
+    let mut idx = 2;
+    for i in 0..inverted.len() * 8 {
+        let bit = (inverted[i / 8] >> (7 - i % 8)) & 1;
+        if !done {
+            if bit == 1 {
+                ones = ones.wrapping_add(1);
+            } else {
+                done = true;
+            }
+            continue;
+        }
+        bits = (bits << 1) | bit;
+        n_bits += 1;
+        if n_bits == 8 {
+            if idx == addr.len() {
+                break;
+            }
+            addr[idx] = bits;
+            idx += 1;
+            n_bits = 0;
+        }
+    }
+    addr[1] = ones;
+    Some(Ipv6Addr::from(addr))
+}
+
+fn default_network() -> String {
+    crate::DEFAULT_NETWORK.to_string()
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+//! What the yggman server and its agents exchange: the messages of the
+//! agent protocol, the signatures the server puts on them, the sealed boxes
+//! node private keys travel in, and the `Secret` wrapper keeping keys and
+//! tokens out of logs on both sides.
+
+pub mod protocol;
+pub mod redact;
+pub mod sealing;
+pub mod signing;
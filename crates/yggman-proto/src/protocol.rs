@@ -0,0 +1,284 @@
+//! Messages of the agent WebSocket protocol, shared by the server and the
+//! agent. Messages are JSON text frames, or CBOR binary frames for agents
+//! that send CBOR. Fields added after the first protocol version default
+//! when missing, and error codes added later parse as `Unknown`, so both
+//! sides accept messages of older and newer peers.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::redact::Secret;
+
+/// Version of the agent protocol this server speaks; agents announce theirs
+/// on registration
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AgentMessage {
+    Register {
+        name: String,
+        addresses: Vec<String>,
+        #[serde(default)]
+        network: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        agent_version: Option<String>,
+        /// `None` when the agent could not determine it
+        #[serde(default)]
+        yggdrasil_version: Option<String>,
+        /// 0 for agents older than protocol versioning
+        #[serde(default)]
+        protocol_version: u32,
+        /// Platform of the agent binary, e.g. `linux-x86_64`
+        #[serde(default)]
+        target: Option<String>,
+        /// The agent only previews the config of its existing node; nothing
+        /// is stored and its running agent stays connected
+        #[serde(default)]
+        dry_run: bool,
+        /// Lets the agent add a new node when the server requires enrollment
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enrollment_token: Option<Secret<String>>,
+        /// Lets the agent take over its existing node, see
+        /// `crate::signing::agent_token`; it comes with the node's config
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        agent_token: Option<Secret<String>>,
+        /// Hex X25519 key to seal the node's private key to, see
+        /// `crate::sealing`; older agents get it in plain text
+        #[serde(default)]
+        key_agreement_key: Option<String>,
+        /// Echoed in the config answering the registration, see
+        /// `crate::signing::nonce`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+    },
+    Heartbeat,
+    UpdateAddresses {
+        addresses: Vec<String>,
+    },
+    ListenResolved {
+        listen: Vec<String>,
+    },
+    ConfigRejected {
+        revision: u64,
+        reason: String,
+    },
+    ConfigApplied {
+        revision: u64,
+        success: bool,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    Status {
+        revision: u64,
+        listen: Vec<String>,
+        peers: Vec<String>,
+        allowed_public_keys: Vec<String>,
+        #[serde(default)]
+        established_peers: Option<Vec<String>>,
+        #[serde(default)]
+        drift: bool,
+        #[serde(default)]
+        traffic: Option<Vec<PeerCounters>>,
+        /// The last error the server answered this agent with
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_server_error: Option<ServerErrorReport>,
+    },
+    FirewallUpdated {
+        backend: String,
+        open_ports: Vec<String>,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    /// Ask for the complete configuration again, e.g. after the local
+    /// config was restored from a backup
+    RequestFullConfig {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// The agent is shutting down and will not reconnect on its own
+    Disconnect {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    Config {
+        #[serde(default)]
+        revision: u64,
+        /// Run of the server that numbered `revision`. A restored or
+        /// ephemeral database restarts the numbering, so agents compare
+        /// revisions only within one epoch; empty from older servers.
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        epoch: String,
+        /// The nonce of the registration or pull this config answers.
+        /// Only such a config moves an agent to another epoch, as old
+        /// messages cannot carry it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+        /// The node the config is for; being signed, it keeps a config
+        /// from being replayed to another node
+        node_id: String,
+        /// Empty when the key is sent sealed
+        #[serde(default, skip_serializing_if = "Secret::is_empty")]
+        private_key: Secret<String>,
+        /// The private key sealed to the agent's key agreement key, hex
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed_private_key: Option<String>,
+        /// For registering as this node again; empty when sent sealed, and
+        /// from servers older than this field
+        #[serde(default, skip_serializing_if = "Secret::is_empty")]
+        agent_token: Secret<String>,
+        /// The agent token sealed like the private key, hex
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sealed_agent_token: Option<String>,
+        listen: Vec<String>,
+        peers: Vec<String>,
+        allowed_public_keys: Vec<String>,
+        #[serde(default)]
+        interface_peers: HashMap<String, Vec<String>>,
+        #[serde(default)]
+        if_name: Option<String>,
+        #[serde(default)]
+        extra_config: HashMap<String, serde_json::Value>,
+        /// Always sent by this server; older ones left it out
+        #[serde(default)]
+        timing: Option<AgentTiming>,
+    },
+    Update {
+        #[serde(default)]
+        revision: u64,
+        /// As in `Config`
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        epoch: String,
+        /// As in `Config`; empty from servers older than this field
+        #[serde(default)]
+        node_id: String,
+        listen: Vec<String>,
+        peers: Vec<String>,
+        allowed_public_keys: Vec<String>,
+        #[serde(default)]
+        interface_peers: HashMap<String, Vec<String>>,
+        /// `None` leaves the agent's interface name alone
+        #[serde(default)]
+        if_name: Option<String>,
+        #[serde(default)]
+        extra_config: HashMap<String, serde_json::Value>,
+    },
+    /// Intervals changed while the agent is connected; sent to agents of
+    /// protocol 3 or newer, older ones pick them up when they reconnect
+    Timing {
+        timing: AgentTiming,
+    },
+    /// A newer agent release; agents started with --auto-update install it
+    UpdateAvailable {
+        version: String,
+        url: String,
+        sha256: String,
+    },
+    Error {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<ErrorCode>,
+        /// What the agent has to upgrade to, with `UnsupportedVersion`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        upgrade: Option<UpgradeHint>,
+    },
+}
+
+/// Intervals agents take from the server, in seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentTiming {
+    pub heartbeat_interval: u64,
+    pub address_scan_interval: u64,
+    pub reconnect_interval: Option<u64>,
+    /// 0 when the server does not ping
+    #[serde(default)]
+    pub ping_interval: u64,
+    #[serde(default = "default_pong_timeout")]
+    pub pong_timeout: u64,
+}
+
+/// Seconds to wait for a pong, from servers that do not say
+pub const DEFAULT_PONG_TIMEOUT: u64 = 20;
+
+fn default_pong_timeout() -> u64 {
+    DEFAULT_PONG_TIMEOUT
+}
+
+impl ServerMessage {
+    pub fn error(message: impl Into<String>) -> Self {
+        ServerMessage::Error { message: message.into(), code: None, upgrade: None }
+    }
+
+    /// The config revision a `Config` or `Update` carries
+    pub fn revision(&self) -> Option<u64> {
+        match self {
+            ServerMessage::Config { revision, .. } | ServerMessage::Update { revision, .. } => Some(*revision),
+            ServerMessage::Timing { .. } | ServerMessage::UpdateAvailable { .. } | ServerMessage::Error { .. } => None,
+        }
+    }
+
+    /// The epoch of that revision, empty from older servers
+    pub fn epoch(&self) -> Option<&str> {
+        match self {
+            ServerMessage::Config { epoch, .. } | ServerMessage::Update { epoch, .. } => Some(epoch),
+            ServerMessage::Timing { .. } | ServerMessage::UpdateAvailable { .. } | ServerMessage::Error { .. } => None,
+        }
+    }
+}
+
+/// Machine-readable reason of a `ServerMessage::Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The agent is older than the server accepts and was not registered
+    UnsupportedVersion,
+    /// A new node was not registered for a missing, unknown, expired or
+    /// used up enrollment token
+    EnrollmentRefused,
+    /// The message could not be parsed and was dropped
+    ParseError,
+    /// The message needs a registered node and was dropped; the agent has
+    /// to register first
+    Unauthorized,
+    /// An existing node was not handed over for a missing or wrong agent
+    /// token
+    AgentTokenRefused,
+    /// A code added by a newer peer
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpgradeHint {
+    #[serde(default)]
+    pub min_agent_version: Option<String>,
+    pub min_protocol_version: u32,
+    /// 0 from servers older than protocol 2
+    #[serde(default)]
+    pub server_protocol_version: u32,
+}
+
+/// A `ServerMessage::Error` as an agent last received it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerErrorReport {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Unix time in milliseconds the agent received it at
+    pub received_at: u64,
+}
+
+/// Byte counters of one peer as an agent reports them, counting since the
+/// peer's session came up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerCounters {
+    pub key: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
@@ -0,0 +1,60 @@
+//! Node private keys encrypted to the agent. The agent makes up an X25519
+//! key pair when it starts and sends the public half with its
+//! registration; the server seals the node key to it (a libsodium sealed
+//! box), so only that agent process can read it. Shared by the server,
+//! which seals, and the agent, which opens.
+
+use crypto_box::{PublicKey, SecretKey};
+
+/// Request header carrying the key when an agent pulls its config over HTTP
+pub const KEY_AGREEMENT_HEADER: &str = "x-key-agreement-key";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SealingError {
+    #[error("invalid key agreement key: {0}")]
+    InvalidKey(String),
+    #[error("malformed sealed key: {0}")]
+    Malformed(String),
+    #[error("sealed key cannot be opened with this agent's key")]
+    Undecryptable,
+}
+
+/// Parse the hex X25519 public key an agent registered with
+pub fn parse_public_key(hex_key: &str) -> Result<PublicKey, SealingError> {
+    let bytes: [u8; 32] = hex::decode(hex_key)
+        .map_err(|e| SealingError::InvalidKey(e.to_string()))?
+        .try_into()
+        .map_err(|_| SealingError::InvalidKey("expected 32 bytes".to_string()))?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Encrypt `private_key` to `recipient`; the result is hex
+pub fn seal(recipient: &PublicKey, private_key: &str) -> Result<String, SealingError> {
+    let sealed = recipient
+        .seal(&mut rand::rngs::OsRng, private_key.as_bytes())
+        .map_err(|_| SealingError::Malformed("encryption failed".to_string()))?;
+    Ok(hex::encode(sealed))
+}
+
+/// The agent's side: a key pair that lives as long as the process
+pub struct KeyOpener {
+    secret: SecretKey,
+}
+
+impl KeyOpener {
+    pub fn generate() -> Self {
+        Self { secret: SecretKey::generate(&mut rand::rngs::OsRng) }
+    }
+
+    /// Hex public key to send with the registration
+    pub fn public_key(&self) -> String {
+        hex::encode(self.secret.public_key().as_bytes())
+    }
+
+    /// Decrypt a key sealed with `seal`
+    pub fn open(&self, sealed: &str) -> Result<String, SealingError> {
+        let sealed = hex::decode(sealed).map_err(|e| SealingError::Malformed(e.to_string()))?;
+        let opened = self.secret.unseal(&sealed).map_err(|_| SealingError::Undecryptable)?;
+        String::from_utf8(opened).map_err(|e| SealingError::Malformed(e.to_string()))
+    }
+}
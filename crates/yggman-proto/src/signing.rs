@@ -0,0 +1,117 @@
+//! Signatures on control-plane messages. Shared by the server, which signs,
+//! and the agent, which verifies.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::Value;
+
+pub const SIGNATURE_FIELD: &str = "signature";
+pub const TIMESTAMP_FIELD: &str = "timestamp";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error("message is not signed")]
+    Missing,
+    #[error("malformed signature: {0}")]
+    Malformed(String),
+    #[error("signature does not match the pinned server key")]
+    Invalid,
+    #[error("invalid public key: {0}")]
+    InvalidKey(String),
+}
+
+/// Serialize a JSON value with object keys sorted at every level, so both
+/// sides sign the same bytes regardless of field order.
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical_json(&map[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Add a timestamp (unix milliseconds) to a message object and sign
+/// everything but the signature field itself.
+pub fn sign_message(key: &SigningKey, mut message: Value) -> Value {
+    if let Value::Object(map) = &mut message {
+        map.remove(SIGNATURE_FIELD);
+        map.insert(TIMESTAMP_FIELD.to_string(), Value::from(unix_millis()));
+        let signature = key.sign(canonical_json(&Value::Object(map.clone())).as_bytes());
+        map.insert(SIGNATURE_FIELD.to_string(), Value::String(hex::encode(signature.to_bytes())));
+    }
+    message
+}
+
+/// Check a message's signature; returns its timestamp.
+pub fn verify_message(key: &VerifyingKey, message: &Value) -> Result<u64, SignatureError> {
+    let Value::Object(map) = message else {
+        return Err(SignatureError::Malformed("message is not an object".to_string()));
+    };
+
+    let signature_hex = map.get(SIGNATURE_FIELD)
+        .and_then(Value::as_str)
+        .ok_or(SignatureError::Missing)?;
+    let signature_bytes: [u8; 64] = hex::decode(signature_hex)
+        .map_err(|e| SignatureError::Malformed(e.to_string()))?
+        .try_into()
+        .map_err(|_| SignatureError::Malformed("signature must be 64 bytes".to_string()))?;
+    let timestamp = map.get(TIMESTAMP_FIELD)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| SignatureError::Malformed("missing timestamp".to_string()))?;
+
+    let mut signed = map.clone();
+    signed.remove(SIGNATURE_FIELD);
+    key.verify(canonical_json(&Value::Object(signed)).as_bytes(), &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| SignatureError::Invalid)?;
+
+    Ok(timestamp)
+}
+
+/// Parse a hex encoded ed25519 public key
+pub fn parse_public_key(key_hex: &str) -> Result<VerifyingKey, SignatureError> {
+    let bytes: [u8; 32] = hex::decode(key_hex.trim())
+        .map_err(|e| SignatureError::InvalidKey(e.to_string()))?
+        .try_into()
+        .map_err(|_| SignatureError::InvalidKey("public key must be 32 bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| SignatureError::InvalidKey(e.to_string()))
+}
+
+/// Bearer token the agent of `node_id` pulls its config over HTTP with.
+/// Derived from the signing key rather than stored, so replacing the key
+/// revokes every token.
+pub fn agent_token(key: &SigningKey, node_id: &str) -> String {
+    hex::encode(key.sign(format!("yggman agent token:{}", node_id).as_bytes()).to_bytes())
+}
+
+/// Whether `token` is the agent token of `node_id`, compared in constant time
+pub fn verify_agent_token(key: &SigningKey, node_id: &str, token: &str) -> bool {
+    let expected = agent_token(key, node_id);
+    expected.len() == token.len() && expected.bytes().zip(token.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Request header carrying the nonce when an agent pulls its config over
+/// HTTP, echoed in the config like the registration's
+pub const NONCE_HEADER: &str = "x-yggman-nonce";
+
+/// Random hex value binding a config to the registration or pull it
+/// answers, see `protocol::ServerMessage::Config`
+pub fn nonce() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,16 @@
+fn main() {
+    // The gRPC control API is generated from the shared service definition,
+    // with a bundled protoc so building it needs no system packages
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=../../proto/yggman.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform");
+        let include = protoc_bin_vendored::include_path().expect("No bundled protobuf includes for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["../../proto/yggman.proto"], &[std::path::Path::new("../../proto"), &include])
+            .expect("Failed to compile proto/yggman.proto");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
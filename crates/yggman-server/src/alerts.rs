@@ -0,0 +1,188 @@
+//! Alert rules from `[alerts]` evaluated against every node. An alert
+//! fires when its rule's condition holds for a node and resolves when it no
+//! longer does; both transitions are recorded as events and delivered
+//! through the notifier.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::{AlertCondition, AlertRule};
+use crate::core::context::AppContext;
+use crate::event_log::EventSeverity;
+use crate::yggdrasil::Node;
+
+/// How long resolved alerts stay listed
+const RESOLVED_RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub node_id: String,
+    pub node_name: String,
+    pub severity: EventSeverity,
+    pub state: AlertState,
+    pub message: String,
+    pub started_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Whether a rule's condition holds for a node, with a description;
+/// `None` when the node reported nothing to judge by
+type RuleResult = Option<(bool, String)>;
+
+/// Firing and recently resolved alerts, keyed by rule name and node
+#[derive(Default)]
+pub struct AlertManager {
+    alerts: Mutex<HashMap<(String, String), Alert>>,
+}
+
+impl AlertManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Firing alerts first, newest first within each state
+    pub fn list(&self) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = self.alerts.lock().unwrap().values().cloned().collect();
+        alerts.sort_by(|a, b| {
+            (a.state == AlertState::Resolved).cmp(&(b.state == AlertState::Resolved))
+                .then(b.started_at.cmp(&a.started_at))
+        });
+        alerts
+    }
+
+    /// Check every rule against every node and fire or resolve alerts
+    pub async fn evaluate(&self, context: &AppContext, nodes: &[Node]) {
+        let rules = context.config_manager.get().alerts.rules.clone();
+        if rules.is_empty() && self.alerts.lock().unwrap().is_empty() {
+            return;
+        }
+
+        let connected = crate::websocket_state::get_connected_node_ids().await;
+        let history = context.status_history.latest().await.unwrap_or_else(|e| {
+            tracing::warn!("Alert rules cannot see node status history: {}", e);
+            HashMap::new()
+        });
+
+        let mut results = Vec::new();
+        for node in nodes {
+            let online = connected.contains(&node.id);
+            let status = crate::websocket_state::get_agent_status(&node.id).await;
+            let ack = crate::websocket_state::get_config_ack(&node.id).await;
+            for rule in &rules {
+                let holds: RuleResult = match &rule.condition {
+                    AlertCondition::NodeOffline { minutes } => match history.get(&node.id) {
+                        _ if online => Some((false, format!("{} is online", node.name))),
+                        Some(change) if !change.online => {
+                            let offline_minutes = (Utc::now() - change.created_at).num_minutes();
+                            Some((
+                                offline_minutes >= *minutes as i64,
+                                format!("{} has been offline for {} minutes", node.name, offline_minutes),
+                            ))
+                        }
+                        // Nodes that never connected have nothing to lose
+                        _ => None,
+                    },
+                    AlertCondition::PeerCountBelow { min_peers } => status
+                        .as_ref()
+                        .filter(|_| online)
+                        .and_then(|status| status.established_peers.as_ref())
+                        .map(|peers| (
+                            peers.len() < *min_peers,
+                            format!("{} has {} established peers, expected at least {}", node.name, peers.len(), min_peers),
+                        )),
+                    AlertCondition::ConfigApplyFailed => ack.as_ref().map(|ack| (
+                        !ack.success,
+                        match &ack.error {
+                            Some(error) if !ack.success => format!("{} failed to apply revision {}: {}", node.name, ack.revision, error),
+                            _ if !ack.success => format!("{} failed to apply revision {}", node.name, ack.revision),
+                            _ => format!("{} applied revision {}", node.name, ack.revision),
+                        },
+                    )),
+                };
+                results.push((rule, node, holds));
+            }
+        }
+
+        let changed = self.apply(results);
+        for alert in changed {
+            let (kind, verb) = match alert.state {
+                AlertState::Firing => ("alert_firing", "firing"),
+                AlertState::Resolved => ("alert_resolved", "resolved"),
+            };
+            let severity = match alert.state {
+                AlertState::Firing => alert.severity,
+                AlertState::Resolved => EventSeverity::Info,
+            };
+            context.event_log.emit(kind, severity, Some(&alert.node_id), &format!("Alert {} {}: {}", alert.rule, verb, alert.message)).await;
+            context.notifier.send(&context.config_manager, kind, serde_json::to_value(&alert).unwrap_or_default());
+        }
+    }
+
+    /// Update alert states from rule results, returning the alerts that
+    /// fired or resolved. Unknown results leave the alert as it is.
+    fn apply(&self, results: Vec<(&AlertRule, &Node, RuleResult)>) -> Vec<Alert> {
+        let now = Utc::now();
+        let mut alerts = self.alerts.lock().unwrap();
+        let mut changed = Vec::new();
+        let mut evaluated = HashSet::new();
+
+        for (rule, node, holds) in results {
+            let key = (rule.name.clone(), node.id.clone());
+            evaluated.insert(key.clone());
+            let Some((holds, message)) = holds else {
+                continue;
+            };
+
+            match alerts.get_mut(&key) {
+                Some(alert) if alert.state == AlertState::Firing => {
+                    alert.message = message;
+                    if !holds {
+                        alert.state = AlertState::Resolved;
+                        alert.resolved_at = Some(now);
+                        changed.push(alert.clone());
+                    }
+                }
+                _ if holds => {
+                    let alert = Alert {
+                        rule: rule.name.clone(),
+                        node_id: node.id.clone(),
+                        node_name: node.name.clone(),
+                        severity: rule.severity,
+                        state: AlertState::Firing,
+                        message,
+                        started_at: now,
+                        resolved_at: None,
+                    };
+                    changed.push(alert.clone());
+                    alerts.insert(key, alert);
+                }
+                _ => {}
+            }
+        }
+
+        // Alerts of removed rules or deleted nodes cannot resolve on their own
+        for (key, alert) in alerts.iter_mut() {
+            if alert.state == AlertState::Firing && !evaluated.contains(key) {
+                alert.state = AlertState::Resolved;
+                alert.resolved_at = Some(now);
+                alert.message = format!("Rule {} or node {} was removed", alert.rule, alert.node_name);
+                changed.push(alert.clone());
+            }
+        }
+        alerts.retain(|_, alert| {
+            alert.resolved_at.is_none_or(|resolved_at| now - resolved_at < Duration::hours(RESOLVED_RETENTION_HOURS))
+        });
+
+        changed
+    }
+}
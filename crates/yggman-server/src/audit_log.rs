@@ -0,0 +1,36 @@
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryOrder, QuerySelect};
+
+use crate::database::entities::audit_log::{self as audit_entity, Model as AuditEntry};
+use crate::error::AppError;
+
+/// Append-only record of sensitive operations such as key exports
+pub struct AuditLog {
+    db: DatabaseConnection,
+}
+
+impl AuditLog {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+    
+    pub async fn record(&self, actor: &str, action: &str, target: &str, details: &str) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        audit_entity::ActiveModel::new(actor.to_string(), action.to_string(), target.to_string(), details.to_string())
+            .insert(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        
+        tracing::info!(actor, action, target, "Audit: {}", details);
+        Ok(())
+    }
+    
+    /// Most recent entries first
+    pub async fn get_recent(&self, limit: u64) -> Result<Vec<AuditEntry>, AppError> {
+        audit_entity::Entity::find()
+            .order_by_desc(audit_entity::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+}
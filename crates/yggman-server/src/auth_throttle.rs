@@ -0,0 +1,302 @@
+//! Failed authentication attempts per client address. Each failure delays
+//! the answer a little longer, and `max_failures` in a row lock the address
+//! out of every token-protected endpoint for a while, twice as long on each
+//! lockout. State is kept in the database so a restart does not reset it,
+//! and addresses quiet for `max_lockout_seconds` are dropped from both.
+
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audit_log::AuditLog;
+use crate::config::AuthLockoutConfig;
+use crate::database::entities::auth_failure as failure_entity;
+use crate::error::AppError;
+
+/// Longest delay added to a failed attempt
+const MAX_DELAY: Duration = Duration::from_secs(4);
+
+fn db_error(e: sea_orm::DbErr) -> AppError {
+    AppError::Config(format!("Database error: {}", e))
+}
+
+#[derive(Debug, Clone)]
+struct Attempts {
+    failures: u32,
+    lockouts: u32,
+    locked_until: Option<DateTime<Utc>>,
+    last_failure_at: DateTime<Utc>,
+}
+
+impl Attempts {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            failures: 0,
+            lockouts: 0,
+            locked_until: None,
+            last_failure_at: now,
+        }
+    }
+
+    /// Count a failure at `now`; returns the seconds of the lockout it
+    /// starts, if it starts one
+    fn fail(&mut self, now: DateTime<Utc>, config: &AuthLockoutConfig) -> Option<u64> {
+        // A quiet max_lockout_seconds forgets everything, the end of a
+        // lockout only the failures that led to it
+        if self.last_failure_at < quiet_since(now, config) {
+            self.failures = 0;
+            self.lockouts = 0;
+        }
+        if self.locked_until.is_some_and(|until| until <= now) {
+            self.locked_until = None;
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.last_failure_at = now;
+
+        if self.failures < config.max_failures || self.locked_until.is_some() {
+            return None;
+        }
+        let seconds = config.lockout_seconds
+            .saturating_mul(1u64.checked_shl(self.lockouts).unwrap_or(u64::MAX))
+            .min(config.max_lockout_seconds);
+        self.lockouts += 1;
+        self.locked_until = Some(now + chrono::Duration::seconds(seconds as i64));
+        Some(seconds)
+    }
+}
+
+fn quiet_since(now: DateTime<Utc>, config: &AuthLockoutConfig) -> DateTime<Utc> {
+    now - chrono::Duration::seconds(config.max_lockout_seconds as i64)
+}
+
+/// Wait before answering the `failures`th failure in a row: 250ms, doubled
+/// for each further one up to `MAX_DELAY`
+fn failure_delay(failures: u32) -> Duration {
+    let delay = Duration::from_millis(250).saturating_mul(1u32.checked_shl(failures.saturating_sub(1)).unwrap_or(u32::MAX));
+    delay.min(MAX_DELAY)
+}
+
+pub struct AuthThrottle {
+    db: DatabaseConnection,
+    audit_log: Arc<AuditLog>,
+    attempts: Mutex<HashMap<IpAddr, Attempts>>,
+}
+
+impl AuthThrottle {
+    pub fn new(db: DatabaseConnection, audit_log: Arc<AuditLog>) -> Self {
+        Self {
+            db,
+            audit_log,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Pick up the failures recorded before the last restart
+    pub async fn load(&self) -> Result<(), AppError> {
+        let rows = failure_entity::Entity::find().all(&self.db).await.map_err(db_error)?;
+        let mut attempts = self.attempts.lock().unwrap();
+        for row in rows {
+            let Ok(ip) = row.client_ip.parse::<IpAddr>() else {
+                continue;
+            };
+            attempts.insert(ip, Attempts {
+                failures: row.failures as u32,
+                lockouts: row.lockouts as u32,
+                locked_until: row.locked_until,
+                last_failure_at: row.last_failure_at,
+            });
+        }
+        Ok(())
+    }
+
+    /// Seconds `ip` is still locked out for, if it is
+    pub fn locked_out(&self, ip: IpAddr) -> Option<u64> {
+        let attempts = self.attempts.lock().unwrap();
+        let locked_until = attempts.get(&ip)?.locked_until?;
+        let remaining = (locked_until - Utc::now()).num_seconds();
+        (remaining > 0).then_some(remaining as u64)
+    }
+
+    /// Record a failed attempt of `ip` at `what`, lock the address out once
+    /// it failed too often, and wait before the caller answers
+    pub async fn failed(&self, ip: IpAddr, what: &str, config: &AuthLockoutConfig) {
+        if config.max_failures == 0 {
+            return;
+        }
+
+        let now = Utc::now();
+        let quiet_since = quiet_since(now, config);
+        let (entry, locked_for, pruned) = {
+            let mut attempts = self.attempts.lock().unwrap();
+            // Addresses quiet for max_lockout_seconds would be forgotten on
+            // their next failure anyway, drop them so a scan from many
+            // addresses does not pile up
+            let before = attempts.len();
+            attempts.retain(|other, entry| *other == ip || entry.last_failure_at >= quiet_since);
+            let pruned = attempts.len() < before;
+
+            let entry = attempts.entry(ip).or_insert_with(|| Attempts::new(now));
+            let locked_for = entry.fail(now, config);
+            (entry.clone(), locked_for, pruned)
+        };
+
+        if pruned {
+            if let Err(e) = self.prune(quiet_since).await {
+                tracing::error!("Failed to prune old failed authentications: {}", e);
+            }
+        }
+
+        if let Err(e) = self.store(ip, &entry).await {
+            tracing::error!("Failed to record failed authentication of {}: {}", ip, e);
+        }
+        match locked_for {
+            Some(seconds) => {
+                tracing::warn!("Locked out {} for {}s after {} failed attempts at {}", ip, seconds, entry.failures, what);
+                let details = format!("Locked out for {}s after {} failed attempts at {}", seconds, entry.failures, what);
+                if let Err(e) = self.audit_log.record(&ip.to_string(), "auth_lockout", what, &details).await {
+                    tracing::error!("Failed to write audit log: {}", e);
+                }
+            }
+            None => tracing::info!("Failed authentication of {} at {} ({} in a row)", ip, what, entry.failures),
+        }
+
+        tokio::time::sleep(failure_delay(entry.failures)).await;
+    }
+
+    /// Forget the failures of `ip` after it authenticated
+    pub async fn succeeded(&self, ip: IpAddr) {
+        if self.attempts.lock().unwrap().remove(&ip).is_none() {
+            return;
+        }
+        let _write = crate::database::queue_write().await;
+        if let Err(e) = failure_entity::Entity::delete_by_id(ip.to_string()).exec(&self.db).await {
+            tracing::error!("Failed to clear failed authentications of {}: {}", ip, e);
+        }
+    }
+
+    async fn prune(&self, quiet_since: DateTime<Utc>) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        failure_entity::Entity::delete_many()
+            .filter(failure_entity::Column::LastFailureAt.lt(quiet_since))
+            .exec(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn store(&self, ip: IpAddr, attempts: &Attempts) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        let existing = failure_entity::Entity::find_by_id(ip.to_string()).one(&self.db).await.map_err(db_error)?;
+        let model = failure_entity::ActiveModel {
+            client_ip: Set(ip.to_string()),
+            failures: Set(attempts.failures as i32),
+            lockouts: Set(attempts.lockouts as i32),
+            locked_until: Set(attempts.locked_until),
+            last_failure_at: Set(attempts.last_failure_at),
+        };
+        match existing {
+            Some(_) => model.update(&self.db).await.map_err(db_error)?,
+            None => model.insert(&self.db).await.map_err(db_error)?,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AuthLockoutConfig {
+        AuthLockoutConfig { max_failures: 3, lockout_seconds: 60, max_lockout_seconds: 200 }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn locks_out_after_max_failures() {
+        let config = config();
+        let mut attempts = Attempts::new(at(0));
+        assert_eq!(attempts.fail(at(0), &config), None);
+        assert_eq!(attempts.fail(at(1), &config), None);
+        assert_eq!(attempts.fail(at(2), &config), Some(60));
+        assert_eq!(attempts.locked_until, Some(at(62)));
+
+        // Failing while locked out neither extends nor restarts the lockout
+        assert_eq!(attempts.fail(at(10), &config), None);
+        assert_eq!(attempts.locked_until, Some(at(62)));
+    }
+
+    #[test]
+    fn doubles_each_lockout_up_to_the_maximum() {
+        let config = config();
+        let mut attempts = Attempts::new(at(0));
+        let mut lockouts = Vec::new();
+        let mut now = 0;
+        for _ in 0..3 {
+            let locked_for = (0..config.max_failures).find_map(|_| {
+                now += 1;
+                attempts.fail(at(now), &config)
+            });
+            lockouts.push(locked_for.unwrap());
+            // The next failure comes once the lockout ended
+            now = (attempts.locked_until.unwrap() - at(0)).num_seconds();
+        }
+        assert_eq!(lockouts, [60, 120, 200]);
+    }
+
+    #[test]
+    fn quiet_period_forgets_lockouts() {
+        let config = config();
+        let mut attempts = Attempts::new(at(0));
+        for second in 0..3 {
+            attempts.fail(at(second), &config);
+        }
+        assert_eq!(attempts.lockouts, 1);
+
+        let later = 2 + config.max_lockout_seconds as i64 + 1;
+        assert_eq!(attempts.fail(at(later), &config), None);
+        assert_eq!((attempts.failures, attempts.lockouts, attempts.locked_until), (1, 0, None));
+        attempts.fail(at(later + 1), &config);
+        assert_eq!(attempts.fail(at(later + 2), &config), Some(60));
+    }
+
+    #[test]
+    fn delay_backs_off_to_the_maximum() {
+        let delays: Vec<u128> = (1..=6).map(|failures| failure_delay(failures).as_millis()).collect();
+        assert_eq!(delays, [250, 500, 1000, 2000, 4000, 4000]);
+        assert_eq!(failure_delay(u32::MAX), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn lockout_survives_a_restart() {
+        let db = crate::database::test_database().await;
+        let audit_log = Arc::new(AuditLog::new(db.clone()));
+        let config = AuthLockoutConfig { max_failures: 2, ..config() };
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let throttle = AuthThrottle::new(db.clone(), audit_log.clone());
+        throttle.failed(ip, "admin token", &config).await;
+        assert_eq!(throttle.locked_out(ip), None);
+        throttle.failed(ip, "admin token", &config).await;
+        throttle.failed(other, "admin token", &config).await;
+        assert!(throttle.locked_out(ip).is_some_and(|seconds| seconds > 50));
+
+        let restarted = AuthThrottle::new(db.clone(), audit_log);
+        restarted.load().await.unwrap();
+        assert!(restarted.locked_out(ip).is_some());
+        assert_eq!(restarted.locked_out(other), None);
+
+        // Authenticating forgets the failures, also across restarts
+        restarted.succeeded(other).await;
+        let reloaded = AuthThrottle::new(db.clone(), Arc::new(AuditLog::new(db)));
+        reloaded.load().await.unwrap();
+        assert!(reloaded.attempts.lock().unwrap().get(&other).is_none());
+    }
+}
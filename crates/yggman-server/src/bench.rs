@@ -0,0 +1,357 @@
+//! `yggman bench`: time config generation for a synthetic mesh, so its
+//! latency can be tracked across changes without a database or agents.
+//! With `--server`, connect simulated agents to a running server instead
+//! and measure how long registrations take, how long an address change
+//! takes to reach every peer, and how many of those updates never arrive.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use ed25519_dalek::VerifyingKey;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::demo::{frame, simulated_address};
+use crate::modules::websocket::{AgentMessage, ServerMessage, PROTOCOL_VERSION};
+use crate::redact::Secret;
+use crate::yggdrasil::Node;
+
+/// The server counts as done with a burst of updates after this long
+/// without a message to any agent
+const QUIET_PERIOD: Duration = Duration::from_secs(2);
+
+pub fn run(node_count: usize, addresses: usize, iterations: usize) {
+    let nodes: Vec<Node> = (0..node_count).map(|i| synthetic_node(i, addresses)).collect();
+    let iterations = iterations.max(1);
+    
+    // One untimed run so allocation warm-up does not skew the first sample
+    let configs = crate::node_manager::generate_network_configs(&nodes);
+    let peers = configs.values().map(|config| config.peers.len()).max().unwrap_or(0);
+    
+    let mut samples: Vec<Duration> = (0..iterations)
+        .map(|_| {
+            let started = std::time::Instant::now();
+            let configs = crate::node_manager::generate_network_configs(&nodes);
+            let elapsed = started.elapsed();
+            drop(configs);
+            elapsed
+        })
+        .collect();
+    
+    println!(
+        "generate_configs: {} nodes, {} addresses each, {} peers per config, {} threads",
+        node_count,
+        addresses,
+        peers,
+        std::thread::available_parallelism().map_or(1, |n| n.get()),
+    );
+    println!("{} iterations: {}", iterations, summarize(&mut samples));
+}
+
+/// Min, median, p95 and max of `samples`
+fn summarize(samples: &mut [Duration]) -> String {
+    if samples.is_empty() {
+        return "no samples".to_string();
+    }
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_secs_f64() * 1000.0;
+    format!(
+        "min {:.2} ms, median {:.2} ms, p95 {:.2} ms, max {:.2} ms",
+        percentile(0),
+        percentile(50),
+        percentile(95),
+        percentile(100),
+    )
+}
+
+fn synthetic_node(index: usize, addresses: usize) -> Node {
+    let public_key = hex::encode(rand::random::<[u8; 32]>());
+    Node {
+        id: format!("node-{:08x}", index),
+        name: format!("bench-{}", index),
+        private_key: format!("{}{}", hex::encode(rand::random::<[u8; 32]>()), public_key).into(),
+        public_key,
+        listen: vec!["tcp://0.0.0.0:9001".to_string(), "tls://[::]:9002".to_string()],
+        listen_policy: Default::default(),
+        addresses: (0..addresses)
+            .map(|a| format!("10.{}.{}.{}", index / 256 % 256, index % 256, a + 1))
+            .collect(),
+        external_peers: Vec::new(),
+        network: crate::network_manager::DEFAULT_NETWORK.to_string(),
+        tags: Vec::new(),
+        resolved_listen: Vec::new(),
+        mtu: None,
+        node_info: HashMap::new(),
+        if_name: None,
+        interface_peers: HashMap::new(),
+        extra_allowed_keys: Vec::new(),
+        extra_config: HashMap::new(),
+        agent_version: None,
+        yggdrasil_version: None,
+        description: None,
+        owner: None,
+        contact: None,
+        external_id: None,
+        provider: None,
+    }
+}
+
+enum Event {
+    /// First config after connecting
+    Registered { latency: Duration },
+    /// Any config or update, after the agent took over its peers
+    Received { agent: usize, at: Instant },
+    Failed { agent: usize, error: String },
+}
+
+/// The coordinator's handle on a simulated agent
+struct BenchAgent {
+    address: String,
+    peers: Arc<Mutex<Vec<String>>>,
+    readdress: mpsc::UnboundedSender<String>,
+}
+
+impl BenchAgent {
+    fn peers_with(&self, address: &str) -> bool {
+        let host = format!("//{}:", address);
+        self.peers.lock().unwrap().iter().any(|peer| peer.contains(&host))
+    }
+}
+
+/// Messages received and agents lost while waiting for the server to go quiet
+#[derive(Default)]
+struct Tally {
+    messages: u64,
+    failures: Vec<(usize, String)>,
+}
+
+impl Tally {
+    fn count(&mut self, event: &Event) {
+        match event {
+            Event::Received { .. } => self.messages += 1,
+            Event::Failed { agent, error } => self.failures.push((*agent, error.clone())),
+            Event::Registered { .. } => {}
+        }
+    }
+
+    /// Take events until none came for `QUIET_PERIOD`, or `limit` passed
+    async fn settle(&mut self, events: &mut mpsc::UnboundedReceiver<Event>, limit: Duration) -> Duration {
+        let started = Instant::now();
+        while started.elapsed() < limit {
+            match tokio::time::timeout(QUIET_PERIOD, events.recv()).await {
+                Ok(Some(event)) => self.count(&event),
+                _ => break,
+            }
+        }
+        started.elapsed()
+    }
+}
+
+pub async fn run_protocol(
+    server: &str,
+    server_key: Option<VerifyingKey>,
+    enrollment_token: Option<Secret<String>>,
+    node_count: usize,
+    rounds: usize,
+    timeout: Duration,
+) -> Result<()> {
+    println!("agent protocol: {} agents against {}", node_count, server);
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let mut agents = Vec::with_capacity(node_count);
+    let started = Instant::now();
+    for index in 0..node_count {
+        let (readdress, readdress_rx) = mpsc::unbounded_channel();
+        let agent = BenchAgent { address: simulated_address(index), peers: Arc::default(), readdress };
+        let register = register_message(index, &agent.address, enrollment_token.as_ref());
+        tokio::spawn(run_agent(index, server.to_string(), register, server_key, agent.peers.clone(), events_tx.clone(), readdress_rx));
+        agents.push(agent);
+    }
+
+    let mut tally = Tally::default();
+    let mut latencies = Vec::with_capacity(node_count);
+    while latencies.len() + tally.failures.len() < node_count {
+        match tokio::time::timeout_at(started + timeout, events.recv()).await {
+            Ok(Some(Event::Registered { latency })) => latencies.push(latency),
+            Ok(Some(event)) => tally.count(&event),
+            _ => break,
+        }
+    }
+    let registered = latencies.len();
+    println!(
+        "registration: {} of {} agents in {:.1} s, {}",
+        registered,
+        node_count,
+        started.elapsed().as_secs_f64(),
+        summarize(&mut latencies),
+    );
+    let settled = tally.settle(&mut events, timeout).await;
+    println!(
+        "{} configs and updates during registration, quiet {:.1} s after the last registration",
+        tally.messages,
+        settled.saturating_sub(QUIET_PERIOD).as_secs_f64(),
+    );
+    if registered == 0 {
+        bail!("no agent registered: {}", tally.failures.first().map_or("timed out", |(_, error)| error.as_str()));
+    }
+
+    let mut fan_out = Vec::with_capacity(rounds);
+    let (mut expected_total, mut lost_total) = (0, 0);
+    for round in 0..rounds {
+        let changer = round % node_count;
+        let old = std::mem::replace(&mut agents[changer].address, simulated_address(node_count + round));
+        let new = agents[changer].address.clone();
+        let mut pending: HashSet<usize> = agents.iter().enumerate()
+            .filter(|(index, agent)| *index != changer && agent.peers_with(&old))
+            .map(|(index, _)| index)
+            .collect();
+        let expected = pending.len();
+
+        let sent = Instant::now();
+        let _ = agents[changer].readdress.send(new.clone());
+        let (mut first, mut last) = (None, Duration::ZERO);
+        while !pending.is_empty() {
+            match tokio::time::timeout_at(sent + timeout, events.recv()).await {
+                Ok(Some(event)) => {
+                    tally.count(&event);
+                    if let Event::Received { agent, at } = event {
+                        if pending.contains(&agent) && agents[agent].peers_with(&new) {
+                            pending.remove(&agent);
+                            first.get_or_insert(at - sent);
+                            last = at - sent;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+        println!(
+            "round {}: bench-{} moved to {}, {} of {} peers updated, first after {:.2} ms, last after {:.2} ms",
+            round + 1,
+            changer + 1,
+            new,
+            expected - pending.len(),
+            expected,
+            first.unwrap_or_default().as_secs_f64() * 1000.0,
+            last.as_secs_f64() * 1000.0,
+        );
+        if expected > pending.len() {
+            fan_out.push(last);
+        }
+        expected_total += expected;
+        lost_total += pending.len();
+        tally.settle(&mut events, timeout).await;
+    }
+
+    if rounds > 0 {
+        println!("fan-out to the last peer: {}", summarize(&mut fan_out));
+        println!("lost updates: {} of {}", lost_total, expected_total);
+    }
+    println!("{} configs and updates received in total", tally.messages);
+    if let Some((agent, error)) = tally.failures.first() {
+        println!("{} agents failed, bench-{} first: {}", tally.failures.len(), agent + 1, error);
+    }
+    Ok(())
+}
+
+fn register_message(index: usize, address: &str, enrollment_token: Option<&Secret<String>>) -> AgentMessage {
+    AgentMessage::Register {
+        name: format!("bench-{}", index + 1),
+        addresses: vec![address.to_string()],
+        network: None,
+        tags: vec!["bench".to_string()],
+        agent_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        yggdrasil_version: None,
+        protocol_version: PROTOCOL_VERSION,
+        target: Some("bench".to_string()),
+        dry_run: false,
+        enrollment_token: enrollment_token.cloned(),
+        agent_token: None,
+        // Servers requiring sealed keys take any key; the bench never opens them
+        key_agreement_key: Some(crate::sealing::KeyOpener::generate().public_key()),
+        nonce: None,
+    }
+}
+
+async fn run_agent(
+    index: usize,
+    url: String,
+    register: AgentMessage,
+    server_key: Option<VerifyingKey>,
+    peers: Arc<Mutex<Vec<String>>>,
+    events: mpsc::UnboundedSender<Event>,
+    readdress: mpsc::UnboundedReceiver<String>,
+) {
+    if let Err(e) = agent_session(index, &url, register, server_key, &peers, &events, readdress).await {
+        let _ = events.send(Event::Failed { agent: index, error: e.to_string() });
+    }
+}
+
+async fn agent_session(
+    index: usize,
+    url: &str,
+    register: AgentMessage,
+    server_key: Option<VerifyingKey>,
+    peers: &Mutex<Vec<String>>,
+    events: &mpsc::UnboundedSender<Event>,
+    mut readdress: mpsc::UnboundedReceiver<String>,
+) -> Result<()> {
+    let connecting = Instant::now();
+    let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let (mut write, mut read) = socket.split();
+    write.send(frame(&register)?).await?;
+
+    let mut registered = false;
+    let mut heartbeat: Option<tokio::time::Interval> = None;
+    loop {
+        tokio::select! {
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => bail!("connection closed by the server"),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+                let value: serde_json::Value = serde_json::from_str(&text)?;
+                if let Some(key) = &server_key {
+                    crate::signing::verify_message(key, &value)?;
+                }
+                let (revision, received_peers) = match serde_json::from_value(value)? {
+                    ServerMessage::Config { revision, peers, timing, .. } => {
+                        if let Some(timing) = timing {
+                            let period = Duration::from_secs(timing.heartbeat_interval.max(1));
+                            heartbeat = Some(tokio::time::interval_at(Instant::now() + period, period));
+                        }
+                        (revision, peers)
+                    }
+                    ServerMessage::Update { revision, peers, .. } => (revision, peers),
+                    ServerMessage::Timing { timing } => {
+                        let period = Duration::from_secs(timing.heartbeat_interval.max(1));
+                        heartbeat = Some(tokio::time::interval_at(Instant::now() + period, period));
+                        continue;
+                    }
+                    ServerMessage::UpdateAvailable { .. } => continue,
+                    ServerMessage::Error { message, .. } => bail!("server error: {}", message),
+                };
+                *peers.lock().unwrap() = received_peers;
+
+                let at = Instant::now();
+                if !registered {
+                    registered = true;
+                    let _ = events.send(Event::Registered { latency: at - connecting });
+                }
+                let _ = events.send(Event::Received { agent: index, at });
+                write.send(frame(&AgentMessage::ConfigApplied { revision, success: true, error: None })?).await?;
+            }
+            Some(address) = readdress.recv() => {
+                write.send(frame(&AgentMessage::UpdateAddresses { addresses: vec![address] })?).await?;
+            }
+            _ = async { heartbeat.as_mut().unwrap().tick().await }, if heartbeat.is_some() => {
+                write.send(frame(&AgentMessage::Heartbeat)?).await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::error::AppError;
+use crate::event_log::{EventLog, EventSeverity};
+use crate::node_manager::NodeManager;
+use crate::websocket_state;
+
+/// Broadcasts kept for inspection through the API
+const MAX_BROADCAST_HISTORY: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RolloutStrategy {
+    /// Push to every connected agent at once
+    #[default]
+    All,
+    /// Push to a canary subset first and to the rest only once the
+    /// canaries applied the config and stayed connected
+    Canary,
+}
+
+/// Canary selection and health criteria. Canaries are the listed nodes
+/// (ids or names) plus nodes carrying `tag`; without either, `count`
+/// connected nodes are picked, 10% of them by default.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CanaryOptions {
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub count: Option<usize>,
+    /// How long canaries get to acknowledge the config
+    #[serde(default = "default_ack_timeout")]
+    pub ack_timeout_secs: u64,
+    /// How long canaries must stay connected after applying it
+    #[serde(default = "default_settle")]
+    pub settle_secs: u64,
+}
+
+impl Default for CanaryOptions {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            tag: None,
+            count: None,
+            ack_timeout_secs: default_ack_timeout(),
+            settle_secs: default_settle(),
+        }
+    }
+}
+
+fn default_ack_timeout() -> u64 {
+    60
+}
+
+fn default_settle() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastStatus {
+    Running,
+    Completed,
+    Halted,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Broadcast {
+    pub id: String,
+    pub strategy: RolloutStrategy,
+    pub revision: u64,
+    pub status: BroadcastStatus,
+    pub canary_nodes: Vec<String>,
+    /// Node id to failure reason
+    pub failures: HashMap<String, String>,
+    pub message: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Explicit, optionally staged, pushes of the current configuration
+pub struct BroadcastManager {
+    node_manager: Arc<NodeManager>,
+    event_log: Arc<EventLog>,
+    broadcasts: RwLock<Vec<Broadcast>>,
+    // Newest revision the rollback watchdog acted on
+    rolled_back_revision: RwLock<u64>,
+}
+
+impl BroadcastManager {
+    pub fn new(node_manager: Arc<NodeManager>, event_log: Arc<EventLog>) -> Self {
+        Self {
+            node_manager,
+            event_log,
+            broadcasts: RwLock::new(Vec::new()),
+            rolled_back_revision: RwLock::new(0),
+        }
+    }
+    
+    /// Start a broadcast of the current configuration. Canary rollouts
+    /// continue in the background; poll `get_broadcast` for the outcome.
+    pub async fn start(self: &Arc<Self>, strategy: RolloutStrategy, canary: CanaryOptions) -> Result<Broadcast, AppError> {
+        let id = format!("broadcast-{}", uuid::Uuid::new_v4().simple());
+        
+        if strategy == RolloutStrategy::All {
+            let (_, revision) = websocket_state::broadcast_new_revision(&self.node_manager, None, false).await;
+            let broadcast = Broadcast {
+                id,
+                strategy,
+                revision,
+                status: BroadcastStatus::Completed,
+                canary_nodes: Vec::new(),
+                failures: HashMap::new(),
+                message: None,
+                created_at: chrono::Utc::now(),
+                finished_at: Some(chrono::Utc::now()),
+            };
+            self.store(broadcast.clone()).await;
+            return Ok(broadcast);
+        }
+        
+        let canaries = self.select_canaries(&canary).await;
+        if canaries.is_empty() {
+            return Err(AppError::Config("No connected agents match the canary selection".to_string()));
+        }
+        if !websocket_state::hold_automatic_broadcasts() {
+            return Err(AppError::Config("Another canary rollout is running".to_string()));
+        }
+        
+        // Canaries are asked to prove the config, so maintenance windows do not hold them back
+        let (_, revision) = websocket_state::broadcast_new_revision(&self.node_manager, Some(&canaries), true).await;
+        
+        let mut canary_nodes: Vec<String> = canaries.iter().cloned().collect();
+        canary_nodes.sort();
+        let broadcast = Broadcast {
+            id: id.clone(),
+            strategy,
+            revision,
+            status: BroadcastStatus::Running,
+            canary_nodes,
+            failures: HashMap::new(),
+            message: Some(format!("Waiting for {} canaries", canaries.len())),
+            created_at: chrono::Utc::now(),
+            finished_at: None,
+        };
+        self.store(broadcast.clone()).await;
+        
+        tracing::info!("Started canary broadcast {} of revision {} with {} canaries", id, revision, canaries.len());
+        
+        let manager = self.clone();
+        tokio::spawn(async move {
+            manager.finish_canary_rollout(id, revision, canaries, canary).await;
+        });
+        
+        Ok(broadcast)
+    }
+    
+    pub async fn get_broadcasts(&self) -> Vec<Broadcast> {
+        self.broadcasts.read().await.clone()
+    }
+    
+    pub async fn get_broadcast(&self, id: &str) -> Option<Broadcast> {
+        self.broadcasts.read().await.iter().find(|b| b.id == id).cloned()
+    }
+    
+    async fn select_canaries(&self, options: &CanaryOptions) -> HashSet<String> {
+        let connected = websocket_state::get_connected_node_ids().await;
+        let mut nodes: Vec<_> = self.node_manager.get_all_nodes().await
+            .into_iter()
+            .filter(|n| connected.contains(&n.id))
+            .collect();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        
+        if !options.nodes.is_empty() || options.tag.is_some() {
+            return nodes.into_iter()
+                .filter(|n| {
+                    options.nodes.iter().any(|wanted| *wanted == n.id || *wanted == n.name)
+                        || options.tag.as_ref().is_some_and(|tag| n.tags.contains(tag))
+                })
+                .map(|n| n.id)
+                .collect();
+        }
+        
+        let count = options.count.unwrap_or(nodes.len().div_ceil(10)).max(1);
+        nodes.into_iter().take(count).map(|n| n.id).collect()
+    }
+    
+    /// Wait for the canaries to apply the revision and stay connected, then
+    /// push the current configs to everyone else, or halt. Automatic
+    /// broadcasts are held meanwhile; when one was, the rest of the mesh
+    /// and the canaries get its changes under a new revision.
+    async fn finish_canary_rollout(
+        &self,
+        id: String,
+        revision: u64,
+        canaries: HashSet<String>,
+        options: CanaryOptions,
+    ) {
+        let mut failures = self.wait_for_acks(&canaries, revision, Duration::from_secs(options.ack_timeout_secs)).await;
+        
+        if failures.is_empty() {
+            tokio::time::sleep(Duration::from_secs(options.settle_secs)).await;
+            failures = self.check_canary_health(&canaries).await;
+        }
+        
+        let held = websocket_state::release_automatic_broadcasts();
+        if !failures.is_empty() {
+            let mut message = format!("Broadcast {} of revision {} halted: {} of {} canaries failed", id, revision, failures.len(), canaries.len());
+            if held {
+                message.push_str("; changes made during the rollout wait for the next broadcast");
+            }
+            self.event_log.emit("broadcast_halted", EventSeverity::Alert, None, &message).await;
+            self.update(&id, |b| {
+                b.status = BroadcastStatus::Halted;
+                b.message = Some(format!("{} of {} canaries failed, rollout halted", failures.len(), canaries.len()));
+                b.failures = failures;
+                b.finished_at = Some(chrono::Utc::now());
+            }).await;
+            return;
+        }
+        
+        let rest: HashSet<String> = websocket_state::get_connected_node_ids().await
+            .into_iter()
+            .filter(|node_id| !canaries.contains(node_id))
+            .collect();
+        // Nodes may have registered during the wait, so the configs are
+        // generated again rather than taken from the canaries' revision
+        let revision = if held {
+            websocket_state::broadcast_new_revision(&self.node_manager, None, false).await.1
+        } else {
+            let configs = self.node_manager.generate_configs().await;
+            let revision = self.node_manager.current_revision().await;
+            websocket_state::push_configuration(&self.node_manager, &configs, revision, &rest, false).await;
+            revision
+        };
+        
+        tracing::info!("Broadcast {} passed its canaries, pushed revision {} to {} more agents", id, revision, rest.len());
+        self.update(&id, |b| {
+            b.status = BroadcastStatus::Completed;
+            b.message = Some(format!("Canaries healthy, rolled out to {} more agents", rest.len()));
+            b.finished_at = Some(chrono::Utc::now());
+        }).await;
+    }
+    
+    /// Watch freshly pushed revisions and roll back the newest one when too
+    /// many of the agents that received it go offline. Runs forever.
+    pub async fn run_rollback_watchdog(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(10));
+        loop {
+            interval.tick().await;
+            
+            let policy = match self.node_manager.settings().get_rollback_policy().await {
+                Ok(policy) if policy.enabled => policy,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::error!("Failed to read rollback policy: {}", e);
+                    continue;
+                }
+            };
+            
+            let history = websocket_state::get_revision_history().await;
+            let Some(latest) = history.first() else {
+                continue;
+            };
+            if latest.rollback
+                || latest.revision <= *self.rolled_back_revision.read().await
+                || latest.pushed_at.elapsed() > Duration::from_secs(policy.window_minutes * 60)
+                || latest.delivered_to.len() < policy.min_agents.max(1)
+            {
+                continue;
+            }
+            
+            let connected = websocket_state::get_connected_node_ids().await;
+            let offline = latest.delivered_to.iter().filter(|id| !connected.contains(*id)).count();
+            let offline_percent = offline * 100 / latest.delivered_to.len();
+            if offline_percent <= policy.offline_threshold_percent as usize {
+                continue;
+            }
+            
+            *self.rolled_back_revision.write().await = latest.revision;
+            
+            let Some(previous) = history.get(1) else {
+                let message = format!(
+                    "{}% of agents went offline after revision {}, but there is no earlier revision to roll back to",
+                    offline_percent, latest.revision
+                );
+                self.event_log.emit("rollback_failed", EventSeverity::Alert, None, &message).await;
+                continue;
+            };
+            
+            let revision = websocket_state::push_rollback(&self.node_manager, &previous.configs).await;
+            
+            // Keep the next change from pushing the bad state again
+            if let Err(e) = self.node_manager.settings().set_auto_broadcast(false).await {
+                tracing::error!("Failed to pause automatic broadcasts after rollback: {}", e);
+            }
+            
+            let message = format!(
+                "{} of {} agents went offline within {} minutes of revision {}; re-broadcast the config of revision {} as revision {} and paused automatic broadcasts",
+                offline, latest.delivered_to.len(), policy.window_minutes, latest.revision, previous.revision, revision
+            );
+            self.event_log.emit("rollback", EventSeverity::Alert, None, &message).await;
+        }
+    }
+    
+    async fn wait_for_acks(&self, canaries: &HashSet<String>, revision: u64, timeout: Duration) -> HashMap<String, String> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut pending: HashSet<String> = canaries.clone();
+        let mut failures = HashMap::new();
+        
+        while !pending.is_empty() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            
+            let connected = websocket_state::get_connected_node_ids().await;
+            for node_id in pending.clone() {
+                if let Some(ack) = websocket_state::get_config_ack(&node_id).await.filter(|a| a.revision >= revision) {
+                    pending.remove(&node_id);
+                    if !ack.success {
+                        failures.insert(node_id, ack.error.unwrap_or_else(|| "Failed to apply config".to_string()));
+                    }
+                } else if !connected.contains(&node_id) {
+                    pending.remove(&node_id);
+                    failures.insert(node_id, "Disconnected before acknowledging the config".to_string());
+                }
+            }
+        }
+        
+        for node_id in pending {
+            failures.insert(node_id, format!("No acknowledgement within {}s", timeout.as_secs()));
+        }
+        
+        failures
+    }
+    
+    async fn check_canary_health(&self, canaries: &HashSet<String>) -> HashMap<String, String> {
+        let connected = websocket_state::get_connected_node_ids().await;
+        let mut failures = HashMap::new();
+        
+        for node_id in canaries {
+            if !connected.contains(node_id) {
+                failures.insert(node_id.clone(), "Disconnected after applying the config".to_string());
+            } else if let Some(ack) = websocket_state::get_config_ack(node_id).await.filter(|a| !a.success) {
+                failures.insert(node_id.clone(), ack.error.unwrap_or_else(|| "Failed to apply config".to_string()));
+            }
+        }
+        
+        failures
+    }
+    
+    async fn store(&self, broadcast: Broadcast) {
+        let mut broadcasts = self.broadcasts.write().await;
+        broadcasts.insert(0, broadcast);
+        broadcasts.truncate(MAX_BROADCAST_HISTORY);
+    }
+    
+    async fn update(&self, id: &str, apply: impl FnOnce(&mut Broadcast)) {
+        if let Some(broadcast) = self.broadcasts.write().await.iter_mut().find(|b| b.id == id) {
+            apply(broadcast);
+        }
+    }
+}
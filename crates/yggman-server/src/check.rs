@@ -0,0 +1,438 @@
+//! `yggman check`: validate the merged configuration before a deploy, with
+//! an exit code pipelines can gate on.
+
+use std::path::Path;
+
+use crate::cli::{CliArgs, EnvConfig};
+use crate::config::{AlertCondition, AppConfig, ConfigManager, StaleNodeAction, StorageBackend};
+
+/// Configuration is valid, possibly with warnings
+pub const EXIT_OK: i32 = 0;
+/// Configuration has errors
+pub const EXIT_INVALID: i32 = 1;
+/// Configuration file is missing or cannot be parsed
+pub const EXIT_UNREADABLE: i32 = 2;
+/// Configuration is valid but the database cannot be reached
+pub const EXIT_DATABASE: i32 = 3;
+
+const LISTEN_SCHEMES: &[&str] = &["tcp", "tls", "quic", "ws", "wss", "unix", "socks", "sockstls"];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+struct Finding {
+    severity: Severity,
+    key: String,
+    message: String,
+}
+
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn error(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Finding { severity: Severity::Error, key: key.into(), message: message.into() });
+    }
+
+    fn warning(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Finding { severity: Severity::Warning, key: key.into(), message: message.into() });
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.0.iter().filter(|f| f.severity == severity).count()
+    }
+}
+
+/// Validate the configuration `cli_args` point at, print the findings and
+/// return the process exit code
+pub async fn run(cli_args: &CliArgs, env_config: &EnvConfig, connect: bool) -> i32 {
+    if !Path::new(&cli_args.config).exists() {
+        println!("error: config file {} not found", cli_args.config);
+        return EXIT_UNREADABLE;
+    }
+
+    let config = match ConfigManager::load_merged_config(cli_args, env_config) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("error: {}: {}", cli_args.config, e);
+            return EXIT_UNREADABLE;
+        }
+    };
+
+    let mut findings = check_config(&config);
+    let file = std::fs::read_to_string(&cli_args.config).ok()
+        .and_then(|content| toml::from_str::<toml::Value>(&content).ok());
+    for unknown in file.iter().flat_map(crate::config::schema::unknown_keys) {
+        let message = match &unknown.suggestion {
+            Some(suggestion) => format!("unknown key, did you mean {}?", suggestion),
+            None => "unknown key".to_string(),
+        };
+        findings.warning(unknown.path, message);
+    }
+    for finding in &findings.0 {
+        let label = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{}: {}: {}", label, finding.key, finding.message);
+    }
+
+    let errors = findings.count(Severity::Error);
+    if errors > 0 {
+        println!("{}: {} errors, {} warnings", cli_args.config, errors, findings.count(Severity::Warning));
+        return EXIT_INVALID;
+    }
+
+    if connect && !config.database.ephemeral {
+        if let Err(message) = check_database(&config).await {
+            println!("error: database.url: {}", message);
+            return EXIT_DATABASE;
+        }
+        println!("database: connected");
+    }
+
+    println!("{}: OK ({} warnings)", cli_args.config, findings.count(Severity::Warning));
+    EXIT_OK
+}
+
+fn check_config(config: &AppConfig) -> Findings {
+    let mut findings = Findings::default();
+
+    let server = &config.server;
+    if server.bind_address.parse::<std::net::IpAddr>().is_err() {
+        findings.error("server.bind_address", format!("\"{}\" is not an IP address", server.bind_address));
+    }
+    if server.port == 0 {
+        findings.error("server.port", "must be between 1 and 65535");
+    }
+    if server.workers == 0 {
+        findings.error("server.workers", "must be at least 1");
+    }
+    if server.max_blocking_threads == 0 {
+        findings.error("server.max_blocking_threads", "must be at least 1");
+    }
+    if server.admin_token.as_ref().is_some_and(|token| token.expose().len() < 16) {
+        findings.warning("server.admin_token", "shorter than 16 characters, easy to guess");
+    }
+    if server.observer_token.as_ref().is_some_and(|token| token.expose().len() < 16) {
+        findings.warning("server.observer_token", "shorter than 16 characters, easy to guess");
+    }
+    if let Some(key_file) = &server.signing_key_file {
+        check_existing_or_creatable(&mut findings, "server.signing_key_file", key_file);
+    }
+    for proxy in &server.trusted_proxies {
+        if let Err(e) = crate::modules::client_ip::IpNetwork::parse(proxy) {
+            findings.error("server.trusted_proxies", e);
+        }
+    }
+    if server.auth_lockout.max_failures > 0 && server.auth_lockout.lockout_seconds == 0 {
+        findings.error("server.auth_lockout.lockout_seconds", "must be at least 1 second while lockouts are enabled");
+    }
+    if server.auth_lockout.lockout_seconds > server.auth_lockout.max_lockout_seconds {
+        findings.warning("server.auth_lockout.lockout_seconds", "longer than max_lockout_seconds, which caps it");
+    }
+    if let Some(socket) = &server.unix_socket {
+        check_parent_dir(&mut findings, "server.unix_socket", socket);
+    }
+    if u32::from_str_radix(&server.unix_socket_mode, 8).map_or(true, |mode| mode > 0o777) {
+        findings.error("server.unix_socket_mode", format!("\"{}\" is not an octal file mode like 660", server.unix_socket_mode));
+    }
+    let tls = &server.tls;
+    if tls.enabled && tls.acme.enabled {
+        if tls.acme.domains.is_empty() {
+            findings.error("server.tls.acme.domains", "at least one domain is required");
+        }
+        for domain in &tls.acme.domains {
+            if !domain.split('.').all(is_dns_label) {
+                findings.error("server.tls.acme.domains", format!("\"{}\" is not a valid domain name", domain));
+            }
+        }
+        if tls.acme.contact.is_empty() {
+            findings.warning("server.tls.acme.contact", "no address to receive certificate expiry notices");
+        }
+        if server.port != 443 {
+            findings.warning("server.port", "ACME validates domains by connecting to port 443, forward it to this port");
+        }
+        check_parent_dir(&mut findings, "server.tls.acme.cache_dir", &tls.acme.cache_dir);
+    } else if tls.enabled {
+        for (key, file) in [("server.tls.cert_file", &tls.cert_file), ("server.tls.key_file", &tls.key_file)] {
+            match file {
+                Some(path) => {
+                    if let Err(e) = std::fs::read(path) {
+                        findings.error(key, format!("{} is not readable: {}", path, e));
+                    }
+                }
+                None => findings.error(key, "required when server.tls is enabled without acme"),
+            }
+        }
+    } else if tls.acme.enabled {
+        findings.warning("server.tls.acme.enabled", "ignored while server.tls.enabled is false");
+    }
+
+    let database = &config.database;
+    if database.ephemeral {
+        if let Some(snapshot_file) = &database.snapshot_file {
+            check_parent_dir(&mut findings, "database.snapshot_file", snapshot_file);
+        }
+    } else {
+        let supported = ["sqlite:", "postgres://", "postgresql://"];
+        if !supported.iter().any(|prefix| database.url.expose().starts_with(prefix)) {
+            findings.error("database.url", format!("unsupported database \"{}\", expected sqlite:// or postgres://", database.url));
+        }
+        if database.snapshot_file.is_some() {
+            findings.warning("database.snapshot_file", "only used in ephemeral mode");
+        }
+    }
+    if database.url.expose().starts_with("sqlite:") && !database.ephemeral && database.sqlite_busy_timeout == 0 {
+        findings.warning("database.sqlite_busy_timeout", "0 fails writes immediately while another connection holds the lock");
+    }
+    if database.max_connections == 0 {
+        findings.error("database.max_connections", "must be at least 1");
+    }
+
+    for (i, endpoint) in config.nodes.default_listen_endpoints.iter().enumerate() {
+        if let Err(message) = check_listen_uri(endpoint) {
+            findings.error(format!("nodes.default_listen_endpoints[{}]", i), message);
+        }
+    }
+    if config.nodes.max_peers_per_node == 0 {
+        findings.warning("nodes.max_peers_per_node", "0 leaves nodes without mesh peers");
+    }
+
+    let public_peers = &config.public_peers;
+    if public_peers.enabled {
+        if !public_peers.url.starts_with("http://") && !public_peers.url.starts_with("https://") {
+            findings.error("public_peers.url", format!("\"{}\" is not an http(s) URL", public_peers.url));
+        }
+        if public_peers.refresh_interval == 0 {
+            findings.error("public_peers.refresh_interval", "must be at least 1 second");
+        }
+    }
+
+    let dns = &config.dns;
+    let domain = dns.domain.trim_end_matches('.');
+    if domain.is_empty() || !domain.split('.').all(is_dns_label) {
+        findings.error("dns.domain", format!("\"{}\" is not a valid domain name", dns.domain));
+    }
+    if let Some(zone_file) = &dns.zone_file {
+        check_parent_dir(&mut findings, "dns.zone_file", zone_file);
+        if dns.ttl == 0 {
+            findings.warning("dns.ttl", "0 disables caching of node records");
+        }
+    }
+
+    if config.agent.heartbeat_interval == 0 {
+        findings.error("agent.heartbeat_interval", "must be at least 1 second");
+    }
+    if config.agent.offline_threshold() <= config.agent.heartbeat_interval {
+        findings.error("agent.offline_threshold", format!(
+            "must be longer than agent.heartbeat_interval ({}s)", config.agent.heartbeat_interval
+        ));
+    }
+    if config.agent.address_scan_interval == 0 {
+        findings.error("agent.address_scan_interval", "must be at least 1 second");
+    }
+    if config.agent.ping_interval > 0 && config.agent.pong_timeout == 0 {
+        findings.error("agent.pong_timeout", "must be at least 1 second while pings are enabled");
+    }
+    for network in &config.agent.allowed_ips {
+        if let Err(e) = crate::modules::client_ip::IpNetwork::parse(network) {
+            findings.error("agent.allowed_ips", e);
+        }
+    }
+    if let Some(min_version) = &config.agent.min_version {
+        if let Err(e) = semver::Version::parse(min_version) {
+            findings.error("agent.min_version", format!("\"{}\" is not a semantic version: {}", min_version, e));
+        }
+    }
+    if let Some(update_version) = &config.agent.update_version {
+        if let Err(e) = semver::Version::parse(update_version) {
+            findings.error("agent.update_version", format!("\"{}\" is not a semantic version: {}", update_version, e));
+        }
+        match &config.agent.update_url {
+            Some(url) if url.starts_with("https://") || url.starts_with("http://") => {}
+            Some(url) => findings.error("agent.update_url", format!("\"{}\" is not an http(s) URL", url)),
+            None => findings.error("agent.update_url", "required when agent.update_version is set"),
+        }
+        if config.agent.update_sha256.is_empty() {
+            findings.warning("agent.update_sha256", "no targets listed, no agent will be offered the update");
+        }
+    }
+    for (target, sha256) in &config.agent.update_sha256 {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            findings.error(format!("agent.update_sha256.{}", target), "must be 64 hex characters");
+        }
+    }
+    if config.agent.min_protocol_version > crate::modules::websocket::PROTOCOL_VERSION {
+        findings.error(
+            "agent.min_protocol_version",
+            format!("this server speaks protocol {}, no agent could register", crate::modules::websocket::PROTOCOL_VERSION),
+        );
+    }
+
+    let traffic = &config.traffic;
+    if traffic.bucket_seconds == 0 || 3600 % traffic.bucket_seconds != 0 {
+        findings.error("traffic.bucket_seconds", "must divide an hour, e.g. 60, 300 or 900");
+    }
+    if traffic.retention_days == 0 {
+        findings.error("traffic.retention_days", "must be at least 1 day");
+    } else if traffic.detail_retention_hours > traffic.retention_days * 24 {
+        findings.warning("traffic.detail_retention_hours", "longer than traffic.retention_days, detailed buckets are deleted first");
+    }
+
+    if config.alerts.evaluation_interval == 0 {
+        findings.error("alerts.evaluation_interval", "must be at least 1 second");
+    }
+    let mut rule_names = std::collections::HashSet::new();
+    for (i, rule) in config.alerts.rules.iter().enumerate() {
+        let key = format!("alerts.rules[{}]", i);
+        if rule.name.is_empty() {
+            findings.error(format!("{}.name", key), "must not be empty");
+        } else if !rule_names.insert(rule.name.as_str()) {
+            findings.error(format!("{}.name", key), format!("duplicate rule name \"{}\"", rule.name));
+        }
+        match rule.condition {
+            AlertCondition::NodeOffline { minutes: 0 } => findings.warning(format!("{}.minutes", key), "0 fires on every disconnect"),
+            AlertCondition::PeerCountBelow { min_peers: 0 } => findings.warning(format!("{}.min_peers", key), "0 never fires"),
+            _ => {}
+        }
+    }
+    for (i, url) in config.notifications.webhooks.iter().enumerate() {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            findings.error(format!("notifications.webhooks[{}]", i), format!("\"{}\" is not an http(s) URL", url));
+        }
+    }
+    if !config.alerts.rules.is_empty() && config.notifications.webhooks.is_empty() {
+        findings.warning("notifications.webhooks", "alerts are only recorded as events, no webhook receives them");
+    }
+
+    let stale = &config.stale_nodes;
+    if stale.after_days == 0 {
+        if stale.action != StaleNodeAction::Flag {
+            findings.warning("stale_nodes.action", "has no effect while stale_nodes.after_days is 0");
+        }
+    } else if stale.check_interval == 0 {
+        findings.error("stale_nodes.check_interval", "must be at least 1 second");
+    }
+
+    let mut partner_names = std::collections::HashSet::new();
+    for (i, partner) in config.federation.partners.iter().enumerate() {
+        let key = format!("federation.partners[{}]", i);
+        if partner.name.is_empty() {
+            findings.error(format!("{}.name", key), "must not be empty");
+        } else if !partner_names.insert(partner.name.as_str()) {
+            findings.error(format!("{}.name", key), format!("duplicate partner name \"{}\"", partner.name));
+        }
+        if !partner.url.starts_with("http://") && !partner.url.starts_with("https://") {
+            findings.error(format!("{}.url", key), format!("\"{}\" is not an http(s) URL", partner.url));
+        } else if partner.url.starts_with("http://") {
+            findings.warning(format!("{}.url", key), "the shared token is sent unencrypted");
+        }
+        if partner.token.expose().len() < 16 {
+            findings.error(format!("{}.token", key), "must be at least 16 characters");
+        }
+        if partner.network.is_empty() {
+            findings.error(format!("{}.network", key), "must not be empty");
+        }
+    }
+
+    let grpc = &config.grpc;
+    if grpc.enabled {
+        if !cfg!(feature = "grpc") {
+            findings.warning("grpc.enabled", "this build has no gRPC support, rebuild with --features grpc");
+        }
+        if grpc.port == 0 {
+            findings.error("grpc.port", "must be between 1 and 65535");
+        } else if grpc.port == config.server.port {
+            findings.error("grpc.port", "must differ from server.port");
+        }
+    }
+
+    if config.storage.backend == StorageBackend::File {
+        check_existing_or_creatable(&mut findings, "storage.path", &config.storage.path);
+    }
+
+    findings
+}
+
+/// Listen URIs as Yggdrasil accepts them, allowing the agent-side host
+/// placeholders
+fn check_listen_uri(uri: &str) -> Result<(), String> {
+    let (scheme, rest) = uri.split_once("://")
+        .ok_or_else(|| format!("\"{}\" has no scheme", uri))?;
+    if !LISTEN_SCHEMES.contains(&scheme) {
+        return Err(format!("unsupported scheme \"{}\" in \"{}\", expected one of {}", scheme, uri, LISTEN_SCHEMES.join(", ")));
+    }
+    if scheme == "unix" {
+        return Ok(());
+    }
+
+    // Placeholders contain colons themselves, so drop them before finding the port
+    let mut authority = String::new();
+    let mut rest = rest.split(['?', '/']).next().unwrap_or_default();
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| format!("unterminated placeholder in \"{}\"", uri))? + start;
+        let placeholder = &rest[start + 1..end];
+        let known = placeholder == "public_ip"
+            || placeholder.strip_prefix("interface:").is_some_and(|name| !name.is_empty())
+            || placeholder.strip_prefix("interface6:").is_some_and(|name| !name.is_empty());
+        if !known {
+            return Err(format!("unknown placeholder {{{}}} in \"{}\"", placeholder, uri));
+        }
+        authority.push_str(&rest[..start]);
+        authority.push_str("host");
+        rest = &rest[end + 1..];
+    }
+    authority.push_str(rest);
+
+    let port = authority.rsplit_once(':')
+        .map(|(_, port)| port)
+        .ok_or_else(|| format!("\"{}\" has no port", uri))?;
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(()),
+        _ => Err(format!("invalid port \"{}\" in \"{}\"", port, uri)),
+    }
+}
+
+fn is_dns_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+fn check_parent_dir(findings: &mut Findings, key: &str, path: &str) {
+    let parent = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty());
+    if parent.is_some_and(|p| !p.is_dir()) {
+        findings.error(key, format!("directory of {} does not exist", path));
+    }
+}
+
+/// Files yggman reads when present and creates otherwise
+fn check_existing_or_creatable(findings: &mut Findings, key: &str, path: &str) {
+    if Path::new(path).exists() {
+        if let Err(e) = std::fs::read(path) {
+            findings.error(key, format!("{} is not readable: {}", path, e));
+        }
+    } else {
+        check_parent_dir(findings, key, path);
+    }
+}
+
+async fn check_database(config: &AppConfig) -> Result<(), String> {
+    // Connecting would create a missing SQLite file
+    if let Some(path) = config.database.url.expose().strip_prefix("sqlite://") {
+        if !Path::new(path).exists() {
+            println!("warning: database.url: {} does not exist yet and will be created", path);
+            return Ok(());
+        }
+    }
+
+    let db = crate::database::connect(&config.database).await.map_err(|e| e.to_string())?;
+    db.ping().await.map_err(|e| e.to_string())
+}
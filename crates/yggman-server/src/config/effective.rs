@@ -0,0 +1,107 @@
+//! The merged configuration as yggman runs with it, each setting tagged
+//! with where its value came from.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::AppConfig;
+use crate::redact::{mask_url_password, REDACTED};
+
+
+/// Where a setting's effective value came from, lowest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Env,
+    Cli,
+    /// Settings stored in the database replace the file at startup
+    Database,
+}
+
+impl ConfigSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File => "file",
+            ConfigSource::Env => "env",
+            ConfigSource::Cli => "cli",
+            ConfigSource::Database => "database",
+        }
+    }
+}
+
+/// Source of every setting, keyed by its dotted path
+pub type ConfigSources = BTreeMap<String, ConfigSource>;
+
+/// Dotted paths of the settings in `value`: top-level keys and the keys of
+/// each section. Deeper values, such as module options, count as one setting.
+pub fn setting_paths(value: &Value) -> Vec<String> {
+    let mut paths = Vec::new();
+    for (key, value) in value.as_object().into_iter().flatten() {
+        match value.as_object() {
+            Some(section) => paths.extend(section.keys().map(|name| format!("{}.{}", key, name))),
+            None => paths.push(key.clone()),
+        }
+    }
+    paths
+}
+
+/// The configuration with the access tokens and database password replaced
+pub fn redacted(config: &AppConfig) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    for pointer in ["/server/admin_token", "/server/observer_token"] {
+        if let Some(token) = value.pointer_mut(pointer).filter(|token| !token.is_null()) {
+            *token = Value::from(REDACTED);
+        }
+    }
+    if let Some(url) = value.pointer_mut("/database/url") {
+        *url = Value::from(mask_url_password(url.as_str().unwrap_or_default()));
+    }
+    if let Some(partners) = value.pointer_mut("/federation/partners").and_then(Value::as_array_mut) {
+        for partner in partners {
+            if let Some(token) = partner.get_mut("token") {
+                *token = Value::from(REDACTED);
+            }
+        }
+    }
+    value
+}
+
+/// The configuration in config file syntax with each value's source as a
+/// trailing comment
+pub fn render(config: &AppConfig, sources: &ConfigSources, show_secrets: bool) -> String {
+    let value = if show_secrets {
+        serde_json::to_value(config).unwrap_or_default()
+    } else {
+        redacted(config)
+    };
+    let mut out = String::from("# effective yggman configuration\n");
+    let Some(root) = value.as_object() else {
+        return out;
+    };
+
+    for (key, value) in root.iter().filter(|(_, value)| !value.is_object()) {
+        out.push_str(&render_value(key, key, value, sources));
+    }
+    for (section, value) in root.iter().filter(|(_, value)| value.is_object()) {
+        out.push_str(&format!("\n[{}]\n", section));
+        for (key, value) in value.as_object().into_iter().flatten() {
+            let path = format!("{}.{}", section, key);
+            out.push_str(&render_value(key, &path, value, sources));
+        }
+    }
+    out
+}
+
+fn render_value(key: &str, path: &str, value: &Value, sources: &ConfigSources) -> String {
+    let source = sources.get(path).copied().unwrap_or(ConfigSource::Default).as_str();
+    if value.is_null() {
+        return format!("# {} = (unset)  # {}\n", key, source);
+    }
+    let rendered = toml::Value::try_from(value).map(|value| value.to_string()).unwrap_or_else(|_| value.to_string());
+    format!("{} = {}  # {}\n", key, rendered, source)
+}
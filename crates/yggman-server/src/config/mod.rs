@@ -0,0 +1,925 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use arc_swap::ArcSwap;
+use std::sync::{Arc, RwLock};
+use crate::cli::{CliArgs, EnvConfig};
+use crate::redact::{Secret, SecretUrl};
+
+pub mod effective;
+pub mod schema;
+
+pub use effective::{ConfigSource, ConfigSources};
+
+/// Unknown top-level sections are errors, since a misspelled section
+/// would silently fall back to defaults; unknown keys inside sections are
+/// reported as warnings by `schema::unknown_keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    /// Layout version of the config file, see `schema::CONFIG_VERSION`
+    #[serde(default = "schema_version")]
+    pub version: u32,
+    
+    #[serde(default)]
+    pub server: ServerConfig,
+    
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    
+    #[serde(default)]
+    pub nodes: NodesConfig,
+    
+    #[serde(default)]
+    pub public_peers: PublicPeersConfig,
+    
+    #[serde(default)]
+    pub dns: DnsConfig,
+    
+    #[serde(default)]
+    pub agent: AgentConfig,
+    
+    #[serde(default)]
+    pub storage: StorageConfig,
+    
+    #[serde(default)]
+    pub traffic: TrafficConfig,
+    
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    
+    #[serde(default)]
+    pub stale_nodes: StaleNodesConfig,
+    
+    #[serde(default)]
+    pub federation: FederationConfig,
+    
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    
+    #[serde(default)]
+    pub modules: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+    /// Threads of the async runtime serving requests and agents
+    pub workers: usize,
+    /// Upper bound of the threads for blocking work such as file access
+    /// and DNS lookups, spawned on demand
+    #[serde(default = "default_max_blocking_threads")]
+    pub max_blocking_threads: usize,
+    /// Seconds an idle blocking thread is kept around for the next task
+    #[serde(default = "default_thread_keep_alive")]
+    pub thread_keep_alive: u64,
+    /// Bearer token for admin-only endpoints; those are disabled when unset
+    #[serde(default)]
+    pub admin_token: Option<Secret<String>>,
+    /// Bearer token for the read-only /ws/observe stream, which the admin
+    /// token opens as well; disabled when neither is set
+    #[serde(default)]
+    pub observer_token: Option<Secret<String>>,
+    /// File holding the control-plane signing key; stored in the database when unset
+    #[serde(default)]
+    pub signing_key_file: Option<String>,
+    /// Serve the dashboard and configs only; mutating endpoints, agent
+    /// connections and broadcasts are disabled
+    #[serde(default)]
+    pub read_only: bool,
+    /// Start even when a preflight check fails, reporting the
+    /// failures on /api/health and the dashboard instead, see `crate::preflight`
+    #[serde(default)]
+    pub start_degraded: bool,
+    /// Reverse proxies, as addresses or networks like 10.0.0.0/8, whose
+    /// Forwarded and X-Forwarded-For headers name the real client
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Also serve the API over plain HTTP on this unix socket, for local
+    /// administration and reverse proxies; its clients count as 127.0.0.1
+    #[serde(default)]
+    pub unix_socket: Option<String>,
+    /// Octal permissions of the unix socket
+    #[serde(default = "default_unix_socket_mode")]
+    pub unix_socket_mode: String,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub auth_lockout: AuthLockoutConfig,
+}
+
+/// Failed token checks per client address before it is locked out of the
+/// admin, observer and agent endpoints, see `crate::auth_throttle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthLockoutConfig {
+    /// Failures in a row that lock an address out; 0 disables lockouts
+    pub max_failures: u32,
+    /// Seconds of the first lockout, doubled for each further one
+    pub lockout_seconds: u64,
+    /// Upper bound of a lockout; a quiet period this long also forgets
+    /// earlier failures
+    pub max_lockout_seconds: u64,
+}
+
+impl Default for AuthLockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 10,
+            lockout_seconds: 300,
+            max_lockout_seconds: 86400,
+        }
+    }
+}
+
+/// HTTPS on the web server port, which agents then reach over wss://
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// PEM certificate chain and private key, unless ACME provides them
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    pub acme: AcmeConfig,
+}
+
+/// Certificates from Let's Encrypt or another ACME CA, obtained at startup
+/// and renewed before they expire. Ownership of the domains is proven with
+/// the TLS-ALPN-01 challenge, so the CA must reach the server on port 443.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AcmeConfig {
+    pub enabled: bool,
+    /// Hostnames the certificate is for, resolving to this server
+    pub domains: Vec<String>,
+    /// Email addresses the CA may send expiry and policy notices to
+    pub contact: Vec<String>,
+    /// Keeps the account key and certificates across restarts, sparing
+    /// the CA's rate limits
+    pub cache_dir: String,
+    /// Let's Encrypt's staging environment, for trying the setup out
+    pub staging: bool,
+    /// Directory URL of another ACME CA; Let's Encrypt when unset
+    pub directory_url: Option<String>,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            domains: Vec::new(),
+            contact: Vec::new(),
+            cache_dir: "acme".to_string(),
+            staging: false,
+            directory_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    pub url: SecretUrl,
+    pub max_connections: u32,
+    pub connect_timeout: u64,
+    pub acquire_timeout: u64,
+    pub idle_timeout: u64,
+    pub max_lifetime: u64,
+    /// Keep all state in an in-memory SQLite database instead of `url`
+    #[serde(default)]
+    pub ephemeral: bool,
+    /// In ephemeral mode, copy the in-memory database to this SQLite file
+    /// every `snapshot_interval` seconds and on shutdown
+    #[serde(default)]
+    pub snapshot_file: Option<String>,
+    #[serde(default = "default_snapshot_interval")]
+    pub snapshot_interval: u64,
+    /// SQLite journal mode; WAL lets readers proceed while a write is in progress
+    #[serde(default)]
+    pub sqlite_journal_mode: SqliteJournalMode,
+    /// Seconds a SQLite connection waits for a lock before failing with
+    /// "database is locked"
+    #[serde(default = "default_sqlite_busy_timeout")]
+    pub sqlite_busy_timeout: u64,
+    /// Queue writes to SQLite one at a time instead of letting them contend
+    /// for the database lock
+    #[serde(default = "default_true")]
+    pub sqlite_serialize_writes: bool,
+    /// Further attempts to reach the database at startup before giving up
+    #[serde(default = "default_connect_retries")]
+    pub connect_retries: u32,
+    /// Cap in seconds of the doubling delay between startup attempts
+    #[serde(default = "default_connect_retry_max_delay")]
+    pub connect_retry_max_delay: u64,
+    /// Seconds between checks that the database is still reachable
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval: u64,
+}
+
+fn default_max_blocking_threads() -> usize {
+    512
+}
+
+fn default_thread_keep_alive() -> u64 {
+    10
+}
+
+fn default_unix_socket_mode() -> String {
+    "660".to_string()
+}
+
+fn default_snapshot_interval() -> u64 {
+    60
+}
+
+fn default_sqlite_busy_timeout() -> u64 {
+    5
+}
+
+fn default_connect_retries() -> u32 {
+    10
+}
+
+fn default_connect_retry_max_delay() -> u64 {
+    30
+}
+
+fn default_health_check_interval() -> u64 {
+    30
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SqliteJournalMode {
+    #[default]
+    Wal,
+    Delete,
+    Truncate,
+    Persist,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodesConfig {
+    pub max_peers_per_node: usize,
+    pub topology_update_interval: u64,
+    pub default_listen_endpoints: Vec<String>,
+}
+
+/// Periodic import of the community public peer list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublicPeersConfig {
+    pub enabled: bool,
+    pub url: String,
+    /// Region files to keep (e.g. "germany"), empty keeps all regions
+    pub regions: Vec<String>,
+    /// Drop peers slower than this, in milliseconds
+    pub max_latency_ms: Option<u64>,
+    pub refresh_interval: u64,
+}
+
+/// DNS zone mapping node names to their Yggdrasil addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// Zone origin; nodes of the default network are `<name>.<domain>`,
+    /// others `<name>.<network>.<domain>`
+    pub domain: String,
+    /// Name server in the SOA and NS records, `ns.<domain>` when unset
+    pub nameserver: Option<String>,
+    pub ttl: u32,
+    /// Zone file kept up to date as nodes change; not written when unset
+    pub zone_file: Option<String>,
+    /// Run after the zone file changed, e.g. `rndc reload mesh.internal`
+    pub reload_command: Option<String>,
+    /// How often to check for node changes, in seconds
+    pub refresh_interval: u64,
+}
+
+/// How agents are handled: the timing handed to them when they connect,
+/// which versions may register and which release they are offered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// Seconds between heartbeats and status reports
+    pub heartbeat_interval: u64,
+    /// Seconds without a message after which an agent counts as offline
+    /// and its connection is closed; three heartbeat intervals when unset
+    pub offline_threshold: Option<u64>,
+    /// Seconds between scans for changed local addresses
+    pub address_scan_interval: u64,
+    /// Seconds to wait before reconnecting; agents keep their own
+    /// --reconnect-interval when unset
+    pub reconnect_interval: Option<u64>,
+    /// Minimum seconds between full config resends one agent may request
+    pub resync_min_interval: u64,
+    /// Oldest agent release allowed to register, as a semantic version
+    pub min_version: Option<String>,
+    /// Oldest agent protocol allowed to register; agents predating
+    /// protocol versioning count as 0
+    pub min_protocol_version: u32,
+    /// Agent release offered to older agents started with --auto-update
+    pub update_version: Option<String>,
+    /// Download URL of `update_version`; `{version}` and `{target}`
+    /// (e.g. `linux-x86_64`) are filled in per agent
+    pub update_url: Option<String>,
+    /// SHA-256 of the release binary per target; agents on other targets
+    /// are not offered the update
+    pub update_sha256: HashMap<String, String>,
+    /// Stop other nodes dialing an agent that announced its shutdown, until
+    /// it registers again
+    pub withdraw_on_shutdown: bool,
+    /// Seconds between WebSocket pings to agents, keeping NAT and proxy
+    /// mappings of idle connections alive; 0 disables pings
+    pub ping_interval: u64,
+    /// Seconds an agent has to answer a ping before its connection is
+    /// considered dead and closed
+    pub pong_timeout: u64,
+    /// Register new nodes only for agents presenting an enrollment token
+    /// from /api/tokens. Known nodes always take the node's agent token.
+    pub require_enrollment: bool,
+    /// Addresses or networks agents may connect from; anywhere when empty
+    pub allowed_ips: Vec<String>,
+    /// Refuse agents that cannot receive their private key encrypted to
+    /// them, see `crate::sealing`
+    pub require_sealed_keys: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// The `nodes` table of the configured database
+    #[default]
+    Database,
+    /// A JSON file at `storage.path`
+    File,
+}
+
+/// Where nodes are kept; settings, networks and logs stay in the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Node file of the file backend
+    pub path: String,
+    /// Seconds the node list is served from memory; writes through this
+    /// server invalidate it at once, writes by other servers sharing the
+    /// database show up within this time. 0 disables the cache.
+    pub cache_ttl: u64,
+}
+
+/// Per-peer traffic counters reported by agents, kept at `bucket_seconds`
+/// resolution for `detail_retention_hours`, then hourly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrafficConfig {
+    /// Width of the detailed buckets; must divide an hour
+    pub bucket_seconds: u64,
+    pub detail_retention_hours: u64,
+    /// Hourly buckets older than this are deleted
+    pub retention_days: u64,
+}
+
+/// Rules checked against every node, see `crate::alerts`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// Seconds between rule evaluations
+    pub evaluation_interval: u64,
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Identifies the rule in alerts and notifications
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    #[serde(default = "default_alert_severity")]
+    pub severity: crate::event_log::EventSeverity,
+}
+
+fn default_alert_severity() -> crate::event_log::EventSeverity {
+    crate::event_log::EventSeverity::Warning
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The node's agent has been disconnected for longer than `minutes`
+    NodeOffline { minutes: u64 },
+    /// The node's agent reports fewer established peers than `min_peers`
+    PeerCountBelow { min_peers: usize },
+    /// The node's agent failed to apply the last config it received
+    ConfigApplyFailed,
+}
+
+/// Where alerts and other notifications are delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// URLs each notification is POSTed to as JSON
+    pub webhooks: Vec<String>,
+    /// Seconds to wait for a webhook to answer
+    pub timeout: u64,
+}
+
+/// Nodes whose agents stay away, see `crate::stale_nodes`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaleNodesConfig {
+    /// Days without an agent connection after which a node is flagged
+    /// stale; 0 disables the policy
+    pub after_days: u64,
+    /// What happens to a node still stale `grace_days` after it was flagged
+    pub action: StaleNodeAction,
+    pub grace_days: u64,
+    /// Nodes with any of these tags are never flagged
+    pub exempt_tags: Vec<String>,
+    /// Seconds between checks
+    pub check_interval: u64,
+}
+
+/// Peering with the meshes of other yggman servers, see `crate::federation`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FederationConfig {
+    /// Servers whose border nodes this server's border nodes peer with
+    pub partners: Vec<FederationPartner>,
+    /// Seconds between fetches of the partners' border nodes
+    pub refresh_interval: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationPartner {
+    /// Identifies the partner in logs and the API
+    pub name: String,
+    /// Base URL of the partner's yggman, e.g. `https://yggman.example.org`
+    pub url: String,
+    /// Secret shared with the partner: sent when fetching its border nodes
+    /// and accepted from it fetching ours
+    pub token: Secret<String>,
+    /// Network whose border nodes peer with the partner's; only these are
+    /// listed to the partner
+    #[serde(default = "default_partner_network")]
+    pub network: String,
+}
+
+fn default_partner_network() -> String {
+    yggman_core::DEFAULT_NETWORK.to_string()
+}
+
+/// gRPC control API described by proto/yggman.proto, served on its own
+/// port by builds with the `grpc` feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleNodeAction {
+    /// Only report the node
+    #[default]
+    Flag,
+    /// Leave the node out of its peers' configs until its agent returns
+    Drain,
+    /// Remove the node, keeping a copy that can be restored
+    Delete,
+}
+
+fn schema_version() -> u32 {
+    schema::CONFIG_VERSION
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            version: schema::CONFIG_VERSION,
+            server: ServerConfig::default(),
+            database: DatabaseConfig::default(),
+            nodes: NodesConfig::default(),
+            public_peers: PublicPeersConfig::default(),
+            dns: DnsConfig::default(),
+            agent: AgentConfig::default(),
+            storage: StorageConfig::default(),
+            traffic: TrafficConfig::default(),
+            alerts: AlertsConfig::default(),
+            notifications: NotificationsConfig::default(),
+            stale_nodes: StaleNodesConfig::default(),
+            federation: FederationConfig::default(),
+            grpc: GrpcConfig::default(),
+            modules: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1".to_string(),
+            port: 8080,
+            workers: 4,
+            max_blocking_threads: default_max_blocking_threads(),
+            thread_keep_alive: default_thread_keep_alive(),
+            admin_token: None,
+            observer_token: None,
+            signing_key_file: None,
+            read_only: false,
+            start_degraded: false,
+            trusted_proxies: Vec::new(),
+            unix_socket: None,
+            unix_socket_mode: default_unix_socket_mode(),
+            tls: TlsConfig::default(),
+            auth_lockout: AuthLockoutConfig::default(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://yggman.db".into(),
+            max_connections: 10,
+            connect_timeout: 30,
+            acquire_timeout: 30,
+            idle_timeout: 600,
+            max_lifetime: 3600,
+            ephemeral: false,
+            snapshot_file: None,
+            snapshot_interval: default_snapshot_interval(),
+            sqlite_journal_mode: SqliteJournalMode::default(),
+            sqlite_busy_timeout: default_sqlite_busy_timeout(),
+            sqlite_serialize_writes: true,
+            connect_retries: default_connect_retries(),
+            connect_retry_max_delay: default_connect_retry_max_delay(),
+            health_check_interval: default_health_check_interval(),
+        }
+    }
+}
+
+impl Default for PublicPeersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "https://publicpeers.neilalexander.dev/publicnodes.json".to_string(),
+            regions: Vec::new(),
+            max_latency_ms: None,
+            refresh_interval: 3600,
+        }
+    }
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            domain: "mesh.internal".to_string(),
+            nameserver: None,
+            ttl: 300,
+            zone_file: None,
+            reload_command: None,
+            refresh_interval: 30,
+        }
+    }
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: 30,
+            offline_threshold: None,
+            address_scan_interval: 60,
+            reconnect_interval: None,
+            resync_min_interval: 60,
+            min_version: None,
+            min_protocol_version: 0,
+            update_version: None,
+            update_url: None,
+            update_sha256: HashMap::new(),
+            withdraw_on_shutdown: false,
+            ping_interval: 25,
+            pong_timeout: 20,
+            require_enrollment: false,
+            allowed_ips: Vec::new(),
+            require_sealed_keys: false,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// `offline_threshold`, or three heartbeat intervals when unset
+    pub fn offline_threshold(&self) -> u64 {
+        self.offline_threshold.unwrap_or(self.heartbeat_interval.saturating_mul(3))
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Database,
+            path: "yggman-nodes.json".to_string(),
+            cache_ttl: 30,
+        }
+    }
+}
+
+impl Default for TrafficConfig {
+    fn default() -> Self {
+        Self {
+            bucket_seconds: 300,
+            detail_retention_hours: 48,
+            retention_days: 30,
+        }
+    }
+}
+
+impl Default for StaleNodesConfig {
+    fn default() -> Self {
+        Self {
+            after_days: 0,
+            action: StaleNodeAction::default(),
+            grace_days: 7,
+            exempt_tags: Vec::new(),
+            check_interval: 3600,
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            evaluation_interval: 30,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            timeout: 10,
+        }
+    }
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            partners: Vec::new(),
+            refresh_interval: 300,
+        }
+    }
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 50051,
+        }
+    }
+}
+
+impl Default for NodesConfig {
+    fn default() -> Self {
+        Self {
+            max_peers_per_node: 3,
+            topology_update_interval: 60,
+            default_listen_endpoints: vec!["tcp://0.0.0.0:9001".to_string()],
+        }
+    }
+}
+
+pub struct ConfigManager {
+    config: Arc<ArcSwap<AppConfig>>,
+    sources: RwLock<ConfigSources>,
+}
+
+impl ConfigManager {
+    pub fn new(config: AppConfig, sources: ConfigSources) -> Self {
+        Self {
+            config: Arc::new(ArcSwap::from_pointee(config)),
+            sources: RwLock::new(sources),
+        }
+    }
+    
+    pub fn get(&self) -> Arc<AppConfig> {
+        self.config.load_full()
+    }
+    
+    /// Where each setting of `get()` came from
+    pub fn sources(&self) -> ConfigSources {
+        self.sources.read().unwrap().clone()
+    }
+    
+    pub fn update_listen_template(&self, new_template: Vec<String>) {
+        let current = self.config.load_full();
+        let mut new_config = current.as_ref().clone();
+        new_config.nodes.default_listen_endpoints = new_template;
+        
+        self.config.store(Arc::new(new_config));
+        self.sources.write().unwrap()
+            .insert("nodes.default_listen_endpoints".to_string(), ConfigSource::Database);
+        tracing::info!("Listen template updated in memory");
+    }
+    
+    pub fn update_node_timing(&self, timing: &crate::settings_manager::NodeTiming) {
+        let current = self.config.load_full();
+        let mut new_config = current.as_ref().clone();
+        new_config.agent.heartbeat_interval = timing.heartbeat_interval;
+        new_config.agent.offline_threshold = Some(timing.offline_threshold);
+        new_config.stale_nodes.check_interval = timing.stale_check_interval;
+        
+        self.config.store(Arc::new(new_config));
+        let mut sources = self.sources.write().unwrap();
+        for key in ["agent.heartbeat_interval", "agent.offline_threshold", "stale_nodes.check_interval"] {
+            sources.insert(key.to_string(), ConfigSource::Database);
+        }
+        tracing::info!("Node timing updated in memory");
+    }
+    
+    
+    /// Load configuration from multiple sources with precedence:
+    /// CLI args > Environment variables > Config file > Defaults
+    pub fn load_merged_config(cli_args: &CliArgs, env_config: &EnvConfig) -> Result<AppConfig, crate::error::AppError> {
+        Self::load_with_sources(cli_args, env_config).map(|(config, _)| config)
+    }
+    
+    /// Like `load_merged_config`, also reporting where each setting came from
+    pub fn load_with_sources(cli_args: &CliArgs, env_config: &EnvConfig) -> Result<(AppConfig, ConfigSources), crate::error::AppError> {
+        // Start with default config
+        let mut config = AppConfig::default();
+        let mut sources = ConfigSources::new();
+        let mut from_env = Vec::new();
+        let mut from_cli = Vec::new();
+        
+        // Load from config file if it exists
+        if std::path::Path::new(&cli_args.config).exists() {
+            let content = std::fs::read_to_string(&cli_args.config)
+                .map_err(|e| crate::error::AppError::Config(format!("Failed to read config file: {}", e)))?;
+            
+            let file: toml::Value = toml::from_str(&content)
+                .map_err(|e| crate::error::AppError::Config(format!("Failed to parse config file: {}", e)))?;
+            schema::check_version(&file)
+                .map_err(|e| crate::error::AppError::Config(format!("Failed to parse config file: {}", e)))?;
+            for unknown in schema::unknown_keys(&file) {
+                tracing::warn!("{}: {}", cli_args.config, unknown);
+            }
+            
+            config = toml::from_str(&content)
+                .map_err(|e| crate::error::AppError::Config(format!("Failed to parse config file: {}", e)))?;
+            
+            // Keys written in the file count as set there, even when they repeat the default
+            let file = serde_json::to_value(&file).unwrap_or_default();
+            for path in effective::setting_paths(&file) {
+                sources.insert(path, ConfigSource::File);
+            }
+        }
+        
+        // Override with environment variables
+        if let Some(bind_address) = &env_config.server.bind_address {
+            config.server.bind_address = bind_address.clone();
+            from_env.push("server.bind_address");
+        }
+        if let Some(port) = env_config.server.port {
+            config.server.port = port;
+            from_env.push("server.port");
+        }
+        if let Some(workers) = env_config.server.workers {
+            config.server.workers = workers;
+            from_env.push("server.workers");
+        }
+        if let Some(admin_token) = &env_config.server.admin_token {
+            config.server.admin_token = Some(admin_token.clone());
+            from_env.push("server.admin_token");
+        }
+        if let Some(observer_token) = &env_config.server.observer_token {
+            config.server.observer_token = Some(observer_token.clone());
+            from_env.push("server.observer_token");
+        }
+        if let Some(read_only) = env_config.server.read_only {
+            config.server.read_only = read_only;
+            from_env.push("server.read_only");
+        }
+        if let Some(db_url) = &env_config.database.url {
+            config.database.url = db_url.clone();
+            from_env.push("database.url");
+        }
+        if let Some(max_connections) = env_config.database.max_connections {
+            config.database.max_connections = max_connections;
+            from_env.push("database.max_connections");
+        }
+        if let Some(ephemeral) = env_config.database.ephemeral {
+            config.database.ephemeral = ephemeral;
+            from_env.push("database.ephemeral");
+        }
+        if let Some(snapshot_file) = &env_config.database.snapshot_file {
+            config.database.snapshot_file = Some(snapshot_file.clone());
+            from_env.push("database.snapshot_file");
+        }
+        if let Some(max_peers) = env_config.nodes.max_peers_per_node {
+            config.nodes.max_peers_per_node = max_peers;
+            from_env.push("nodes.max_peers_per_node");
+        }
+        if let Some(topology_update) = env_config.nodes.topology_update_interval {
+            config.nodes.topology_update_interval = topology_update;
+            from_env.push("nodes.topology_update_interval");
+        }
+        
+        // Override with CLI arguments (highest priority)
+        if let Some(bind_address) = &cli_args.bind_address {
+            config.server.bind_address = bind_address.clone();
+            from_cli.push(("server.bind_address", "bind_address"));
+        }
+        if let Some(port) = cli_args.port {
+            config.server.port = port;
+            from_cli.push(("server.port", "port"));
+        }
+        if let Some(workers) = cli_args.workers {
+            config.server.workers = workers;
+            from_cli.push(("server.workers", "workers"));
+        }
+        if let Some(max_blocking_threads) = cli_args.max_blocking_threads {
+            config.server.max_blocking_threads = max_blocking_threads;
+            from_cli.push(("server.max_blocking_threads", "max_blocking_threads"));
+        }
+        if let Some(thread_keep_alive) = cli_args.thread_keep_alive {
+            config.server.thread_keep_alive = thread_keep_alive;
+            from_cli.push(("server.thread_keep_alive", "thread_keep_alive"));
+        }
+        if let Some(admin_token) = &cli_args.admin_token {
+            config.server.admin_token = Some(admin_token.clone());
+            from_cli.push(("server.admin_token", "admin_token"));
+        }
+        if let Some(observer_token) = &cli_args.observer_token {
+            config.server.observer_token = Some(observer_token.clone());
+            from_cli.push(("server.observer_token", "observer_token"));
+        }
+        if cli_args.read_only {
+            config.server.read_only = true;
+            from_cli.push(("server.read_only", "read_only"));
+        }
+        if cli_args.start_degraded {
+            config.server.start_degraded = true;
+            from_cli.push(("server.start_degraded", "start_degraded"));
+        }
+        if let Some(db_url) = &cli_args.database_url {
+            config.database.url = db_url.clone();
+            from_cli.push(("database.url", "database_url"));
+        }
+        if let Some(max_connections) = cli_args.max_db_connections {
+            config.database.max_connections = max_connections;
+            from_cli.push(("database.max_connections", "max_db_connections"));
+        }
+        if cli_args.ephemeral {
+            config.database.ephemeral = true;
+            from_cli.push(("database.ephemeral", "ephemeral"));
+        }
+        if let Some(snapshot_file) = &cli_args.snapshot_file {
+            config.database.snapshot_file = Some(snapshot_file.clone());
+            from_cli.push(("database.snapshot_file", "snapshot_file"));
+        }
+        if let Some(max_peers) = cli_args.max_peers {
+            config.nodes.max_peers_per_node = max_peers;
+            from_cli.push(("nodes.max_peers_per_node", "max_peers"));
+        }
+        if let Some(topology_update) = cli_args.topology_update_interval {
+            config.nodes.topology_update_interval = topology_update;
+            from_cli.push(("nodes.topology_update_interval", "topology_update_interval"));
+        }
+        
+        for key in from_env {
+            sources.insert(key.to_string(), ConfigSource::Env);
+        }
+        // Flags can be given through their YGGMAN_ variable as well
+        for (key, arg) in from_cli {
+            let source = if cli_args.from_env.iter().any(|id| id == arg) { ConfigSource::Env } else { ConfigSource::Cli };
+            sources.insert(key.to_string(), source);
+        }
+        
+        // Nothing but the optional snapshot may touch the disk
+        if config.database.ephemeral && config.storage.backend != StorageBackend::Database {
+            config.storage.backend = StorageBackend::Database;
+            let source = sources.get("database.ephemeral").copied().unwrap_or(ConfigSource::Default);
+            sources.insert("storage.backend".to_string(), source);
+        }
+        
+        let settings = serde_json::to_value(&config).unwrap_or_default();
+        for path in effective::setting_paths(&settings) {
+            sources.entry(path).or_insert(ConfigSource::Default);
+        }
+        
+        Ok((config, sources))
+    }
+}
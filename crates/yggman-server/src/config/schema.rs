@@ -0,0 +1,108 @@
+//! Config file versioning, unknown-key detection and a printable schema,
+//! all derived from the serialized defaults so they follow the structs.
+
+use serde_json::Value;
+
+use super::AppConfig;
+
+/// Version of the config file layout; files declare theirs in `version`
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Sections whose keys are free-form
+const FREE_FORM: &[&str] = &["modules", "agent.update_sha256"];
+
+/// A key in the config file that no setting reads
+#[derive(Debug, Clone)]
+pub struct UnknownKey {
+    pub path: String,
+    /// The closest known key at the same level, if any is close enough
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.suggestion {
+            Some(suggestion) => write!(f, "unknown key {} (did you mean {}?)", self.path, suggestion),
+            None => write!(f, "unknown key {}", self.path),
+        }
+    }
+}
+
+/// Every setting with its default; unset optional settings are `null`
+fn defaults() -> Value {
+    serde_json::to_value(AppConfig::default()).unwrap_or_default()
+}
+
+/// Reject files written for a newer layout than this build understands
+pub fn check_version(file: &toml::Value) -> Result<(), String> {
+    match file.get("version") {
+        None => Ok(()),
+        Some(toml::Value::Integer(version)) if *version >= 1 && *version <= i64::from(CONFIG_VERSION) => Ok(()),
+        Some(toml::Value::Integer(version)) => Err(format!(
+            "config version {} is not supported, this build reads versions up to {}",
+            version, CONFIG_VERSION
+        )),
+        Some(other) => Err(format!("version must be an integer, got {}", other)),
+    }
+}
+
+pub fn unknown_keys(file: &toml::Value) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    collect_unknown(file, &defaults(), "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown(file: &toml::Value, known: &Value, prefix: &str, unknown: &mut Vec<UnknownKey>) {
+    let (Some(file), Some(known)) = (file.as_table(), known.as_object()) else {
+        return;
+    };
+
+    for (key, value) in file {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match known.get(key) {
+            Some(_) if FREE_FORM.contains(&path.as_str()) => {}
+            Some(known_value) => collect_unknown(value, known_value, &path, unknown),
+            None => {
+                let suggestion = known.keys()
+                    .map(|candidate| (strsim::damerau_levenshtein(key, candidate), candidate))
+                    .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 4))
+                    .min_by_key(|(distance, _)| *distance)
+                    .map(|(_, candidate)| if prefix.is_empty() { candidate.clone() } else { format!("{}.{}", prefix, candidate) });
+                unknown.push(UnknownKey { path, suggestion });
+            }
+        }
+    }
+}
+
+/// All settings with their defaults in config file syntax; settings without
+/// a default are listed commented out
+pub fn render() -> String {
+    let defaults = defaults();
+    let mut out = String::from("# yggman configuration schema with defaults\n");
+    let Some(root) = defaults.as_object() else {
+        return out;
+    };
+
+    for (key, value) in root.iter().filter(|(_, value)| !value.is_object()) {
+        out.push_str(&render_value(key, value));
+    }
+    for (section, value) in root.iter().filter(|(_, value)| value.is_object()) {
+        out.push_str(&format!("\n[{}]\n", section));
+        if FREE_FORM.contains(&section.as_str()) {
+            out.push_str("# free-form, read by the named modules\n");
+            continue;
+        }
+        for (key, value) in value.as_object().into_iter().flatten() {
+            out.push_str(&render_value(key, value));
+        }
+    }
+    out
+}
+
+fn render_value(key: &str, value: &Value) -> String {
+    if value.is_null() {
+        return format!("# {} = (unset)\n", key);
+    }
+    // TOML and JSON agree on strings, numbers, booleans and arrays of those
+    format!("{} = {}\n", key, value)
+}
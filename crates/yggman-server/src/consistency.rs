@@ -0,0 +1,150 @@
+//! Inconsistencies between the stored nodes and the live state, found by
+//! GET /api/system/consistency and repaired with `?repair=true`: agents
+//! still connected for nodes that were deleted, nodes with stored fields
+//! that do not parse, and names used by several nodes of a network.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::node_manager::NodeManager;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    /// An agent is connected for a node that no longer exists
+    OrphanedConnection { node_id: String },
+    /// Stored fields of a node do not parse and read as empty
+    UnreadableFields { node_id: String, name: String, fields: Vec<&'static str> },
+    /// Several nodes of a network share a name, the first of `node_ids`
+    /// keeping it on repair
+    DuplicateName { network: String, name: String, node_ids: Vec<String> },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    #[serde(flatten)]
+    pub issue: Issue,
+    /// What a repair does, or did
+    pub repair: String,
+    pub repaired: bool,
+    /// Why the repair failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Finding {
+    fn new(issue: Issue, repair: String) -> Self {
+        Self { issue, repair, repaired: false, error: None }
+    }
+
+    fn record(&mut self, result: Result<(), AppError>) {
+        match result {
+            Ok(()) => self.repaired = true,
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub consistent: bool,
+    pub findings: Vec<Finding>,
+    /// Findings repaired by this request
+    pub repaired: usize,
+}
+
+impl Report {
+    /// Ids of the nodes a repair changed, whose peers need new configs
+    pub fn changed_nodes(&self) -> Vec<&str> {
+        self.findings.iter()
+            .filter(|finding| finding.repaired)
+            .flat_map(|finding| match &finding.issue {
+                Issue::OrphanedConnection { .. } => Vec::new(),
+                Issue::UnreadableFields { node_id, .. } => vec![node_id.as_str()],
+                Issue::DuplicateName { node_ids, .. } => node_ids.iter().skip(1).map(String::as_str).collect(),
+            })
+            .collect()
+    }
+}
+
+pub async fn check(node_manager: &NodeManager, repair: bool) -> Result<Report, AppError> {
+    let nodes = node_manager.get_all_nodes().await;
+    let known: HashSet<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+    let mut findings = Vec::new();
+
+    let mut orphaned: Vec<String> = crate::websocket_state::get_connected_node_ids().await
+        .into_iter()
+        .filter(|id| !known.contains(id.as_str()))
+        .collect();
+    orphaned.sort();
+    for node_id in orphaned {
+        let mut finding = Finding::new(Issue::OrphanedConnection { node_id: node_id.clone() }, "disconnect the agent".to_string());
+        if repair {
+            crate::websocket_state::disconnect_agent(&node_id, "the node was deleted, register again").await;
+            finding.repaired = true;
+        }
+        findings.push(finding);
+    }
+
+    for (node_id, fields) in node_manager.unreadable_nodes().await? {
+        let name = nodes.iter().find(|node| node.id == node_id).map(|node| node.name.clone()).unwrap_or_default();
+        let repair_text = format!("store {} as empty", fields.join(", "));
+        let mut finding = Finding::new(Issue::UnreadableFields { node_id: node_id.clone(), name, fields }, repair_text);
+        if repair {
+            finding.record(node_manager.rewrite_node(&node_id).await);
+        }
+        findings.push(finding);
+    }
+
+    let mut by_name: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for node in &nodes {
+        by_name.entry((node.network.as_str(), node.name.as_str())).or_default().push(node.id.as_str());
+    }
+    let mut taken: HashSet<(String, String)> = nodes.iter().map(|node| (node.network.clone(), node.name.clone())).collect();
+    for ((network, name), ids) in by_name.into_iter().filter(|(_, ids)| ids.len() > 1) {
+        // The later nodes get the first free "<name>-<n>"
+        let mut renames = Vec::new();
+        let mut suffix = 2;
+        for id in &ids[1..] {
+            let new_name = loop {
+                let candidate = format!("{}-{}", name, suffix);
+                suffix += 1;
+                if taken.insert((network.to_string(), candidate.clone())) {
+                    break candidate;
+                }
+            };
+            renames.push((*id, new_name));
+        }
+        let repair_text = renames.iter().map(|(id, new_name)| format!("rename {} to {}", id, new_name)).collect::<Vec<_>>().join(", ");
+        let issue = Issue::DuplicateName {
+            network: network.to_string(),
+            name: name.to_string(),
+            node_ids: ids.iter().map(|id| id.to_string()).collect(),
+        };
+        let mut finding = Finding::new(issue, repair_text);
+        if repair {
+            let result = async {
+                for (id, new_name) in &renames {
+                    rename(node_manager, id, new_name).await?;
+                }
+                Ok(())
+            };
+            finding.record(result.await);
+        }
+        findings.push(finding);
+    }
+
+    Ok(Report {
+        consistent: findings.is_empty(),
+        repaired: findings.iter().filter(|finding| finding.repaired).count(),
+        findings,
+    })
+}
+
+async fn rename(node_manager: &NodeManager, node_id: &str, name: &str) -> Result<(), AppError> {
+    let node = node_manager.get_node_by_id(node_id).await
+        .ok_or_else(|| AppError::Config("Node not found".to_string()))?;
+    node_manager.update_node(node_id, name.to_string(), node.listen, node.addresses, None).await
+}
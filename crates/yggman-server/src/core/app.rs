@@ -0,0 +1,112 @@
+use std::sync::Arc;
+use crate::config::AppConfig;
+use crate::core::builder::ApplicationBuilder;
+use crate::core::context::AppContext;
+use crate::core::module::ModuleManager;
+use crate::error::Result;
+use crate::node_manager::NodeManager;
+use tokio::signal;
+
+/// The control plane: the web server and agent endpoint, and the modules
+/// keeping DNS, alerts and traffic statistics up to date
+pub struct Application {
+    module_manager: ModuleManager,
+    context: Arc<AppContext>,
+    node_manager: Arc<NodeManager>,
+}
+
+impl Application {
+    pub fn builder(config: AppConfig) -> ApplicationBuilder {
+        ApplicationBuilder::new(config)
+    }
+    
+    pub(crate) fn new(context: AppContext, node_manager: Arc<NodeManager>) -> Self {
+        let context = Arc::new(context);
+        let module_manager = ModuleManager::new(context.clone());
+        
+        Self {
+            module_manager,
+            context,
+            node_manager,
+        }
+    }
+    
+    pub fn register_module(&mut self, module: Box<dyn crate::core::module::Module>) {
+        self.module_manager.register(module);
+    }
+    
+    pub fn context(&self) -> Arc<AppContext> {
+        self.context.clone()
+    }
+    
+    /// The nodes the modules serve. Changes made through it reach connected
+    /// agents with `crate::websocket_state::broadcast_node_change`.
+    pub fn node_manager(&self) -> Arc<NodeManager> {
+        self.node_manager.clone()
+    }
+    
+    /// Start the modules and serve until SIGINT or SIGTERM
+    pub async fn run(mut self) -> Result<()> {
+        self.start().await?;
+        
+        tokio::select! {
+            _ = signal::ctrl_c() => {
+                tracing::info!("Received SIGINT, shutting down");
+            }
+            _ = terminate() => {
+                tracing::info!("Received SIGTERM, shutting down");
+            }
+        }
+        
+        self.stop().await?;
+        
+        Ok(())
+    }
+    
+    /// Start the modules in the background, for applications embedding the
+    /// control plane that handle signals themselves
+    pub async fn start(&mut self) -> Result<()> {
+        tracing::info!("Starting application");
+        
+        self.module_manager.init_all().await?;
+        
+        self.module_manager.start_all().await?;
+        
+        crate::systemd::notify_ready();
+        crate::systemd::spawn_watchdog();
+        Ok(())
+    }
+    
+    pub async fn stop(self) -> Result<()> {
+        tracing::info!("Shutting down application");
+        crate::systemd::notify_stopping();
+        
+        self.module_manager.stop_all().await?;
+        
+        tracing::info!("Application shutdown complete");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Application {
+    /// An application on a fresh in-memory database with default settings,
+    /// its modules not started, for tests
+    pub(crate) async fn for_tests() -> Self {
+        Self::builder(AppConfig::default())
+            .database(crate::database::test_database().await)
+            .build()
+            .await
+            .expect("application on an in-memory database")
+    }
+}
+
+/// SIGTERM, as sent by `systemctl stop`; never resolves elsewhere
+async fn terminate() {
+    #[cfg(unix)]
+    if let Ok(mut sigterm) = signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        sigterm.recv().await;
+        return;
+    }
+    std::future::pending::<()>().await
+}
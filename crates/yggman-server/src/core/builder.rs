@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use sea_orm::DatabaseConnection;
+
+use crate::config::{AppConfig, ConfigManager, ConfigSources};
+use crate::core::app::Application;
+use crate::core::context::AppContext;
+use crate::error::{AppError, Result};
+use crate::log_level::LogLevel;
+use crate::node_manager::NodeManager;
+use crate::{
+    alerts, audit_log, auth_throttle, database, enrollment, event_log, modules, network_manager, notifier, preflight,
+    settings_manager, stale_nodes, status_history, storage, traffic_stats,
+};
+
+/// Assembles an `Application` from a configuration: connects and migrates
+/// the database, runs the preflight checks, loads the signing key and
+/// registers the built-in modules. Created with `Application::builder`.
+pub struct ApplicationBuilder {
+    config: AppConfig,
+    sources: ConfigSources,
+    database: Option<DatabaseConnection>,
+    log_level: Option<Arc<LogLevel>>,
+}
+
+impl ApplicationBuilder {
+    pub(crate) fn new(config: AppConfig) -> Self {
+        Self { config, sources: ConfigSources::new(), database: None, log_level: None }
+    }
+
+    /// Where each setting came from, shown by GET /api/system/config
+    pub fn config_sources(mut self, sources: ConfigSources) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Use an open connection instead of connecting to `database.url`;
+    /// the yggman tables are created in it as needed
+    pub fn database(mut self, database: DatabaseConnection) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Let admins change the log filter over the API. Left out when the
+    /// host application installs its own tracing subscriber.
+    pub fn log_level(mut self, log_level: Arc<LogLevel>) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    pub async fn build(self) -> Result<Application> {
+        let Self { config, sources, database, log_level } = self;
+
+        let db = match database {
+            Some(db) => db,
+            None => {
+                let db = database::create_connection(&config.database).await
+                    .map_err(|e| AppError::Config(format!("Failed to connect to database: {}", e)))?;
+                tracing::info!("Database connection established");
+                db
+            }
+        };
+
+        // Run migrations
+        database::migrate_database(&db).await
+            .map_err(|e| AppError::Config(format!("Failed to migrate database: {}", e)))?;
+
+        // Create settings manager and initialize defaults
+        let settings_manager = settings_manager::SettingsManager::new(db.clone());
+        settings_manager.initialize_defaults().await
+            .map_err(|e| AppError::Config(format!("Failed to initialize settings: {}", e)))?;
+
+        // Make sure the default network exists
+        let network_manager = network_manager::NetworkManager::new(db.clone());
+        network_manager.initialize_defaults().await
+            .map_err(|e| AppError::Config(format!("Failed to initialize networks: {}", e)))?;
+
+        let mut preflight = preflight::run(&config, &db, &settings_manager, &network_manager).await;
+        preflight.log();
+        let failures: Vec<String> = preflight.failures().map(|check| format!("{}: {}", check.name, check.message)).collect();
+        if !failures.is_empty() {
+            if !config.server.start_degraded {
+                return Err(AppError::Config(format!(
+                    "Preflight checks failed (start with --start-degraded to serve anyway): {}",
+                    failures.join("; "),
+                )));
+            }
+            tracing::warn!("Starting degraded despite failed preflight checks");
+            preflight.degraded = true;
+        }
+
+        // Key the agents pin to verify that messages come from this server
+        let signing_key = settings_manager.get_or_create_signing_key(config.server.signing_key_file.as_deref()).await
+            .map_err(|e| AppError::Config(format!("Failed to load server signing key: {}", e)))?;
+        tracing::info!("Server signing public key: {} (pin on agents with --server-pubkey)", hex::encode(signing_key.verifying_key().to_bytes()));
+
+        // Create config manager first
+        let config_manager = ConfigManager::new(config, sources);
+
+        // Load settings from database to config
+        if let Err(e) = settings_manager.load_settings_to_config(&config_manager).await {
+            if !preflight.degraded {
+                return Err(AppError::Config(format!("Failed to load settings to config: {}", e)));
+            }
+            tracing::warn!("Failed to load settings to config, keeping the configured defaults: {}", e);
+        }
+
+        let node_store = storage::open(&config_manager.get().storage, db.clone()).await
+            .map_err(|e| AppError::Config(format!("Failed to open node storage: {}", e)))?;
+
+        let audit_log = Arc::new(audit_log::AuditLog::new(db.clone()));
+        let auth_throttle = auth_throttle::AuthThrottle::new(db.clone(), audit_log.clone());
+        auth_throttle.load().await
+            .map_err(|e| AppError::Config(format!("Failed to read failed authentications: {}", e)))?;
+        let event_log = event_log::EventLog::new(db.clone());
+        let status_history = status_history::StatusHistory::new(db.clone());
+        // Agents connected when the server stopped are offline until they reconnect
+        status_history.close_open_intervals().await
+            .map_err(|e| AppError::Config(format!("Failed to read status history: {}", e)))?;
+
+        let context = AppContext {
+            config_manager: Arc::new(config_manager),
+            settings_manager: Arc::new(settings_manager.clone()),
+            network_manager: Arc::new(network_manager),
+            audit_log,
+            auth_throttle: Arc::new(auth_throttle),
+            enrollment_tokens: Arc::new(enrollment::EnrollmentTokens::new(db.clone())),
+            event_log: Arc::new(event_log),
+            status_history: Arc::new(status_history),
+            traffic_stats: Arc::new(traffic_stats::TrafficStats::new(db.clone())),
+            alerts: Arc::new(alerts::AlertManager::new()),
+            stale_nodes: Arc::new(stale_nodes::StaleNodes::new(db.clone())),
+            notifier: Arc::new(notifier::Notifier::new()),
+            db_health: Arc::new(database::health::DatabaseHealth::new()),
+            signing_key: Arc::new(signing_key),
+            log_level,
+            preflight: Arc::new(preflight),
+        };
+
+        let node_manager = Arc::new(NodeManager::new(node_store, settings_manager));
+        let mut app = Application::new(context, node_manager.clone());
+        app.register_module(Box::new(modules::dns::DnsZoneModule::new(node_manager.clone())));
+        app.register_module(Box::new(modules::alerts::AlertsModule::new(node_manager.clone())));
+        app.register_module(Box::new(modules::web::WebModule::new(node_manager.clone())));
+        #[cfg(feature = "grpc")]
+        app.register_module(Box::new(modules::grpc::GrpcModule::new(node_manager.clone())));
+        app.register_module(Box::new(modules::federation::FederationModule::new(node_manager)));
+        app.register_module(Box::new(modules::public_peers::PublicPeersModule::new()));
+        app.register_module(Box::new(modules::traffic::TrafficModule::new()));
+        app.register_module(Box::new(modules::db_health::DatabaseHealthModule::new(db.clone())));
+        app.register_module(Box::new(modules::snapshot::SnapshotModule::new(db)));
+        Ok(app)
+    }
+}
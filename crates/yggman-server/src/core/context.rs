@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use crate::alerts::AlertManager;
+use crate::audit_log::AuditLog;
+use crate::auth_throttle::AuthThrottle;
+use crate::enrollment::EnrollmentTokens;
+use crate::event_log::EventLog;
+use crate::config::ConfigManager;
+use crate::database::health::DatabaseHealth;
+use crate::log_level::LogLevel;
+use crate::network_manager::NetworkManager;
+use crate::notifier::Notifier;
+use crate::preflight::Report as PreflightReport;
+use crate::settings_manager::SettingsManager;
+use crate::stale_nodes::StaleNodes;
+use crate::status_history::StatusHistory;
+use crate::traffic_stats::TrafficStats;
+
+pub struct AppContext {
+    pub config_manager: Arc<ConfigManager>,
+    pub settings_manager: Arc<SettingsManager>,
+    pub network_manager: Arc<NetworkManager>,
+    pub audit_log: Arc<AuditLog>,
+    pub auth_throttle: Arc<AuthThrottle>,
+    pub enrollment_tokens: Arc<EnrollmentTokens>,
+    pub event_log: Arc<EventLog>,
+    pub status_history: Arc<StatusHistory>,
+    pub traffic_stats: Arc<TrafficStats>,
+    pub alerts: Arc<AlertManager>,
+    pub stale_nodes: Arc<StaleNodes>,
+    pub notifier: Arc<Notifier>,
+    pub db_health: Arc<DatabaseHealth>,
+    pub signing_key: Arc<ed25519_dalek::SigningKey>,
+    /// `None` when the host application owns the tracing subscriber
+    pub log_level: Option<Arc<LogLevel>>,
+    pub preflight: Arc<PreflightReport>,
+}
+
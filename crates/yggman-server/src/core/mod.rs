@@ -0,0 +1,4 @@
+pub mod app;
+pub mod builder;
+pub mod context;
+pub mod module;
\ No newline at end of file
@@ -0,0 +1,389 @@
+use sea_orm::{Database, DatabaseConnection, DbErr, ConnectionTrait};
+use sea_orm::{Schema, DbBackend, Statement};
+use sea_orm::{EntityTrait, sea_query::Table};
+use migration::prelude::{SqliteQueryBuilder, PostgresQueryBuilder, MysqlQueryBuilder};
+use std::time::Duration;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::config::{DatabaseConfig, SqliteJournalMode};
+
+/// Version of the schema `migrate_database` creates, recorded in the
+/// settings table so that an older build notices a database a newer one
+/// migrated
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Writers wait here in arrival order when SQLite writes are serialized
+static SQLITE_WRITE_QUEUE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+static SERIALIZE_WRITES: AtomicBool = AtomicBool::new(false);
+
+/// Wait for this writer's turn when writes to SQLite are serialized; hold
+/// the returned guard until the write is done. Other backends, and SQLite
+/// with `sqlite_serialize_writes` off, write concurrently.
+pub async fn queue_write() -> Option<tokio::sync::MutexGuard<'static, ()>> {
+    if SERIALIZE_WRITES.load(Ordering::Relaxed) {
+        Some(SQLITE_WRITE_QUEUE.lock().await)
+    } else {
+        None
+    }
+}
+
+/// Connect to the configured database, retrying with a doubling delay so a
+/// database that starts after yggman is waited for
+pub async fn create_connection(config: &DatabaseConfig) -> Result<DatabaseConnection, DbErr> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        match connect(config).await {
+            Ok(db) => return Ok(db),
+            Err(e) if attempt < config.connect_retries && !config.ephemeral => {
+                attempt += 1;
+                tracing::warn!(
+                    "Cannot connect to the database ({}), attempt {} of {} in {}s",
+                    e, attempt, config.connect_retries, delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(config.connect_retry_max_delay.max(1)));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connect to the configured database once
+pub async fn connect(config: &DatabaseConfig) -> Result<DatabaseConnection, DbErr> {
+    if config.ephemeral {
+        return create_memory_connection(config).await;
+    }
+    
+    // Create SQLite database file if it doesn't exist
+    if config.url.expose().starts_with("sqlite://") {
+        let db_path = config.url.expose().strip_prefix("sqlite://").unwrap_or(config.url.expose());
+        
+        // Create parent directories if they don't exist
+        if let Some(parent) = Path::new(db_path).parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| DbErr::Custom(format!("Failed to create database directory: {}", e)))?;
+            }
+        }
+        
+        // Create empty database file if it doesn't exist
+        if !Path::new(db_path).exists() {
+            std::fs::File::create(db_path)
+                .map_err(|e| DbErr::Custom(format!("Failed to create database file: {}", e)))?;
+            tracing::info!("Created SQLite database file: {}", db_path);
+        }
+    }
+    
+    let mut options = sea_orm::ConnectOptions::new(config.url.expose());
+    
+    options
+        .max_connections(config.max_connections)
+        .min_connections(1)
+        .connect_timeout(Duration::from_secs(config.connect_timeout))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout))
+        .idle_timeout(Duration::from_secs(config.idle_timeout))
+        .max_lifetime(Duration::from_secs(config.max_lifetime))
+        // Connections broken by a database restart are replaced on next use
+        .test_before_acquire(true)
+        .sqlx_logging(true)
+        .sqlx_logging_level(tracing::log::LevelFilter::Debug);
+    
+    if config.url.expose().starts_with("sqlite:") {
+        use sea_orm::sqlx::sqlite::SqliteJournalMode as Journal;
+        
+        let journal_mode = match config.sqlite_journal_mode {
+            SqliteJournalMode::Wal => Journal::Wal,
+            SqliteJournalMode::Delete => Journal::Delete,
+            SqliteJournalMode::Truncate => Journal::Truncate,
+            SqliteJournalMode::Persist => Journal::Persist,
+        };
+        let busy_timeout = Duration::from_secs(config.sqlite_busy_timeout);
+        options.map_sqlx_sqlite_opts(move |opts| opts.journal_mode(journal_mode).busy_timeout(busy_timeout));
+        SERIALIZE_WRITES.store(config.sqlite_serialize_writes, Ordering::Relaxed);
+        tracing::info!(
+            "SQLite journal mode {}, busy timeout {}s, writes {}",
+            format!("{:?}", config.sqlite_journal_mode).to_lowercase(),
+            config.sqlite_busy_timeout,
+            if config.sqlite_serialize_writes { "serialized" } else { "concurrent" },
+        );
+    }
+
+    Database::connect(options).await
+}
+
+/// Every connection to `sqlite::memory:` opens its own empty database, so
+/// the pool holds exactly one connection and never recycles it
+async fn create_memory_connection(config: &DatabaseConfig) -> Result<DatabaseConnection, DbErr> {
+    const FOREVER: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+    
+    let mut options = sea_orm::ConnectOptions::new("sqlite::memory:");
+    options
+        .max_connections(1)
+        .min_connections(1)
+        .connect_timeout(Duration::from_secs(config.connect_timeout))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout))
+        .idle_timeout(FOREVER)
+        .max_lifetime(FOREVER)
+        .sqlx_logging(true)
+        .sqlx_logging_level(tracing::log::LevelFilter::Debug);
+    
+    Database::connect(options).await
+}
+
+/// An empty in-memory database with the yggman tables, for tests
+#[cfg(test)]
+pub async fn test_database() -> DatabaseConnection {
+    let db = create_memory_connection(&DatabaseConfig::default()).await.expect("in-memory database");
+    migrate_database(&db).await.expect("migrated in-memory database");
+    db
+}
+
+/// Copy the whole database into a new SQLite file at `path`, replacing it
+/// atomically
+pub async fn snapshot_database(db: &DatabaseConnection, path: &str) -> Result<(), DbErr> {
+    let temp_path = format!("{}.tmp", path);
+    // VACUUM INTO refuses to overwrite an existing file
+    let _ = std::fs::remove_file(&temp_path);
+    // A plain name would inherit mode=memory from the in-memory database
+    let uri = format!("file:{}?mode=rwc", temp_path.replace('%', "%25").replace('?', "%3f").replace('#', "%23"));
+    db.execute_unprepared(&format!("VACUUM INTO '{}'", uri.replace('\'', "''"))).await?;
+    // The copy holds node private keys
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| DbErr::Custom(format!("Failed to restrict snapshot {}: {}", temp_path, e)))?;
+    }
+    std::fs::rename(&temp_path, path)
+        .map_err(|e| DbErr::Custom(format!("Failed to replace snapshot {}: {}", path, e)))
+}
+
+pub async fn migrate_database(db: &DatabaseConnection) -> Result<(), DbErr> {
+    // Get the database backend
+    let backend = db.get_database_backend();
+    let schema = Schema::new(backend);
+    
+    // Create nodes table if it doesn't exist
+    let mut create_nodes_stmt = schema.create_table_from_entity(crate::database::entities::node::Entity);
+    
+    // Convert to SQL
+    let nodes_sql = match backend {
+        DbBackend::Sqlite => create_nodes_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_nodes_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_nodes_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    // Execute the statement
+    db.execute(Statement::from_string(backend, nodes_sql)).await?;
+    
+    // Create settings table if it doesn't exist
+    let mut create_settings_stmt = schema.create_table_from_entity(crate::database::entities::settings::Entity);
+    
+    // Convert to SQL
+    let settings_sql = match backend {
+        DbBackend::Sqlite => create_settings_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_settings_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_settings_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    // Execute the statement
+    db.execute(Statement::from_string(backend, settings_sql)).await?;
+    
+    // Create networks table if it doesn't exist
+    let mut create_networks_stmt = schema.create_table_from_entity(crate::database::entities::network::Entity);
+    
+    let networks_sql = match backend {
+        DbBackend::Sqlite => create_networks_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_networks_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_networks_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, networks_sql)).await?;
+    
+    // Create audit log table if it doesn't exist
+    let mut create_audit_log_stmt = schema.create_table_from_entity(crate::database::entities::audit_log::Entity);
+    
+    let audit_log_sql = match backend {
+        DbBackend::Sqlite => create_audit_log_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_audit_log_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_audit_log_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, audit_log_sql)).await?;
+    
+    // Create events table if it doesn't exist
+    let mut create_events_stmt = schema.create_table_from_entity(crate::database::entities::event::Entity);
+    
+    let events_sql = match backend {
+        DbBackend::Sqlite => create_events_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_events_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_events_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, events_sql)).await?;
+    
+    // Create status history table if it doesn't exist
+    let mut create_status_history_stmt = schema.create_table_from_entity(crate::database::entities::status_history::Entity);
+    
+    let status_history_sql = match backend {
+        DbBackend::Sqlite => create_status_history_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_status_history_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_status_history_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, status_history_sql)).await?;
+    
+    // Create traffic stats table if it doesn't exist
+    let mut create_traffic_stats_stmt = schema.create_table_from_entity(crate::database::entities::traffic_stats::Entity);
+    
+    let traffic_stats_sql = match backend {
+        DbBackend::Sqlite => create_traffic_stats_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_traffic_stats_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_traffic_stats_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, traffic_stats_sql)).await?;
+    
+    // Create config outbox table if it doesn't exist
+    let mut create_config_outbox_stmt = schema.create_table_from_entity(crate::database::entities::config_outbox::Entity);
+    
+    let config_outbox_sql = match backend {
+        DbBackend::Sqlite => create_config_outbox_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_config_outbox_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_config_outbox_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, config_outbox_sql)).await?;
+    
+    // Create enrollment tokens table if it doesn't exist
+    let mut create_enrollment_token_stmt = schema.create_table_from_entity(crate::database::entities::enrollment_token::Entity);
+    
+    let enrollment_token_sql = match backend {
+        DbBackend::Sqlite => create_enrollment_token_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_enrollment_token_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_enrollment_token_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, enrollment_token_sql)).await?;
+    
+    // Create auth failures table if it doesn't exist
+    let mut create_auth_failure_stmt = schema.create_table_from_entity(crate::database::entities::auth_failure::Entity);
+    
+    let auth_failure_sql = match backend {
+        DbBackend::Sqlite => create_auth_failure_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_auth_failure_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_auth_failure_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, auth_failure_sql)).await?;
+    
+    // Create stale nodes table if it doesn't exist
+    let mut create_stale_node_stmt = schema.create_table_from_entity(crate::database::entities::stale_node::Entity);
+    
+    let stale_node_sql = match backend {
+        DbBackend::Sqlite => create_stale_node_stmt.if_not_exists().to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => create_stale_node_stmt.if_not_exists().to_string(PostgresQueryBuilder),
+        DbBackend::MySql => create_stale_node_stmt.if_not_exists().to_string(MysqlQueryBuilder),
+    };
+    
+    db.execute(Statement::from_string(backend, stale_node_sql)).await?;
+    
+    // Add columns introduced after the initial schema
+    use crate::database::entities::node;
+    for column in [node::Column::ExternalPeers, node::Column::Network, node::Column::Tags, node::Column::ResolvedListen, node::Column::Mtu, node::Column::NodeInfo, node::Column::IfName, node::Column::InterfacePeers, node::Column::ExtraConfig, node::Column::AgentVersion, node::Column::YggdrasilVersion, node::Column::Description, node::Column::Owner, node::Column::Contact, node::Column::ListenPolicy, node::Column::ExternalId, node::Column::Provider, node::Column::ExtraAllowedKeys] {
+        add_column_if_missing::<node::Entity>(db, &schema, column).await?;
+    }
+    
+    // Never lower the version a newer build recorded
+    if stored_schema_version(db).await?.is_none_or(|version| version < SCHEMA_VERSION) {
+        use crate::database::entities::settings;
+        use sea_orm::{ActiveModelTrait, IntoActiveModel};
+        let existing = settings::Entity::find_by_id(SCHEMA_VERSION_KEY).one(db).await?;
+        match existing {
+            Some(model) => {
+                let mut model = model.into_active_model();
+                model.update_value(&SCHEMA_VERSION).map_err(|e| DbErr::Custom(e.to_string()))?;
+                model.update(db).await?;
+            }
+            None => {
+                settings::ActiveModel::new(SCHEMA_VERSION_KEY.to_string(), &SCHEMA_VERSION)
+                    .map_err(|e| DbErr::Custom(e.to_string()))?
+                    .insert(db)
+                    .await?;
+            }
+        }
+    }
+    
+    tracing::info!("Database migration completed");
+    Ok(())
+}
+
+/// Schema version the database was last migrated to, `None` before any
+/// build recorded one
+pub async fn stored_schema_version(db: &DatabaseConnection) -> Result<Option<u32>, DbErr> {
+    let setting = crate::database::entities::settings::Entity::find_by_id(SCHEMA_VERSION_KEY).one(db).await?;
+    setting.map(|setting| setting.parse_json_value::<u32>())
+        .transpose()
+        .map_err(|e| DbErr::Custom(format!("Invalid {}: {}", SCHEMA_VERSION_KEY, e)))
+}
+
+/// Read a row of every table, which fails when a table or one of the
+/// columns the entities expect is missing
+pub async fn verify_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    async fn probe<E: EntityTrait>(db: &DatabaseConnection) -> Result<(), DbErr> {
+        use sea_orm::QuerySelect;
+        E::find().limit(1).all(db).await
+            .map(|_| ())
+            .map_err(|e| DbErr::Custom(format!("table {}: {}", E::default().table_name(), e)))
+    }
+    use crate::database::entities::*;
+    probe::<node::Entity>(db).await?;
+    probe::<settings::Entity>(db).await?;
+    probe::<network::Entity>(db).await?;
+    probe::<audit_log::Entity>(db).await?;
+    probe::<event::Entity>(db).await?;
+    probe::<status_history::Entity>(db).await?;
+    probe::<traffic_stats::Entity>(db).await?;
+    probe::<config_outbox::Entity>(db).await?;
+    probe::<enrollment_token::Entity>(db).await?;
+    probe::<auth_failure::Entity>(db).await?;
+    probe::<stale_node::Entity>(db).await
+}
+
+/// Add a single entity column to an existing table, ignoring the error
+/// returned when the column is already present.
+async fn add_column_if_missing<E: EntityTrait>(
+    db: &DatabaseConnection,
+    schema: &Schema,
+    column: E::Column,
+) -> Result<(), DbErr> {
+    let backend = db.get_database_backend();
+    let alter_stmt = Table::alter()
+        .table(E::default())
+        .add_column(schema.get_column_def::<E>(column))
+        .to_owned();
+    
+    let sql = match backend {
+        DbBackend::Sqlite => alter_stmt.to_string(SqliteQueryBuilder),
+        DbBackend::Postgres => alter_stmt.to_string(PostgresQueryBuilder),
+        DbBackend::MySql => alter_stmt.to_string(MysqlQueryBuilder),
+    };
+    
+    match db.execute(Statement::from_string(backend, sql)).await {
+        Ok(_) => {
+            tracing::info!("Added column {} to {}", sea_orm::IdenStatic::as_str(&column), E::default().table_name());
+            Ok(())
+        }
+        Err(e) => {
+            let message = e.to_string().to_lowercase();
+            if message.contains("duplicate column") || message.contains("already exists") {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
\ No newline at end of file
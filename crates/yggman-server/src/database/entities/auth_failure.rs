@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Failed authentication attempts of one client address
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "auth_failures")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub client_ip: String,
+    /// Failures since the last success or lockout
+    pub failures: i32,
+    /// Lockouts so far, each lasting twice as long as the previous one
+    pub lockouts: i32,
+    #[sea_orm(nullable)]
+    pub locked_until: Option<DateTimeUtc>,
+    pub last_failure_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// A config revision pushed to a node that its agent has not acknowledged
+/// yet; at most one per node
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "config_outbox")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub node_id: String,
+    pub revision: i64,
+    pub config: String, // YggdrasilConfig as JSON
+    /// Times the revision was sent to a connected agent
+    pub attempts: i32,
+    pub created_at: DateTimeUtc,
+    #[sea_orm(nullable)]
+    pub last_attempt_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(node_id: String, revision: i64, config: String, attempted: bool) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            node_id: Set(node_id),
+            revision: Set(revision),
+            config: Set(config),
+            attempts: Set(attempted as i32),
+            created_at: Set(now),
+            last_attempt_at: Set(attempted.then_some(now)),
+        }
+    }
+}
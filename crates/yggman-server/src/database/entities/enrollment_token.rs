@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// A token letting agents register new nodes; only its hash is kept
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "enrollment_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    /// SHA-256 of the token, hex encoded
+    #[sea_orm(unique)]
+    pub token_hash: String,
+    pub description: String,
+    #[sea_orm(nullable)]
+    pub network: Option<String>,
+    pub tags: String, // JSON array stored as string
+    #[sea_orm(nullable)]
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    #[sea_orm(nullable)]
+    pub expires_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(
+        id: String,
+        token_hash: String,
+        description: String,
+        network: Option<String>,
+        tags: String,
+        max_uses: Option<i32>,
+        expires_at: Option<DateTimeUtc>,
+    ) -> Self {
+        Self {
+            id: Set(id),
+            token_hash: Set(token_hash),
+            description: Set(description),
+            network: Set(network),
+            tags: Set(tags),
+            max_uses: Set(max_uses),
+            uses: Set(0),
+            expires_at: Set(expires_at),
+            created_at: Set(chrono::Utc::now()),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub kind: String,
+    pub severity: String,
+    #[sea_orm(nullable)]
+    pub node_id: Option<String>,
+    pub message: String,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(kind: String, severity: String, node_id: Option<String>, message: String) -> Self {
+        Self {
+            kind: Set(kind),
+            severity: Set(severity),
+            node_id: Set(node_id),
+            message: Set(message),
+            created_at: Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub mod audit_log;
+pub mod auth_failure;
+pub mod config_outbox;
+pub mod enrollment_token;
+pub mod event;
+pub mod network;
+pub mod node;
+pub mod settings;
+pub mod stale_node;
+pub mod status_history;
+pub mod traffic_stats;
@@ -0,0 +1,194 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "nodes")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    pub name: String,
+    pub public_key: String,
+    pub private_key: String,
+    pub listen: String, // JSON array stored as string
+    #[sea_orm(default_value = "template")]
+    pub listen_policy: String,
+    pub addresses: String, // JSON array stored as string
+    #[sea_orm(default_value = "[]")]
+    pub external_peers: String, // JSON array stored as string
+    #[sea_orm(default_value = "default")]
+    pub network: String,
+    #[sea_orm(default_value = "[]")]
+    pub tags: String, // JSON array stored as string
+    #[sea_orm(default_value = "[]")]
+    pub resolved_listen: String, // JSON array stored as string
+    #[sea_orm(nullable)]
+    pub mtu: Option<i32>,
+    #[sea_orm(default_value = "{}")]
+    pub node_info: String, // JSON object stored as string
+    #[sea_orm(nullable)]
+    pub if_name: Option<String>,
+    #[sea_orm(default_value = "{}")]
+    pub interface_peers: String, // JSON object stored as string
+    #[sea_orm(default_value = "[]")]
+    pub extra_allowed_keys: String, // JSON array stored as string
+    #[sea_orm(default_value = "{}")]
+    pub extra_config: String, // JSON object stored as string
+    #[sea_orm(nullable)]
+    pub agent_version: Option<String>,
+    #[sea_orm(nullable)]
+    pub yggdrasil_version: Option<String>,
+    #[sea_orm(nullable)]
+    pub description: Option<String>,
+    #[sea_orm(nullable)]
+    pub owner: Option<String>,
+    #[sea_orm(nullable)]
+    pub contact: Option<String>,
+    #[sea_orm(nullable)]
+    pub external_id: Option<String>,
+    #[sea_orm(nullable)]
+    pub provider: Option<String>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            id: Set(uuid::Uuid::new_v4().to_string()),
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    fn before_save<'life0, 'async_trait, C>(
+        mut self,
+        _db: &'life0 C,
+        _insert: bool,
+    ) -> core::pin::Pin<Box<dyn core::future::Future<Output = Result<Self, DbErr>> + core::marker::Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        C: 'async_trait + ConnectionTrait,
+        Self: 'async_trait,
+    {
+        Box::pin(async move {
+            self.updated_at = Set(chrono::Utc::now());
+            Ok(self)
+        })
+    }
+}
+
+impl Model {
+    /// Columns holding JSON that does not parse, which convert to empty
+    /// values instead
+    pub fn unreadable_fields(&self) -> Vec<&'static str> {
+        fn parses<T: serde::de::DeserializeOwned>(json: &str) -> bool {
+            serde_json::from_str::<T>(json).is_ok()
+        }
+        type Object = std::collections::HashMap<String, serde_json::Value>;
+        [
+            ("listen", parses::<Vec<String>>(&self.listen)),
+            ("addresses", parses::<Vec<String>>(&self.addresses)),
+            ("external_peers", parses::<Vec<String>>(&self.external_peers)),
+            ("tags", parses::<Vec<String>>(&self.tags)),
+            ("resolved_listen", parses::<Vec<String>>(&self.resolved_listen)),
+            ("node_info", parses::<Object>(&self.node_info)),
+            ("interface_peers", parses::<std::collections::HashMap<String, Vec<String>>>(&self.interface_peers)),
+            ("extra_allowed_keys", parses::<Vec<String>>(&self.extra_allowed_keys)),
+            ("extra_config", parses::<Object>(&self.extra_config)),
+        ]
+        .into_iter()
+        .filter(|(_, parses)| !parses)
+        .map(|(field, _)| field)
+        .collect()
+    }
+}
+
+// Conversion functions between database model and domain model
+impl From<Model> for crate::yggdrasil::Node {
+    fn from(model: Model) -> Self {
+        let listen: Vec<String> = serde_json::from_str(&model.listen).unwrap_or_default();
+        let addresses: Vec<String> = serde_json::from_str(&model.addresses).unwrap_or_default();
+        let external_peers: Vec<String> = serde_json::from_str(&model.external_peers).unwrap_or_default();
+        let tags: Vec<String> = serde_json::from_str(&model.tags).unwrap_or_default();
+        let resolved_listen: Vec<String> = serde_json::from_str(&model.resolved_listen).unwrap_or_default();
+        let node_info = serde_json::from_str(&model.node_info).unwrap_or_default();
+        let interface_peers = serde_json::from_str(&model.interface_peers).unwrap_or_default();
+        let extra_allowed_keys: Vec<String> = serde_json::from_str(&model.extra_allowed_keys).unwrap_or_default();
+        let extra_config = serde_json::from_str(&model.extra_config).unwrap_or_default();
+        
+        crate::yggdrasil::Node {
+            id: model.id,
+            name: model.name,
+            public_key: model.public_key,
+            private_key: model.private_key.into(),
+            listen,
+            listen_policy: crate::yggdrasil::ListenPolicy::parse(&model.listen_policy),
+            addresses,
+            external_peers,
+            network: model.network,
+            tags,
+            resolved_listen,
+            mtu: model.mtu.and_then(|mtu| u16::try_from(mtu).ok()),
+            node_info,
+            if_name: model.if_name,
+            interface_peers,
+            extra_allowed_keys,
+            extra_config,
+            agent_version: model.agent_version,
+            yggdrasil_version: model.yggdrasil_version,
+            description: model.description,
+            owner: model.owner,
+            contact: model.contact,
+            external_id: model.external_id,
+            provider: model.provider,
+        }
+    }
+}
+
+impl From<&crate::yggdrasil::Node> for ActiveModel {
+    fn from(node: &crate::yggdrasil::Node) -> Self {
+        let listen = serde_json::to_string(&node.listen).unwrap_or_default();
+        let addresses = serde_json::to_string(&node.addresses).unwrap_or_default();
+        let external_peers = serde_json::to_string(&node.external_peers).unwrap_or_default();
+        let tags = serde_json::to_string(&node.tags).unwrap_or_default();
+        let resolved_listen = serde_json::to_string(&node.resolved_listen).unwrap_or_default();
+        let node_info = serde_json::to_string(&node.node_info).unwrap_or_default();
+        let interface_peers = serde_json::to_string(&node.interface_peers).unwrap_or_default();
+        let extra_allowed_keys = serde_json::to_string(&node.extra_allowed_keys).unwrap_or_default();
+        let extra_config = serde_json::to_string(&node.extra_config).unwrap_or_default();
+        
+        ActiveModel {
+            id: Set(node.id.clone()),
+            name: Set(node.name.clone()),
+            public_key: Set(node.public_key.clone()),
+            private_key: Set(node.private_key.expose().clone()),
+            listen: Set(listen),
+            listen_policy: Set(node.listen_policy.as_str().to_string()),
+            addresses: Set(addresses),
+            external_peers: Set(external_peers),
+            network: Set(node.network.clone()),
+            tags: Set(tags),
+            resolved_listen: Set(resolved_listen),
+            mtu: Set(node.mtu.map(i32::from)),
+            node_info: Set(node_info),
+            if_name: Set(node.if_name.clone()),
+            interface_peers: Set(interface_peers),
+            extra_allowed_keys: Set(extra_allowed_keys),
+            extra_config: Set(extra_config),
+            agent_version: Set(node.agent_version.clone()),
+            yggdrasil_version: Set(node.yggdrasil_version.clone()),
+            description: Set(node.description.clone()),
+            owner: Set(node.owner.clone()),
+            contact: Set(node.contact.clone()),
+            external_id: Set(node.external_id.clone()),
+            provider: Set(node.provider.clone()),
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,42 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// A node whose agent has stayed away past `[stale_nodes]` after_days
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "stale_nodes")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub node_id: String,
+    pub network: String,
+    pub name: String,
+    pub last_seen: DateTimeUtc,
+    pub flagged_at: DateTimeUtc,
+    /// "flagged", "drained" or "deleted"
+    pub state: String,
+    #[sea_orm(nullable)]
+    pub acted_at: Option<DateTimeUtc>,
+    /// The removed node as JSON, private key included, once deleted
+    #[sea_orm(nullable)]
+    pub node: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(node_id: String, network: String, name: String, last_seen: DateTimeUtc) -> Self {
+        Self {
+            node_id: Set(node_id),
+            network: Set(network),
+            name: Set(name),
+            last_seen: Set(last_seen),
+            flagged_at: Set(chrono::Utc::now()),
+            state: Set("flagged".to_string()),
+            acted_at: Set(None),
+            node: Set(None),
+        }
+    }
+}
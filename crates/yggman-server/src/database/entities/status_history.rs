@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// A node's agent coming online or going offline
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "status_history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub node_id: String,
+    pub online: bool,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(node_id: String, online: bool) -> Self {
+        Self {
+            node_id: Set(node_id),
+            online: Set(online),
+            created_at: Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+use serde::{Deserialize, Serialize};
+
+/// Bytes exchanged between a node and one peer within a time bucket
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "traffic_stats")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub node_id: String,
+    pub peer_key: String,
+    pub bucket_start: DateTimeUtc,
+    /// Width of the bucket in seconds
+    pub resolution: i64,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    pub fn new(node_id: String, peer_key: String, bucket_start: DateTimeUtc, resolution: i64, rx_bytes: i64, tx_bytes: i64) -> Self {
+        Self {
+            node_id: Set(node_id),
+            peer_key: Set(peer_key),
+            bucket_start: Set(bucket_start),
+            resolution: Set(resolution),
+            rx_bytes: Set(rx_bytes),
+            tx_bytes: Set(tx_bytes),
+            ..Default::default()
+        }
+    }
+}
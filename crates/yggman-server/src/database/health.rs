@@ -0,0 +1,62 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use sea_orm::DatabaseConnection;
+
+/// Reachability of the database as of the last health check
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    /// When the database last became reachable or unreachable
+    pub since: DateTime<Utc>,
+    pub checked_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+/// Tracks whether the database answers, so an outage is reported once and
+/// its end is noticed
+pub struct DatabaseHealth {
+    status: Mutex<HealthStatus>,
+}
+
+impl Default for DatabaseHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DatabaseHealth {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            status: Mutex::new(HealthStatus {
+                healthy: true,
+                since: now,
+                checked_at: now,
+                error: None,
+            }),
+        }
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Ping the database and record the result. Returns the previous status
+    /// when reachability changed.
+    pub async fn check(&self, db: &DatabaseConnection) -> Option<HealthStatus> {
+        let result = db.ping().await;
+        let now = Utc::now();
+
+        let mut status = self.status.lock().unwrap();
+        let previous = status.clone();
+        status.checked_at = now;
+        status.error = result.as_ref().err().map(|e| e.to_string());
+        if status.healthy == result.is_ok() {
+            return None;
+        }
+        status.healthy = result.is_ok();
+        status.since = now;
+        Some(previous)
+    }
+}
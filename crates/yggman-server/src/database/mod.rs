@@ -1,4 +1,5 @@
 pub mod entities;
 pub mod connection;
+pub mod health;
 
 pub use connection::*;
\ No newline at end of file
@@ -0,0 +1,321 @@
+//! `yggman demo`: simulated agents speaking the agent protocol over
+//! WebSocket, against an in-memory server started alongside them or, with
+//! `--server`, against a running one. Each agent registers a node with a
+//! made-up address, acknowledges the configs it is sent and reports a
+//! status with growing traffic counters, so the dashboard fills up without
+//! Yggdrasil installed anywhere. The periodic summaries (connected agents,
+//! configs received, delivery latency) make it a load test as well.
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use ed25519_dalek::VerifyingKey;
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::cli::DemoArgs;
+use crate::config::ServerConfig;
+use crate::modules::websocket::{AgentMessage, ServerMessage, PROTOCOL_VERSION};
+use crate::redact::Secret;
+use crate::sealing::KeyOpener;
+use crate::traffic_stats::PeerCounters;
+
+/// Between connection attempts, including while the embedded server starts
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// Until the first config brings the server's interval
+const DEFAULT_HEARTBEAT_INTERVAL: u64 = 30;
+
+#[derive(Default)]
+struct Stats {
+    connected: AtomicUsize,
+    configs: AtomicU64,
+    updates: AtomicU64,
+    reconnects: AtomicU64,
+    errors: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    /// Milliseconds from signing to receipt, since the last summary
+    latencies: Mutex<Vec<u64>>,
+}
+
+impl Stats {
+    /// Failures before the first connection, e.g. while the embedded
+    /// server starts, are shown but not counted
+    fn error(&self, agent: &str, error: impl std::fmt::Display, counted: bool) {
+        if counted {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        *self.last_error.lock().unwrap() = Some(format!("{}: {}", agent, error));
+    }
+}
+
+/// `host:port` of the server `yggman demo` starts itself
+pub fn local_address(server: &ServerConfig) -> String {
+    let host = match server.bind_address.parse::<IpAddr>() {
+        Ok(address) if address.is_unspecified() => "127.0.0.1".to_string(),
+        Ok(IpAddr::V6(address)) => format!("[{}]", address),
+        _ => server.bind_address.clone(),
+    };
+    format!("{}:{}", host, server.port)
+}
+
+/// Run `args.nodes` agents against `url` until interrupted, messages
+/// checked against `server_key` when given
+pub async fn run(args: DemoArgs, url: String, server_key: Option<VerifyingKey>) -> Result<()> {
+    tracing::info!("Demo: starting {} simulated agents against {}", args.nodes, url);
+    let stats = Arc::new(Stats::default());
+    for index in 0..args.nodes {
+        let agent = Agent::new(index, &args, server_key);
+        // Spread over the ramp-up, the first connecting right away
+        let delay = Duration::from_secs(args.ramp_up).mul_f64(index as f64 / args.nodes as f64);
+        tokio::spawn(agent.run(url.clone(), delay, stats.clone()));
+    }
+
+    tokio::select! {
+        _ = report(&stats, args.nodes, args.report_interval) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    Ok(())
+}
+
+async fn report(stats: &Stats, nodes: usize, report_interval: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(report_interval.max(1)));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let mut latencies = std::mem::take(&mut *stats.latencies.lock().unwrap());
+        latencies.sort_unstable();
+        let percentile = |p: usize| latencies.get(latencies.len().saturating_sub(1) * p / 100).copied().unwrap_or_default();
+        tracing::info!(
+            "Demo: {}/{} agents connected, {} configs and {} updates received, {} reconnects, {} errors; delivery p50 {} ms, p95 {} ms, max {} ms over {} messages",
+            stats.connected.load(Ordering::Relaxed),
+            nodes,
+            stats.configs.load(Ordering::Relaxed),
+            stats.updates.load(Ordering::Relaxed),
+            stats.reconnects.load(Ordering::Relaxed),
+            stats.errors.load(Ordering::Relaxed),
+            percentile(50),
+            percentile(95),
+            percentile(100),
+            latencies.len(),
+        );
+        if let Some(error) = stats.last_error.lock().unwrap().take() {
+            tracing::warn!("Demo: last agent error: {}", error);
+        }
+    }
+}
+
+/// Counts the agent as connected while alive
+struct Connected<'a>(&'a Stats);
+
+impl<'a> Connected<'a> {
+    fn new(stats: &'a Stats) -> Self {
+        stats.connected.fetch_add(1, Ordering::Relaxed);
+        Self(stats)
+    }
+}
+
+impl Drop for Connected<'_> {
+    fn drop(&mut self) {
+        self.0.connected.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Agent {
+    name: String,
+    address: String,
+    enrollment_token: Option<Secret<String>>,
+    /// Sent with the first config, for registering again after a reconnect
+    agent_token: Option<Secret<String>>,
+    server_key: Option<VerifyingKey>,
+    key_opener: KeyOpener,
+    heartbeat_interval: u64,
+    has_connected: bool,
+    revision: u64,
+    listen: Vec<String>,
+    peers: Vec<String>,
+    allowed_public_keys: Vec<String>,
+    /// Byte counters by peer key; `None` for peers it pretends not to reach
+    sessions: HashMap<String, Option<PeerCounters>>,
+}
+
+impl Agent {
+    fn new(index: usize, args: &DemoArgs, server_key: Option<VerifyingKey>) -> Self {
+        Self {
+            name: format!("demo-{}", index + 1),
+            address: simulated_address(index),
+            enrollment_token: args.enrollment_token.clone(),
+            agent_token: None,
+            server_key,
+            key_opener: KeyOpener::generate(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            has_connected: false,
+            revision: 0,
+            listen: Vec::new(),
+            peers: Vec::new(),
+            allowed_public_keys: Vec::new(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    async fn run(mut self, url: String, delay: Duration, stats: Arc<Stats>) {
+        tokio::time::sleep(delay).await;
+        loop {
+            if let Err(e) = self.connect(&url, &stats).await {
+                tracing::debug!("Demo agent {}: {}", self.name, e);
+                stats.error(&self.name, e, self.has_connected);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn connect(&mut self, url: &str, stats: &Stats) -> Result<()> {
+        let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+        let (mut write, mut read) = socket.split();
+        if self.has_connected {
+            stats.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        self.has_connected = true;
+        let _connected = Connected::new(stats);
+
+        write.send(frame(&self.register_message())?).await?;
+        let mut heartbeat = heartbeat_timer(self.heartbeat_interval);
+        loop {
+            tokio::select! {
+                message = read.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | None => bail!("connection closed by the server"),
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e.into()),
+                    };
+                    match self.receive(&text, stats)? {
+                        ServerMessage::Config { revision, agent_token, listen, peers, allowed_public_keys, timing, .. } => {
+                            stats.configs.fetch_add(1, Ordering::Relaxed);
+                            if !agent_token.is_empty() {
+                                self.agent_token = Some(agent_token);
+                            }
+                            if let Some(timing) = timing.filter(|timing| timing.heartbeat_interval != self.heartbeat_interval) {
+                                self.heartbeat_interval = timing.heartbeat_interval;
+                                heartbeat = heartbeat_timer(self.heartbeat_interval);
+                            }
+                            self.apply(revision, listen, peers, allowed_public_keys);
+                        }
+                        ServerMessage::Update { revision, listen, peers, allowed_public_keys, .. } => {
+                            stats.updates.fetch_add(1, Ordering::Relaxed);
+                            self.apply(revision, listen, peers, allowed_public_keys);
+                        }
+                        ServerMessage::Timing { timing } => {
+                            if timing.heartbeat_interval != self.heartbeat_interval {
+                                self.heartbeat_interval = timing.heartbeat_interval;
+                                heartbeat = heartbeat_timer(self.heartbeat_interval);
+                            }
+                            continue;
+                        }
+                        ServerMessage::UpdateAvailable { .. } => continue,
+                        ServerMessage::Error { message, .. } => bail!("server error: {}", message),
+                    }
+                    write.send(frame(&AgentMessage::ConfigApplied { revision: self.revision, success: true, error: None })?).await?;
+                    write.send(frame(&self.status_message())?).await?;
+                }
+                _ = heartbeat.tick() => {
+                    write.send(frame(&AgentMessage::Heartbeat)?).await?;
+                    write.send(frame(&self.status_message())?).await?;
+                }
+            }
+        }
+    }
+
+    fn register_message(&self) -> AgentMessage {
+        AgentMessage::Register {
+            name: self.name.clone(),
+            addresses: vec![self.address.clone()],
+            network: None,
+            tags: vec!["demo".to_string()],
+            agent_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            yggdrasil_version: Some("demo".to_string()),
+            protocol_version: PROTOCOL_VERSION,
+            target: Some("demo".to_string()),
+            dry_run: false,
+            enrollment_token: self.enrollment_token.clone(),
+            agent_token: self.agent_token.clone(),
+            key_agreement_key: Some(self.key_opener.public_key()),
+            nonce: None,
+        }
+    }
+
+    /// Check and decode a server message, timing its delivery
+    fn receive(&self, text: &str, stats: &Stats) -> Result<ServerMessage> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        let signed_at = match &self.server_key {
+            Some(key) => Some(crate::signing::verify_message(key, &value)?),
+            None => value.get(crate::signing::TIMESTAMP_FIELD).and_then(serde_json::Value::as_u64),
+        };
+        if let Some(signed_at) = signed_at {
+            stats.latencies.lock().unwrap().push(crate::signing::unix_millis().saturating_sub(signed_at));
+        }
+
+        let mut message = serde_json::from_value(value)?;
+        if let ServerMessage::Config { sealed_private_key: Some(sealed), .. } = &message {
+            self.key_opener.open(sealed)?;
+        }
+        if let ServerMessage::Config { agent_token, sealed_agent_token: Some(sealed), .. } = &mut message {
+            *agent_token = Secret::new(self.key_opener.open(sealed)?);
+        }
+        Ok(message)
+    }
+
+    fn apply(&mut self, revision: u64, listen: Vec<String>, peers: Vec<String>, allowed_public_keys: Vec<String>) {
+        self.revision = revision;
+        self.listen = listen;
+        self.peers = peers;
+        self.allowed_public_keys = allowed_public_keys;
+
+        let keys: HashSet<&str> = self.peers.iter().filter_map(|peer| crate::node_manager::peer_uri_key(peer)).collect();
+        self.sessions.retain(|key, _| keys.contains(key.as_str()));
+        for key in keys {
+            // One peer in ten stays unreachable, so the dashboard has some to show
+            self.sessions.entry(key.to_string()).or_insert_with(|| {
+                (rand::random::<f64>() >= 0.1).then(|| PeerCounters { key: key.to_string(), rx_bytes: 0, tx_bytes: 0 })
+            });
+        }
+    }
+
+    fn status_message(&mut self) -> AgentMessage {
+        for counters in self.sessions.values_mut().flatten() {
+            counters.rx_bytes += rand::random::<u16>() as u64 * 16;
+            counters.tx_bytes += rand::random::<u16>() as u64 * 16;
+        }
+        let traffic: Vec<PeerCounters> = self.sessions.values().flatten().cloned().collect();
+        AgentMessage::Status {
+            revision: self.revision,
+            listen: self.listen.clone(),
+            peers: self.peers.clone(),
+            allowed_public_keys: self.allowed_public_keys.clone(),
+            established_peers: Some(traffic.iter().map(|counters| counters.key.clone()).collect()),
+            drift: false,
+            traffic: Some(traffic),
+            last_server_error: None,
+        }
+    }
+}
+
+/// Address of the `index`th simulated node. 198.18.0.0/15 is set aside
+/// for benchmarks, so no real node has it.
+pub fn simulated_address(index: usize) -> String {
+    let host = index + 1;
+    format!("198.{}.{}.{}", 18 + host / 65536 % 2, host / 256 % 256, host % 256)
+}
+
+pub fn frame(message: &AgentMessage) -> Result<Message> {
+    Ok(Message::Text(serde_json::to_string(message)?))
+}
+
+/// Ticks every `seconds`, the first time one interval from now
+fn heartbeat_timer(seconds: u64) -> tokio::time::Interval {
+    let period = Duration::from_secs(seconds.max(1));
+    tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+}
@@ -0,0 +1,236 @@
+//! Enrollment tokens let agents register new nodes without an admin
+//! credential. A token can be bound to a network, tags the nodes it enrolls,
+//! and stops working after a number of uses or at an expiry time. Only a
+//! hash of each token is stored; the token itself is shown once on creation.
+
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::database::entities::enrollment_token as token_entity;
+use crate::error::AppError;
+
+/// A token as listed through the API, without its hash
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EnrollmentToken {
+    pub id: String,
+    pub description: String,
+    pub network: Option<String>,
+    pub tags: Vec<String>,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<token_entity::Model> for EnrollmentToken {
+    fn from(model: token_entity::Model) -> Self {
+        Self {
+            id: model.id,
+            description: model.description,
+            network: model.network,
+            tags: serde_json::from_str(&model.tags).unwrap_or_default(),
+            max_uses: model.max_uses.map(|uses| uses as u32),
+            uses: model.uses as u32,
+            expires_at: model.expires_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+/// Limits of a new token; `None` means unlimited
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct NewEnrollmentToken {
+    pub description: String,
+    /// Network the enrolled nodes must join
+    pub network: Option<String>,
+    /// Added to the tags of every node enrolled with the token
+    pub tags: Vec<String>,
+    pub max_uses: Option<u32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Why a token did not enroll a node
+#[derive(Error, Debug)]
+pub enum EnrollmentError {
+    #[error("unknown enrollment token")]
+    Unknown,
+
+    #[error("enrollment token {0} has expired")]
+    Expired(String),
+
+    #[error("enrollment token {0} has been used up")]
+    UsedUp(String),
+
+    #[error("enrollment token {0} is for network {1}")]
+    WrongNetwork(String, String),
+
+    #[error("database error: {0}")]
+    Database(#[from] sea_orm::DbErr),
+}
+
+fn db_error(e: sea_orm::DbErr) -> AppError {
+    AppError::Config(format!("Database error: {}", e))
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub struct EnrollmentTokens {
+    db: DatabaseConnection,
+}
+
+impl EnrollmentTokens {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Newest first
+    pub async fn list(&self) -> Result<Vec<EnrollmentToken>, AppError> {
+        let tokens = token_entity::Entity::find()
+            .order_by_desc(token_entity::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(tokens.into_iter().map(EnrollmentToken::from).collect())
+    }
+
+    /// Store a new token, returning it together with the secret to hand out
+    pub async fn create(&self, new: NewEnrollmentToken) -> Result<(EnrollmentToken, String), AppError> {
+        if new.max_uses == Some(0) {
+            return Err(AppError::Config("max_uses must be at least 1".to_string()));
+        }
+        if new.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(AppError::Config("expires_at lies in the past".to_string()));
+        }
+
+        let id = format!("enr-{}", hex::encode(rand::random::<[u8; 6]>()));
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let tags = serde_json::to_string(&new.tags)?;
+        let max_uses = new.max_uses.map(|uses| i32::try_from(uses).unwrap_or(i32::MAX));
+
+        let _write = crate::database::queue_write().await;
+        let model = token_entity::ActiveModel::new(id, token_hash(&secret), new.description, new.network, tags, max_uses, new.expires_at)
+            .insert(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok((model.into(), secret))
+    }
+
+    /// Returns whether the token existed
+    pub async fn delete(&self, id: &str) -> Result<bool, AppError> {
+        let _write = crate::database::queue_write().await;
+        let result = token_entity::Entity::delete_by_id(id)
+            .exec(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Use up one enrollment of `token` for a node joining `network`. The
+    /// use is counted with a single conditional update, so concurrent
+    /// registrations cannot exceed the limit.
+    pub async fn redeem(&self, token: &str, network: &str) -> Result<EnrollmentToken, EnrollmentError> {
+        let Some(model) = token_entity::Entity::find()
+            .filter(token_entity::Column::TokenHash.eq(token_hash(token)))
+            .one(&self.db)
+            .await?
+        else {
+            return Err(EnrollmentError::Unknown);
+        };
+
+        if model.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(EnrollmentError::Expired(model.id));
+        }
+        if let Some(required) = model.network.as_ref().filter(|required| *required != network) {
+            return Err(EnrollmentError::WrongNetwork(model.id.clone(), required.clone()));
+        }
+
+        let _write = crate::database::queue_write().await;
+        let counted = token_entity::Entity::update_many()
+            .col_expr(token_entity::Column::Uses, Expr::col(token_entity::Column::Uses).add(1))
+            .filter(token_entity::Column::Id.eq(&model.id))
+            .filter(
+                Condition::any()
+                    .add(token_entity::Column::MaxUses.is_null())
+                    .add(Expr::col(token_entity::Column::Uses).lt(Expr::col(token_entity::Column::MaxUses))),
+            )
+            .exec(&self.db)
+            .await?;
+        if counted.rows_affected == 0 {
+            return Err(EnrollmentError::UsedUp(model.id));
+        }
+
+        let mut token = EnrollmentToken::from(model);
+        token.uses += 1;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn tokens() -> EnrollmentTokens {
+        EnrollmentTokens::new(crate::database::test_database().await)
+    }
+
+    #[tokio::test]
+    async fn counts_uses_up_to_the_limit() {
+        let tokens = tokens().await;
+        let (created, secret) = tokens.create(NewEnrollmentToken { max_uses: Some(2), ..Default::default() }).await.unwrap();
+        assert_eq!(created.uses, 0);
+
+        assert_eq!(tokens.redeem(&secret, "default").await.unwrap().uses, 1);
+        assert_eq!(tokens.redeem(&secret, "default").await.unwrap().uses, 2);
+        assert!(matches!(tokens.redeem(&secret, "default").await, Err(EnrollmentError::UsedUp(id)) if id == created.id));
+        assert_eq!(tokens.list().await.unwrap()[0].uses, 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_redeems_stay_within_the_limit() {
+        let tokens = tokens().await;
+        let (_, secret) = tokens.create(NewEnrollmentToken { max_uses: Some(3), ..Default::default() }).await.unwrap();
+
+        let results = futures::future::join_all((0..8).map(|_| tokens.redeem(&secret, "default"))).await;
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+        assert_eq!(tokens.list().await.unwrap()[0].uses, 3);
+    }
+
+    #[tokio::test]
+    async fn unlimited_tokens_keep_counting() {
+        let tokens = tokens().await;
+        let (_, secret) = tokens.create(NewEnrollmentToken::default()).await.unwrap();
+        for _ in 0..5 {
+            tokens.redeem(&secret, "default").await.unwrap();
+        }
+        assert_eq!(tokens.list().await.unwrap()[0].uses, 5);
+    }
+
+    #[tokio::test]
+    async fn refusals_use_nothing_up() {
+        let tokens = tokens().await;
+        let (_, secret) = tokens.create(NewEnrollmentToken {
+            network: Some("lab".to_string()),
+            max_uses: Some(1),
+            ..Default::default()
+        }).await.unwrap();
+
+        assert!(matches!(tokens.redeem("not-a-token", "lab").await, Err(EnrollmentError::Unknown)));
+        assert!(matches!(tokens.redeem(&secret, "default").await, Err(EnrollmentError::WrongNetwork(_, network)) if network == "lab"));
+        assert_eq!(tokens.list().await.unwrap()[0].uses, 0);
+        assert!(tokens.redeem(&secret, "lab").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_limits_that_never_enroll() {
+        let tokens = tokens().await;
+        assert!(tokens.create(NewEnrollmentToken { max_uses: Some(0), ..Default::default() }).await.is_err());
+        let expired = NewEnrollmentToken { expires_at: Some(Utc::now() - chrono::Duration::seconds(1)), ..Default::default() };
+        assert!(tokens.create(expired).await.is_err());
+    }
+}
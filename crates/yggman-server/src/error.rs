@@ -10,6 +10,15 @@ pub enum AppError {
     
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    
+    #[error("HTTP error: {0}")]
+    Http(String),
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Http(e.to_string())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, AppError>;
\ No newline at end of file
@@ -0,0 +1,75 @@
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryOrder, QuerySelect};
+
+use crate::database::entities::event::{self as event_entity, Model as Event};
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSeverity {
+    Info,
+    Warning,
+    Alert,
+}
+
+impl EventSeverity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventSeverity::Info => "info",
+            EventSeverity::Warning => "warning",
+            EventSeverity::Alert => "alert",
+        }
+    }
+}
+
+/// Persisted operational events such as rollbacks and halted broadcasts
+pub struct EventLog {
+    db: DatabaseConnection,
+    live: tokio::sync::broadcast::Sender<Event>,
+}
+
+impl EventLog {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            live: tokio::sync::broadcast::channel(256).0,
+        }
+    }
+    
+    /// Receive events as they are stored
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Event> {
+        self.live.subscribe()
+    }
+    
+    /// Record an event. Failures are logged rather than returned, raising an
+    /// event must never abort the operation that raised it.
+    pub async fn emit(&self, kind: &str, severity: EventSeverity, node_id: Option<&str>, message: &str) {
+        match severity {
+            EventSeverity::Info => tracing::info!(kind, "Event: {}", message),
+            EventSeverity::Warning | EventSeverity::Alert => tracing::warn!(kind, "{} event: {}", severity.as_str(), message),
+        }
+        
+        let model = event_entity::ActiveModel::new(
+            kind.to_string(),
+            severity.as_str().to_string(),
+            node_id.map(str::to_string),
+            message.to_string(),
+        );
+        
+        let _write = crate::database::queue_write().await;
+        match model.insert(&self.db).await {
+            // No subscribers is not an error
+            Ok(event) => drop(self.live.send(event)),
+            Err(e) => tracing::error!("Failed to store {} event: {}", kind, e),
+        }
+    }
+    
+    /// Most recent events first
+    pub async fn get_recent(&self, limit: u64) -> Result<Vec<Event>, AppError> {
+        event_entity::Entity::find()
+            .order_by_desc(event_entity::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+}
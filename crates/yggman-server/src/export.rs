@@ -0,0 +1,158 @@
+//! Node inventory exports for tools that reach nodes over the mesh, keyed by
+//! node name and pointing at each node's Yggdrasil address.
+
+use serde_json::{json, Map, Value};
+
+use crate::config::DnsConfig;
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::yggdrasil::Node;
+
+/// Ansible group and SSH host names may not contain whitespace or most
+/// punctuation
+fn sanitize_name(name: &str, replacement: char) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { replacement })
+        .collect()
+}
+
+fn tag_group(tag: &str) -> String {
+    format!("tag_{}", sanitize_name(&tag.to_lowercase(), '_').replace(['-', '.'], "_"))
+}
+
+/// Nodes paired with their mesh address, skipping ones with unusable keys
+fn addressed(nodes: &[Node]) -> Vec<(&Node, std::net::Ipv6Addr)> {
+    let mut result: Vec<_> = nodes.iter()
+        .filter_map(|node| node.yggdrasil_address().map(|address| (node, address)))
+        .collect();
+    result.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    result
+}
+
+/// Ansible YAML inventory layout, expressed as JSON (which the YAML
+/// inventory plugin reads as well), with a group per tag
+pub fn ansible_inventory(nodes: &[Node]) -> Value {
+    let mut hosts = Map::new();
+    let mut groups: Map<String, Value> = Map::new();
+
+    for (node, address) in addressed(nodes) {
+        let host = sanitize_name(&node.name, '-');
+        hosts.insert(host.clone(), json!({
+            "ansible_host": address.to_string(),
+            "yggdrasil_public_key": node.public_key,
+            "yggman_node_id": node.id,
+            "yggman_network": node.network,
+        }));
+        for tag in &node.tags {
+            let group = groups.entry(tag_group(tag)).or_insert_with(|| json!({ "hosts": {} }));
+            group["hosts"][&host] = json!({});
+        }
+    }
+
+    json!({
+        "all": {
+            "hosts": hosts,
+            "children": groups,
+        }
+    })
+}
+
+/// Ansible INI inventory with the same hosts and tag groups
+pub fn ansible_inventory_ini(nodes: &[Node]) -> String {
+    let mut out = String::from("# Generated by yggman\n[all]\n");
+    let mut groups: std::collections::BTreeMap<String, Vec<String>> = std::collections::BTreeMap::new();
+
+    for (node, address) in addressed(nodes) {
+        let host = sanitize_name(&node.name, '-');
+        out.push_str(&format!("{} ansible_host={}\n", host, address));
+        for tag in &node.tags {
+            groups.entry(tag_group(tag)).or_default().push(host.clone());
+        }
+    }
+    for (group, hosts) in groups {
+        out.push_str(&format!("\n[{}]\n", group));
+        for host in hosts {
+            out.push_str(&format!("{}\n", host));
+        }
+    }
+    out
+}
+
+/// ssh_config `Host` blocks, suitable for an `Include` from ~/.ssh/config
+pub fn ssh_config(nodes: &[Node], user: Option<&str>) -> String {
+    let mut out = String::from("# Generated by yggman\n");
+    for (node, address) in addressed(nodes) {
+        out.push_str(&format!("\nHost {}\n    HostName {}\n", sanitize_name(&node.name, '-'), address));
+        if let Some(user) = user {
+            out.push_str(&format!("    User {}\n", user));
+        }
+    }
+    out
+}
+
+/// Prometheus HTTP service discovery target groups, one per node, with the
+/// node's tags as `__meta_yggman_tag_<tag>` labels
+pub fn prometheus_targets(nodes: &[Node], port: u16) -> Value {
+    let groups: Vec<Value> = addressed(nodes).into_iter()
+        .map(|(node, address)| {
+            let mut labels = Map::new();
+            labels.insert("__meta_yggman_node_id".to_string(), json!(node.id));
+            labels.insert("__meta_yggman_node_name".to_string(), json!(node.name));
+            labels.insert("__meta_yggman_network".to_string(), json!(node.network));
+            labels.insert("__meta_yggman_public_key".to_string(), json!(node.public_key));
+            // Comma-wrapped like other service discoveries, so `.*,tag,.*` matches
+            labels.insert("__meta_yggman_tags".to_string(), json!(format!(",{},", node.tags.join(","))));
+            for tag in &node.tags {
+                let label = sanitize_name(tag, '_').replace(['-', '.'], "_");
+                labels.insert(format!("__meta_yggman_tag_{}", label), json!("true"));
+            }
+            json!({
+                "targets": [format!("[{}]:{}", address, port)],
+                "labels": labels,
+            })
+        })
+        .collect();
+    Value::Array(groups)
+}
+
+/// Lowercase letters, digits and inner dashes, as DNS labels allow
+fn dns_label(name: &str) -> String {
+    let label: String = name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    label.trim_matches('-').to_string()
+}
+
+/// AAAA records for the nodes, without the SOA header; unchanged records
+/// mean the zone needs no new serial
+pub fn dns_records(nodes: &[Node], ttl: u32) -> String {
+    let mut records = String::new();
+    for (node, address) in addressed(nodes) {
+        let mut name = dns_label(&node.name);
+        if name.is_empty() {
+            continue;
+        }
+        if node.network != DEFAULT_NETWORK {
+            name = format!("{}.{}", name, dns_label(&node.network));
+        }
+        records.push_str(&format!("{} {} IN AAAA {}\n", name, ttl, address));
+    }
+    records
+}
+
+/// A complete zone file for `records` under the given serial
+pub fn dns_zone(config: &DnsConfig, records: &str, serial: u32) -> String {
+    let domain = config.domain.trim_end_matches('.');
+    let nameserver = config.nameserver.clone().unwrap_or_else(|| format!("ns.{}", domain));
+    let nameserver = nameserver.trim_end_matches('.');
+    format!(
+        "; Generated by yggman\n$ORIGIN {domain}.\n$TTL {ttl}\n\
+         @ IN SOA {ns}. hostmaster.{domain}. ({serial} 3600 600 86400 {ttl})\n\
+         @ IN NS {ns}.\n\n{records}",
+        domain = domain,
+        ttl = config.ttl,
+        ns = nameserver,
+        serial = serial,
+        records = records,
+    )
+}
@@ -0,0 +1,131 @@
+//! Peering between the meshes of two yggman servers. Each partner is
+//! federated with one network: the server lists that network's border
+//! nodes, those tagged `border`, at GET /api/federation/nodes for the
+//! partner presenting the token it shares with it, and fetches the
+//! partner's in turn. Border nodes of the network then dial and allow the
+//! partner's border nodes on top of their own mesh; other networks stay
+//! isolated. Only the names, public keys and public peer URIs of border
+//! nodes are exchanged; neither server stores the other's nodes beyond
+//! that list.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::FederationPartner;
+use crate::error::Result;
+use crate::node_manager::{peer_uri_key, NodeManager};
+use crate::settings_manager::SettingsManager;
+use crate::yggdrasil::Node;
+
+/// Nodes with this tag peer with the partners' border nodes
+pub const BORDER_TAG: &str = "border";
+
+/// A border node as partners see it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FederatedNode {
+    pub name: String,
+    pub public_key: String,
+    /// Peer URIs on public addresses; empty for nodes that only dial out
+    pub endpoints: Vec<String>,
+}
+
+/// A partner's border nodes as last fetched, with the network they are
+/// federated with
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PartnerNodes {
+    pub network: String,
+    pub nodes: Vec<FederatedNode>,
+}
+
+pub fn is_border(node: &Node) -> bool {
+    node.tags.iter().any(|tag| tag == BORDER_TAG)
+}
+
+/// The border nodes of `network`, for the partners federated with it
+pub async fn border_nodes(node_manager: &NodeManager, network: &str) -> Vec<FederatedNode> {
+    let mut endpoints: HashMap<String, Vec<String>> = node_manager.get_public_peers(network).await.into_iter()
+        .map(|(node, uris)| (node.id, uris))
+        .collect();
+    node_manager.get_nodes_in_network(network).await.into_iter()
+        .filter(is_border)
+        .map(|node| FederatedNode {
+            endpoints: endpoints.remove(&node.id).unwrap_or_default(),
+            name: node.name,
+            public_key: node.public_key,
+        })
+        .collect()
+}
+
+/// Fetch a partner's border nodes, dropping malformed keys and endpoints
+/// that are not pinned to their node's key
+pub async fn fetch_border_nodes(partner: &FederationPartner) -> Result<Vec<FederatedNode>> {
+    let url = format!("{}/api/federation/nodes", partner.url.trim_end_matches('/'));
+    let nodes: Vec<FederatedNode> = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(partner.token.expose())
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(nodes.into_iter().filter_map(sanitize).collect())
+}
+
+fn sanitize(mut node: FederatedNode) -> Option<FederatedNode> {
+    if node.public_key.len() != 64 || !node.public_key.chars().all(|c| c.is_ascii_hexdigit()) {
+        tracing::warn!("Ignoring federated node {} with invalid public key", node.name);
+        return None;
+    }
+    node.public_key = node.public_key.to_lowercase();
+    // Otherwise a partner could have border nodes dial anyone
+    node.endpoints.retain(|uri| peer_uri_key(uri).is_some_and(|key| key.eq_ignore_ascii_case(&node.public_key)));
+    Some(node)
+}
+
+/// Fetch every partner's border nodes and store them by partner name.
+/// Partners that cannot be reached keep their last list, partners no
+/// longer configured are dropped. Returns whether anything changed.
+pub async fn refresh(partners: &[FederationPartner], settings_manager: &SettingsManager) -> Result<bool> {
+    let previous = settings_manager.get_federated_nodes().await?;
+    let mut current = HashMap::new();
+    for partner in partners {
+        match fetch_border_nodes(partner).await {
+            Ok(nodes) => {
+                tracing::debug!("Fetched {} border nodes from partner {}", nodes.len(), partner.name);
+                current.insert(partner.name.clone(), PartnerNodes { network: partner.network.clone(), nodes });
+            }
+            Err(e) => {
+                tracing::warn!("Failed to fetch border nodes from partner {}: {}", partner.name, e);
+                if let Some(last) = previous.get(&partner.name) {
+                    let nodes = last.nodes.clone();
+                    current.insert(partner.name.clone(), PartnerNodes { network: partner.network.clone(), nodes });
+                }
+            }
+        }
+    }
+
+    if current == previous {
+        return Ok(false);
+    }
+    settings_manager.set_federated_nodes(&current).await?;
+    Ok(true)
+}
+
+/// Have the border nodes among `nodes` dial and allow the border nodes of
+/// the partners federated with their network
+pub fn apply(nodes: &mut [Node], federated: &HashMap<String, PartnerNodes>) {
+    for node in nodes.iter_mut().filter(|node| is_border(node)) {
+        let remotes = federated.values()
+            .filter(|partner| partner.network == node.network)
+            .flat_map(|partner| &partner.nodes)
+            .filter(|remote| remote.public_key != node.public_key);
+        for remote in remotes {
+            node.external_peers.extend(remote.endpoints.iter().cloned());
+            if !node.extra_allowed_keys.contains(&remote.public_key) {
+                node.extra_allowed_keys.push(remote.public_key.clone());
+            }
+        }
+    }
+}
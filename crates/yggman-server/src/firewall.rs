@@ -0,0 +1,145 @@
+//! Firewall hints derived from a node's generated config: the ports it must
+//! accept connections on and the endpoints it dials.
+
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+use crate::yggdrasil::YggdrasilConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundRule {
+    pub transport: Transport,
+    pub port: u16,
+    /// Listener the rule is needed for
+    pub listen: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboundRule {
+    pub transport: Transport,
+    pub host: String,
+    pub port: u16,
+    /// Peer URI the rule is needed for
+    pub peer: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FirewallHints {
+    pub inbound: Vec<InboundRule>,
+    pub outbound: Vec<OutboundRule>,
+}
+
+/// Split a yggdrasil URI into transport, host and port. Unix sockets and
+/// unparseable URIs yield `None`.
+fn parse_endpoint(uri: &str) -> Option<(Transport, String, u16)> {
+    let (scheme, rest) = uri.split_once("://")?;
+    let transport = match scheme {
+        "tcp" | "tls" | "ws" | "wss" | "socks" | "sockstls" => Transport::Tcp,
+        "quic" => Transport::Udp,
+        _ => return None,
+    };
+
+    // Drop query parameters and, for socks, the target behind the proxy
+    let authority = rest.split(['?', '/']).next()?;
+    let (host, port) = authority.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((transport, host.to_string(), port.parse().ok()?))
+}
+
+pub fn hints_for(config: &YggdrasilConfig, listen: &[String]) -> FirewallHints {
+    let mut inbound: Vec<InboundRule> = Vec::new();
+    for uri in listen {
+        if let Some((transport, _, port)) = parse_endpoint(uri) {
+            if !inbound.iter().any(|r| r.transport == transport && r.port == port) {
+                inbound.push(InboundRule { transport, port, listen: uri.clone() });
+            }
+        }
+    }
+
+    let mut outbound: Vec<OutboundRule> = Vec::new();
+    for uri in &config.peers {
+        if let Some((transport, host, port)) = parse_endpoint(uri) {
+            if !outbound.iter().any(|r| r.transport == transport && r.host == host && r.port == port) {
+                outbound.push(OutboundRule { transport, host, port, peer: uri.clone() });
+            }
+        }
+    }
+
+    inbound.sort_by_key(|r| (r.port, r.transport));
+    outbound.sort_by(|a, b| (&a.host, a.port, a.transport).cmp(&(&b.host, b.port, b.transport)));
+    FirewallHints { inbound, outbound }
+}
+
+impl FirewallHints {
+    /// Render as an nftables table that accepts the listed traffic. Hosts
+    /// that are names rather than addresses only match on port.
+    pub fn to_nftables(&self, node_name: &str) -> String {
+        let mut out = format!("# yggman firewall hints for {}\ntable inet yggman {{\n", node_name);
+
+        out.push_str("    chain input {\n        type filter hook input priority 0; policy accept;\n");
+        for rule in &self.inbound {
+            out.push_str(&format!(
+                "        {} dport {} accept comment \"yggdrasil listen\"\n",
+                rule.transport.as_str(), rule.port
+            ));
+        }
+        out.push_str("    }\n\n");
+
+        out.push_str("    chain output {\n        type filter hook output priority 0; policy accept;\n");
+        for rule in &self.outbound {
+            let daddr = match rule.host.parse::<IpAddr>() {
+                Ok(IpAddr::V4(ip)) => format!("ip daddr {} ", ip),
+                Ok(IpAddr::V6(ip)) => format!("ip6 daddr {} ", ip),
+                Err(_) => String::new(),
+            };
+            out.push_str(&format!(
+                "        {}{} dport {} accept comment \"peer {}\"\n",
+                daddr, rule.transport.as_str(), rule.port, rule.host
+            ));
+        }
+        out.push_str("    }\n}\n");
+        out
+    }
+
+    /// Render as ufw commands. Hosts that are names rather than addresses
+    /// only match on port.
+    pub fn to_ufw(&self, node_name: &str) -> String {
+        let mut out = format!("# yggman firewall hints for {}\n", node_name);
+        for rule in &self.inbound {
+            out.push_str(&format!(
+                "ufw allow in {}/{} comment 'yggdrasil listen'\n",
+                rule.port, rule.transport.as_str()
+            ));
+        }
+        for rule in &self.outbound {
+            match rule.host.parse::<IpAddr>() {
+                Ok(ip) => out.push_str(&format!(
+                    "ufw allow out to {} port {} proto {} comment 'yggdrasil peer'\n",
+                    ip, rule.port, rule.transport.as_str()
+                )),
+                Err(_) => out.push_str(&format!(
+                    "ufw allow out {}/{} comment 'yggdrasil peer {}'\n",
+                    rule.port, rule.transport.as_str(), rule.host
+                )),
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,77 @@
+//! yggman's control plane as a library, for running it inside another
+//! service instead of as the `yggman` process. `Application::builder`
+//! assembles the same server the binary runs from an `AppConfig`:
+//!
+//! ```no_run
+//! # async fn embed() -> yggman_server::error::Result<()> {
+//! use yggman_server::config::AppConfig;
+//! use yggman_server::Application;
+//!
+//! let mut config = AppConfig::default();
+//! config.database.url = "sqlite:///var/lib/myservice/yggman.db".into();
+//! config.server.port = 8440;
+//!
+//! let mut app = Application::builder(config).build().await?;
+//! let nodes = app.node_manager();
+//! app.start().await?;
+//! for node in nodes.get_all_nodes().await {
+//!     println!("{} {:?}", node.name, node.yggdrasil_address());
+//! }
+//! app.stop().await
+//! # }
+//! ```
+//!
+//! `NodeManager` reads and changes the nodes and generates their configs,
+//! using the mesh generation of `yggman_core::mesh`; `topology` compares
+//! the intended mesh with what agents report.
+
+pub mod alerts;
+pub mod audit_log;
+pub mod auth_throttle;
+#[doc(hidden)]
+pub mod bench;
+pub mod broadcast_manager;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod cli;
+pub mod config;
+pub mod consistency;
+pub mod core;
+pub mod database;
+#[doc(hidden)]
+pub mod demo;
+pub mod enrollment;
+pub mod error;
+pub mod event_log;
+pub mod export;
+pub mod federation;
+#[doc(hidden)]
+pub mod init;
+pub mod firewall;
+pub mod log_level;
+pub mod manifest;
+pub mod modules;
+pub mod network_manager;
+pub mod node_events;
+pub mod node_manager;
+pub mod notifier;
+pub mod outbox;
+pub mod peer_schedules;
+pub mod preflight;
+pub mod search;
+pub mod settings_manager;
+pub mod stale_nodes;
+pub mod status_history;
+pub mod storage;
+pub mod systemd;
+pub mod topology;
+pub mod traffic_stats;
+pub mod websocket_state;
+
+pub use yggman_core::{mesh, yggdrasil};
+pub use yggman_proto::{redact, sealing, signing};
+
+pub use crate::core::app::Application;
+pub use crate::core::builder::ApplicationBuilder;
+pub use crate::node_manager::NodeManager;
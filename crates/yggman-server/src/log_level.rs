@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::error::AppError;
+
+const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error", "off"];
+
+/// The log filter of the running server, replaceable without a restart
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// Filter the server started with, restored by the SIGUSR1 toggle
+    initial: String,
+    current: Mutex<String>,
+}
+
+impl LogLevel {
+    /// Install the global subscriber, filtering by `RUST_LOG` when set and
+    /// by `level` for yggman otherwise
+    pub fn init(level: &str) -> Arc<Self> {
+        let filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| directives_for(level).into());
+        let initial = filter.to_string();
+        let (filter, handle) = reload::Layer::new(filter);
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .init();
+
+        Arc::new(Self {
+            handle,
+            current: Mutex::new(initial.clone()),
+            initial,
+        })
+    }
+
+    pub fn current(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Switch to `level`, either a bare level for yggman's own logs or a
+    /// full filter such as `yggman=trace,sea_orm=debug`. Returns the
+    /// filter now in effect.
+    pub fn set(&self, level: &str) -> Result<String, AppError> {
+        let directives = if LEVELS.contains(&level) {
+            directives_for(level)
+        } else {
+            level.to_string()
+        };
+        let filter = EnvFilter::try_new(&directives)
+            .map_err(|e| AppError::Config(format!("Invalid log level {}: {}", level, e)))?;
+        let applied = filter.to_string();
+
+        let mut current = self.current.lock().unwrap();
+        self.handle.reload(filter)
+            .map_err(|e| AppError::Config(format!("Failed to change log level: {}", e)))?;
+        *current = applied.clone();
+        Ok(applied)
+    }
+
+    /// Switch between debug and the startup filter
+    pub fn toggle_debug(&self) -> Result<String, AppError> {
+        if self.current() == self.initial {
+            self.set("debug")
+        } else {
+            self.set(&self.initial)
+        }
+    }
+
+    /// Toggle debug logging on every SIGUSR1
+    #[cfg(unix)]
+    pub fn watch_signal(self: Arc<Self>) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut signals = signal(SignalKind::user_defined1())?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                match self.toggle_debug() {
+                    Ok(filter) => tracing::warn!("Received SIGUSR1, log filter is now {}", filter),
+                    Err(e) => tracing::error!("Received SIGUSR1 but could not change the log level: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+fn directives_for(level: &str) -> String {
+    format!("yggman={},info", level)
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use yggman_server::{bench, check, cli, config, demo, init, log_level, signing, Application};
+
+fn main() -> Result<()> {
+    // Parse command line arguments
+    let cli_args = cli::CliArgs::parse_args();
+    
+    // Load environment variables with YGGMAN_ prefix
+    let env_config = cli::load_env_config()
+        .unwrap_or_else(|_| cli::EnvConfig::default());
+    
+    build_runtime(&cli_args, &env_config)?.block_on(run(cli_args, env_config))
+}
+
+/// Runtime sized by the server.workers and blocking thread settings. An
+/// unreadable config file falls back to the defaults here, `run` reports it.
+fn build_runtime(cli_args: &cli::CliArgs, env_config: &cli::EnvConfig) -> Result<tokio::runtime::Runtime> {
+    let server = config::ConfigManager::load_merged_config(cli_args, env_config)
+        .map(|config| config.server)
+        .unwrap_or_default();
+    // `yggman check` reports zeros as errors, everything else runs with one
+    Ok(tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(server.workers.max(1))
+        .max_blocking_threads(server.max_blocking_threads.max(1))
+        .thread_keep_alive(std::time::Duration::from_secs(server.thread_keep_alive))
+        .enable_all()
+        .build()?)
+}
+
+async fn run(cli_args: cli::CliArgs, env_config: cli::EnvConfig) -> Result<()> {
+    match &cli_args.command {
+        Some(cli::Command::Check { connect }) => {
+            std::process::exit(check::run(&cli_args, &env_config, *connect).await);
+        }
+        Some(cli::Command::Config { command: cli::ConfigCommand::Schema }) => {
+            print!("{}", config::schema::render());
+            return Ok(());
+        }
+        Some(cli::Command::Config { command: cli::ConfigCommand::Show { show_secrets } }) => {
+            let (config, sources) = config::ConfigManager::load_with_sources(&cli_args, &env_config)?;
+            print!("{}", config::effective::render(&config, &sources, *show_secrets));
+            return Ok(());
+        }
+        Some(cli::Command::Init(args)) => {
+            return init::run(&cli_args.config, args).await;
+        }
+        Some(cli::Command::Bench { nodes, iterations, server: Some(server), server_pubkey, enrollment_token, timeout, .. }) => {
+            let server_key = server_pubkey.as_deref().map(signing::parse_public_key).transpose()?;
+            let timeout = std::time::Duration::from_secs(*timeout);
+            return bench::run_protocol(server, server_key, enrollment_token.clone(), *nodes, *iterations, timeout).await;
+        }
+        Some(cli::Command::Bench { nodes, addresses, iterations, .. }) => {
+            bench::run(*nodes, *addresses, *iterations);
+            return Ok(());
+        }
+        Some(cli::Command::Demo(_)) | None => {}
+    }
+    
+    // Initialize tracing with log level from CLI or env
+    let level = if cli_args.debug {
+        "debug"
+    } else {
+        &cli_args.log_level
+    };
+    
+    let log_level = log_level::LogLevel::init(level);
+    #[cfg(unix)]
+    log_level.clone().watch_signal()?;
+    
+    tracing::info!("Starting yggman v{}", env!("CARGO_PKG_VERSION"));
+    tracing::debug!("CLI args: {:?}", cli_args);
+    tracing::debug!("Environment config: {:?}", env_config);
+    
+    let demo = match &cli_args.command {
+        Some(cli::Command::Demo(args)) => Some(args.clone()),
+        _ => None,
+    };
+    if let Some(args) = &demo {
+        if let Some(server) = &args.server {
+            let server_key = args.server_pubkey.as_deref().map(signing::parse_public_key).transpose()?;
+            return demo::run(args.clone(), server.clone(), server_key).await;
+        }
+    }
+    
+    // Load merged configuration
+    let (mut config, config_sources) = config::ConfigManager::load_with_sources(&cli_args, &env_config)?;
+    let demo_address = demo.is_some().then(|| {
+        // Nothing of the demo outlives it, and the simulated agents need plain TCP
+        config.database.ephemeral = true;
+        config.database.snapshot_file = None;
+        config.agent.require_enrollment = false;
+        config.server.read_only = false;
+        config.server.unix_socket = None;
+        config.server.tls.enabled = false;
+        config.server.tls.acme.enabled = false;
+        demo::local_address(&config.server)
+    });
+    tracing::info!("Configuration loaded from: CLI args, env vars, config file: {}", cli_args.config);
+    tracing::info!(
+        "Runtime: {} worker threads, up to {} blocking threads kept {}s when idle",
+        config.server.workers.max(1), config.server.max_blocking_threads.max(1), config.server.thread_keep_alive,
+    );
+    if config.database.ephemeral {
+        tracing::info!("Ephemeral mode: all state is kept in memory and lost on exit");
+    } else {
+        tracing::info!("Database URL: {}", config.database.url);
+    }
+    
+    let app = Application::builder(config)
+        .config_sources(config_sources)
+        .log_level(log_level)
+        .build()
+        .await?;
+    
+    if let (Some(args), Some(address)) = (demo, demo_address) {
+        tracing::info!("Demo: dashboard at http://{}/", address);
+        let server_key = app.context().signing_key.verifying_key();
+        tokio::spawn(demo::run(args, format!("ws://{}/ws/agent", address), Some(server_key)));
+    }
+    
+    app.run().await?;
+    
+    Ok(())
+}
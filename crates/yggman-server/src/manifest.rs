@@ -0,0 +1,813 @@
+//! Mesh manifest: everything yggman manages, as one versioned JSON
+//! document served at `GET /api/manifest`. `PUT /api/manifest` makes the
+//! server match a manifest: first it answers with the changes that would
+//! take and a confirmation, and only a second PUT of the same manifest with
+//! `?confirm=<confirmation>` applies them, provided nothing changed since.
+//! External reconcilers post the same document to `/api/reconcile`, which
+//! applies without that round trip when asked to.
+//!
+//! Version 1 of the layout:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "networks": [{
+//!     "id": "default", "name": "Default", "description": "",
+//!     "settings": {
+//!       "listen_template": ["tcp://0.0.0.0:9001"], "listen_template_rules": [],
+//!       "node_templates": [], "maintenance_windows": [], "peer_schedules": [],
+//!       "node_info_template": {}
+//!     }
+//!   }],
+//!   "nodes": [{
+//!     "id": "node-…", "network": "default", "name": "a", "public_key": "…",
+//!     "listen": [], "listen_policy": "template", "addresses": [], "external_peers": [],
+//!     "tags": [], "mtu": null, "node_info": {}, "if_name": null, "interface_peers": {},
+//!     "extra_allowed_keys": [], "extra_config": {}, "description": null, "owner": null,
+//!     "contact": null, "external_id": null, "provider": null
+//!   }],
+//!   "links": [{ "network": "default", "nodes": ["a", "b"] }],
+//!   "settings": { "auto_broadcast": true, "rollback_policy": {…}, "key_escrow_policy": "exportable" }
+//! }
+//! ```
+//!
+//! Nodes are matched by `id`, then by provider and `external_id`, then by
+//! network and name; unmatched nodes are created with new keys, and nodes
+//! missing from the manifest are deleted, as are missing networks other
+//! than the default one. `public_key` and `links` follow from the rest and
+//! are ignored when importing. A `key_escrow_policy` left out keeps the
+//! current one, and a `write_only` policy cannot be relaxed. Unknown fields
+//! are rejected.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::core::context::AppContext;
+use crate::error::AppError;
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::node_events::NodeLifecycle;
+use crate::node_manager::{NodeManager, NodeSpec};
+use crate::settings_manager::{KeyEscrowPolicy, ListenTemplateRule, MaintenanceWindow, NodeInfoTemplate, NodeTemplate, PeerSchedule, RollbackPolicy};
+use crate::signing::canonical_json;
+use crate::yggdrasil::{ListenPolicy, Node};
+
+/// Version of the manifest layout; manifests declare theirs in `version`
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub version: u32,
+    pub networks: Vec<ManifestNetwork>,
+    #[serde(default)]
+    pub nodes: Vec<ManifestNode>,
+    /// Pairs of nodes that peer, by name; exported for reference only
+    #[serde(default)]
+    pub links: Vec<ManifestLink>,
+    #[serde(default)]
+    pub settings: ServerSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestNetwork {
+    pub id: String,
+    /// When empty, the current name or, for a new network, the id
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub settings: NetworkSettings,
+}
+
+/// Settings of a network; those left out take their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    pub listen_template: Vec<String>,
+    pub listen_template_rules: Vec<ListenTemplateRule>,
+    pub node_templates: Vec<NodeTemplate>,
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    pub peer_schedules: Vec<PeerSchedule>,
+    pub node_info_template: NodeInfoTemplate,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            listen_template: vec!["tcp://0.0.0.0:9001".to_string()],
+            listen_template_rules: Vec::new(),
+            node_templates: Vec::new(),
+            maintenance_windows: Vec::new(),
+            peer_schedules: Vec::new(),
+            node_info_template: NodeInfoTemplate::default(),
+        }
+    }
+}
+
+/// Settings of the whole server; those left out take their defaults,
+/// except the key escrow policy, which is then left as it is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub auto_broadcast: bool,
+    pub rollback_policy: RollbackPolicy,
+    /// Can be tightened to `write_only` but never relaxed again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_escrow_policy: Option<KeyEscrowPolicy>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            auto_broadcast: true,
+            rollback_policy: RollbackPolicy::default(),
+            key_escrow_policy: None,
+        }
+    }
+}
+
+/// A node as set by hand; keys and what its agent reports are left out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestNode {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default = "default_network")]
+    pub network: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    #[serde(default)]
+    pub listen_policy: ListenPolicy,
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    #[serde(default)]
+    pub external_peers: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub mtu: Option<u16>,
+    #[serde(default)]
+    pub node_info: HashMap<String, Value>,
+    #[serde(default)]
+    pub if_name: Option<String>,
+    #[serde(default)]
+    pub interface_peers: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub extra_allowed_keys: Vec<String>,
+    #[serde(default)]
+    pub extra_config: HashMap<String, Value>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub contact: Option<String>,
+    #[serde(default)]
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+fn default_network() -> String {
+    DEFAULT_NETWORK.to_string()
+}
+
+impl ManifestNode {
+    fn from_node(node: &Node) -> Self {
+        Self {
+            id: Some(node.id.clone()),
+            network: node.network.clone(),
+            name: node.name.clone(),
+            public_key: Some(node.public_key.clone()),
+            listen: node.listen.clone(),
+            listen_policy: node.listen_policy,
+            addresses: node.addresses.clone(),
+            external_peers: node.external_peers.clone(),
+            tags: node.tags.clone(),
+            mtu: node.mtu,
+            node_info: node.node_info.clone(),
+            if_name: node.if_name.clone(),
+            interface_peers: node.interface_peers.clone(),
+            extra_allowed_keys: node.extra_allowed_keys.clone(),
+            extra_config: node.extra_config.clone(),
+            description: node.description.clone(),
+            owner: node.owner.clone(),
+            contact: node.contact.clone(),
+            external_id: node.external_id.clone(),
+            provider: node.provider.clone(),
+        }
+    }
+
+    fn spec(&self) -> NodeSpec {
+        NodeSpec {
+            name: self.name.clone(),
+            listen: self.listen.clone(),
+            listen_policy: self.listen_policy,
+            addresses: self.addresses.clone(),
+            tags: self.tags.clone(),
+            mtu: self.mtu,
+            node_info: self.node_info.clone(),
+            if_name: self.if_name.clone(),
+            interface_peers: self.interface_peers.clone(),
+            extra_allowed_keys: self.extra_allowed_keys.clone(),
+            extra_config: self.extra_config.clone(),
+            description: self.description.clone(),
+            owner: self.owner.clone(),
+            contact: self.contact.clone(),
+            external_id: self.external_id.clone(),
+            provider: self.provider.clone(),
+        }
+    }
+
+    /// The fields compared when diffing, as they would be stored
+    fn comparable(&self) -> Value {
+        let blank = |text: &Option<String>| text.as_ref().map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+        let mut node = self.clone();
+        node.id = None;
+        node.public_key = None;
+        node.if_name = node.if_name.filter(|name| name != "auto");
+        node.description = blank(&node.description);
+        node.owner = blank(&node.owner);
+        node.contact = blank(&node.contact);
+        node.external_id = blank(&node.external_id);
+        node.provider = blank(&node.provider);
+        serde_json::to_value(node).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestLink {
+    pub network: String,
+    pub nodes: [String; 2],
+}
+
+impl Manifest {
+    /// Check the manifest as a whole before anything is changed
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.version == 0 || self.version > MANIFEST_VERSION {
+            return Err(AppError::Config(format!(
+                "Manifest version {} is not supported, this build reads versions up to {}",
+                self.version, MANIFEST_VERSION
+            )));
+        }
+
+        let mut networks = HashSet::new();
+        for network in &self.networks {
+            if !crate::network_manager::is_valid_network_id(&network.id) {
+                return Err(AppError::Config(format!(
+                    "Invalid network id {:?}: use 1-64 lowercase letters, digits, '-' or '_'",
+                    network.id
+                )));
+            }
+            if !networks.insert(network.id.as_str()) {
+                return Err(AppError::Config(format!("Network {} is listed twice", network.id)));
+            }
+            let settings = &network.settings;
+            for window in &settings.maintenance_windows {
+                window.parse_schedule()?;
+            }
+            for schedule in &settings.peer_schedules {
+                schedule.validate()?;
+            }
+            settings.node_info_template.validate()?;
+        }
+        if !networks.contains(DEFAULT_NETWORK) {
+            return Err(AppError::Config(format!("The {} network cannot be removed, list it in the manifest", DEFAULT_NETWORK)));
+        }
+
+        let mut names = HashSet::new();
+        let mut ids = HashSet::new();
+        for node in &self.nodes {
+            if node.name.trim().is_empty() {
+                return Err(AppError::Config("Every node needs a name".to_string()));
+            }
+            if !networks.contains(node.network.as_str()) {
+                return Err(AppError::Config(format!("Node {} is in network {}, which the manifest does not list", node.name, node.network)));
+            }
+            if !names.insert((node.network.as_str(), node.name.as_str())) {
+                return Err(AppError::Config(format!("Node {} is listed twice in network {}", node.name, node.network)));
+            }
+            if let Some(id) = &node.id {
+                if !ids.insert(id.as_str()) {
+                    return Err(AppError::Config(format!("Node id {} is listed twice", id)));
+                }
+            }
+            node.spec().validate()
+                .map_err(|e| AppError::Config(format!("Node {}: {}", node.name, e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// The current state as a manifest
+pub async fn export(node_manager: &NodeManager, context: &AppContext) -> Result<Manifest, AppError> {
+    let settings_manager = &context.settings_manager;
+    let mut networks = Vec::new();
+    let mut nodes = Vec::new();
+    let mut links = Vec::new();
+    for network in context.network_manager.get_all_networks().await? {
+        let mut members = node_manager.get_nodes_in_network(&network.id).await;
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: HashMap<&str, &str> = members.iter().map(|node| (node.id.as_str(), node.name.as_str())).collect();
+        let mut pairs: Vec<ManifestLink> = node_manager.intended_links(&network.id).await
+            .into_iter()
+            .filter_map(|(a, b)| {
+                let mut pair = [names.get(a.as_str())?.to_string(), names.get(b.as_str())?.to_string()];
+                pair.sort();
+                Some(ManifestLink { network: network.id.clone(), nodes: pair })
+            })
+            .collect();
+        pairs.sort();
+        links.extend(pairs);
+        nodes.extend(members.iter().map(ManifestNode::from_node));
+
+        networks.push(ManifestNetwork {
+            settings: network_settings(context, &network.id).await?,
+            id: network.id,
+            name: network.name,
+            description: network.description,
+        });
+    }
+
+    Ok(Manifest {
+        version: MANIFEST_VERSION,
+        networks,
+        nodes,
+        links,
+        settings: ServerSettings {
+            auto_broadcast: settings_manager.get_auto_broadcast().await?,
+            rollback_policy: settings_manager.get_rollback_policy().await?,
+            key_escrow_policy: Some(settings_manager.get_key_escrow_policy().await?),
+        },
+    })
+}
+
+/// Settings of `network` as generation sees them, inherited ones included
+async fn network_settings(context: &AppContext, network: &str) -> Result<NetworkSettings, AppError> {
+    let settings_manager = &context.settings_manager;
+    Ok(NetworkSettings {
+        listen_template: settings_manager.get_listen_template(network).await?,
+        listen_template_rules: settings_manager.get_listen_template_rules(network).await?,
+        node_templates: settings_manager.get_node_templates(network).await?,
+        maintenance_windows: settings_manager.get_maintenance_windows(network).await?,
+        peer_schedules: settings_manager.get_peer_schedules(network).await?,
+        node_info_template: settings_manager.get_node_info_template(network).await?,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Network,
+    Node,
+    Setting,
+}
+
+/// One difference between the current state and a manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub action: Action,
+    pub kind: Kind,
+    /// Network of the node or setting; `None` for networks and server-wide settings
+    pub network: Option<String>,
+    /// Network id, node name or setting name
+    pub name: String,
+    /// Fields that differ, for updates
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+enum Step {
+    CreateNetwork(ManifestNetwork),
+    UpdateNetwork(ManifestNetwork),
+    NetworkSettings { network: String, settings: NetworkSettings, fields: Vec<String> },
+    ServerSettings { settings: ServerSettings, fields: Vec<String> },
+    DeleteNode(String),
+    UpdateNode(String, ManifestNode),
+    CreateNode(ManifestNode),
+    DeleteNetwork(String),
+}
+
+/// What it takes to go from the current state to a manifest
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// Digest of the current state and the manifest; applying requires it,
+    /// so that what is applied is what was reviewed
+    pub confirmation: String,
+    steps: Vec<Step>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Diff the current state against `manifest`, which must be valid
+pub async fn plan(node_manager: &NodeManager, context: &AppContext, manifest: &Manifest) -> Result<Plan, AppError> {
+    manifest.validate()?;
+    let current = export(node_manager, context).await?;
+    let mut changes = Vec::new();
+    let mut steps = Vec::new();
+
+    // Networks and their settings
+    let current_networks: HashMap<&str, &ManifestNetwork> = current.networks.iter().map(|n| (n.id.as_str(), n)).collect();
+    for network in &manifest.networks {
+        let mut network = network.clone();
+        let existing = current_networks.get(network.id.as_str());
+        if network.name.is_empty() {
+            network.name = existing.map_or_else(|| network.id.clone(), |existing| existing.name.clone());
+        }
+        match existing {
+            None => {
+                changes.push(Change { action: Action::Create, kind: Kind::Network, network: None, name: network.id.clone(), fields: Vec::new() });
+                steps.push(Step::CreateNetwork(network.clone()));
+            }
+            Some(existing) => {
+                let fields = differing_fields(&serde_json::json!({ "name": existing.name, "description": existing.description }), &serde_json::json!({ "name": network.name, "description": network.description }));
+                if !fields.is_empty() {
+                    changes.push(Change { action: Action::Update, kind: Kind::Network, network: None, name: network.id.clone(), fields });
+                    steps.push(Step::UpdateNetwork(network.clone()));
+                }
+            }
+        }
+
+        // A new network starts out with inherited settings
+        let current_settings = match existing {
+            Some(existing) => existing.settings.clone(),
+            None => network_settings(context, &network.id).await?,
+        };
+        let fields = differing_fields(&serde_json::to_value(&current_settings)?, &serde_json::to_value(&network.settings)?);
+        for field in &fields {
+            changes.push(Change { action: Action::Update, kind: Kind::Setting, network: Some(network.id.clone()), name: field.clone(), fields: Vec::new() });
+        }
+        if !fields.is_empty() {
+            steps.push(Step::NetworkSettings { network: network.id.clone(), settings: network.settings.clone(), fields });
+        }
+    }
+
+    if current.settings.key_escrow_policy == Some(KeyEscrowPolicy::WriteOnly)
+        && manifest.settings.key_escrow_policy.is_some_and(|policy| policy != KeyEscrowPolicy::WriteOnly)
+    {
+        return Err(AppError::Config("The write_only key escrow policy cannot be relaxed".to_string()));
+    }
+    let fields = differing_fields(&serde_json::to_value(&current.settings)?, &serde_json::to_value(&manifest.settings)?);
+    for field in &fields {
+        changes.push(Change { action: Action::Update, kind: Kind::Setting, network: None, name: field.clone(), fields: Vec::new() });
+    }
+    if !fields.is_empty() {
+        steps.push(Step::ServerSettings { settings: manifest.settings.clone(), fields });
+    }
+
+    // Nodes, matched by id first and then by network and name
+    let by_id: HashMap<&str, &ManifestNode> = current.nodes.iter().filter_map(|n| Some((n.id.as_deref()?, n))).collect();
+    let mut matched: HashSet<&str> = HashSet::new();
+    let mut matches: Vec<(&ManifestNode, Option<&ManifestNode>)> = Vec::new();
+    for node in &manifest.nodes {
+        let existing = node.id.as_deref().and_then(|id| by_id.get(id).copied());
+        if let Some(existing) = existing {
+            if existing.network != node.network {
+                return Err(AppError::Config(format!(
+                    "Node {} is in network {}, nodes cannot move to another network",
+                    existing.name, existing.network
+                )));
+            }
+            matched.insert(existing.id.as_deref().unwrap_or_default());
+        }
+        matches.push((node, existing));
+    }
+    match_nodes(&mut matches, &current.nodes, &mut matched, |a, b| {
+        a.external_id.is_some() && a.external_id == b.external_id && a.provider == b.provider
+    });
+    match_nodes(&mut matches, &current.nodes, &mut matched, |a, b| a.name == b.name);
+
+    for node in current.nodes.iter().filter(|n| !matched.contains(n.id.as_deref().unwrap_or_default())) {
+        changes.push(Change { action: Action::Delete, kind: Kind::Node, network: Some(node.network.clone()), name: node.name.clone(), fields: Vec::new() });
+        steps.push(Step::DeleteNode(node.id.clone().unwrap_or_default()));
+    }
+    for (node, existing) in &matches {
+        match existing {
+            Some(existing) => {
+                let fields = differing_fields(&existing.comparable(), &node.comparable());
+                if !fields.is_empty() {
+                    changes.push(Change { action: Action::Update, kind: Kind::Node, network: Some(node.network.clone()), name: existing.name.clone(), fields });
+                    steps.push(Step::UpdateNode(existing.id.clone().unwrap_or_default(), (*node).clone()));
+                }
+            }
+            None => {
+                changes.push(Change { action: Action::Create, kind: Kind::Node, network: Some(node.network.clone()), name: node.name.clone(), fields: Vec::new() });
+                steps.push(Step::CreateNode((*node).clone()));
+            }
+        }
+    }
+
+    for network in current.networks.iter().filter(|n| !manifest.networks.iter().any(|m| m.id == n.id)) {
+        changes.push(Change { action: Action::Delete, kind: Kind::Network, network: None, name: network.id.clone(), fields: Vec::new() });
+        steps.push(Step::DeleteNetwork(network.id.clone()));
+    }
+
+    let mut digest = Sha256::new();
+    digest.update(canonical_json(&serde_json::to_value(&current)?));
+    digest.update(canonical_json(&serde_json::to_value(manifest)?));
+    let confirmation = hex::encode(&digest.finalize()[..16]);
+
+    Ok(Plan { changes, confirmation, steps })
+}
+
+/// Pair manifest nodes still without a match with the first unmatched
+/// current node of their network that is the `same`
+fn match_nodes<'a>(
+    matches: &mut [(&ManifestNode, Option<&'a ManifestNode>)],
+    current: &'a [ManifestNode],
+    matched: &mut HashSet<&'a str>,
+    same: impl Fn(&ManifestNode, &ManifestNode) -> bool,
+) {
+    for (node, existing) in matches.iter_mut().filter(|m| m.1.is_none()) {
+        *existing = current.iter()
+            .find(|n| n.network == node.network && same(n, node) && !matched.contains(n.id.as_deref().unwrap_or_default()));
+        if let Some(existing) = existing {
+            matched.insert(existing.id.as_deref().unwrap_or_default());
+        }
+    }
+}
+
+/// Carry out `plan` and push the resulting configs. Stops at the first
+/// failure, leaving the steps before it applied; those are pushed all the
+/// same, so agents never lag behind what the database holds.
+pub async fn apply(node_manager: &Arc<NodeManager>, context: &AppContext, plan: Plan) -> Result<(), AppError> {
+    let mut applied = Applied::default();
+    let result = apply_steps(node_manager, context, plan, &mut applied).await;
+    if applied.steps == 0 {
+        return result;
+    }
+
+    if applied.schedules_changed {
+        crate::peer_schedules::reschedule();
+    }
+    if applied.removed {
+        // Revoke deleted nodes' keys everywhere now, regardless of maintenance windows
+        crate::websocket_state::broadcast_urgent_configuration_update(node_manager).await;
+    } else {
+        crate::websocket_state::broadcast_configuration_update(node_manager).await;
+    }
+    result
+}
+
+/// What `apply_steps` got to, counting the step that failed
+#[derive(Default)]
+struct Applied {
+    steps: usize,
+    removed: bool,
+    schedules_changed: bool,
+}
+
+async fn apply_steps(node_manager: &Arc<NodeManager>, context: &AppContext, plan: Plan, applied: &mut Applied) -> Result<(), AppError> {
+    let settings_manager = &context.settings_manager;
+    let network_manager = &context.network_manager;
+
+    for step in plan.steps {
+        applied.steps += 1;
+        match step {
+            Step::CreateNetwork(network) => {
+                network_manager.create_network(network.id, network.name, network.description).await?;
+            }
+            Step::UpdateNetwork(network) => {
+                network_manager.update_network(&network.id, network.name, network.description).await?;
+            }
+            Step::NetworkSettings { network, settings, fields } => {
+                for field in fields {
+                    match field.as_str() {
+                        "listen_template" => {
+                            settings_manager.set_listen_template(&network, settings.listen_template.clone()).await?;
+                            if network == DEFAULT_NETWORK {
+                                context.config_manager.update_listen_template(settings.listen_template.clone());
+                            }
+                        }
+                        "listen_template_rules" => settings_manager.set_listen_template_rules(&network, &settings.listen_template_rules).await?,
+                        "node_templates" => settings_manager.set_node_templates(&network, &settings.node_templates).await?,
+                        "maintenance_windows" => settings_manager.set_maintenance_windows(&network, &settings.maintenance_windows).await?,
+                        "peer_schedules" => {
+                            applied.schedules_changed = true;
+                            settings_manager.set_peer_schedules(&network, &settings.peer_schedules).await?;
+                        }
+                        "node_info_template" => settings_manager.set_node_info_template(&network, &settings.node_info_template).await?,
+                        _ => {}
+                    }
+                }
+            }
+            Step::ServerSettings { settings, fields } => {
+                for field in fields {
+                    match field.as_str() {
+                        "auto_broadcast" => settings_manager.set_auto_broadcast(settings.auto_broadcast).await?,
+                        "rollback_policy" => settings_manager.set_rollback_policy(&settings.rollback_policy).await?,
+                        "key_escrow_policy" => {
+                            let Some(policy) = settings.key_escrow_policy else { continue };
+                            let current = settings_manager.get_key_escrow_policy().await?;
+                            settings_manager.set_key_escrow_policy(policy).await?;
+                            let details = format!("Key escrow policy changed from {:?} to {:?} by manifest", current, policy);
+                            if let Err(e) = context.audit_log.record("admin", "key_escrow_policy", "settings", &details).await {
+                                tracing::error!("Failed to write audit log: {}", e);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Step::DeleteNode(node_id) => {
+                let node = node_manager.get_node_by_id(&node_id).await;
+                applied.removed = true;
+                node_manager.remove_node(&node_id).await?;
+                if let Some(node) = node {
+                    crate::node_events::record(context, NodeLifecycle::Deleted, &node).await;
+                }
+            }
+            Step::UpdateNode(node_id, node) => {
+                node_manager.replace_node(&node_id, node.spec(), node.external_peers.clone()).await?;
+                if let Some(node) = node_manager.get_node_by_id(&node_id).await {
+                    crate::node_events::record(context, NodeLifecycle::Updated, &node).await;
+                }
+            }
+            Step::CreateNode(node) => {
+                let mut created = node_manager.add_node(&node.network, node.spec()).await?;
+                if !node.external_peers.is_empty() {
+                    node_manager.set_external_peers(&created.id, node.external_peers.clone()).await?;
+                    created.external_peers = node.external_peers;
+                }
+                crate::node_events::record(context, NodeLifecycle::Created, &created).await;
+            }
+            Step::DeleteNetwork(network) => {
+                applied.removed = true;
+                network_manager.remove_network(&network).await?;
+                if let Err(e) = settings_manager.remove_network_settings(&network).await {
+                    tracing::warn!("Failed to remove settings of network {}: {}", network, e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Top-level keys of two objects whose values differ
+fn differing_fields(current: &Value, desired: &Value) -> Vec<String> {
+    let (Some(current), Some(desired)) = (current.as_object(), desired.as_object()) else {
+        return Vec::new();
+    };
+    desired.iter()
+        .filter(|(key, value)| current.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::app::Application;
+
+    fn manifest(value: Value) -> Manifest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn two_nodes() -> Manifest {
+        manifest(serde_json::json!({
+            "version": 1,
+            "networks": [{ "id": "default" }],
+            "nodes": [
+                { "name": "a", "listen": ["tcp://0.0.0.0:9001"], "addresses": ["192.0.2.1"] },
+                { "name": "b", "listen": ["tcp://0.0.0.0:9001"], "addresses": ["192.0.2.2"] }
+            ]
+        }))
+    }
+
+    fn node_changes(plan: &Plan) -> Vec<(Action, &str, Vec<&str>)> {
+        let mut changes: Vec<_> = plan.changes.iter()
+            .filter(|change| change.kind == Kind::Node)
+            .map(|change| (change.action, change.name.as_str(), change.fields.iter().map(String::as_str).collect()))
+            .collect();
+        changes.sort_by_key(|change| change.1);
+        changes
+    }
+
+    #[tokio::test]
+    async fn applied_manifest_plans_no_changes() {
+        let _pushes = crate::websocket_state::TEST_PUSHES.lock().await;
+        let app = Application::for_tests().await;
+        let (context, node_manager) = (app.context(), app.node_manager());
+
+        let planned = plan(&node_manager, &context, &two_nodes()).await.unwrap();
+        assert_eq!(node_changes(&planned), [(Action::Create, "a", vec![]), (Action::Create, "b", vec![])]);
+        apply(&node_manager, &context, planned).await.unwrap();
+        assert!(node_manager.get_node_by_name(DEFAULT_NETWORK, "a").await.is_some());
+
+        let again = plan(&node_manager, &context, &two_nodes()).await.unwrap();
+        assert!(again.is_empty(), "{:?}", again.changes);
+    }
+
+    #[tokio::test]
+    async fn plans_updates_and_deletions() {
+        let _pushes = crate::websocket_state::TEST_PUSHES.lock().await;
+        let app = Application::for_tests().await;
+        let (context, node_manager) = (app.context(), app.node_manager());
+        apply(&node_manager, &context, plan(&node_manager, &context, &two_nodes()).await.unwrap()).await.unwrap();
+        let a = node_manager.get_node_by_name(DEFAULT_NETWORK, "a").await.unwrap();
+        let revision = node_manager.current_revision().await;
+
+        let desired = manifest(serde_json::json!({
+            "version": 1,
+            "networks": [{ "id": "default" }, { "id": "lab" }],
+            "nodes": [
+                { "name": "a", "listen": ["tcp://0.0.0.0:9001"], "addresses": ["192.0.2.1"], "tags": ["edge"] },
+                { "network": "lab", "name": "c", "listen": ["tcp://0.0.0.0:9001"], "addresses": ["192.0.2.3"] }
+            ]
+        }));
+        let planned = plan(&node_manager, &context, &desired).await.unwrap();
+        assert_eq!(node_changes(&planned), [
+            (Action::Update, "a", vec!["tags"]),
+            (Action::Delete, "b", vec![]),
+            (Action::Create, "c", vec![]),
+        ]);
+        assert!(planned.changes.iter().any(|change| change.kind == Kind::Network && change.action == Action::Create && change.name == "lab"));
+
+        apply(&node_manager, &context, planned).await.unwrap();
+        let updated = node_manager.get_node_by_name(DEFAULT_NETWORK, "a").await.unwrap();
+        assert_eq!((updated.id, updated.public_key, updated.tags), (a.id, a.public_key, vec!["edge".to_string()]));
+        assert!(node_manager.get_node_by_name(DEFAULT_NETWORK, "b").await.is_none());
+        assert!(node_manager.get_node_by_name("lab", "c").await.is_some());
+        assert!(node_manager.current_revision().await > revision);
+    }
+
+    #[tokio::test]
+    async fn confirmation_follows_the_state() {
+        let _pushes = crate::websocket_state::TEST_PUSHES.lock().await;
+        let app = Application::for_tests().await;
+        let (context, node_manager) = (app.context(), app.node_manager());
+
+        let first = plan(&node_manager, &context, &two_nodes()).await.unwrap();
+        assert_eq!(plan(&node_manager, &context, &two_nodes()).await.unwrap().confirmation, first.confirmation);
+        apply(&node_manager, &context, first).await.unwrap();
+        let applied = plan(&node_manager, &context, &two_nodes()).await.unwrap();
+        assert_ne!(applied.confirmation, plan(&node_manager, &context, &manifest(serde_json::json!({
+            "version": 1,
+            "networks": [{ "id": "default" }]
+        }))).await.unwrap().confirmation);
+    }
+
+    #[tokio::test]
+    async fn partial_apply_pushes_what_it_changed() {
+        let _pushes = crate::websocket_state::TEST_PUSHES.lock().await;
+        let app = Application::for_tests().await;
+        let (context, node_manager) = (app.context(), app.node_manager());
+        apply(&node_manager, &context, plan(&node_manager, &context, &two_nodes()).await.unwrap()).await.unwrap();
+
+        let mut desired = two_nodes();
+        desired.nodes.truncate(1);
+        let planned = plan(&node_manager, &context, &desired).await.unwrap();
+        // Removed behind the plan's back, so deleting it fails
+        let b = node_manager.get_node_by_name(DEFAULT_NETWORK, "b").await.unwrap();
+        node_manager.remove_node(&b.id).await.unwrap();
+
+        let revision = node_manager.current_revision().await;
+        assert!(apply(&node_manager, &context, planned).await.is_err());
+        assert!(node_manager.current_revision().await > revision);
+    }
+
+    #[test]
+    fn validation_rejects_inconsistent_manifests() {
+        let without_default = manifest(serde_json::json!({ "version": 1, "networks": [{ "id": "lab" }] }));
+        assert!(without_default.validate().is_err());
+        let mut twice = two_nodes();
+        twice.nodes[1].name = "a".to_string();
+        assert!(twice.validate().is_err());
+        let mut elsewhere = two_nodes();
+        elsewhere.nodes[0].network = "lab".to_string();
+        assert!(elsewhere.validate().is_err());
+        let newer = manifest(serde_json::json!({ "version": MANIFEST_VERSION + 1, "networks": [{ "id": "default" }] }));
+        assert!(newer.validate().is_err());
+        assert!(two_nodes().validate().is_ok());
+    }
+
+    #[test]
+    fn differing_fields_lists_changed_keys() {
+        let current = serde_json::json!({ "name": "a", "tags": [], "mtu": null });
+        let desired = serde_json::json!({ "name": "a", "tags": ["edge"], "mtu": 1280 });
+        assert_eq!(differing_fields(&current, &desired), ["mtu", "tags"]);
+        assert!(differing_fields(&current, &current).is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+use crate::node_manager::NodeManager;
+
+/// Evaluates the alert rules from `[alerts]` against the node inventory on
+/// a fixed interval.
+pub struct AlertsModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    node_manager: Arc<NodeManager>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AlertsModule {
+    pub fn new(node_manager: Arc<NodeManager>) -> Self {
+        Self {
+            name: "alerts".to_string(),
+            context: None,
+            node_manager,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for AlertsModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Alerts module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        let config = context.config_manager.get().alerts.clone();
+        tracing::info!("Evaluating {} alert rules every {}s", config.rules.len(), config.evaluation_interval);
+        
+        let node_manager = self.node_manager.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.evaluation_interval.max(1)));
+            loop {
+                interval.tick().await;
+                let nodes = node_manager.get_all_nodes().await;
+                context.alerts.evaluate(&context, &nodes).await;
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("Alerts module stopped");
+        Ok(())
+    }
+}
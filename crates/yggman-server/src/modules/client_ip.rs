@@ -0,0 +1,163 @@
+//! The address of the client behind a reverse proxy. Forwarding headers are
+//! only believed when the connection comes from one of `trusted_proxies`,
+//! anyone else could put any address in them.
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::{request::Parts, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::core::context::AppContext;
+
+/// Address of the client that made the request, as far as trusted proxies
+/// tell; set on every request by `resolve_client_ip`
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<ClientIp>().copied().ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// An address or network, e.g. of trusted proxies
+#[derive(Debug, Clone, Copy)]
+pub struct IpNetwork {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Parse an address like `10.0.0.1` or a network like `10.0.0.0/8`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+        let network: IpAddr = address.trim().parse().map_err(|_| format!("\"{}\" is not an IP address", address))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.trim().parse::<u8>().ok().filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("\"{}\" is not a prefix length between 0 and {}", len, max_len))?,
+            None => max_len,
+        };
+        Ok(Self { network, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Middleware storing the `ClientIp` of each request
+pub async fn resolve_client_ip(
+    State(context): State<Arc<AppContext>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let trusted: Vec<IpNetwork> = context.config_manager.get().server.trusted_proxies.iter()
+        .filter_map(|proxy| IpNetwork::parse(proxy).ok())
+        .collect();
+    let client = client_ip(peer.ip(), request.headers(), &trusted);
+    request.extensions_mut().insert(ClientIp(client));
+    next.run(request).await
+}
+
+/// Middleware refusing agent connections from addresses outside
+/// `agent.allowed_ips`, when set; needs the `ClientIp` of the request
+pub async fn agent_allowlist(
+    State(context): State<Arc<AppContext>>,
+    ClientIp(client): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = context.config_manager.get();
+    let allowed = &config.agent.allowed_ips;
+    if allowed.is_empty() || allowed.iter().filter_map(|network| IpNetwork::parse(network).ok()).any(|network| network.contains(client)) {
+        return next.run(request).await;
+    }
+    
+    tracing::warn!("Refused agent connection from {}, not in agent.allowed_ips", client);
+    (StatusCode::FORBIDDEN, "Agent connections are not allowed from this address").into_response()
+}
+
+/// Walk the forwarding chain from the nearest hop back, past trusted
+/// proxies, to the first address that is not one; that is the client.
+/// `Forwarded` is preferred over `X-Forwarded-For` when both are present.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNetwork]) -> IpAddr {
+    let peer = peer.to_canonical();
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|proxy| proxy.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+
+    let forwarded = forwarded_for(headers);
+    let chain = if forwarded.is_empty() { x_forwarded_for(headers) } else { forwarded };
+    let mut client = peer;
+    for hop in chain.iter().rev() {
+        // An unparseable or obfuscated hop ends what can be known
+        let Some(ip) = parse_hop(hop) else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// `for=` parameters of RFC 7239 `Forwarded` headers, nearest client first
+fn forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    headers.get_all("forwarded").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for").then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .collect()
+}
+
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    headers.get_all("x-forwarded-for").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().to_string())
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+/// An address as proxies write it: bare, with a port, or bracketed IPv6
+/// with an optional port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+    if let Ok(address) = hop.parse::<SocketAddr>() {
+        return Some(address.ip().to_canonical());
+    }
+    hop.strip_prefix('[')
+        .and_then(|rest| rest.split(']').next())
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+}
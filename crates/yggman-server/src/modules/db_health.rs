@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+
+/// Pings the database on an interval so outages and recoveries are logged,
+/// announced through the notifier and visible at /api/health. The
+/// connection pool reconnects on its own once the database is back.
+pub struct DatabaseHealthModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    db: DatabaseConnection,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DatabaseHealthModule {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            name: "db_health".to_string(),
+            context: None,
+            db,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for DatabaseHealthModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Database health module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        let interval = context.config_manager.get().database.health_check_interval;
+        
+        let db = self.db.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1)));
+            loop {
+                interval.tick().await;
+                let Some(previous) = context.db_health.check(&db).await else {
+                    continue;
+                };
+                let status = context.db_health.status();
+                let outage_seconds = (status.since - previous.since).num_seconds();
+                if status.healthy {
+                    let message = format!(
+                        "Database reachable again after {}s ({})",
+                        outage_seconds,
+                        previous.error.as_deref().unwrap_or("unknown error"),
+                    );
+                    context.event_log.emit("database_restored", crate::event_log::EventSeverity::Warning, None, &message).await;
+                    context.notifier.send(&context.config_manager, "database_restored", serde_json::to_value(&status).unwrap_or_default());
+                } else {
+                    // The event log lives in the database, only the notifier can tell anyone
+                    tracing::error!("Database unreachable: {}", status.error.as_deref().unwrap_or("unknown error"));
+                    context.notifier.send(&context.config_manager, "database_unreachable", serde_json::to_value(&status).unwrap_or_default());
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("Database health module stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::config::DnsConfig;
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+use crate::node_manager::NodeManager;
+
+/// Keeps a DNS zone file mapping node names to Yggdrasil addresses in step
+/// with the node inventory.
+pub struct DnsZoneModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    node_manager: Arc<NodeManager>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DnsZoneModule {
+    pub fn new(node_manager: Arc<NodeManager>) -> Self {
+        Self {
+            name: "dns".to_string(),
+            context: None,
+            node_manager,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for DnsZoneModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("DNS zone module initialized");
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<()> {
+        let config = self.context.as_ref().unwrap().config_manager.get().dns.clone();
+
+        let Some(zone_file) = config.zone_file.clone() else {
+            tracing::info!("DNS zone file disabled");
+            return Ok(());
+        };
+
+        let node_manager = self.node_manager.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.refresh_interval.max(5)));
+            let mut written: Option<(String, u32)> = None;
+            loop {
+                interval.tick().await;
+
+                let records = crate::export::dns_records(&node_manager.get_all_nodes().await, config.ttl);
+                if written.as_ref().is_some_and(|(last, _)| *last == records) {
+                    continue;
+                }
+
+                // Serials must grow even when changes land within one second
+                let serial = unix_seconds().max(written.as_ref().map_or(0, |(_, serial)| serial + 1));
+                match write_zone(&config, &zone_file, &records, serial).await {
+                    Ok(()) => {
+                        tracing::info!("Wrote DNS zone {} (serial {})", zone_file, serial);
+                        written = Some((records, serial));
+                    }
+                    Err(e) => tracing::warn!("Failed to write DNS zone {}: {}", zone_file, e),
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("DNS zone module stopped");
+        Ok(())
+    }
+}
+
+/// Replace the zone file atomically, then run the reload command
+async fn write_zone(config: &DnsConfig, zone_file: &str, records: &str, serial: u32) -> Result<()> {
+    let temp_file = format!("{}.tmp", zone_file);
+    tokio::fs::write(&temp_file, crate::export::dns_zone(config, records, serial)).await?;
+    tokio::fs::rename(&temp_file, zone_file).await?;
+
+    if let Some(command) = &config.reload_command {
+        let output = tokio::process::Command::new("sh")
+            .args(["-c", command])
+            .output()
+            .await?;
+        if !output.status.success() {
+            tracing::warn!("DNS reload command failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+    Ok(())
+}
+
+pub fn unix_seconds() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default()
+}
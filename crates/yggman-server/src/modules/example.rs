@@ -4,6 +4,7 @@ use crate::core::context::AppContext;
 use crate::core::module::Module;
 use crate::error::Result;
 
+#[allow(dead_code)]
 pub struct ExampleModule {
     name: String,
     context: Option<Arc<AppContext>>,
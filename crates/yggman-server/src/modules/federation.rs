@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+use crate::node_manager::NodeManager;
+
+/// Periodically fetches the federation partners' border nodes, pushing new
+/// configs to the border nodes when they change.
+pub struct FederationModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    node_manager: Arc<NodeManager>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl FederationModule {
+    pub fn new(node_manager: Arc<NodeManager>) -> Self {
+        Self {
+            name: "federation".to_string(),
+            context: None,
+            node_manager,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for FederationModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Federation module initialized");
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        let config = context.config_manager.get().federation.clone();
+
+        if config.partners.is_empty() {
+            tracing::info!("Federation disabled, no partners configured");
+            return Ok(());
+        }
+        if context.config_manager.get().server.read_only {
+            tracing::info!("Federation refresh skipped in read-only mode");
+            return Ok(());
+        }
+
+        let node_manager = self.node_manager.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.refresh_interval.max(30)));
+            loop {
+                interval.tick().await;
+                match crate::federation::refresh(&config.partners, &context.settings_manager).await {
+                    Ok(true) => {
+                        tracing::info!("Border nodes of federation partners changed, updating border nodes");
+                        crate::websocket_state::broadcast_configuration_update(&node_manager).await;
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Failed to refresh federation partners: {}", e),
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("Federation module stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+//! GraphQL API over the same managers the REST handlers use, served at
+//! /api/graphql with subscriptions at /api/graphql/ws.
+
+use async_graphql::{Context, Enum, Json, Object, Result, Schema, SimpleObject, Subscription, ID};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::core::context::AppContext;
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::node_events::NodeLifecycle;
+use crate::node_manager::{NodeManager, NodeSpec};
+use crate::settings_manager::{MaintenanceWindow, NodeTemplate, RollbackPolicy};
+use crate::yggdrasil::{Node, YggdrasilConfig};
+
+pub type YggmanSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+pub fn build_schema(node_manager: Arc<NodeManager>, context: Arc<AppContext>) -> YggmanSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(node_manager)
+        .data(context)
+        .finish()
+}
+
+fn node_manager<'a>(ctx: &Context<'a>) -> &'a Arc<NodeManager> {
+    ctx.data_unchecked::<Arc<NodeManager>>()
+}
+
+fn app_context<'a>(ctx: &Context<'a>) -> &'a Arc<AppContext> {
+    ctx.data_unchecked::<Arc<AppContext>>()
+}
+
+/// Configs generated at most once per request, however many nodes of it
+/// ask for their peers; added to each request by the HTTP handler
+#[derive(Default)]
+pub struct GeneratedConfigs(tokio::sync::OnceCell<HashMap<String, YggdrasilConfig>>);
+
+fn ensure_writable(ctx: &Context<'_>) -> Result<()> {
+    if app_context(ctx).config_manager.get().server.read_only {
+        return Err("Server is in read-only mode".into());
+    }
+    Ok(())
+}
+
+pub struct NodeObject(Node);
+
+#[Object(name = "Node")]
+impl NodeObject {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn network(&self) -> &str {
+        &self.0.network
+    }
+
+    async fn public_key(&self) -> &str {
+        &self.0.public_key
+    }
+
+    /// Address inside the mesh, derived from the public key
+    async fn yggdrasil_address(&self) -> Option<String> {
+        self.0.yggdrasil_address().map(|a| a.to_string())
+    }
+
+    async fn listen(&self) -> &[String] {
+        self.0.advertised_listen()
+    }
+
+    /// `template` or `pinned`
+    async fn listen_policy(&self) -> &str {
+        self.0.listen_policy.as_str()
+    }
+
+    async fn addresses(&self) -> &[String] {
+        &self.0.addresses
+    }
+
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+
+    async fn external_peers(&self) -> &[String] {
+        &self.0.external_peers
+    }
+
+    /// Keys of devices outside yggman allowed to peer with the node
+    async fn extra_allowed_keys(&self) -> &[String] {
+        &self.0.extra_allowed_keys
+    }
+
+    /// Version of the agent as of its last registration
+    async fn agent_version(&self) -> Option<&str> {
+        self.0.agent_version.as_deref()
+    }
+
+    async fn yggdrasil_version(&self) -> Option<&str> {
+        self.0.yggdrasil_version.as_deref()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// Who runs the node
+    async fn owner(&self) -> Option<&str> {
+        self.0.owner.as_deref()
+    }
+
+    /// How to reach the owner
+    async fn contact(&self) -> Option<&str> {
+        self.0.contact.as_deref()
+    }
+
+    /// The node in an outside system, e.g. a Kubernetes node or cloud instance
+    async fn external_id(&self) -> Option<&str> {
+        self.0.external_id.as_deref()
+    }
+
+    /// System the external id belongs to
+    async fn provider(&self) -> Option<&str> {
+        self.0.provider.as_deref()
+    }
+
+    async fn connected(&self) -> bool {
+        crate::websocket_state::get_connected_node_ids().await.contains(&self.0.id)
+    }
+
+    /// What the agent last reported as applied
+    async fn status(&self) -> Option<AgentStatusObject> {
+        crate::websocket_state::get_agent_status(&self.0.id).await.map(|status| AgentStatusObject {
+            revision: status.revision,
+            listen: status.listen,
+            peers: status.peers,
+            allowed_public_keys: status.allowed_public_keys,
+            established_peers: status.established_peers,
+            drift: status.drift,
+            last_server_error: status.last_server_error.map(|report| ServerErrorObject {
+                code: serde_json::to_value(report.code).ok().and_then(|code| code.as_str().map(str::to_string)).unwrap_or_default(),
+                message: report.message,
+                received_at: chrono::DateTime::from_timestamp_millis(report.received_at as i64).unwrap_or_default(),
+            }),
+            reported_at: status.reported_at,
+        })
+    }
+
+    /// Peers generated for the node
+    async fn peers(&self, ctx: &Context<'_>) -> Vec<String> {
+        let peers = |configs: &HashMap<String, YggdrasilConfig>| configs.get(&self.0.id).map(|config| config.peers.clone()).unwrap_or_default();
+        match ctx.data_opt::<GeneratedConfigs>() {
+            Some(cache) => peers(cache.0.get_or_init(|| node_manager(ctx).generate_configs()).await),
+            None => peers(&node_manager(ctx).generate_configs().await),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "AgentStatus")]
+pub struct AgentStatusObject {
+    revision: u64,
+    listen: Vec<String>,
+    peers: Vec<String>,
+    allowed_public_keys: Vec<String>,
+    established_peers: Option<Vec<String>>,
+    /// The node's config file was edited by hand since the agent wrote it
+    drift: bool,
+    /// The last error the server answered the agent with
+    last_server_error: Option<ServerErrorObject>,
+    reported_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "ServerError")]
+pub struct ServerErrorObject {
+    code: String,
+    message: String,
+    received_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Network")]
+pub struct NetworkObject {
+    id: ID,
+    name: String,
+    description: String,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Ok,
+    Missing,
+    Unexpected,
+    Unknown,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Link")]
+pub struct LinkObject {
+    a: ID,
+    b: ID,
+    status: LinkState,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Settings")]
+pub struct SettingsObject {
+    auto_broadcast: bool,
+    key_escrow_policy: String,
+    rollback_policy: Json<RollbackPolicy>,
+    listen_template: Vec<String>,
+    maintenance_windows: Json<Vec<MaintenanceWindow>>,
+    node_templates: Json<Vec<NodeTemplate>>,
+}
+
+#[derive(SimpleObject, Clone)]
+#[graphql(name = "NodeStatusEvent")]
+pub struct NodeStatusObject {
+    node_id: ID,
+    connected: bool,
+    revision: Option<u64>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn networks(&self, ctx: &Context<'_>) -> Result<Vec<NetworkObject>> {
+        let networks = app_context(ctx).network_manager.get_all_networks().await?;
+        Ok(networks.into_iter()
+            .map(|n| NetworkObject { id: ID(n.id), name: n.name, description: n.description })
+            .collect())
+    }
+
+    /// Nodes of one network, or of all networks
+    async fn nodes(&self, ctx: &Context<'_>, network: Option<String>) -> Vec<NodeObject> {
+        let nodes = match network {
+            Some(network) => node_manager(ctx).get_nodes_in_network(&network).await,
+            None => node_manager(ctx).get_all_nodes().await,
+        };
+        nodes.into_iter().map(NodeObject).collect()
+    }
+
+    async fn node(&self, ctx: &Context<'_>, id: ID) -> Option<NodeObject> {
+        node_manager(ctx).get_node_by_id(&id).await.map(NodeObject)
+    }
+
+    /// Intended and established links of a network
+    async fn links(&self, ctx: &Context<'_>, network: Option<String>) -> Vec<LinkObject> {
+        let network = network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+        crate::topology::connectivity(node_manager(ctx), &network).await
+            .links
+            .into_iter()
+            .map(|link| LinkObject {
+                a: ID(link.a),
+                b: ID(link.b),
+                status: match link.status {
+                    crate::topology::LinkStatus::Ok => LinkState::Ok,
+                    crate::topology::LinkStatus::Missing => LinkState::Missing,
+                    crate::topology::LinkStatus::Unexpected => LinkState::Unexpected,
+                    crate::topology::LinkStatus::Unknown => LinkState::Unknown,
+                },
+            })
+            .collect()
+    }
+
+    /// Global settings plus those of a network
+    async fn settings(&self, ctx: &Context<'_>, network: Option<String>) -> Result<SettingsObject> {
+        let network = network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+        let settings = &app_context(ctx).settings_manager;
+        Ok(SettingsObject {
+            auto_broadcast: settings.get_auto_broadcast().await?,
+            key_escrow_policy: serde_json::to_value(settings.get_key_escrow_policy().await?)?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            rollback_policy: Json(settings.get_rollback_policy().await?),
+            listen_template: settings.get_listen_template(&network).await?,
+            maintenance_windows: Json(settings.get_maintenance_windows(&network).await?),
+            node_templates: Json(settings.get_node_templates(&network).await?),
+        })
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn add_node(
+        &self,
+        ctx: &Context<'_>,
+        network: Option<String>,
+        name: String,
+        #[graphql(default)] listen: Vec<String>,
+        #[graphql(default)] addresses: Vec<String>,
+        #[graphql(default)] tags: Vec<String>,
+    ) -> Result<NodeObject> {
+        ensure_writable(ctx)?;
+        let network = network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+        if app_context(ctx).network_manager.get_network(&network).await?.is_none() {
+            return Err(format!("Network '{}' not found", network).into());
+        }
+
+        let spec = NodeSpec { name, listen, addresses, tags, ..Default::default() };
+        let node = node_manager(ctx).add_node(&network, spec).await?;
+        crate::node_events::record(app_context(ctx), NodeLifecycle::Created, &node).await;
+        crate::websocket_state::broadcast_configuration_update(node_manager(ctx)).await;
+        Ok(NodeObject(node))
+    }
+
+    /// Change a node; omitted fields keep their current value
+    async fn update_node(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        name: Option<String>,
+        listen: Option<Vec<String>>,
+        addresses: Option<Vec<String>>,
+        tags: Option<Vec<String>>,
+    ) -> Result<NodeObject> {
+        ensure_writable(ctx)?;
+        let manager = node_manager(ctx);
+        let node = manager.get_node_by_id(&id).await.ok_or("Node not found")?;
+
+        manager.update_node(
+            &id,
+            name.unwrap_or(node.name),
+            listen.unwrap_or(node.listen),
+            addresses.unwrap_or(node.addresses),
+            tags,
+        ).await?;
+        crate::websocket_state::broadcast_node_change(manager, &[id.as_str()]).await;
+        let node = manager.get_node_by_id(&id).await.ok_or("Node not found")?;
+        crate::node_events::record(app_context(ctx), NodeLifecycle::Updated, &node).await;
+        Ok(NodeObject(node))
+    }
+
+    async fn delete_node(&self, ctx: &Context<'_>, id: ID) -> Result<bool> {
+        ensure_writable(ctx)?;
+        let node = node_manager(ctx).get_node_by_id(&id).await.ok_or("Node not found")?;
+        node_manager(ctx).remove_node(&id).await?;
+        crate::node_events::record(app_context(ctx), NodeLifecycle::Deleted, &node).await;
+        // Revoke the node's key everywhere now, regardless of maintenance windows
+        crate::websocket_state::broadcast_urgent_configuration_update(node_manager(ctx)).await;
+        Ok(true)
+    }
+
+    async fn set_auto_broadcast(&self, ctx: &Context<'_>, enabled: bool) -> Result<bool> {
+        ensure_writable(ctx)?;
+        app_context(ctx).settings_manager.set_auto_broadcast(enabled).await?;
+        Ok(enabled)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Agent connects, disconnects and applied revisions, optionally for
+    /// one node only
+    async fn node_status(&self, node_id: Option<ID>) -> impl Stream<Item = NodeStatusObject> {
+        let receiver = crate::websocket_state::subscribe_node_status();
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .filter(move |event| futures::future::ready(node_id.as_ref().is_none_or(|id| id.as_str() == event.node_id)))
+        .map(|event| NodeStatusObject {
+            node_id: ID(event.node_id),
+            connected: event.connected,
+            revision: event.revision,
+        })
+    }
+}
@@ -0,0 +1,354 @@
+//! gRPC control API over the same managers the REST and GraphQL handlers
+//! use, generated from proto/yggman.proto and served on its own port when
+//! `grpc.enabled` is set.
+
+// The generated service traits fix `tonic::Status` as the error type
+#![allow(clippy::result_large_err)]
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::{AppError, Result};
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::node_events::NodeLifecycle;
+use crate::node_manager::{NodeManager, NodeSpec};
+use crate::websocket_state::NodeStatusEvent;
+use crate::yggdrasil::Node;
+
+#[allow(clippy::all)]
+pub mod pb {
+    tonic::include_proto!("yggman.v1");
+}
+
+use pb::nodes_server::{Nodes, NodesServer};
+use pb::settings_server::{Settings, SettingsServer};
+use pb::topology_server::{Topology, TopologyServer};
+
+/// Serves the Nodes, Topology and Settings services.
+pub struct GrpcModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    node_manager: Arc<NodeManager>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl GrpcModule {
+    pub fn new(node_manager: Arc<NodeManager>) -> Self {
+        Self {
+            name: "grpc".to_string(),
+            context: None,
+            node_manager,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for GrpcModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("gRPC module initialized");
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        let config = context.config_manager.get().grpc.clone();
+
+        if !config.enabled {
+            tracing::info!("gRPC API disabled");
+            return Ok(());
+        }
+
+        let bind_addr = format!("{}:{}", config.bind_address, config.port);
+        let listener = tokio::net::TcpListener::bind(&bind_addr).await.map_err(AppError::Io)?;
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| AppError::Config(format!("Failed to listen for gRPC on {}: {}", bind_addr, e)))?;
+        tracing::info!("Starting gRPC API on {}", bind_addr);
+
+        let api = ControlApi { node_manager: self.node_manager.clone(), context };
+        let server = tonic::transport::Server::builder()
+            .add_service(NodesServer::new(api.clone()))
+            .add_service(TopologyServer::new(api.clone()))
+            .add_service(SettingsServer::new(api))
+            .serve_with_incoming(incoming);
+        let handle = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("gRPC module stopped");
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct ControlApi {
+    node_manager: Arc<NodeManager>,
+    context: Arc<AppContext>,
+}
+
+impl ControlApi {
+    fn ensure_writable(&self) -> std::result::Result<(), Status> {
+        if self.context.config_manager.get().server.read_only {
+            return Err(Status::failed_precondition("Server is in read-only mode"));
+        }
+        Ok(())
+    }
+
+    /// `network`, or the default network when empty, provided it exists
+    async fn network(&self, network: String) -> std::result::Result<String, Status> {
+        let network = if network.is_empty() { DEFAULT_NETWORK.to_string() } else { network };
+        match self.context.network_manager.get_network(&network).await.map_err(internal)? {
+            Some(_) => Ok(network),
+            None => Err(Status::not_found(format!("Network '{}' not found", network))),
+        }
+    }
+
+    async fn require_node(&self, id: &str) -> std::result::Result<Node, Status> {
+        self.node_manager.get_node_by_id(id).await.ok_or_else(|| Status::not_found("Node not found"))
+    }
+
+    async fn connectivity(&self, network: &str) -> pb::Connectivity {
+        let links = crate::topology::connectivity(&self.node_manager, network).await
+            .links
+            .into_iter()
+            .map(|link| pb::Link {
+                a: link.a,
+                b: link.b,
+                status: match link.status {
+                    crate::topology::LinkStatus::Ok => pb::LinkStatus::Ok,
+                    crate::topology::LinkStatus::Missing => pb::LinkStatus::Missing,
+                    crate::topology::LinkStatus::Unexpected => pb::LinkStatus::Unexpected,
+                    crate::topology::LinkStatus::Unknown => pb::LinkStatus::Unknown,
+                } as i32,
+            })
+            .collect();
+        pb::Connectivity {
+            network: network.to_string(),
+            links,
+            generated_at: Some(std::time::SystemTime::now().into()),
+        }
+    }
+
+    /// Global settings plus those of `network`
+    async fn settings(&self, network: &str) -> std::result::Result<pb::SettingsSnapshot, Status> {
+        let settings = &self.context.settings_manager;
+        let key_escrow_policy = serde_json::to_value(settings.get_key_escrow_policy().await.map_err(internal)?)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(pb::SettingsSnapshot {
+            auto_broadcast: settings.get_auto_broadcast().await.map_err(internal)?,
+            key_escrow_policy: key_escrow_policy.as_str().unwrap_or_default().to_string(),
+            listen_template: settings.get_listen_template(network).await.map_err(internal)?,
+        })
+    }
+}
+
+fn internal(e: AppError) -> Status {
+    Status::internal(e.to_string())
+}
+
+async fn node_message(node: Node, connected: &HashSet<String>) -> pb::Node {
+    let applied_revision = crate::websocket_state::get_agent_status(&node.id).await.map(|status| status.revision);
+    pb::Node {
+        yggdrasil_address: node.yggdrasil_address().map(|a| a.to_string()).unwrap_or_default(),
+        listen: node.advertised_listen().to_vec(),
+        connected: connected.contains(&node.id),
+        applied_revision,
+        id: node.id,
+        name: node.name,
+        network: node.network,
+        public_key: node.public_key,
+        addresses: node.addresses,
+        tags: node.tags,
+    }
+}
+
+/// Agent connects, disconnects and applied revisions as they happen
+fn node_status_events() -> BoxStream<'static, NodeStatusEvent> {
+    let receiver = crate::websocket_state::subscribe_node_status();
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+#[tonic::async_trait]
+impl Nodes for ControlApi {
+    type WatchNodeStatusStream = BoxStream<'static, std::result::Result<pb::NodeStatusEvent, Status>>;
+
+    async fn list_nodes(&self, request: Request<pb::ListNodesRequest>) -> std::result::Result<Response<pb::ListNodesResponse>, Status> {
+        let network = request.into_inner().network;
+        let nodes = match network.is_empty() {
+            true => self.node_manager.get_all_nodes().await,
+            false => self.node_manager.get_nodes_in_network(&self.network(network).await?).await,
+        };
+        let connected = crate::websocket_state::get_connected_node_ids().await;
+        let mut messages = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            messages.push(node_message(node, &connected).await);
+        }
+        Ok(Response::new(pb::ListNodesResponse { nodes: messages }))
+    }
+
+    async fn get_node(&self, request: Request<pb::GetNodeRequest>) -> std::result::Result<Response<pb::Node>, Status> {
+        let node = self.require_node(&request.into_inner().id).await?;
+        let connected = crate::websocket_state::get_connected_node_ids().await;
+        Ok(Response::new(node_message(node, &connected).await))
+    }
+
+    async fn add_node(&self, request: Request<pb::AddNodeRequest>) -> std::result::Result<Response<pb::Node>, Status> {
+        self.ensure_writable()?;
+        let request = request.into_inner();
+        let network = self.network(request.network).await?;
+
+        let spec = NodeSpec {
+            name: request.name,
+            listen: request.listen,
+            addresses: request.addresses,
+            tags: request.tags,
+            ..Default::default()
+        };
+        let node = self.node_manager.add_node(&network, spec).await
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        crate::node_events::record(&self.context, NodeLifecycle::Created, &node).await;
+        crate::websocket_state::broadcast_configuration_update(&self.node_manager).await;
+        Ok(Response::new(node_message(node, &HashSet::new()).await))
+    }
+
+    /// Change a node; omitted fields keep their current value
+    async fn update_node(&self, request: Request<pb::UpdateNodeRequest>) -> std::result::Result<Response<pb::Node>, Status> {
+        self.ensure_writable()?;
+        let request = request.into_inner();
+        let node = self.require_node(&request.id).await?;
+
+        self.node_manager.update_node(
+            &request.id,
+            request.name.unwrap_or(node.name),
+            request.listen.map(|list| list.values).unwrap_or(node.listen),
+            request.addresses.map(|list| list.values).unwrap_or(node.addresses),
+            request.tags.map(|list| list.values),
+        ).await.map_err(|e| Status::invalid_argument(e.to_string()))?;
+        crate::websocket_state::broadcast_node_change(&self.node_manager, &[request.id.as_str()]).await;
+        let node = self.require_node(&request.id).await?;
+        crate::node_events::record(&self.context, NodeLifecycle::Updated, &node).await;
+        let connected = crate::websocket_state::get_connected_node_ids().await;
+        Ok(Response::new(node_message(node, &connected).await))
+    }
+
+    async fn delete_node(&self, request: Request<pb::DeleteNodeRequest>) -> std::result::Result<Response<pb::DeleteNodeResponse>, Status> {
+        self.ensure_writable()?;
+        let id = request.into_inner().id;
+        let node = self.require_node(&id).await?;
+        self.node_manager.remove_node(&id).await.map_err(internal)?;
+        crate::node_events::record(&self.context, NodeLifecycle::Deleted, &node).await;
+        // Revoke the node's key everywhere now, regardless of maintenance windows
+        crate::websocket_state::broadcast_urgent_configuration_update(&self.node_manager).await;
+        Ok(Response::new(pb::DeleteNodeResponse {}))
+    }
+
+    async fn watch_node_status(&self, request: Request<pb::WatchNodeStatusRequest>) -> std::result::Result<Response<Self::WatchNodeStatusStream>, Status> {
+        let node_id = request.into_inner().node_id;
+        let events = node_status_events()
+            .filter(move |event| futures::future::ready(node_id.is_empty() || node_id == event.node_id))
+            .map(|event| Ok(pb::NodeStatusEvent {
+                node_id: event.node_id,
+                connected: event.connected,
+                revision: event.revision,
+            }));
+        Ok(Response::new(events.boxed()))
+    }
+}
+
+#[tonic::async_trait]
+impl Topology for ControlApi {
+    type WatchConnectivityStream = BoxStream<'static, std::result::Result<pb::Connectivity, Status>>;
+
+    async fn get_connectivity(&self, request: Request<pb::GetConnectivityRequest>) -> std::result::Result<Response<pb::Connectivity>, Status> {
+        let network = self.network(request.into_inner().network).await?;
+        Ok(Response::new(self.connectivity(&network).await))
+    }
+
+    /// The current connectivity, then again whenever an agent of the
+    /// network connects, disconnects or applies a revision
+    async fn watch_connectivity(&self, request: Request<pb::GetConnectivityRequest>) -> std::result::Result<Response<Self::WatchConnectivityStream>, Status> {
+        let network = self.network(request.into_inner().network).await?;
+        let current = self.connectivity(&network).await;
+
+        let api = self.clone();
+        let updates = node_status_events().filter_map(move |event| {
+            let api = api.clone();
+            let network = network.clone();
+            async move {
+                let node = api.node_manager.get_node_by_id(&event.node_id).await?;
+                if node.network != network {
+                    return None;
+                }
+                Some(Ok(api.connectivity(&network).await))
+            }
+        });
+        Ok(Response::new(futures::stream::once(futures::future::ready(Ok(current))).chain(updates).boxed()))
+    }
+}
+
+#[tonic::async_trait]
+impl Settings for ControlApi {
+    async fn get_settings(&self, request: Request<pb::GetSettingsRequest>) -> std::result::Result<Response<pb::SettingsSnapshot>, Status> {
+        let network = self.network(request.into_inner().network).await?;
+        Ok(Response::new(self.settings(&network).await?))
+    }
+
+    async fn set_auto_broadcast(&self, request: Request<pb::SetAutoBroadcastRequest>) -> std::result::Result<Response<pb::SettingsSnapshot>, Status> {
+        self.ensure_writable()?;
+        self.context.settings_manager.set_auto_broadcast(request.into_inner().enabled).await.map_err(internal)?;
+        Ok(Response::new(self.settings(DEFAULT_NETWORK).await?))
+    }
+
+    async fn set_listen_template(&self, request: Request<pb::SetListenTemplateRequest>) -> std::result::Result<Response<pb::SettingsSnapshot>, Status> {
+        self.ensure_writable()?;
+        let request = request.into_inner();
+        let network = self.network(request.network).await?;
+        let settings_manager = &self.context.settings_manager;
+
+        let previous = settings_manager.get_listen_template(&network).await.map_err(internal)?;
+        settings_manager.set_listen_template(&network, request.template.clone()).await.map_err(internal)?;
+        if network == DEFAULT_NETWORK {
+            self.context.config_manager.update_listen_template(request.template.clone());
+        }
+
+        if request.apply_to_existing {
+            let updated = self.node_manager.follow_listen_templates(&network).await
+                .map_err(|e| Status::internal(format!("Template saved, but applying it to existing nodes failed: {}", e)))?;
+            if previous != request.template || !updated.is_empty() {
+                crate::websocket_state::broadcast_configuration_update(&self.node_manager).await;
+            }
+        }
+        Ok(Response::new(self.settings(&network).await?))
+    }
+}
@@ -0,0 +1,19 @@
+pub mod alerts;
+pub mod client_ip;
+pub mod db_health;
+pub mod dns;
+pub mod example;
+pub mod federation;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod observer;
+pub mod public_peers;
+pub mod snapshot;
+pub mod tls;
+pub mod traffic;
+#[cfg(feature = "ui")]
+pub mod ui;
+pub mod unix_socket;
+pub mod web;
+pub mod websocket;
\ No newline at end of file
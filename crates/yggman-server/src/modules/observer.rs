@@ -0,0 +1,144 @@
+//! Read-only WebSocket stream for dashboards, wall displays and bots. It
+//! carries the topology of every network and live status events, never
+//! private keys or configs, and accepts nothing from the client.
+
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use crate::core::context::AppContext;
+use crate::database::entities::event::Model as Event;
+use crate::node_manager::NodeManager;
+use crate::topology::{ConnectivityLink, ConnectivitySummary};
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ObserverMessage {
+    /// Sent on connect and after every pushed revision
+    Topology {
+        revision: u64,
+        networks: Vec<ObservedNetwork>,
+    },
+    NodeStatus {
+        node_id: String,
+        connected: bool,
+        revision: Option<u64>,
+    },
+    Event {
+        #[serde(flatten)]
+        event: Event,
+    },
+}
+
+#[derive(Serialize)]
+struct ObservedNetwork {
+    id: String,
+    name: String,
+    nodes: Vec<ObservedNode>,
+    links: Vec<ConnectivityLink>,
+    summary: ConnectivitySummary,
+}
+
+#[derive(Serialize)]
+struct ObservedNode {
+    id: String,
+    name: String,
+    public_key: String,
+    tags: Vec<String>,
+    connected: bool,
+    /// Revision the agent last reported as applied
+    revision: Option<u64>,
+    agent_version: Option<String>,
+}
+
+pub async fn handle_observer_socket(socket: WebSocket, node_manager: Arc<NodeManager>, context: Arc<AppContext>) {
+    let (mut sender, mut receiver) = socket.split();
+    // Subscribe before the first snapshot so nothing falls between the two
+    let mut statuses = crate::websocket_state::subscribe_node_status();
+    let mut revisions = crate::websocket_state::subscribe_revisions();
+    let mut events = context.event_log.subscribe();
+
+    let mut message = Some(topology(&node_manager, &context).await);
+    loop {
+        if let Some(message) = message.take() {
+            let Ok(json) = serde_json::to_string(&message) else {
+                continue;
+            };
+            if sender.send(Message::Text(json)).await.is_err() {
+                break;
+            }
+        }
+
+        message = tokio::select! {
+            status = statuses.recv() => match status {
+                Ok(status) => Some(ObserverMessage::NodeStatus {
+                    node_id: status.node_id,
+                    connected: status.connected,
+                    revision: status.revision,
+                }),
+                // Missed statuses are covered by a fresh snapshot
+                Err(RecvError::Lagged(_)) => Some(topology(&node_manager, &context).await),
+                Err(RecvError::Closed) => break,
+            },
+            revision = revisions.recv() => match revision {
+                Ok(_) | Err(RecvError::Lagged(_)) => Some(topology(&node_manager, &context).await),
+                Err(RecvError::Closed) => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) => Some(ObserverMessage::Event { event }),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Observer fell behind, skipped {} events", skipped);
+                    None
+                }
+                Err(RecvError::Closed) => break,
+            },
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Observers only listen
+                Some(Ok(_)) => None,
+            },
+        };
+    }
+
+    debug!("Observer disconnected");
+}
+
+async fn topology(node_manager: &NodeManager, context: &AppContext) -> ObserverMessage {
+    let networks = context.network_manager.get_all_networks().await.unwrap_or_else(|e| {
+        warn!("Failed to list networks for observers: {}", e);
+        Vec::new()
+    });
+
+    let mut observed = Vec::new();
+    for network in networks {
+        let connectivity = crate::topology::connectivity(node_manager, &network.id).await;
+        let mut nodes = Vec::new();
+        for node in node_manager.get_nodes_in_network(&network.id).await {
+            let status = crate::websocket_state::get_agent_status(&node.id).await;
+            nodes.push(ObservedNode {
+                connected: connectivity.nodes.iter().any(|n| n.id == node.id && n.connected),
+                revision: status.map(|status| status.revision),
+                id: node.id,
+                name: node.name,
+                public_key: node.public_key,
+                tags: node.tags,
+                agent_version: node.agent_version,
+            });
+        }
+        observed.push(ObservedNetwork {
+            id: network.id,
+            name: network.name,
+            nodes,
+            links: connectivity.links,
+            summary: connectivity.summary,
+        });
+    }
+
+    ObserverMessage::Topology {
+        revision: node_manager.current_revision().await,
+        networks: observed,
+    }
+}
@@ -0,0 +1,151 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::config::PublicPeersConfig;
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+use crate::settings_manager::{ExternalPeer, SettingsManager};
+
+/// Periodically imports the community public peer list so its entries can
+/// be assigned to nodes as external peers.
+pub struct PublicPeersModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for PublicPeersModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PublicPeersModule {
+    pub fn new() -> Self {
+        Self {
+            name: "public_peers".to_string(),
+            context: None,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for PublicPeersModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Public peers module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        let config = context.config_manager.get().public_peers.clone();
+        
+        if !config.enabled {
+            tracing::info!("Public peers import disabled");
+            return Ok(());
+        }
+        if context.config_manager.get().server.read_only {
+            tracing::info!("Public peers import skipped in read-only mode");
+            return Ok(());
+        }
+        
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.refresh_interval.max(60)));
+            loop {
+                interval.tick().await;
+                match refresh_public_peers(&config, &context.settings_manager).await {
+                    Ok(count) => tracing::info!("Imported {} public peers from {}", count, config.url),
+                    Err(e) => tracing::warn!("Failed to import public peers: {}", e),
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("Public peers module stopped");
+        Ok(())
+    }
+}
+
+/// Fetch the public peer list, filter it by region and latency, and store
+/// the result in settings. Returns the number of imported peers.
+pub async fn refresh_public_peers(config: &PublicPeersConfig, settings_manager: &SettingsManager) -> Result<usize> {
+    let body: serde_json::Value = reqwest::Client::new()
+        .get(&config.url)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    
+    let peers = parse_public_peers(&body, config);
+    settings_manager.set_imported_public_peers(&peers).await?;
+    
+    Ok(peers.len())
+}
+
+/// Parse the `publicnodes.json` layout: a map of region files (e.g.
+/// "germany.md") to a map of peer URIs and their probe results.
+fn parse_public_peers(body: &serde_json::Value, config: &PublicPeersConfig) -> Vec<ExternalPeer> {
+    let mut peers = Vec::new();
+    
+    let Some(regions) = body.as_object() else {
+        return peers;
+    };
+    
+    for (file, entries) in regions {
+        let region = file
+            .rsplit('/')
+            .next()
+            .unwrap_or(file)
+            .trim_end_matches(".md")
+            .to_lowercase();
+        
+        if !config.regions.is_empty() && !config.regions.iter().any(|r| r.eq_ignore_ascii_case(&region)) {
+            continue;
+        }
+        
+        let Some(entries) = entries.as_object() else {
+            continue;
+        };
+        
+        for (uri, status) in entries {
+            // Entries without probe data are kept; explicitly down ones are not
+            if status.get("up").and_then(|v| v.as_bool()) == Some(false) {
+                continue;
+            }
+            
+            let response_ms = status.get("response_ms").and_then(|v| v.as_u64());
+            if let (Some(max), Some(latency)) = (config.max_latency_ms, response_ms) {
+                if latency > max {
+                    continue;
+                }
+            }
+            
+            peers.push(ExternalPeer {
+                uri: uri.clone(),
+                region: region.clone(),
+                response_ms,
+            });
+        }
+    }
+    
+    peers.sort_by_key(|p| p.response_ms.unwrap_or(u64::MAX));
+    peers
+}
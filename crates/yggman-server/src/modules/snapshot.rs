@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+
+/// Saves the in-memory database of an ephemeral server to a SQLite file,
+/// which a regular server can later open with --database-url.
+pub struct SnapshotModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    db: DatabaseConnection,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SnapshotModule {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            name: "snapshot".to_string(),
+            context: None,
+            db,
+            task: Mutex::new(None),
+        }
+    }
+    
+    fn snapshot_file(&self) -> Option<String> {
+        let config = self.context.as_ref()?.config_manager.get();
+        config.database.snapshot_file.clone().filter(|_| config.database.ephemeral)
+    }
+}
+
+#[async_trait]
+impl Module for SnapshotModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Snapshot module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let Some(snapshot_file) = self.snapshot_file() else {
+            return Ok(());
+        };
+        let interval = self.context.as_ref().unwrap().config_manager.get().database.snapshot_interval;
+        tracing::info!("Saving snapshots to {} every {}s", snapshot_file, interval);
+        
+        let db = self.db.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1)));
+            // The first tick fires immediately, with nothing worth saving yet
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = crate::database::snapshot_database(&db, &snapshot_file).await {
+                    tracing::warn!("Failed to save snapshot to {}: {}", snapshot_file, e);
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        if let Some(snapshot_file) = self.snapshot_file() {
+            match crate::database::snapshot_database(&self.db, &snapshot_file).await {
+                Ok(()) => tracing::info!("Saved final snapshot to {}", snapshot_file),
+                Err(e) => tracing::warn!("Failed to save snapshot to {}: {}", snapshot_file, e),
+            }
+        }
+        tracing::info!("Snapshot module stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! HTTPS for the web server, with a certificate from files or obtained and
+//! renewed over ACME, so small deployments need no reverse proxy in front.
+
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::Router;
+use futures::StreamExt;
+use hyper::body::Incoming;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+use crate::config::TlsConfig;
+use crate::error::{AppError, Result};
+
+/// Clients get this long to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serve `app` over TLS on `listener` in the background
+pub fn spawn(listener: TcpListener, app: Router, tls: &TlsConfig) -> Result<()> {
+    if tls.acme.enabled {
+        spawn_acme(listener, app, tls);
+        return Ok(());
+    }
+
+    let acceptor = TlsAcceptor::from(Arc::new(load_server_config(tls)?));
+    tokio::spawn(async move {
+        loop {
+            let (tcp, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let app = app.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                    Ok(Ok(stream)) => serve_connection(stream, peer, app).await,
+                    Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => tracing::debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn load_server_config(tls: &TlsConfig) -> Result<ServerConfig> {
+    let (Some(cert_file), Some(key_file)) = (&tls.cert_file, &tls.key_file) else {
+        return Err(AppError::Config("server.tls needs cert_file and key_file unless acme is enabled".to_string()));
+    };
+
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| AppError::Config(format!("Failed to read {}: {}", path, e)))
+    };
+    let certs = rustls_pemfile::certs(&mut BufReader::new(read(cert_file)?.as_slice()))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Config(format!("Invalid certificate in {}: {}", cert_file, e)))?;
+    if certs.is_empty() {
+        return Err(AppError::Config(format!("No certificate found in {}", cert_file)));
+    }
+    let key = rustls_pemfile::private_key(&mut BufReader::new(read(key_file)?.as_slice()))
+        .map_err(|e| AppError::Config(format!("Invalid private key in {}: {}", key_file, e)))?
+        .ok_or_else(|| AppError::Config(format!("No private key found in {}", key_file)))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| AppError::Config(format!("Unusable certificate or key: {}", e)))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    tracing::info!("Serving HTTPS with the certificate from {}", cert_file);
+    Ok(config)
+}
+
+fn spawn_acme(listener: TcpListener, app: Router, tls: &TlsConfig) {
+    let acme = &tls.acme;
+    let mut config = rustls_acme::AcmeConfig::new(&acme.domains)
+        .contact(acme.contact.iter().map(|email| format!("mailto:{}", email)))
+        .cache(rustls_acme::caches::DirCache::new(acme.cache_dir.clone()));
+    config = match &acme.directory_url {
+        Some(url) => config.directory(url),
+        None => config.directory_lets_encrypt(!acme.staging),
+    };
+    tracing::info!("Serving HTTPS for {} with certificates over ACME", acme.domains.join(", "));
+
+    // Answers TLS-ALPN-01 challenges itself, handing on everything else,
+    // and orders a new certificate before the current one expires
+    let tcp = Box::pin(futures::stream::unfold(listener, |listener| async move {
+        let connection = listener.accept().await.map(|(tcp, _)| tcp);
+        Some((connection, listener))
+    }));
+    let mut incoming = config.tokio_incoming(tcp, vec![b"http/1.1".to_vec()]);
+    tokio::spawn(async move {
+        while let Some(connection) = incoming.next().await {
+            match connection {
+                Ok(stream) => {
+                    let (tcp, _) = stream.get_ref().get_ref();
+                    match tcp.get_ref().peer_addr() {
+                        Ok(peer) => {
+                            tokio::spawn(serve_connection(stream, peer, app.clone()));
+                        }
+                        Err(e) => tracing::debug!("Dropping connection without a peer address: {}", e),
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    });
+}
+
+/// Serve HTTP/1.1 on an accepted connection, the way `axum::serve` does
+pub(crate) async fn serve_connection<S>(stream: S, peer: SocketAddr, app: Router)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // What `into_make_service_with_connect_info` provides on plain HTTP
+    let app = app.map_request(move |mut request: Request<Incoming>| {
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    });
+    let result = hyper::server::conn::http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+        // Agent and observer WebSockets
+        .with_upgrades()
+        .await;
+    if let Err(e) = result {
+        tracing::debug!("Connection from {} ended with an error: {}", peer, e);
+    }
+}
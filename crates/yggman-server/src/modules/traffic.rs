@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::error::Result;
+
+/// How often detailed traffic buckets are merged and old ones deleted
+const COMPACT_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Applies the traffic retention policy; agents' counters are recorded as
+/// their status reports arrive.
+pub struct TrafficModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for TrafficModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrafficModule {
+    pub fn new() -> Self {
+        Self {
+            name: "traffic".to_string(),
+            context: None,
+            task: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for TrafficModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Traffic module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap().clone();
+        
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(COMPACT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let config = context.config_manager.get().traffic.clone();
+                if let Err(e) = context.traffic_stats.compact(&config).await {
+                    tracing::warn!("Failed to compact traffic statistics: {}", e);
+                }
+            }
+        });
+        *self.task.lock().unwrap() = Some(handle);
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(handle) = self.task.lock().unwrap().take() {
+            handle.abort();
+        }
+        tracing::info!("Traffic module stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+//! The dashboard pages embedded into the binary, left out by builds without
+//! the `ui` feature, which serve the API only.
+
+use axum::{extract::Path, response::Html, routing::get, Router};
+
+pub fn routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+        .route("/", get(index_handler))
+        .route("/edit/:id", get(edit_page_handler))
+        .route("/topology", get(topology_page_handler))
+}
+
+async fn index_handler() -> Html<&'static str> {
+    Html(include_str!("../../static/index.html"))
+}
+
+// Edit page handler
+async fn edit_page_handler(Path(node_id): Path<String>) -> Html<String> {
+    let html = include_str!("../../static/edit.html");
+    let content = html.replace("{{NODE_ID}}", &node_id);
+    Html(content)
+}
+
+// Live mesh graph, drawn from the observer stream at /ws/observe
+async fn topology_page_handler() -> Html<&'static str> {
+    Html(include_str!("../../static/topology.html"))
+}
@@ -0,0 +1,84 @@
+//! The web API on a unix socket next to the TCP port, for local tools and
+//! reverse proxies that prefer sockets over loopback TCP.
+
+use axum::Router;
+use std::net::SocketAddr;
+
+use crate::config::ServerConfig;
+use crate::error::{AppError, Result};
+use crate::systemd::ActivatedSockets;
+
+/// What trusted_proxies and logs see as the client of socket connections
+#[cfg_attr(not(unix), allow(dead_code))]
+const SOCKET_PEER: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 0);
+
+/// Serve `app` in the background on the unix socket systemd passed, or on
+/// `server.unix_socket` when set. Returns the path of a socket this server
+/// created, for `remove` to clean up.
+#[cfg(unix)]
+pub fn spawn(server: &ServerConfig, activated: &mut ActivatedSockets, app: Router) -> Result<Option<String>> {
+    if let Some(listener) = activated.unix.take() {
+        listener.set_nonblocking(true)?;
+        serve(tokio::net::UnixListener::from_std(listener)?, app);
+        return Ok(None);
+    }
+    let Some(path) = &server.unix_socket else {
+        return Ok(None);
+    };
+    serve(bind(path, &server.unix_socket_mode)?, app);
+    tracing::info!("Serving the API on unix socket {}", path);
+    Ok(Some(path.clone()))
+}
+
+#[cfg(not(unix))]
+pub fn spawn(server: &ServerConfig, _activated: &mut ActivatedSockets, _app: Router) -> Result<Option<String>> {
+    match server.unix_socket {
+        Some(_) => Err(AppError::Config("server.unix_socket is only supported on unix".to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Bind `path` with the octal permissions in `mode`, replacing a socket
+/// left behind by an earlier run
+#[cfg(unix)]
+fn bind(path: &str, mode: &str) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let mode = u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| AppError::Config(format!("server.unix_socket_mode \"{}\" is not an octal file mode", mode)))?;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => return Err(AppError::Config(format!("{} exists and is not a socket", path))),
+        Err(_) => {}
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn serve(listener: tokio::net::UnixListener, app: Router) {
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(super::tls::serve_connection(stream, SOCKET_PEER, app.clone()));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to accept unix socket connection: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                }
+            }
+        }
+    });
+}
+
+/// Remove the socket file on shutdown
+pub fn remove(path: &str) {
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::debug!("Failed to remove unix socket {}: {}", path, e);
+    }
+}
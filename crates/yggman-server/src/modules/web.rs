@@ -0,0 +1,2604 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, State, Path, Query, WebSocketUpgrade},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post, put, delete},
+    Router,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+
+use crate::broadcast_manager::{BroadcastManager, CanaryOptions, RolloutStrategy};
+use crate::core::context::AppContext;
+use crate::core::module::Module;
+use crate::modules::client_ip::ClientIp;
+use crate::error::Result;
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::node_events::NodeLifecycle;
+use crate::node_manager::{BulkChanges, NodeFilter, NodeManager, NodeOptions, NodeSpec};
+use crate::config::FederationPartner;
+use crate::federation::FederatedNode;
+use crate::settings_manager::{KeyEscrowPolicy, ListenTemplateRule, MaintenanceWindow, NodeInfoTemplate, NodeTemplate, NodeTiming, PeerSchedule, RollbackPolicy};
+use crate::database::entities::network::Model as Network;
+use crate::yggdrasil::{ListenPolicy, Node, YggdrasilConfig};
+
+#[derive(Clone)]
+struct AppState {
+    node_manager: Arc<NodeManager>,
+    broadcast_manager: Arc<BroadcastManager>,
+    context: Arc<AppContext>,
+    graphql_schema: crate::modules::graphql::YggmanSchema,
+}
+
+pub struct WebModule {
+    name: String,
+    context: Option<Arc<AppContext>>,
+    node_manager: Arc<NodeManager>,
+    /// Unix socket this module created, removed again on stop
+    unix_socket: std::sync::Mutex<Option<String>>,
+}
+
+impl WebModule {
+    pub fn new(node_manager: Arc<NodeManager>) -> Self {
+        Self {
+            name: "web".to_string(),
+            context: None,
+            node_manager,
+            unix_socket: std::sync::Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Module for WebModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn init(&mut self, context: Arc<AppContext>) -> Result<()> {
+        self.context = Some(context);
+        tracing::info!("Web module initialized");
+        Ok(())
+    }
+    
+    async fn start(&self) -> Result<()> {
+        let context = self.context.as_ref().unwrap();
+        let config = context.config_manager.get();
+        let port = config.server.port;
+        
+        tracing::info!("Starting web server on port {}", port);
+        
+        let broadcast_manager = Arc::new(BroadcastManager::new(self.node_manager.clone(), context.event_log.clone()));
+        if config.server.read_only {
+            tracing::info!("Read-only mode: changes, agent connections and broadcasts are disabled");
+        } else {
+            tokio::spawn(broadcast_manager.clone().run_rollback_watchdog());
+            tokio::spawn(crate::websocket_state::run_deferred_delivery(self.node_manager.clone()));
+            tokio::spawn(crate::stale_nodes::run(self.node_manager.clone(), context.clone()));
+            tokio::spawn(crate::peer_schedules::run(self.node_manager.clone(), context.clone()));
+        }
+        
+        let app_state = AppState {
+            node_manager: self.node_manager.clone(),
+            broadcast_manager,
+            context: context.clone(),
+            graphql_schema: crate::modules::graphql::build_schema(self.node_manager.clone(), context.clone()),
+        };
+        
+        let app = Router::new();
+        #[cfg(feature = "ui")]
+        let app = app.merge(crate::modules::ui::routes());
+        let app = app
+            .route("/api/networks", get(get_networks_handler))
+            .route("/api/networks", post(add_network_handler))
+            .route("/api/networks/:net", get(get_network_handler))
+            .route("/api/networks/:net", delete(delete_network_handler))
+            .route("/api/public-peers/imported", get(get_imported_public_peers_handler))
+            .route("/api/public-peers/refresh", post(refresh_public_peers_handler))
+            .route("/api/settings/key-escrow", get(get_key_escrow_handler))
+            .route("/api/settings/key-escrow", put(update_key_escrow_handler))
+            .route("/api/audit", get(get_audit_log_handler))
+            .route("/api/tokens", get(get_enrollment_tokens_handler))
+            .route("/api/tokens", post(create_enrollment_token_handler))
+            .route("/api/tokens/:id", delete(delete_enrollment_token_handler))
+            .route("/api/server/public-key", get(get_server_public_key_handler))
+            .route("/api/system/config", get(get_system_config_handler))
+            .route("/api/system/log-level", get(get_log_level_handler))
+            .route("/api/system/log-level", put(update_log_level_handler))
+            .route("/api/maintenance/deferred", get(get_deferred_updates_handler))
+            .route("/api/settings/auto-broadcast", get(get_auto_broadcast_handler))
+            .route("/api/settings/auto-broadcast", put(update_auto_broadcast_handler))
+            .route("/api/broadcasts", get(get_broadcasts_handler))
+            .route("/api/broadcasts", post(start_broadcast_handler))
+            .route("/api/broadcasts/:id", get(get_broadcast_handler))
+            .route("/api/settings/rollback-policy", get(get_rollback_policy_handler))
+            .route("/api/settings/rollback-policy", put(update_rollback_policy_handler))
+            .route("/api/settings/node-timing", get(get_node_timing_handler))
+            .route("/api/settings/node-timing", put(update_node_timing_handler))
+            .route("/api/federation/nodes", get(get_federation_nodes_handler))
+            .route("/api/federation/partners", get(get_federation_partners_handler))
+            .route("/api/events", get(get_events_handler))
+            .route("/api/health", get(health_handler))
+            .route("/api/system/cache", get(get_cache_stats_handler))
+            .route("/api/system/outbox", get(get_outbox_handler))
+            .route("/api/system/consistency", get(get_consistency_handler))
+            .route("/api/system/preflight", get(get_preflight_handler))
+            .route("/api/topology/rebroadcast", post(rebroadcast_handler))
+            .route("/api/alerts", get(get_alerts_handler))
+            .route("/api/stale-nodes", get(get_stale_nodes_handler))
+            .route("/api/stale-nodes/:id/restore", post(restore_stale_node_handler))
+            .route("/api/search", get(search_handler))
+            .route("/api/whoami", get(whoami_handler))
+            .route("/api/sd/prometheus", get(prometheus_sd_handler))
+            .route("/api/export/dns-zone", get(export_dns_zone_handler))
+            .route("/api/manifest", get(get_manifest_handler))
+            .route("/api/manifest", put(update_manifest_handler))
+            .route("/api/reconcile", post(reconcile_handler))
+            .route("/api/graphql", post(graphql_handler))
+            .route("/api/graphql/ws", get(graphql_ws_handler))
+            // Network-scoped API, legacy paths operate on the default network
+            .nest("/api", network_routes())
+            .nest("/api/networks/:net", network_routes())
+            .route("/ws/agent", get(ws_agent_handler).layer(axum::middleware::from_fn_with_state(context.clone(), crate::modules::client_ip::agent_allowlist)))
+            .route("/ws/observe", get(ws_observe_handler))
+            .layer(axum::middleware::from_fn_with_state(config.server.read_only, read_only_guard))
+            .layer(axum::middleware::from_fn_with_state(context.clone(), crate::modules::client_ip::resolve_client_ip))
+            .layer(CorsLayer::permissive())
+            .with_state(app_state);
+        
+        let bind_addr = format!("{}:{}", config.server.bind_address, port);
+        
+        let mut activated = crate::systemd::activated_sockets();
+        let listener = match activated.tcp.take() {
+            Some(listener) => {
+                listener.set_nonblocking(true)?;
+                tokio::net::TcpListener::from_std(listener)?
+            }
+            None => tokio::net::TcpListener::bind(&bind_addr)
+                .await
+                .map_err(crate::error::AppError::Io)?,
+        };
+            
+        *self.unix_socket.lock().unwrap() = crate::modules::unix_socket::spawn(&config.server, &mut activated, app.clone())?;
+        if config.server.tls.enabled {
+            crate::modules::tls::spawn(listener, app, &config.server.tls)?;
+        } else {
+            tokio::spawn(async move {
+                axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+                    .await
+                    .expect("Failed to run web server");
+            });
+        }
+        
+        Ok(())
+    }
+    
+    async fn stop(&self) -> Result<()> {
+        if let Some(path) = self.unix_socket.lock().unwrap().take() {
+            crate::modules::unix_socket::remove(&path);
+        }
+        tracing::info!("Web module stopped");
+        Ok(())
+    }
+}
+
+/// On a read-only server, refuse requests that could change state: anything
+/// but GET, HEAD and OPTIONS, and agent connections, which register nodes.
+/// GraphQL queries are POSTed too; its mutations check the mode themselves.
+async fn read_only_guard(
+    State(read_only): State<bool>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let safe_method = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let path = request.uri().path();
+    // Changing the log level touches no managed state
+    let exempt = path == "/api/graphql" || path == "/api/system/log-level";
+    if !read_only || (safe_method && path != "/ws/agent") || exempt {
+        return next.run(request).await;
+    }
+    
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "Server is in read-only mode"
+        })),
+    ).into_response()
+}
+
+fn network_routes() -> Router<AppState> {
+    Router::new()
+        .route("/nodes", get(get_nodes_handler))
+        .route("/nodes", post(add_node_handler))
+        .route("/nodes/bulk-update", post(bulk_update_nodes_handler))
+        .route("/nodes/:id", get(get_node_handler))
+        .route("/nodes/:id", put(update_node_handler))
+        .route("/nodes/:id", delete(delete_node_handler))
+        .route("/nodes/:id/clone", post(clone_node_handler))
+        .route("/nodes/:id/keys", get(get_node_keys_handler))
+        .route("/nodes/:id/agent-token", get(get_agent_token_handler))
+        .route("/node-templates", get(get_node_templates_handler))
+        .route("/node-templates", put(update_node_templates_handler))
+        .route("/configs", get(get_configs_handler))
+        .route("/nodes/:id/config", get(get_node_config_handler))
+        .route("/nodes/:id/effective-peers", get(get_effective_peers_handler))
+        .route("/nodes/:id/firewall", get(get_node_firewall_handler))
+        .route("/nodes/:id/availability", get(get_node_availability_handler))
+        .route("/nodes/:id/traffic", get(get_node_traffic_handler))
+        .route("/topology/connectivity", get(get_connectivity_handler))
+        .route("/fleet/versions", get(get_fleet_versions_handler))
+        .route("/export/ansible", get(export_ansible_handler))
+        .route("/export/ssh-config", get(export_ssh_config_handler))
+        .route("/nodes/:id/external-peers", put(update_external_peers_handler))
+        .route("/public-peers", get(get_public_peers_handler))
+        .route("/settings/listen-template", get(get_listen_template_handler))
+        .route("/settings/listen-template", put(update_listen_template_handler))
+        .route("/settings/listen-template-rules", get(get_listen_template_rules_handler))
+        .route("/settings/listen-template-rules", put(update_listen_template_rules_handler))
+        .route("/settings/maintenance-windows", get(get_maintenance_windows_handler))
+        .route("/settings/maintenance-windows", put(update_maintenance_windows_handler))
+        .route("/settings/peer-schedules", get(get_peer_schedules_handler))
+        .route("/settings/peer-schedules", put(update_peer_schedules_handler))
+        .route("/settings/node-info-template", get(get_node_info_template_handler))
+        .route("/settings/node-info-template", put(update_node_info_template_handler))
+}
+
+/// Network a request operates on, taken from the `:net` path segment or
+/// the default network for the legacy un-prefixed routes.
+struct NetworkScope(String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for NetworkScope {
+    type Rejection = StatusCode;
+    
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> std::result::Result<Self, Self::Rejection> {
+        let params = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map(|Path(params)| params)
+            .unwrap_or_default();
+        
+        let Some(network) = params.get("net") else {
+            return Ok(NetworkScope(DEFAULT_NETWORK.to_string()));
+        };
+        
+        match state.context.network_manager.get_network(network).await {
+            Ok(Some(_)) => Ok(NetworkScope(network.clone())),
+            Ok(None) => Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                tracing::error!("Failed to look up network {}: {}", network, e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+/// Caller that presented the configured admin token as a Bearer token
+struct AdminAuth;
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = (StatusCode, &'static str);
+    
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> std::result::Result<Self, Self::Rejection> {
+        let config = state.context.config_manager.get();
+        let Some(expected) = config.server.admin_token.as_ref().map(|t| t.expose().as_str()).filter(|t| !t.is_empty()) else {
+            return Err((StatusCode::FORBIDDEN, "Admin endpoints are disabled, no admin_token configured"));
+        };
+        
+        let provided = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        
+        match check_token(state, parts, provided, &[expected], "admin token").await? {
+            true => Ok(AdminAuth),
+            false => Err((StatusCode::UNAUTHORIZED, "Invalid or missing admin token")),
+        }
+    }
+}
+
+/// Caller that presented the observer or admin token, as a Bearer token or
+/// in the `token` query parameter since browsers cannot set headers on
+/// WebSocket requests
+struct ObserverAuth;
+
+#[derive(serde::Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for ObserverAuth {
+    type Rejection = (StatusCode, &'static str);
+    
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> std::result::Result<Self, Self::Rejection> {
+        let config = state.context.config_manager.get();
+        let accepted: Vec<&str> = [&config.server.observer_token, &config.server.admin_token]
+            .into_iter()
+            .filter_map(|token| token.as_ref().map(|t| t.expose().as_str()).filter(|t| !t.is_empty()))
+            .collect();
+        if accepted.is_empty() {
+            return Err((StatusCode::FORBIDDEN, "Observer stream is disabled, no observer_token configured"));
+        }
+        
+        let query_token = Query::<TokenQuery>::try_from_uri(&parts.uri).ok().and_then(|query| query.0.token);
+        let provided = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or(query_token.as_deref());
+        
+        match check_token(state, parts, provided, &accepted, "observer token").await? {
+            true => Ok(ObserverAuth),
+            false => Err((StatusCode::UNAUTHORIZED, "Invalid or missing observer token")),
+        }
+    }
+}
+
+/// Federation partner that presented the token shared with it
+struct FederationAuth(FederationPartner);
+
+#[async_trait]
+impl FromRequestParts<AppState> for FederationAuth {
+    type Rejection = (StatusCode, &'static str);
+    
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> std::result::Result<Self, Self::Rejection> {
+        let config = state.context.config_manager.get();
+        let partners = &config.federation.partners;
+        if partners.is_empty() {
+            return Err((StatusCode::FORBIDDEN, "Federation is disabled, no partners configured"));
+        }
+        
+        let provided = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let accepted: Vec<&str> = partners.iter().map(|partner| partner.token.expose().as_str()).filter(|t| !t.is_empty()).collect();
+        
+        if !check_token(state, parts, provided, &accepted, "federation token").await? {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid or missing federation token"));
+        }
+        partners.iter()
+            .find(|partner| provided.is_some_and(|token| constant_time_eq(token.as_bytes(), partner.token.expose().as_bytes())))
+            .map(|partner| FederationAuth(partner.clone()))
+            .ok_or((StatusCode::UNAUTHORIZED, "Invalid or missing federation token"))
+    }
+}
+
+/// Whether `provided` is one of the `accepted` tokens. Wrong tokens count
+/// towards a lockout of the client address, which is refused outright
+/// while it lasts; requests without a token do not count.
+async fn check_token(
+    state: &AppState,
+    parts: &Parts,
+    provided: Option<&str>,
+    accepted: &[&str],
+    what: &str,
+) -> std::result::Result<bool, (StatusCode, &'static str)> {
+    let Some(token) = provided else {
+        return Ok(false);
+    };
+    let throttle = &state.context.auth_throttle;
+    let client = parts.extensions.get::<ClientIp>().map(|ClientIp(ip)| *ip);
+    if client.is_some_and(|ip| throttle.locked_out(ip).is_some()) {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Too many failed attempts, try again later"));
+    }
+    
+    let valid = accepted.iter().any(|expected| constant_time_eq(token.as_bytes(), expected.as_bytes()));
+    if let Some(ip) = client {
+        if valid {
+            throttle.succeeded(ip).await;
+        } else {
+            throttle.failed(ip, what, &state.context.config_manager.get().server.auth_lockout).await;
+        }
+    }
+    Ok(valid)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(serde::Deserialize)]
+struct NodePath {
+    id: String,
+}
+
+/// Fetch a node, treating nodes of other networks as missing
+async fn find_scoped_node(app_state: &AppState, network: &str, node_id: &str) -> std::result::Result<Node, StatusCode> {
+    match app_state.node_manager.get_node_by_id(node_id).await {
+        Some(node) if node.network == network => Ok(node),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct NodesResponse {
+    nodes: Vec<Node>,
+}
+
+#[derive(serde::Deserialize)]
+struct NodesQuery {
+    external_id: Option<String>,
+    provider: Option<String>,
+}
+
+/// Nodes of a network, optionally only those with an external id and/or
+/// provider, e.g. for a controller to find the node of an instance
+async fn get_nodes_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Query(query): Query<NodesQuery>,
+) -> Json<NodesResponse> {
+    let mut nodes = app_state.node_manager.get_nodes_in_network(&network).await;
+    nodes.retain(|n| {
+        query.external_id.as_ref().is_none_or(|external_id| n.external_id.as_ref() == Some(external_id))
+            && query.provider.as_ref().is_none_or(|provider| n.provider.as_ref() == Some(provider))
+    });
+    Json(NodesResponse { nodes })
+}
+
+#[derive(serde::Serialize)]
+struct VersionGroup {
+    /// `None` for nodes whose agent did not report it
+    version: Option<String>,
+    count: usize,
+    nodes: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct FleetVersionsResponse {
+    server_version: &'static str,
+    total: usize,
+    agent_versions: Vec<VersionGroup>,
+    yggdrasil_versions: Vec<VersionGroup>,
+}
+
+/// Nodes grouped by the versions their agents reported
+fn group_by_version<'a>(nodes: impl Iterator<Item = (Option<&'a String>, &'a String)>) -> Vec<VersionGroup> {
+    let mut groups: std::collections::BTreeMap<Option<String>, Vec<String>> = std::collections::BTreeMap::new();
+    for (version, name) in nodes {
+        groups.entry(version.cloned()).or_default().push(name.clone());
+    }
+    groups.into_iter()
+        .map(|(version, nodes)| VersionGroup { version, count: nodes.len(), nodes })
+        .collect()
+}
+
+async fn get_fleet_versions_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> Json<FleetVersionsResponse> {
+    let nodes = app_state.node_manager.get_nodes_in_network(&network).await;
+    Json(FleetVersionsResponse {
+        server_version: env!("CARGO_PKG_VERSION"),
+        total: nodes.len(),
+        agent_versions: group_by_version(nodes.iter().map(|n| (n.agent_version.as_ref(), &n.name))),
+        yggdrasil_versions: group_by_version(nodes.iter().map(|n| (n.yggdrasil_version.as_ref(), &n.name))),
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct AddNodeRequest {
+    name: String,
+    #[serde(default)]
+    listen: Vec<String>,
+    /// `pinned` keeps hand-set listen endpoints when templates change
+    #[serde(default)]
+    listen_policy: Option<ListenPolicy>,
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    template: Option<String>,
+    #[serde(default)]
+    mtu: Option<u16>,
+    #[serde(default)]
+    node_info: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default)]
+    if_name: Option<String>,
+    #[serde(default)]
+    interface_peers: Option<HashMap<String, Vec<String>>>,
+    /// Public keys of devices outside yggman allowed to peer with the node
+    #[serde(default)]
+    extra_allowed_keys: Option<Vec<String>>,
+    #[serde(default)]
+    extra_config: Option<HashMap<String, serde_json::Value>>,
+    /// On updates, an empty string clears the field
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    contact: Option<String>,
+    /// The node in an outside system, e.g. a Kubernetes node name
+    #[serde(default)]
+    external_id: Option<String>,
+    #[serde(default)]
+    provider: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct AddNodeResponse {
+    success: bool,
+    message: String,
+}
+
+async fn add_node_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(payload): Json<AddNodeRequest>,
+) -> Json<AddNodeResponse> {
+    let template = match &payload.template {
+        Some(template_name) => match app_state.context.settings_manager.get_node_template(&network, template_name).await {
+            Ok(Some(template)) => Some(template),
+            Ok(None) => {
+                return Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Node template '{}' not found", template_name),
+                });
+            }
+            Err(e) => {
+                return Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Failed to load node template: {}", e),
+                });
+            }
+        },
+        None => None,
+    };
+    
+    let spec = node_spec_from_request(payload, template);
+    match app_state.node_manager.add_node(&network, spec).await {
+        Ok(node) => {
+            crate::node_events::record(&app_state.context, NodeLifecycle::Created, &node).await;
+            // Broadcast update to all connected agents
+            crate::websocket_state::broadcast_configuration_update(&app_state.node_manager).await;
+            
+            Json(AddNodeResponse {
+                success: true,
+                message: "Node added successfully".to_string(),
+            })
+        }
+        Err(e) => Json(AddNodeResponse {
+            success: false,
+            message: format!("Failed to add node: {}", e),
+        }),
+    }
+}
+
+/// Fields given in the request take precedence over the template's; tags
+/// and NodeInfo entries are merged.
+fn node_spec_from_request(payload: AddNodeRequest, template: Option<NodeTemplate>) -> NodeSpec {
+    let mut spec = match template {
+        Some(template) => NodeSpec {
+            listen: template.listen,
+            tags: template.tags,
+            mtu: template.mtu,
+            node_info: template.node_info,
+            ..Default::default()
+        },
+        None => NodeSpec::default(),
+    };
+    
+    spec.name = payload.name;
+    spec.addresses = payload.addresses;
+    if !payload.listen.is_empty() {
+        spec.listen = payload.listen;
+    }
+    if let Some(listen_policy) = payload.listen_policy {
+        spec.listen_policy = listen_policy;
+    }
+    for tag in payload.tags.unwrap_or_default() {
+        if !spec.tags.contains(&tag) {
+            spec.tags.push(tag);
+        }
+    }
+    if payload.mtu.is_some() {
+        spec.mtu = payload.mtu;
+    }
+    spec.node_info.extend(payload.node_info.unwrap_or_default());
+    spec.if_name = payload.if_name;
+    spec.interface_peers = payload.interface_peers.unwrap_or_default();
+    spec.extra_allowed_keys = payload.extra_allowed_keys.unwrap_or_default();
+    spec.extra_config = payload.extra_config.unwrap_or_default();
+    spec.description = payload.description;
+    spec.owner = payload.owner;
+    spec.contact = payload.contact;
+    spec.external_id = payload.external_id;
+    spec.provider = payload.provider;
+    
+    spec
+}
+
+#[derive(serde::Serialize)]
+struct ConfigsResponse {
+    configs: Vec<NodeConfig>,
+}
+
+#[derive(serde::Serialize)]
+struct NodeConfig {
+    node_id: String,
+    node_name: String,
+    node_public_key: String,
+    node_addresses: Vec<String>,
+    node_owner: Option<String>,
+    node_contact: Option<String>,
+    node_description: Option<String>,
+    config: YggdrasilConfig,
+}
+
+async fn get_configs_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> Json<ConfigsResponse> {
+    let nodes = app_state.node_manager.get_nodes_in_network(&network).await;
+    let configs_map = app_state.node_manager.generate_configs().await;
+    
+    let mut configs = Vec::new();
+    for node in nodes {
+        if let Some(config) = configs_map.get(&node.id) {
+            configs.push(NodeConfig {
+                node_id: node.id.clone(),
+                node_name: node.name.clone(),
+                node_public_key: node.public_key.clone(),
+                node_addresses: node.addresses.clone(),
+                node_owner: node.owner.clone(),
+                node_contact: node.contact.clone(),
+                node_description: node.description.clone(),
+                config: config.clone(),
+            });
+        }
+    }
+    
+    Json(ConfigsResponse { configs })
+}
+
+// Get single node handler
+async fn get_node_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> std::result::Result<Json<Node>, StatusCode> {
+    let node = find_scoped_node(&app_state, &network, &node_id).await?;
+    Ok(Json(node))
+}
+
+// Update node handler
+async fn update_node_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    Json(payload): Json<AddNodeRequest>,
+) -> std::result::Result<Json<AddNodeResponse>, StatusCode> {
+    find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    let options = NodeOptions {
+        listen_policy: payload.listen_policy,
+        mtu: payload.mtu,
+        node_info: payload.node_info,
+        if_name: payload.if_name,
+        interface_peers: payload.interface_peers,
+        extra_allowed_keys: payload.extra_allowed_keys,
+        extra_config: payload.extra_config,
+        description: payload.description,
+        owner: payload.owner,
+        contact: payload.contact,
+        external_id: payload.external_id,
+        provider: payload.provider,
+    };
+    let result = match app_state.node_manager.update_node(&node_id, payload.name, payload.listen, payload.addresses, payload.tags).await {
+        Ok(_) if !options.is_empty() => app_state.node_manager.update_node_options(&node_id, options).await,
+        result => result,
+    };
+    
+    match result {
+        Ok(_) => {
+            if let Some(node) = app_state.node_manager.get_node_by_id(&node_id).await {
+                crate::node_events::record(&app_state.context, NodeLifecycle::Updated, &node).await;
+            }
+            // Push the new configs to the agents the change affects
+            crate::websocket_state::broadcast_node_change(&app_state.node_manager, &[&node_id]).await;
+            
+            Ok(Json(AddNodeResponse {
+                success: true,
+                message: "Node updated successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            if e.to_string().contains("Node not found") {
+                Err(StatusCode::NOT_FOUND)
+            } else {
+                Ok(Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Failed to update node: {}", e),
+                }))
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct BulkUpdateRequest {
+    filter: NodeFilter,
+    #[serde(flatten)]
+    changes: BulkChanges,
+}
+
+// Bulk update handler: one transaction and one broadcast for all matched nodes
+async fn bulk_update_nodes_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(payload): Json<BulkUpdateRequest>,
+) -> Json<serde_json::Value> {
+    match app_state.node_manager.bulk_update(&network, &payload.filter, &payload.changes).await {
+        Ok(updated) => {
+            for node_id in &updated {
+                if let Some(node) = app_state.node_manager.get_node_by_id(node_id).await {
+                    crate::node_events::record(&app_state.context, NodeLifecycle::Updated, &node).await;
+                }
+            }
+            if !updated.is_empty() {
+                crate::websocket_state::broadcast_configuration_update(&app_state.node_manager).await;
+            }
+            
+            Json(serde_json::json!({
+                "success": true,
+                "message": format!("Updated {} nodes", updated.len()),
+                "updated": updated,
+            }))
+        }
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to update nodes: {}", e)
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CloneNodeRequest {
+    name: String,
+    #[serde(default)]
+    addresses: Vec<String>,
+}
+
+// Clone node handler: same listen, tags, MTU and NodeInfo, fresh keys
+async fn clone_node_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    Json(payload): Json<CloneNodeRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let source = find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    if app_state.node_manager.get_node_by_name(&network, &payload.name).await.is_some() {
+        return Ok(Json(serde_json::json!({
+            "success": false,
+            "message": format!("Node '{}' already exists", payload.name)
+        })));
+    }
+    
+    let spec = NodeSpec::clone_of(&source, payload.name, payload.addresses);
+    match app_state.node_manager.add_node(&network, spec).await {
+        Ok(node) => {
+            crate::node_events::record(&app_state.context, NodeLifecycle::Created, &node).await;
+            crate::websocket_state::broadcast_configuration_update(&app_state.node_manager).await;
+            
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Node cloned successfully",
+                "node": node
+            })))
+        }
+        Err(e) => Ok(Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to clone node: {}", e)
+        }))),
+    }
+}
+
+// Export a node's keypair; admin-only and always audited
+async fn get_node_keys_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> Response {
+    let node = match find_scoped_node(&app_state, &network, &node_id).await {
+        Ok(node) => node,
+        Err(status) => return status.into_response(),
+    };
+    
+    let audit_log = &app_state.context.audit_log;
+    let policy = match app_state.context.settings_manager.get_key_escrow_policy().await {
+        Ok(policy) => policy,
+        Err(e) => {
+            tracing::error!("Failed to get key escrow policy: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    
+    if policy == KeyEscrowPolicy::WriteOnly {
+        if let Err(e) = audit_log.record("admin", "key_export_denied", &node.id, "Key escrow policy is write_only").await {
+            tracing::error!("Failed to write audit log: {}", e);
+        }
+        return (StatusCode::FORBIDDEN, Json(serde_json::json!({
+            "success": false,
+            "message": "Private keys are write-only under the current key escrow policy"
+        }))).into_response();
+    }
+    
+    // Refuse to hand out keys that leave no trace
+    if let Err(e) = audit_log.record("admin", "key_export", &node.id, &format!("Exported keypair of node {}", node.name)).await {
+        tracing::error!("Failed to write audit log: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    
+    Json(serde_json::json!({
+        "node_id": node.id,
+        "public_key": node.public_key,
+        "private_key": node.private_key
+    })).into_response()
+}
+
+// Issue the token an agent pulls the node's config with; admin-only and audited
+async fn get_agent_token_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> Response {
+    let node = match find_scoped_node(&app_state, &network, &node_id).await {
+        Ok(node) => node,
+        Err(status) => return status.into_response(),
+    };
+    
+    // The token gives access to the private key, like a key export
+    if let Err(e) = app_state.context.audit_log.record("admin", "agent_token_issued", &node.id, &format!("Issued agent token of node {}", node.name)).await {
+        tracing::error!("Failed to write audit log: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    
+    Json(serde_json::json!({
+        "node_id": node.id,
+        "token": crate::signing::agent_token(&app_state.context.signing_key, &node.id)
+    })).into_response()
+}
+
+// Delete node handler
+async fn delete_node_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> std::result::Result<Json<AddNodeResponse>, StatusCode> {
+    let node = find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    match app_state.node_manager.remove_node(&node_id).await {
+        Ok(_) => {
+            crate::node_events::record(&app_state.context, NodeLifecycle::Deleted, &node).await;
+            // Revoke the node's key everywhere now, regardless of maintenance windows
+            crate::websocket_state::broadcast_urgent_configuration_update(&app_state.node_manager).await;
+            
+            Ok(Json(AddNodeResponse {
+                success: true,
+                message: "Node deleted successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            if e.to_string().contains("Node not found") {
+                Err(StatusCode::NOT_FOUND)
+            } else {
+                Ok(Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Failed to delete node: {}", e),
+                }))
+            }
+        }
+    }
+}
+
+// Get node configuration for agent
+async fn get_node_config_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    ClientIp(client_ip): ClientIp,
+    headers: HeaderMap,
+) -> std::result::Result<Response, StatusCode> {
+    // Get the node
+    let node = find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    // Agents pulling over HTTP get the signed message the WebSocket would send
+    let bearer = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if let Some(token) = bearer {
+        let throttle = &app_state.context.auth_throttle;
+        if throttle.locked_out(client_ip).is_some() {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+        if !crate::signing::verify_agent_token(&app_state.context.signing_key, &node.id, token) {
+            throttle.failed(client_ip, "agent token", &app_state.context.config_manager.get().server.auth_lockout).await;
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        throttle.succeeded(client_ip).await;
+        let sealed_to = match headers.get(crate::sealing::KEY_AGREEMENT_HEADER) {
+            Some(value) => {
+                let key = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+                Some(crate::sealing::parse_public_key(key).map_err(|_| StatusCode::BAD_REQUEST)?)
+            }
+            None if app_state.context.config_manager.get().agent.require_sealed_keys => return Err(StatusCode::BAD_REQUEST),
+            None => None,
+        };
+        let nonce = headers.get(crate::signing::NONCE_HEADER).map(|value| value.to_str().map_err(|_| StatusCode::BAD_REQUEST)).transpose()?;
+        let message = crate::modules::websocket::full_config(&app_state.node_manager, &app_state.context, &node, sealed_to.as_ref(), nonce).await
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let message = serde_json::to_value(&message).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        tracing::debug!("Agent of {} pulled its config", node.id);
+        return Ok(Json(crate::signing::sign_message(&app_state.context.signing_key, message)).into_response());
+    }
+    
+    // Generate configurations for all nodes
+    let configs_map = app_state.node_manager.generate_configs().await;
+    
+    // Get config for this specific node
+    let Some(config) = configs_map.get(&node_id).cloned() else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    
+    Ok(Json(NodeConfig {
+        node_id: node.id.clone(),
+        node_name: node.name.clone(),
+        node_public_key: node.public_key.clone(),
+        node_addresses: node.addresses.clone(),
+        node_owner: node.owner.clone(),
+        node_contact: node.contact.clone(),
+        node_description: node.description.clone(),
+        config,
+    }).into_response())
+}
+
+#[derive(serde::Serialize)]
+struct EffectiveConfig {
+    listen: Vec<String>,
+    peers: Vec<String>,
+    allowed_public_keys: Vec<String>,
+}
+
+/// Entries yggman expects but the agent lacks, and the other way round
+#[derive(serde::Serialize, Default)]
+struct ListDrift {
+    missing: Vec<String>,
+    unexpected: Vec<String>,
+}
+
+impl ListDrift {
+    fn between(expected: &[String], reported: &[String]) -> Self {
+        Self {
+            missing: expected.iter().filter(|e| !reported.contains(e)).cloned().collect(),
+            unexpected: reported.iter().filter(|r| !expected.contains(r)).cloned().collect(),
+        }
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ConfigDrift {
+    in_sync: bool,
+    revision_behind: bool,
+    /// The agent found hand edits in the node's config file
+    local_edits: bool,
+    listen: ListDrift,
+    peers: ListDrift,
+    allowed_public_keys: ListDrift,
+}
+
+#[derive(serde::Serialize)]
+struct EffectivePeersResponse {
+    node_id: String,
+    node_name: String,
+    connected: bool,
+    current_revision: u64,
+    expected: EffectiveConfig,
+    reported: Option<crate::websocket_state::AgentStatus>,
+    /// Absent until the agent has reported its applied config
+    drift: Option<ConfigDrift>,
+}
+
+/// Listen endpoints as the agent writes them: resolved when it reported
+/// its listen templates, as generated otherwise
+fn effective_listen(node: &Node, config: &YggdrasilConfig) -> Vec<String> {
+    if node.resolved_listen.is_empty() {
+        config.listen.clone()
+    } else {
+        node.resolved_listen.clone()
+    }
+}
+
+async fn get_effective_peers_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> std::result::Result<Json<EffectivePeersResponse>, StatusCode> {
+    let node = find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    let configs_map = app_state.node_manager.generate_configs().await;
+    let Some(config) = configs_map.get(&node_id) else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    
+    let expected = EffectiveConfig {
+        listen: effective_listen(&node, config),
+        peers: config.peers.clone(),
+        allowed_public_keys: config.allowed_public_keys.clone(),
+    };
+    
+    let current_revision = app_state.node_manager.current_revision().await;
+    let expected_revision = crate::websocket_state::get_expected_revision(&node_id).await.unwrap_or(current_revision);
+    let reported = crate::websocket_state::get_agent_status(&node_id).await;
+    let drift = reported.as_ref().map(|status| {
+        let listen = ListDrift::between(&expected.listen, &status.listen);
+        let peers = ListDrift::between(&expected.peers, &status.peers);
+        let allowed_public_keys = ListDrift::between(&expected.allowed_public_keys, &status.allowed_public_keys);
+        ConfigDrift {
+            in_sync: listen.is_empty() && peers.is_empty() && allowed_public_keys.is_empty() && !status.drift,
+            revision_behind: status.revision < expected_revision,
+            local_edits: status.drift,
+            listen,
+            peers,
+            allowed_public_keys,
+        }
+    });
+    
+    Ok(Json(EffectivePeersResponse {
+        node_id: node.id.clone(),
+        node_name: node.name.clone(),
+        connected: crate::websocket_state::get_connected_node_ids().await.contains(&node_id),
+        current_revision,
+        expected,
+        reported,
+        drift,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct FirewallQuery {
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Ports a node must accept and endpoints it dials, as JSON or rendered
+/// with `?format=nftables` or `?format=ufw`.
+async fn get_node_firewall_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    Query(query): Query<FirewallQuery>,
+) -> std::result::Result<Response, StatusCode> {
+    let node = find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    let configs_map = app_state.node_manager.generate_configs().await;
+    let Some(config) = configs_map.get(&node_id) else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let hints = crate::firewall::hints_for(config, &effective_listen(&node, config));
+    
+    Ok(match query.format.as_deref() {
+        Some("json") | None => Json(serde_json::json!({
+            "node_id": node.id,
+            "node_name": node.name,
+            "inbound": hints.inbound,
+            "outbound": hints.outbound,
+            // What the agent reports opening, when it manages the firewall
+            "agent_managed": crate::websocket_state::get_firewall_status(&node_id).await,
+        })).into_response(),
+        Some("nftables") | Some("nft") => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            hints.to_nftables(&node.name),
+        ).into_response(),
+        Some("ufw") => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            hints.to_ufw(&node.name),
+        ).into_response(),
+        Some(other) => (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported format: {}", other),
+        ).into_response(),
+    })
+}
+
+async fn get_node_availability_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+) -> std::result::Result<Json<crate::status_history::Availability>, StatusCode> {
+    find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    app_state.context.status_history.availability(&node_id).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read status history of node {}: {}", node_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(serde::Deserialize)]
+struct TrafficQuery {
+    /// How far back to go, default one day
+    hours: Option<i64>,
+    /// Public key of a single peer
+    peer: Option<String>,
+}
+
+async fn get_node_traffic_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    Query(query): Query<TrafficQuery>,
+) -> std::result::Result<Json<crate::traffic_stats::TrafficSeries>, StatusCode> {
+    find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    let from = chrono::Utc::now() - chrono::Duration::hours(query.hours.unwrap_or(24).max(1));
+    app_state.context.traffic_stats.series(&node_id, from, query.peer.as_deref()).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read traffic of node {}: {}", node_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(serde::Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+}
+
+/// Ansible inventory of the network's nodes by Yggdrasil address, YAML
+/// compatible JSON by default or `?format=ini`
+async fn export_ansible_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let nodes = app_state.node_manager.get_nodes_in_network(&network).await;
+    
+    match query.format.as_deref() {
+        Some("json") | Some("yaml") | None => Json(crate::export::ansible_inventory(&nodes)).into_response(),
+        Some("ini") => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            crate::export::ansible_inventory_ini(&nodes),
+        ).into_response(),
+        Some(other) => (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported format: {}", other),
+        ).into_response(),
+    }
+}
+
+/// ssh_config entries for the network's nodes, optionally with `?user=`
+async fn export_ssh_config_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let nodes = app_state.node_manager.get_nodes_in_network(&network).await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        crate::export::ssh_config(&nodes, query.user.as_deref()),
+    ).into_response()
+}
+
+/// Zone file mapping node names under the configured domain to their
+/// Yggdrasil addresses
+async fn export_dns_zone_handler(State(app_state): State<AppState>) -> Response {
+    let config = app_state.context.config_manager.get().dns.clone();
+    let records = crate::export::dns_records(&app_state.node_manager.get_all_nodes().await, config.ttl);
+    (
+        [(header::CONTENT_TYPE, "text/dns; charset=utf-8")],
+        crate::export::dns_zone(&config, &records, crate::modules::dns::unix_seconds()),
+    ).into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct PrometheusSdQuery {
+    #[serde(default)]
+    network: Option<String>,
+    /// Port to scrape on each node, node_exporter's by default
+    #[serde(default = "default_scrape_port")]
+    port: u16,
+}
+
+fn default_scrape_port() -> u16 {
+    9100
+}
+
+/// Prometheus http_sd targets for every node, or those of `?network=`
+async fn prometheus_sd_handler(
+    State(app_state): State<AppState>,
+    Query(query): Query<PrometheusSdQuery>,
+) -> Json<serde_json::Value> {
+    let nodes = match &query.network {
+        Some(network) => app_state.node_manager.get_nodes_in_network(network).await,
+        None => app_state.node_manager.get_all_nodes().await,
+    };
+    Json(crate::export::prometheus_targets(&nodes, query.port))
+}
+
+async fn get_connectivity_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> Json<crate::topology::Connectivity> {
+    Json(crate::topology::connectivity(&app_state.node_manager, &network).await)
+}
+
+// WebSocket handler for agents
+async fn ws_agent_handler(
+    ws: WebSocketUpgrade,
+    ClientIp(client_ip): ClientIp,
+    State(app_state): State<AppState>,
+) -> Response {
+    ws.on_upgrade(move |socket| crate::modules::websocket::handle_agent_socket(socket, client_ip, app_state.node_manager, app_state.context))
+}
+
+/// The caller's address as the server sees it, past trusted proxies; lets
+/// agents behind NAT learn their public address
+async fn whoami_handler(ClientIp(client_ip): ClientIp) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "ip": client_ip }))
+}
+
+async fn ws_observe_handler(
+    _observer: ObserverAuth,
+    ws: WebSocketUpgrade,
+    State(app_state): State<AppState>,
+) -> Response {
+    ws.on_upgrade(move |socket| crate::modules::observer::handle_observer_socket(socket, app_state.node_manager, app_state.context))
+}
+
+async fn graphql_handler(
+    State(app_state): State<AppState>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = request.data(crate::modules::graphql::GeneratedConfigs::default());
+    Json(app_state.graphql_schema.execute(request).await)
+}
+
+// GraphQL subscriptions over WebSocket, speaking graphql-transport-ws or the
+// older graphql-ws protocol, whichever the client asks for first
+async fn graphql_ws_handler(
+    ws: WebSocketUpgrade,
+    headers: axum::http::HeaderMap,
+    State(app_state): State<AppState>,
+) -> Response {
+    use async_graphql::http::{WebSocket, WsMessage, ALL_WEBSOCKET_PROTOCOLS};
+    use axum::extract::ws::{CloseFrame, Message};
+    use futures::{SinkExt, StreamExt};
+
+    let protocol = headers.get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').find_map(|p| p.trim().parse().ok()));
+    let Some(protocol) = protocol else {
+        return (StatusCode::BAD_REQUEST, "Unsupported GraphQL WebSocket protocol").into_response();
+    };
+
+    ws.protocols(ALL_WEBSOCKET_PROTOCOLS).on_upgrade(move |socket| async move {
+        let (mut sink, stream) = socket.split();
+        let input = stream
+            .take_while(|message| futures::future::ready(message.is_ok()))
+            .filter_map(|message| async move {
+                match message {
+                    Ok(Message::Text(text)) => Some(text.into_bytes()),
+                    Ok(Message::Binary(bytes)) => Some(bytes),
+                    _ => None,
+                }
+            });
+
+        let mut output = std::pin::pin!(WebSocket::new(app_state.graphql_schema, input, protocol));
+        while let Some(message) = output.next().await {
+            let message = match message {
+                WsMessage::Text(text) => Message::Text(text),
+                WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame { code, reason: reason.into() })),
+            };
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+// Listen template handlers
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ListenTemplateResponse {
+    template: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct UpdateListenTemplateRequest {
+    template: Vec<String>,
+    /// Also rewrite the listen endpoints of nodes following the templates.
+    /// Nodes whose generated listen changes get new configs either way
+    #[serde(default)]
+    apply_to_existing: bool,
+}
+
+async fn get_listen_template_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> Json<ListenTemplateResponse> {
+    match app_state.context.settings_manager.get_listen_template(&network).await {
+        Ok(template) => Json(ListenTemplateResponse { template }),
+        Err(e) => {
+            tracing::error!("Failed to get listen template from database: {}", e);
+            // Return fallback default
+            Json(ListenTemplateResponse {
+                template: vec!["tcp://0.0.0.0:9001".to_string()],
+            })
+        }
+    }
+}
+
+async fn update_listen_template_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(payload): Json<UpdateListenTemplateRequest>,
+) -> Json<serde_json::Value> {
+    tracing::info!("Listen template update request for network {}: {:?}", network, payload.template);
+    let settings_manager = &app_state.context.settings_manager;
+    
+    // Nodes without listen endpoints take the template when configs are
+    // generated, so compare what they generate to before
+    let before = app_state.node_manager.generate_configs().await;
+    
+    // Save to database
+    if let Err(e) = settings_manager.set_listen_template(&network, payload.template.clone()).await {
+        tracing::error!("Failed to save listen template: {}", e);
+        return Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save template: {}", e)
+        }));
+    }
+    
+    // Update in-memory config
+    if network == DEFAULT_NETWORK {
+        app_state.context.config_manager.update_listen_template(payload.template.clone());
+    }
+    
+    let response = if payload.apply_to_existing {
+        match app_state.node_manager.follow_listen_templates(&network).await {
+            Ok(updated) => serde_json::json!({
+                "success": true,
+                "message": format!("Listen template updated and applied to {} existing nodes", updated.len()),
+                "updated": updated
+            }),
+            Err(e) => {
+                tracing::error!("Failed to apply listen template to existing nodes: {}", e);
+                serde_json::json!({
+                    "success": false,
+                    "message": format!("Template saved, but applying it to existing nodes failed: {}", e)
+                })
+            }
+        }
+    } else {
+        serde_json::json!({
+            "success": true,
+            "message": "Listen template updated successfully"
+        })
+    };
+    
+    let after = app_state.node_manager.generate_configs().await;
+    let changed: Vec<&str> = after.iter()
+        .filter(|(node_id, config)| before.get(*node_id).is_none_or(|previous| previous.listen != config.listen))
+        .map(|(node_id, _)| node_id.as_str())
+        .collect();
+    if !changed.is_empty() {
+        crate::websocket_state::broadcast_node_change(&app_state.node_manager, &changed).await;
+    }
+    
+    Json(response)
+}
+
+async fn get_listen_template_rules_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> std::result::Result<Json<Vec<ListenTemplateRule>>, StatusCode> {
+    app_state.context.settings_manager.get_listen_template_rules(&network).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get listen template rules from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_listen_template_rules_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(rules): Json<Vec<ListenTemplateRule>>,
+) -> Json<serde_json::Value> {
+    match app_state.context.settings_manager.set_listen_template_rules(&network, &rules).await {
+        Ok(_) => Json(serde_json::json!({
+            "success": true,
+            "message": "Listen template rules updated successfully"
+        })),
+        Err(e) => {
+            tracing::error!("Failed to save listen template rules: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to save template rules: {}", e)
+            }))
+        }
+    }
+}
+
+async fn get_maintenance_windows_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> std::result::Result<Json<Vec<MaintenanceWindow>>, StatusCode> {
+    app_state.context.settings_manager.get_maintenance_windows(&network).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get maintenance windows from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_maintenance_windows_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(windows): Json<Vec<MaintenanceWindow>>,
+) -> Json<serde_json::Value> {
+    match app_state.context.settings_manager.set_maintenance_windows(&network, &windows).await {
+        Ok(_) => Json(serde_json::json!({
+            "success": true,
+            "message": "Maintenance windows updated successfully"
+        })),
+        Err(e) => {
+            tracing::error!("Failed to save maintenance windows: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to save maintenance windows: {}", e)
+            }))
+        }
+    }
+}
+
+async fn get_peer_schedules_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> std::result::Result<Json<Vec<PeerSchedule>>, StatusCode> {
+    app_state.context.settings_manager.get_peer_schedules(&network).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get peer schedules from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_peer_schedules_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(schedules): Json<Vec<PeerSchedule>>,
+) -> Json<serde_json::Value> {
+    match app_state.context.settings_manager.set_peer_schedules(&network, &schedules).await {
+        Ok(_) => {
+            // Pushes new configs right away if schedules open now changed
+            crate::peer_schedules::reschedule();
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Peer schedules updated successfully"
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Failed to save peer schedules: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to save peer schedules: {}", e)
+            }))
+        }
+    }
+}
+
+async fn get_node_info_template_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> std::result::Result<Json<NodeInfoTemplate>, StatusCode> {
+    app_state.context.settings_manager.get_node_info_template(&network).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get NodeInfo template from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_node_info_template_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(template): Json<NodeInfoTemplate>,
+) -> Json<serde_json::Value> {
+    let settings_manager = &app_state.context.settings_manager;
+    let previous = match settings_manager.get_node_info_template(&network).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            return Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to get NodeInfo template: {}", e)
+            }));
+        }
+    };
+    
+    if let Err(e) = settings_manager.set_node_info_template(&network, &template).await {
+        tracing::error!("Failed to save NodeInfo template: {}", e);
+        return Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save NodeInfo template: {}", e)
+        }));
+    }
+    
+    if previous != template {
+        crate::websocket_state::broadcast_configuration_update(&app_state.node_manager).await;
+    }
+    Json(serde_json::json!({
+        "success": true,
+        "message": "NodeInfo template updated successfully"
+    }))
+}
+
+async fn get_deferred_updates_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "nodes": crate::websocket_state::get_deferred_updates().await
+    }))
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AutoBroadcastSetting {
+    enabled: bool,
+}
+
+async fn get_auto_broadcast_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<AutoBroadcastSetting>, StatusCode> {
+    app_state.context.settings_manager.get_auto_broadcast().await
+        .map(|enabled| Json(AutoBroadcastSetting { enabled }))
+        .map_err(|e| {
+            tracing::error!("Failed to get auto broadcast setting: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_auto_broadcast_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(payload): Json<AutoBroadcastSetting>,
+) -> Json<serde_json::Value> {
+    match app_state.context.settings_manager.set_auto_broadcast(payload.enabled).await {
+        Ok(_) => Json(serde_json::json!({
+            "success": true,
+            "message": "Auto broadcast setting updated successfully"
+        })),
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save auto broadcast setting: {}", e)
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct StartBroadcastRequest {
+    #[serde(default)]
+    strategy: RolloutStrategy,
+    #[serde(default)]
+    canary: CanaryOptions,
+}
+
+async fn start_broadcast_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(payload): Json<StartBroadcastRequest>,
+) -> Response {
+    match app_state.broadcast_manager.start(payload.strategy, payload.canary).await {
+        Ok(broadcast) => (StatusCode::ACCEPTED, Json(broadcast)).into_response(),
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to start broadcast: {}", e)
+        })).into_response(),
+    }
+}
+
+async fn get_broadcasts_handler(
+    State(app_state): State<AppState>,
+) -> Json<Vec<crate::broadcast_manager::Broadcast>> {
+    Json(app_state.broadcast_manager.get_broadcasts().await)
+}
+
+async fn get_broadcast_handler(
+    State(app_state): State<AppState>,
+    Path(broadcast_id): Path<String>,
+) -> std::result::Result<Json<crate::broadcast_manager::Broadcast>, StatusCode> {
+    app_state.broadcast_manager.get_broadcast(&broadcast_id).await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_rollback_policy_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<RollbackPolicy>, StatusCode> {
+    app_state.context.settings_manager.get_rollback_policy().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get rollback policy: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_rollback_policy_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(policy): Json<RollbackPolicy>,
+) -> Json<serde_json::Value> {
+    match app_state.context.settings_manager.set_rollback_policy(&policy).await {
+        Ok(_) => Json(serde_json::json!({
+            "success": true,
+            "message": "Rollback policy updated successfully"
+        })),
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save rollback policy: {}", e)
+        })),
+    }
+}
+
+/// The timing in effect, from the settings or else the config file
+async fn get_node_timing_handler(
+    State(app_state): State<AppState>,
+) -> Json<NodeTiming> {
+    Json(NodeTiming::from_config(&app_state.context.config_manager.get()))
+}
+
+async fn update_node_timing_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(timing): Json<NodeTiming>,
+) -> Json<serde_json::Value> {
+    if let Err(e) = app_state.context.settings_manager.set_node_timing(&timing).await {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save node timing: {}", e)
+        }));
+    }
+    app_state.context.config_manager.update_node_timing(&timing);
+    let agent_timing = crate::modules::websocket::AgentTiming::from(&app_state.context.config_manager.get().agent);
+    crate::websocket_state::broadcast_timing(agent_timing).await;
+    
+    Json(serde_json::json!({
+        "success": true,
+        "message": "Node timing updated successfully",
+        "timing": timing
+    }))
+}
+
+/// Border nodes of the network federated with the calling partner
+async fn get_federation_nodes_handler(
+    FederationAuth(partner): FederationAuth,
+    State(app_state): State<AppState>,
+) -> Json<Vec<FederatedNode>> {
+    let nodes = crate::federation::border_nodes(&app_state.node_manager, &partner.network).await;
+    tracing::debug!("Sending {} border nodes of network {} to federation partner {}", nodes.len(), partner.network, partner.name);
+    Json(nodes)
+}
+
+#[derive(serde::Serialize)]
+struct FederationPartnerStatus {
+    name: String,
+    url: String,
+    network: String,
+    /// As last fetched; kept while the partner cannot be reached
+    nodes: Vec<FederatedNode>,
+}
+
+async fn get_federation_partners_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<FederationPartnerStatus>>, StatusCode> {
+    let mut federated = app_state.context.settings_manager.get_federated_nodes().await
+        .map_err(|e| {
+            tracing::error!("Failed to get federated nodes: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let partners = app_state.context.config_manager.get().federation.partners.iter()
+        .map(|partner| FederationPartnerStatus {
+            name: partner.name.clone(),
+            url: partner.url.clone(),
+            network: partner.network.clone(),
+            nodes: federated.remove(&partner.name).map(|partner| partner.nodes).unwrap_or_default(),
+        })
+        .collect();
+    Ok(Json(partners))
+}
+
+#[derive(serde::Deserialize)]
+struct EventsQuery {
+    #[serde(default)]
+    limit: Option<u64>,
+}
+
+async fn get_events_handler(
+    State(app_state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> std::result::Result<Json<Vec<crate::database::entities::event::Model>>, StatusCode> {
+    app_state.context.event_log.get_recent(query.limit.unwrap_or(100)).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read events: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Liveness for load balancers and container health checks; 503 while the
+/// database is unreachable. A degraded start keeps serving 200 but reports
+/// the failed preflight checks.
+async fn health_handler(State(app_state): State<AppState>) -> Response {
+    let database = app_state.context.db_health.status();
+    let preflight = &app_state.context.preflight;
+    let status = if database.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(serde_json::json!({
+        "status": if database.healthy && !preflight.degraded { "ok" } else { "degraded" },
+        "version": env!("CARGO_PKG_VERSION"),
+        "database": database,
+        "failed_preflight_checks": preflight.failures().map(|check| check.name).collect::<Vec<_>>(),
+    }))).into_response()
+}
+
+/// Results of the checks run at startup, see `crate::preflight`
+async fn get_preflight_handler(State(app_state): State<AppState>) -> Json<crate::preflight::Report> {
+    Json((*app_state.context.preflight).clone())
+}
+
+async fn get_cache_stats_handler(State(app_state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        // null when storage.cache_ttl is 0
+        "nodes": app_state.node_manager.cache_stats(),
+    }))
+}
+
+/// Config revisions agents have not acknowledged yet
+async fn get_outbox_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<crate::outbox::OutboxEntry>>, StatusCode> {
+    app_state.node_manager.outbox().list().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list pending deliveries: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(serde::Deserialize)]
+struct ConsistencyQuery {
+    #[serde(default)]
+    repair: bool,
+}
+
+async fn get_consistency_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Query(query): Query<ConsistencyQuery>,
+) -> Response {
+    // A GET passes the read-only guard, a repair must not
+    if query.repair && app_state.context.config_manager.get().server.read_only {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({ "success": false, "message": "Server is in read-only mode" })),
+        ).into_response();
+    }
+    let report = match crate::consistency::check(&app_state.node_manager, query.repair).await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!("Failed to check consistency: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    
+    if report.repaired > 0 {
+        let changed = report.changed_nodes();
+        if !changed.is_empty() {
+            crate::websocket_state::broadcast_node_change(&app_state.node_manager, &changed).await;
+        }
+        let details = format!("Repaired {} of {} inconsistencies", report.repaired, report.findings.len());
+        if let Err(e) = app_state.context.audit_log.record("admin", "consistency_repaired", "system", &details).await {
+            tracing::error!("Failed to record audit entry: {}", e);
+        }
+    }
+    Json(report).into_response()
+}
+
+async fn get_stale_nodes_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<crate::stale_nodes::StaleNode>>, StatusCode> {
+    app_state.context.stale_nodes.list().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list stale nodes: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn restore_stale_node_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    match app_state.context.stale_nodes.restore(&app_state.node_manager, &app_state.context, &id).await {
+        Ok(Some(restored)) => {
+            let details = format!("Restored {} stale node {} in network {}", restored.state.as_str(), restored.name, restored.network);
+            if let Err(e) = app_state.context.audit_log.record("admin", "stale_node_restored", &id, &details).await {
+                tracing::error!("Failed to write audit log: {}", e);
+            }
+            crate::websocket_state::broadcast_configuration_update(&app_state.node_manager).await;
+            
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": format!("Node {} restored", restored.name)
+            })))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Ok(Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to restore node: {}", e)
+        }))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct AlertsQuery {
+    /// Only alerts in this state, `firing` or `resolved`
+    state: Option<String>,
+}
+
+async fn get_alerts_handler(
+    State(app_state): State<AppState>,
+    Query(query): Query<AlertsQuery>,
+) -> std::result::Result<Json<Vec<crate::alerts::Alert>>, StatusCode> {
+    let state = match query.state.as_deref() {
+        None => None,
+        Some("firing") => Some(crate::alerts::AlertState::Firing),
+        Some("resolved") => Some(crate::alerts::AlertState::Resolved),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    
+    let alerts = app_state.context.alerts.list().into_iter()
+        .filter(|alert| state.is_none_or(|state| alert.state == state))
+        .collect();
+    Ok(Json(alerts))
+}
+
+#[derive(serde::Deserialize)]
+struct SearchQuery {
+    q: String,
+    /// At most this many nodes, 50 by default
+    limit: Option<usize>,
+}
+
+async fn search_handler(
+    State(app_state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> std::result::Result<Json<Vec<crate::search::SearchHit>>, StatusCode> {
+    if query.q.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = query.limit.unwrap_or(50).min(500);
+    Ok(Json(crate::search::search(&app_state.node_manager, &query.q, limit).await))
+}
+
+async fn get_node_templates_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+) -> std::result::Result<Json<Vec<NodeTemplate>>, StatusCode> {
+    app_state.context.settings_manager.get_node_templates(&network).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get node templates from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn update_node_templates_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Json(templates): Json<Vec<NodeTemplate>>,
+) -> Json<serde_json::Value> {
+    let mut names = HashSet::new();
+    if let Some(duplicate) = templates.iter().find(|t| !names.insert(t.name.as_str())) {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": format!("Duplicate node template name: {}", duplicate.name)
+        }));
+    }
+    
+    match app_state.context.settings_manager.set_node_templates(&network, &templates).await {
+        Ok(_) => Json(serde_json::json!({
+            "success": true,
+            "message": "Node templates updated successfully"
+        })),
+        Err(e) => {
+            tracing::error!("Failed to save node templates: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to save node templates: {}", e)
+            }))
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KeyEscrowSetting {
+    policy: KeyEscrowPolicy,
+}
+
+async fn get_key_escrow_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<KeyEscrowSetting>, StatusCode> {
+    app_state.context.settings_manager.get_key_escrow_policy().await
+        .map(|policy| Json(KeyEscrowSetting { policy }))
+        .map_err(|e| {
+            tracing::error!("Failed to get key escrow policy: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Switching to `write_only` is one-way: keys that were promised to never
+/// leave the server must not become exportable again.
+async fn update_key_escrow_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(payload): Json<KeyEscrowSetting>,
+) -> Json<serde_json::Value> {
+    let settings_manager = &app_state.context.settings_manager;
+    let current = match settings_manager.get_key_escrow_policy().await {
+        Ok(policy) => policy,
+        Err(e) => {
+            return Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to get key escrow policy: {}", e)
+            }));
+        }
+    };
+    
+    if current == KeyEscrowPolicy::WriteOnly && payload.policy != KeyEscrowPolicy::WriteOnly {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": "The write_only key escrow policy cannot be relaxed"
+        }));
+    }
+    
+    match settings_manager.set_key_escrow_policy(payload.policy).await {
+        Ok(_) => {
+            let details = format!("Key escrow policy changed from {:?} to {:?}", current, payload.policy);
+            if let Err(e) = app_state.context.audit_log.record("admin", "key_escrow_policy", "settings", &details).await {
+                tracing::error!("Failed to write audit log: {}", e);
+            }
+            
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Key escrow policy updated successfully"
+            }))
+        }
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to save key escrow policy: {}", e)
+        })),
+    }
+}
+
+// Public half of the signing key, for pinning on agents
+async fn get_server_public_key_handler(
+    State(app_state): State<AppState>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "public_key": hex::encode(app_state.context.signing_key.verifying_key().to_bytes())
+    }))
+}
+
+/// The running configuration with secrets redacted, and where each setting came from
+async fn get_system_config_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let config_manager = &app_state.context.config_manager;
+    Json(serde_json::json!({
+        "config": crate::config::effective::redacted(&config_manager.get()),
+        "sources": config_manager.sources()
+    }))
+}
+
+async fn get_manifest_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<crate::manifest::Manifest>, StatusCode> {
+    crate::manifest::export(&app_state.node_manager, &app_state.context).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to export manifest: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(serde::Deserialize)]
+struct ManifestQuery {
+    /// Confirmation of the changes returned for the same manifest
+    confirm: Option<String>,
+}
+
+/// Replace the whole state with a manifest. Without `confirm`, only
+/// returns the changes and their confirmation; with it, applies them
+/// unless the state changed in between.
+async fn update_manifest_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Query(query): Query<ManifestQuery>,
+    Json(payload): Json<serde_json::Value>,
+) -> Response {
+    let plan = match plan_manifest(&app_state, payload).await {
+        Ok(plan) => plan,
+        Err(response) => return response,
+    };
+    
+    match query.confirm {
+        None => Json(serde_json::json!({
+            "success": true,
+            "applied": false,
+            "changes": plan.changes,
+            "confirmation": plan.confirmation
+        })).into_response(),
+        Some(confirm) if confirm != plan.confirmation => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "The state or the manifest changed since the confirmation was issued, review the changes again",
+                "changes": plan.changes,
+                "confirmation": plan.confirmation
+            })),
+        ).into_response(),
+        Some(_) => {
+            let changes = plan.changes.clone();
+            if let Err(response) = apply_manifest(&app_state, plan, "manifest_applied").await {
+                return response;
+            }
+            Json(serde_json::json!({
+                "success": true,
+                "applied": true,
+                "changes": changes
+            })).into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ReconcileQuery {
+    #[serde(default)]
+    apply: bool,
+}
+
+/// Actions that would bring the state to a desired-state manifest, taken
+/// right away with `apply=true`. Unlike `PUT /api/manifest` there is no
+/// confirmation, so a controller can call it on every loop; once in sync
+/// it returns no actions and changes nothing.
+async fn reconcile_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Query(query): Query<ReconcileQuery>,
+    Json(payload): Json<serde_json::Value>,
+) -> Response {
+    let plan = match plan_manifest(&app_state, payload).await {
+        Ok(plan) => plan,
+        Err(response) => return response,
+    };
+    
+    let in_sync = plan.is_empty();
+    let actions = plan.changes.clone();
+    if query.apply {
+        if let Err(response) = apply_manifest(&app_state, plan, "reconciled").await {
+            return response;
+        }
+    }
+    Json(serde_json::json!({
+        "success": true,
+        "in_sync": in_sync,
+        "applied": query.apply && !in_sync,
+        "actions": actions
+    })).into_response()
+}
+
+fn manifest_failure(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+}
+
+/// Parse a manifest and diff it against the current state
+async fn plan_manifest(app_state: &AppState, payload: serde_json::Value) -> std::result::Result<crate::manifest::Plan, Response> {
+    let manifest: crate::manifest::Manifest = serde_json::from_value(payload)
+        .map_err(|e| manifest_failure(StatusCode::BAD_REQUEST, format!("Invalid manifest: {}", e)))?;
+    match crate::manifest::plan(&app_state.node_manager, &app_state.context, &manifest).await {
+        Ok(plan) => Ok(plan),
+        Err(crate::error::AppError::Config(message)) => Err(manifest_failure(StatusCode::BAD_REQUEST, format!("Invalid manifest: {}", message))),
+        Err(e) => Err(manifest_failure(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to compare manifest: {}", e))),
+    }
+}
+
+/// Apply a plan, recording it in the audit log as `action`
+async fn apply_manifest(app_state: &AppState, plan: crate::manifest::Plan, action: &str) -> std::result::Result<(), Response> {
+    if plan.is_empty() {
+        return Ok(());
+    }
+    let changes = plan.changes.len();
+    // Steps before a failure stay applied and pushed, so they are audited too
+    let result = crate::manifest::apply(&app_state.node_manager, &app_state.context, plan).await;
+    let details = match &result {
+        Ok(()) => format!("Applied manifest with {} changes", changes),
+        Err(e) => format!("Applied manifest with {} changes partially: {}", changes, e),
+    };
+    if let Err(e) = app_state.context.audit_log.record("admin", action, "", &details).await {
+        tracing::error!("Failed to write audit log: {}", e);
+    }
+    if let Err(e) = result {
+        tracing::error!("Failed to apply manifest: {}", e);
+        return Err(manifest_failure(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply manifest, it was applied partially: {}", e)));
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct LogLevelRequest {
+    level: String,
+}
+
+async fn get_log_level_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        // null when an embedding application owns the logging
+        "level": app_state.context.log_level.as_ref().map(|log_level| log_level.current())
+    }))
+}
+
+/// Takes effect immediately and lasts until the next change or restart
+async fn update_log_level_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(payload): Json<LogLevelRequest>,
+) -> Json<serde_json::Value> {
+    let Some(log_level) = &app_state.context.log_level else {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": "The log level is managed by the application embedding yggman"
+        }));
+    };
+    let previous = log_level.current();
+    match log_level.set(&payload.level) {
+        Ok(level) => {
+            tracing::warn!("Log filter changed from {} to {}", previous, level);
+            let details = format!("Log filter changed from {} to {}", previous, level);
+            if let Err(e) = app_state.context.audit_log.record("admin", "log_level", "system", &details).await {
+                tracing::error!("Failed to write audit log: {}", e);
+            }
+            
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Log level updated successfully",
+                "level": level
+            }))
+        }
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(default)]
+struct RebroadcastRequest {
+    /// Only agents of this network
+    network: Option<String>,
+    #[serde(flatten)]
+    filter: NodeFilter,
+    /// Bypass maintenance windows
+    urgent: bool,
+}
+
+/// Regenerate every config from the store and push it to the connected
+/// agents again, for recovery after hand edits of the database or when
+/// agents are suspected to have missed an update. Without a body every
+/// connected agent is targeted.
+async fn rebroadcast_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    payload: Option<Json<RebroadcastRequest>>,
+) -> Json<serde_json::Value> {
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+    app_state.node_manager.invalidate_cache();
+    
+    let connected = crate::websocket_state::get_connected_node_ids().await;
+    let targets: HashSet<String> = app_state.node_manager.get_all_nodes().await
+        .into_iter()
+        .filter(|node| connected.contains(&node.id))
+        .filter(|node| request.network.as_ref().is_none_or(|network| &node.network == network))
+        .filter(|node| request.filter.matches(node))
+        .map(|node| node.id)
+        .collect();
+    if targets.is_empty() {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": "No connected agents match"
+        }));
+    }
+    
+    let (_, revision) = crate::websocket_state::broadcast_new_revision(&app_state.node_manager, Some(&targets), request.urgent).await;
+    
+    let details = format!("Revision {} pushed to {} connected agents", revision, targets.len());
+    if let Err(e) = app_state.context.audit_log.record("admin", "topology_rebroadcast", "topology", &details).await {
+        tracing::error!("Failed to write audit log: {}", e);
+    }
+    
+    let mut targets: Vec<String> = targets.into_iter().collect();
+    targets.sort();
+    Json(serde_json::json!({
+        "success": true,
+        "message": details,
+        "revision": revision,
+        "targets": targets
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct AuditLogQuery {
+    #[serde(default)]
+    limit: Option<u64>,
+}
+
+async fn get_audit_log_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Query(query): Query<AuditLogQuery>,
+) -> std::result::Result<Json<Vec<crate::database::entities::audit_log::Model>>, StatusCode> {
+    app_state.context.audit_log.get_recent(query.limit.unwrap_or(100)).await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read audit log: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn get_enrollment_tokens_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<crate::enrollment::EnrollmentToken>>, StatusCode> {
+    app_state.context.enrollment_tokens.list().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list enrollment tokens: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// The token is only ever returned here; the server keeps its hash
+async fn create_enrollment_token_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Json(payload): Json<crate::enrollment::NewEnrollmentToken>,
+) -> Json<serde_json::Value> {
+    if let Some(network) = &payload.network {
+        match app_state.context.network_manager.get_network(network).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return Json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Unknown network: {}", network)
+                }));
+            }
+            Err(e) => {
+                return Json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Failed to look up network {}: {}", network, e)
+                }));
+            }
+        }
+    }
+    
+    match app_state.context.enrollment_tokens.create(payload).await {
+        Ok((token, secret)) => {
+            let details = format!(
+                "Created enrollment token '{}' for {} uses until {}",
+                token.description,
+                token.max_uses.map_or("unlimited".to_string(), |uses| uses.to_string()),
+                token.expires_at.map_or("revoked".to_string(), |expires_at| expires_at.to_rfc3339())
+            );
+            if let Err(e) = app_state.context.audit_log.record("admin", "enrollment_token_created", &token.id, &details).await {
+                tracing::error!("Failed to write audit log: {}", e);
+            }
+            
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Enrollment token created; it is not shown again",
+                "token": secret,
+                "enrollment_token": token
+            }))
+        }
+        Err(e) => Json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to create enrollment token: {}", e)
+        })),
+    }
+}
+
+async fn delete_enrollment_token_handler(
+    _admin: AdminAuth,
+    State(app_state): State<AppState>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    match app_state.context.enrollment_tokens.delete(&id).await {
+        Ok(true) => {
+            if let Err(e) = app_state.context.audit_log.record("admin", "enrollment_token_revoked", &id, "Revoked enrollment token").await {
+                tracing::error!("Failed to write audit log: {}", e);
+            }
+            
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Enrollment token revoked"
+            })))
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to revoke enrollment token {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Public peers handler
+#[derive(serde::Deserialize)]
+struct PublicPeersQuery {
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    region: Option<String>,
+}
+
+/// Render publicly reachable listeners in the format used by the
+/// yggdrasil public-peers repository (markdown by default, or the
+/// `publicnodes.json` layout with `?format=json`).
+async fn get_public_peers_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Query(query): Query<PublicPeersQuery>,
+) -> Response {
+    let region = query.region.unwrap_or_else(|| "yggman".to_string());
+    let public_peers = app_state.node_manager.get_public_peers(&network).await;
+    
+    match query.format.as_deref() {
+        Some("json") => {
+            let mut entries = serde_json::Map::new();
+            for (node, uris) in &public_peers {
+                for uri in uris {
+                    entries.insert(uri.clone(), serde_json::json!({
+                        "name": node.name,
+                        "key": node.public_key,
+                    }));
+                }
+            }
+            
+            let mut body = serde_json::Map::new();
+            body.insert(format!("{}.md", region), serde_json::Value::Object(entries));
+            Json(serde_json::Value::Object(body)).into_response()
+        }
+        Some("markdown") | Some("md") | None => {
+            let mut body = format!("{}, Yggdrasil public peers\n===\n\n", region);
+            for (node, uris) in &public_peers {
+                body.push_str(&format!("* {}\n", node.name));
+                for uri in uris {
+                    body.push_str(&format!("  * `{}`\n", uri));
+                }
+                body.push('\n');
+            }
+            
+            ([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], body).into_response()
+        }
+        Some(other) => (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported format: {}", other),
+        ).into_response(),
+    }
+}
+
+// Imported public peers handlers
+async fn get_imported_public_peers_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<crate::settings_manager::ExternalPeer>>, StatusCode> {
+    app_state.context.settings_manager.get_imported_public_peers().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to get imported public peers: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn refresh_public_peers_handler(
+    State(app_state): State<AppState>,
+) -> Json<serde_json::Value> {
+    let config = app_state.context.config_manager.get().public_peers.clone();
+    
+    match crate::modules::public_peers::refresh_public_peers(&config, &app_state.context.settings_manager).await {
+        Ok(count) => Json(serde_json::json!({
+            "success": true,
+            "message": format!("Imported {} public peers", count)
+        })),
+        Err(e) => {
+            tracing::error!("Failed to refresh public peers: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to refresh public peers: {}", e)
+            }))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct UpdateExternalPeersRequest {
+    peers: Vec<String>,
+}
+
+async fn update_external_peers_handler(
+    State(app_state): State<AppState>,
+    NetworkScope(network): NetworkScope,
+    Path(NodePath { id: node_id }): Path<NodePath>,
+    Json(payload): Json<UpdateExternalPeersRequest>,
+) -> std::result::Result<Json<AddNodeResponse>, StatusCode> {
+    find_scoped_node(&app_state, &network, &node_id).await?;
+    
+    match app_state.node_manager.set_external_peers(&node_id, payload.peers).await {
+        Ok(_) => {
+            crate::websocket_state::broadcast_node_change(&app_state.node_manager, &[&node_id]).await;
+            
+            Ok(Json(AddNodeResponse {
+                success: true,
+                message: "External peers updated successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            if e.to_string().contains("Node not found") {
+                Err(StatusCode::NOT_FOUND)
+            } else {
+                Ok(Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Failed to update external peers: {}", e),
+                }))
+            }
+        }
+    }
+}
+
+// Network handlers
+async fn get_networks_handler(
+    State(app_state): State<AppState>,
+) -> std::result::Result<Json<Vec<Network>>, StatusCode> {
+    app_state.context.network_manager.get_all_networks().await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list networks: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn get_network_handler(
+    State(app_state): State<AppState>,
+    Path(network_id): Path<String>,
+) -> std::result::Result<Json<Network>, StatusCode> {
+    match app_state.context.network_manager.get_network(&network_id).await {
+        Ok(Some(network)) => Ok(Json(network)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Failed to get network {}: {}", network_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct AddNetworkRequest {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: String,
+}
+
+async fn add_network_handler(
+    State(app_state): State<AppState>,
+    Json(payload): Json<AddNetworkRequest>,
+) -> Json<AddNodeResponse> {
+    let name = payload.name.unwrap_or_else(|| payload.id.clone());
+    
+    match app_state.context.network_manager.create_network(payload.id, name, payload.description).await {
+        Ok(_) => Json(AddNodeResponse {
+            success: true,
+            message: "Network added successfully".to_string(),
+        }),
+        Err(e) => Json(AddNodeResponse {
+            success: false,
+            message: format!("Failed to add network: {}", e),
+        }),
+    }
+}
+
+async fn delete_network_handler(
+    State(app_state): State<AppState>,
+    Path(network_id): Path<String>,
+) -> std::result::Result<Json<AddNodeResponse>, StatusCode> {
+    if !app_state.node_manager.get_nodes_in_network(&network_id).await.is_empty() {
+        return Ok(Json(AddNodeResponse {
+            success: false,
+            message: "Network still has nodes, remove them first".to_string(),
+        }));
+    }
+    
+    match app_state.context.network_manager.remove_network(&network_id).await {
+        Ok(_) => {
+            if let Err(e) = app_state.context.settings_manager.remove_network_settings(&network_id).await {
+                tracing::warn!("Failed to remove settings of network {}: {}", network_id, e);
+            }
+            
+            Ok(Json(AddNodeResponse {
+                success: true,
+                message: "Network deleted successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            if e.to_string().contains("Network not found") {
+                Err(StatusCode::NOT_FOUND)
+            } else {
+                Ok(Json(AddNodeResponse {
+                    success: false,
+                    message: format!("Failed to delete network: {}", e),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn list_drift_names_missing_and_unexpected_entries() {
+        let drift = ListDrift::between(&strings(&["tcp://a:1", "tcp://b:1", "tcp://c:1"]), &strings(&["tcp://c:1", "tcp://d:1", "tcp://a:1"]));
+        assert_eq!(drift.missing, strings(&["tcp://b:1"]));
+        assert_eq!(drift.unexpected, strings(&["tcp://d:1"]));
+        assert!(!drift.is_empty());
+    }
+
+    #[test]
+    fn list_drift_ignores_order() {
+        let drift = ListDrift::between(&strings(&["a", "b"]), &strings(&["b", "a"]));
+        assert!(drift.is_empty());
+    }
+
+    #[test]
+    fn list_drift_against_empty_lists() {
+        let expected = strings(&["a", "b"]);
+        let nothing_reported = ListDrift::between(&expected, &[]);
+        assert_eq!((nothing_reported.missing, nothing_reported.unexpected), (expected.clone(), Vec::new()));
+        let nothing_expected = ListDrift::between(&[], &expected);
+        assert_eq!((nothing_expected.missing, nothing_expected.unexpected), (Vec::new(), expected));
+        assert!(ListDrift::between(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,775 @@
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::node_manager::NodeManager;
+use crate::core::context::AppContext;
+use crate::enrollment::EnrollmentError;
+use crate::network_manager::DEFAULT_NETWORK;
+use crate::redact::Secret;
+use crate::yggdrasil::{ListenPolicy, Node, YggdrasilConfig};
+
+pub use yggman_proto::protocol::{AgentMessage, AgentTiming, ErrorCode, ServerMessage, UpgradeHint, PROTOCOL_VERSION};
+
+/// First protocol version that understands `ServerMessage::UpdateAvailable`
+const UPDATE_PROTOCOL_VERSION: u32 = 2;
+/// First protocol version that understands `ServerMessage::Timing`
+pub const TIMING_PROTOCOL_VERSION: u32 = 3;
+
+/// How long the close frame may take to go out before the connection is dropped
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+impl From<&crate::config::AgentConfig> for AgentTiming {
+    fn from(config: &crate::config::AgentConfig) -> Self {
+        Self {
+            heartbeat_interval: config.heartbeat_interval,
+            address_scan_interval: config.address_scan_interval,
+            reconnect_interval: config.reconnect_interval,
+            ping_interval: config.ping_interval,
+            pong_timeout: config.pong_timeout,
+        }
+    }
+}
+
+/// Serve one agent connection. Everything logged meanwhile, by the handler
+/// and the node and config code it calls, carries the agent's address and,
+/// once registered, its node name and ID.
+#[tracing::instrument(name = "agent", skip_all, fields(peer = %client_ip, name, node_id))]
+pub async fn handle_agent_socket(
+    socket: WebSocket,
+    client_ip: std::net::IpAddr,
+    node_manager: Arc<NodeManager>,
+    context: Arc<AppContext>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<ServerMessage>(100);
+    
+    let mut node_id: Option<String> = None;
+    // When this agent last got its full config resent on request
+    let mut last_resync: Option<std::time::Instant> = None;
+    // Set when the agent announced its shutdown rather than dropping away
+    let mut going_offline = false;
+    // Key the node's private key is sealed to, if the agent sent one
+    let mut sealed_to: Option<crypto_box::PublicKey> = None;
+
+    // Set once the agent speaks CBOR, which it is then answered in
+    let cbor = Arc::new(AtomicBool::new(false));
+
+    // Any frame, pongs included, proves the agent alive; silence for a ping
+    // interval plus the pong timeout means the connection is gone
+    let agent_config = context.config_manager.get().agent.clone();
+    let ping_interval = (agent_config.ping_interval > 0).then(|| Duration::from_secs(agent_config.ping_interval));
+    let idle_limit = ping_interval.map(|interval| interval + Duration::from_secs(agent_config.pong_timeout));
+
+    // Ends the send task with a close frame: the reply to the agent's, or
+    // the server's own when it gives up on the connection
+    let (close_tx, mut close_rx) = tokio::sync::oneshot::channel::<Option<CloseFrame<'static>>>();
+
+    // Spawn task to forward messages from channel to WebSocket, signing each one
+    let signing_key = context.signing_key.clone();
+    let send_cbor = cbor.clone();
+    let mut send_task = tokio::spawn(async move {
+        // Without pings the branch below is disabled and the period unused
+        let mut ping = tokio::time::interval(ping_interval.unwrap_or(Duration::from_secs(3600)));
+        ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick is immediate
+        ping.tick().await;
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => msg,
+                close = &mut close_rx => {
+                    if let Ok(frame) = close {
+                        // After the agent's close frame the WebSocket layer
+                        // has the reply queued already, which closing flushes
+                        let _ = sender.send(Message::Close(frame)).await;
+                        let _ = sender.close().await;
+                    }
+                    break;
+                }
+                _ = ping.tick(), if ping_interval.is_some() => {
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
+            let Ok(value) = serde_json::to_value(&msg) else {
+                continue;
+            };
+            let signed = crate::signing::sign_message(&signing_key, value);
+            let frame = if send_cbor.load(Ordering::Relaxed) {
+                let mut bytes = Vec::new();
+                match ciborium::into_writer(&signed, &mut bytes) {
+                    Ok(()) => Message::Binary(bytes),
+                    Err(_) => continue,
+                }
+            } else {
+                match serde_json::to_string(&signed) {
+                    Ok(json) => Message::Text(json),
+                    Err(_) => continue,
+                }
+            };
+            if sender.send(frame).await.is_err() {
+                break;
+            }
+        }
+    }.in_current_span());
+
+    // Handle incoming messages; `None` once the connection is gone and
+    // there is no one to send a close frame to
+    let mut close: Option<Option<CloseFrame<'static>>> = None;
+    loop {
+        // Read for every message, so a changed threshold applies at once
+        let offline_after = Duration::from_secs(context.config_manager.get().agent.offline_threshold());
+        let (limit, silence) = match idle_limit {
+            Some(limit) if limit < offline_after => (limit, "no answer to pings"),
+            _ => (offline_after, "no heartbeat"),
+        };
+        let msg = match tokio::time::timeout(limit, receiver.next()).await {
+            Ok(msg) => msg,
+            Err(_) => {
+                warn!("Agent at {} sent nothing for {}s ({}), closing the connection", client_ip, limit.as_secs(), silence);
+                close = Some(Some(CloseFrame { code: close_code::AWAY, reason: silence.into() }));
+                break;
+            }
+        };
+        let msg = match msg {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => {
+                debug!("Connection to agent at {} failed: {}", client_ip, e);
+                break;
+            }
+            None => break,
+        };
+        let msg = match msg {
+            Message::Close(frame) => {
+                // Complete the handshake with the same code, and take the
+                // agent offline now rather than when the socket drops
+                debug!(
+                    "Agent at {} closed the connection: {}",
+                    client_ip,
+                    frame.as_ref().map_or("no reason".to_string(), |frame| format!("{} {}", frame.code, frame.reason)),
+                );
+                close = Some(frame);
+                break;
+            }
+            // Answered with a pong by the WebSocket layer; like any frame it
+            // already counted as a sign of life above
+            Message::Ping(_) => continue,
+            // Answers to the pings of the send task
+            Message::Pong(_) => continue,
+            msg => msg,
+        };
+        if let Some(parsed) = decode_agent_message(msg, &cbor) {
+            match parsed {
+                Ok(agent_msg) => {
+                    if node_id.is_none() && !matches!(agent_msg, AgentMessage::Register { .. } | AgentMessage::Disconnect { .. }) {
+                        warn!("Dropping message from agent at {} that is not registered", client_ip);
+                        let _ = tx.send(ServerMessage::Error {
+                            message: "register before sending other messages".to_string(),
+                            code: Some(ErrorCode::Unauthorized),
+                            upgrade: None,
+                        }).await;
+                        continue;
+                    }
+                    match agent_msg {
+                        AgentMessage::Register { name, addresses, network, tags, agent_version, yggdrasil_version, protocol_version, target, dry_run, enrollment_token, agent_token, key_agreement_key, nonce } => {
+                            let network = network.unwrap_or_else(|| DEFAULT_NETWORK.to_string());
+                            tracing::Span::current().record("name", name.as_str());
+                            info!("Agent registration: {} in network {} from {} with addresses {:?}", name, network, client_ip, addresses);
+                            debug!(
+                                "Agent {} runs version {}, Yggdrasil {}",
+                                name,
+                                agent_version.as_deref().unwrap_or("unknown"),
+                                yggdrasil_version.as_deref().unwrap_or("unknown")
+                            );
+                            
+                            let agent_config = context.config_manager.get().agent.clone();
+                            if let Err(message) = check_agent_version(&agent_config, agent_version.as_deref(), protocol_version) {
+                                warn!("Refusing registration of {}: {}", name, message);
+                                let _ = tx.send(ServerMessage::Error {
+                                    message,
+                                    code: Some(ErrorCode::UnsupportedVersion),
+                                    upgrade: Some(UpgradeHint {
+                                        min_agent_version: agent_config.min_version.clone(),
+                                        min_protocol_version: agent_config.min_protocol_version,
+                                        server_protocol_version: PROTOCOL_VERSION,
+                                    }),
+                                }).await;
+                                continue;
+                            }
+                            
+                            match key_agreement_key.as_deref().map(crate::sealing::parse_public_key) {
+                                Some(Ok(key)) => sealed_to = Some(key),
+                                Some(Err(e)) => {
+                                    warn!("Refusing registration of {}: {}", name, e);
+                                    let _ = tx.send(ServerMessage::error(format!("Failed to register node: {}", e))).await;
+                                    continue;
+                                }
+                                None if agent_config.require_sealed_keys => {
+                                    warn!("Refusing registration of {}: agent cannot receive a sealed private key", name);
+                                    let _ = tx.send(ServerMessage::Error {
+                                        message: "the server only sends sealed private keys, upgrade yggman-agent".to_string(),
+                                        code: Some(ErrorCode::UnsupportedVersion),
+                                        upgrade: Some(UpgradeHint {
+                                            min_agent_version: agent_config.min_version.clone(),
+                                            min_protocol_version: agent_config.min_protocol_version,
+                                            server_protocol_version: PROTOCOL_VERSION,
+                                        }),
+                                    }).await;
+                                    continue;
+                                }
+                                None => sealed_to = None,
+                            }
+                            
+                            match context.network_manager.get_network(&network).await {
+                                Ok(Some(_)) => {}
+                                Ok(None) => {
+                                    let _ = tx.send(ServerMessage::error(format!("Unknown network: {}", network))).await;
+                                    continue;
+                                }
+                                Err(e) => {
+                                    error!("Failed to look up network {}: {}", network, e);
+                                    let _ = tx.send(ServerMessage::error("Failed to register node")).await;
+                                    continue;
+                                }
+                            }
+                            
+                            let existing_node = node_manager.get_node_by_name(&network, &name).await;
+                            
+                            // Taking over a known node hands out its private key, so it always
+                            // takes the node's agent token; only a node created by this
+                            // registration is handed out without one
+                            if let Some(node) = &existing_node {
+                                let throttle = &context.auth_throttle;
+                                let refusal = match agent_token.as_ref().map(|token| token.expose().as_str()) {
+                                    Some(_) if throttle.locked_out(client_ip).is_some() => Some("too many failed attempts, try again later".to_string()),
+                                    Some(token) if crate::signing::verify_agent_token(&context.signing_key, &node.id, token) => {
+                                        throttle.succeeded(client_ip).await;
+                                        None
+                                    }
+                                    Some(_) => {
+                                        throttle.failed(client_ip, "agent token", &context.config_manager.get().server.auth_lockout).await;
+                                        Some(format!("wrong agent token for node {}", name))
+                                    }
+                                    None => Some(format!("node {} exists, an agent token is required to register as it", name)),
+                                };
+                                if let Some(message) = refusal {
+                                    warn!("Refusing registration of {}: {}", name, message);
+                                    let _ = tx.send(ServerMessage::Error {
+                                        message,
+                                        code: Some(ErrorCode::AgentTokenRefused),
+                                        upgrade: None,
+                                    }).await;
+                                    continue;
+                                }
+                            }
+                            
+                            // A preview leaves the node, its enrollment token and the
+                            // connection of its running agent alone
+                            if dry_run {
+                                let Some(node) = existing_node else {
+                                    warn!("Refusing dry run of {}: no such node in network {}", name, network);
+                                    let _ = tx.send(ServerMessage::error(format!("Node {} does not exist yet, register it before a dry run", name))).await;
+                                    continue;
+                                };
+                                info!("Dry run of {}, sending its config without registering it", node.id);
+                                match preview_config(&node_manager, &context, &node, sealed_to.as_ref(), nonce.as_deref()).await {
+                                    Some(response) => {
+                                        let _ = tx.send(response).await;
+                                    }
+                                    None => {
+                                        let _ = tx.send(ServerMessage::error("Failed to generate config")).await;
+                                    }
+                                }
+                                continue;
+                            }
+                            
+                            // New nodes spend one use of their enrollment token, which may
+                            // be required; known nodes reconnect with their agent token
+                            let mut enrolled_by = None;
+                            let mut tags = tags;
+                            if existing_node.is_none() {
+                                let throttle = &context.auth_throttle;
+                                let refusal = match enrollment_token.as_ref().map(|token| token.expose().as_str()) {
+                                    Some(_) if throttle.locked_out(client_ip).is_some() => Some("too many failed attempts, try again later".to_string()),
+                                    Some(token) => match context.enrollment_tokens.redeem(token, &network).await {
+                                        Ok(token) => {
+                                            info!("Enrolling {} with token {} ({} of {} uses)", name, token.id, token.uses, token.max_uses.map_or("unlimited".to_string(), |uses| uses.to_string()));
+                                            tags.extend(token.tags);
+                                            enrolled_by = Some(token.id);
+                                            None
+                                        }
+                                        Err(e @ EnrollmentError::Database(_)) => {
+                                            error!("Failed to check enrollment token of {}: {}", name, e);
+                                            Some("Failed to check enrollment token".to_string())
+                                        }
+                                        Err(e @ EnrollmentError::Unknown) => {
+                                            throttle.failed(client_ip, "enrollment token", &context.config_manager.get().server.auth_lockout).await;
+                                            Some(e.to_string())
+                                        }
+                                        Err(e) => Some(e.to_string()),
+                                    },
+                                    None if agent_config.require_enrollment => Some("an enrollment token is required to register new nodes".to_string()),
+                                    None => None,
+                                };
+                                if let Some(message) = refusal {
+                                    warn!("Refusing registration of {}: {}", name, message);
+                                    let _ = tx.send(ServerMessage::Error {
+                                        message,
+                                        code: Some(ErrorCode::EnrollmentRefused),
+                                        upgrade: None,
+                                    }).await;
+                                    continue;
+                                }
+                            }
+                            
+                            // Tags announced by the agent are added to the ones set through the API
+                            let mut node_tags = existing_node.as_ref().map(|n| n.tags.clone()).unwrap_or_default();
+                            for tag in tags {
+                                if !node_tags.contains(&tag) {
+                                    node_tags.push(tag);
+                                }
+                            }
+                            
+                            // Resolve listen endpoints from the network's templates
+                            let default_listen = match context.settings_manager.get_listen_templates(&network).await {
+                                Ok(templates) => templates.resolve(&node_tags),
+                                Err(e) => {
+                                    error!("Failed to get listen template from database: {}", e);
+                                    vec!["tcp://0.0.0.0:9001".to_string()] // fallback
+                                }
+                            };
+                            
+                            // Check if node already exists
+                            let node = if let Some(existing_node) = existing_node {
+                                info!("Reusing existing node: {} ({})", existing_node.name, existing_node.id);
+                                // Pinned listen endpoints were set by hand and are kept
+                                let listen = match existing_node.listen_policy {
+                                    ListenPolicy::Pinned => existing_node.listen.clone(),
+                                    ListenPolicy::Template => default_listen.clone(),
+                                };
+                                // Update addresses for existing node
+                                match node_manager.update_node(&existing_node.id, name.clone(), listen, addresses, Some(node_tags)).await {
+                                    Ok(_) => {
+                                        // Get the updated node
+                                        node_manager.get_node_by_id(&existing_node.id).await
+                                    }
+                                    Err(e) => {
+                                        warn!("Failed to update existing node addresses: {}", e);
+                                        Some(existing_node)
+                                    }
+                                }
+                            } else {
+                                // Create new node
+                                info!("Creating new node: {}", name);
+                                let spec = crate::node_manager::NodeSpec {
+                                    name: name.clone(),
+                                    listen: default_listen.clone(),
+                                    addresses,
+                                    tags: node_tags,
+                                    ..Default::default()
+                                };
+                                match node_manager.add_node(&network, spec).await {
+                                    Ok(node) => {
+                                        crate::node_events::record(&context, crate::node_events::NodeLifecycle::Created, &node).await;
+                                        if let Some(token_id) = &enrolled_by {
+                                            let details = format!("Enrolled node {} in network {} from {}", node.name, network, client_ip);
+                                            if let Err(e) = context.audit_log.record(token_id, "node_enrolled", &node.id, &details).await {
+                                                error!("Failed to write audit log: {}", e);
+                                            }
+                                        }
+                                        Some(node)
+                                    }
+                                    Err(e) => {
+                                        let error_msg = ServerMessage::error(format!("Failed to register node: {}", e));
+                                        let _ = tx.send(error_msg).await;
+                                        None
+                                    }
+                                }
+                            };
+                            
+                            if let Some(node) = node {
+                                node_id = Some(node.id.clone());
+                                tracing::Span::current().record("node_id", node.id.as_str());
+                                
+                                let update = update_offer(&agent_config, agent_version.as_deref(), protocol_version, target.as_deref());
+                                if let Err(e) = node_manager.set_versions(&node.id, agent_version, yggdrasil_version).await {
+                                    warn!("Failed to store versions of node {}: {}", node.id, e);
+                                }
+                                
+                                // Register connection
+                                crate::websocket_state::register_agent_connection(node.id.clone(), tx.clone(), protocol_version).await;
+                                context.status_history.record(&node.id, true).await;
+                                // Peers dial it again with the update broadcast below
+                                if node_manager.reinstate(&node.id) {
+                                    info!("Node {} is back, reinstating it in its peers' configs", node.id);
+                                }
+                                
+                                if let Some(response) = registration_config(&node_manager, &context, &node, sealed_to.as_ref(), nonce.as_deref()).await {
+                                    if let Err(e) = tx.send(response).await {
+                                        error!("Failed to send config to agent: {}", e);
+                                    }
+                                    
+                                    // Notify other agents about node connection
+                                    crate::websocket_state::broadcast_configuration_update(&node_manager).await;
+                                }
+                                
+                                if let Some(update) = update {
+                                    info!("Offering agent update to {}", node.id);
+                                    let _ = tx.send(update).await;
+                                }
+                            }
+                        }
+                        AgentMessage::ConfigRejected { revision, reason } => {
+                            warn!(
+                                "Agent {} rejected config revision {}: {}",
+                                node_id.as_deref().unwrap_or("unregistered"), revision, reason
+                            );
+                            // Agents only reject revisions older than one they applied
+                            if let Some(id) = &node_id {
+                                if let Err(e) = node_manager.outbox().acknowledge(id, revision).await {
+                                    warn!("Failed to clear pending config of {}: {}", id, e);
+                                }
+                            }
+                        }
+                        AgentMessage::ConfigApplied { revision, success, error } => {
+                            if let Some(id) = &node_id {
+                                if success {
+                                    debug!("Agent {} applied config revision {}", id, revision);
+                                } else {
+                                    warn!("Agent {} failed to apply config revision {}: {}", id, revision, error.as_deref().unwrap_or("unknown error"));
+                                }
+                                if let Err(e) = node_manager.outbox().acknowledge(id, revision).await {
+                                    warn!("Failed to clear pending config of {}: {}", id, e);
+                                }
+                                crate::websocket_state::record_config_ack(id, crate::websocket_state::ConfigAck { revision, success, error }).await;
+                            }
+                        }
+                        AgentMessage::Status { revision, listen, peers, allowed_public_keys, established_peers, drift, traffic, last_server_error } => {
+                            if let Some(id) = &node_id {
+                                debug!("Status from {}: revision {}, {} peers", id, revision, peers.len());
+                                let drifted = crate::websocket_state::get_agent_status(id).await.is_some_and(|last| last.drift);
+                                if drift && !drifted {
+                                    context.event_log.emit(
+                                        "config_drift",
+                                        crate::event_log::EventSeverity::Warning,
+                                        Some(id),
+                                        &format!("Managed sections of the Yggdrasil config on {} were edited by hand", id),
+                                    ).await;
+                                }
+                                if let Some(traffic) = &traffic {
+                                    let bucket_seconds = context.config_manager.get().traffic.bucket_seconds;
+                                    context.traffic_stats.record(id, traffic, bucket_seconds).await;
+                                }
+                                crate::websocket_state::record_agent_status(id, crate::websocket_state::AgentStatus {
+                                    revision,
+                                    listen,
+                                    peers,
+                                    allowed_public_keys,
+                                    established_peers,
+                                    drift,
+                                    last_server_error,
+                                    reported_at: chrono::Utc::now(),
+                                }).await;
+                            }
+                        }
+                        AgentMessage::ListenResolved { listen } => {
+                            if let Some(id) = &node_id {
+                                info!("Resolved listen endpoints for {}: {:?}", id, listen);
+                                match node_manager.set_resolved_listen(id, listen).await {
+                                    Ok(true) => {
+                                        // Peers of this node have to dial the resolved addresses
+                                        crate::websocket_state::broadcast_node_change(&node_manager, &[id.as_str()]).await;
+                                    }
+                                    Ok(false) => {
+                                        debug!("Resolved listen endpoints unchanged for node {}", id);
+                                    }
+                                    Err(e) => {
+                                        error!("Failed to store resolved listen endpoints for node {}: {}", id, e);
+                                    }
+                                }
+                            }
+                        }
+                        AgentMessage::FirewallUpdated { backend, open_ports, error } => {
+                            if let Some(id) = &node_id {
+                                if let Some(error) = &error {
+                                    context.event_log.emit(
+                                        "firewall_failed",
+                                        crate::event_log::EventSeverity::Warning,
+                                        Some(id),
+                                        &format!("Agent {} could not update its {} firewall: {}", id, backend, error),
+                                    ).await;
+                                } else {
+                                    debug!("Agent {} has {} ports open in {}: {:?}", id, open_ports.len(), backend, open_ports);
+                                }
+                                crate::websocket_state::record_firewall_status(id, crate::websocket_state::FirewallStatus {
+                                    backend,
+                                    open_ports,
+                                    error,
+                                    reported_at: chrono::Utc::now(),
+                                }).await;
+                            }
+                        }
+                        AgentMessage::RequestFullConfig { reason } => {
+                            let Some(id) = &node_id else {
+                                continue;
+                            };
+                            let reason = reason.as_deref().unwrap_or("no reason given");
+                            
+                            let min_interval = Duration::from_secs(context.config_manager.get().agent.resync_min_interval);
+                            if let Some(wait) = last_resync.map(|at| min_interval.saturating_sub(at.elapsed())).filter(|wait| !wait.is_zero()) {
+                                warn!("Refusing full config request from {} ({}), last one was too recent", id, reason);
+                                let _ = tx.send(ServerMessage::error(format!("Full config requested too often, retry in {}s", wait.as_secs().max(1)))).await;
+                                continue;
+                            }
+                            
+                            info!("Agent {} requested its full config: {}", id, reason);
+                            match node_manager.get_node_by_id(id).await {
+                                Some(node) => {
+                                    if let Some(response) = full_config(&node_manager, &context, &node, sealed_to.as_ref(), None).await {
+                                        last_resync = Some(std::time::Instant::now());
+                                        if let Err(e) = tx.send(response).await {
+                                            error!("Failed to send config to agent: {}", e);
+                                        }
+                                    }
+                                }
+                                None => warn!("Cannot resend config to unknown node: {}", id),
+                            }
+                        }
+                        AgentMessage::Disconnect { reason } => {
+                            info!(
+                                "Agent {} is going offline: {}",
+                                node_id.as_deref().unwrap_or("unregistered"),
+                                reason.as_deref().unwrap_or("no reason given")
+                            );
+                            going_offline = true;
+                            break;
+                        }
+                        AgentMessage::Heartbeat => {
+                            debug!("Heartbeat from {:?}", node_id);
+                        }
+                        AgentMessage::UpdateAddresses { addresses } => {
+                            if let Some(id) = &node_id {
+                                info!("Address update for {}: {:?}", id, addresses);
+                                
+                                // Get current node information
+                                if let Some(current_node) = node_manager.get_node_by_id(id).await {
+                                    // Sort addresses for comparison to avoid false positives
+                                    let mut new_addresses = addresses.clone();
+                                    new_addresses.sort();
+                                    let mut current_addresses = current_node.addresses.clone();
+                                    current_addresses.sort();
+                                    
+                                    // Only update if addresses actually changed
+                                    if new_addresses != current_addresses {
+                                        // Update node with new addresses
+                                        match node_manager.update_node(
+                                            id, 
+                                            current_node.name.clone(), 
+                                            current_node.listen.clone(),
+                                            addresses,
+                                            None,
+                                        ).await {
+                                            Ok(_) => {
+                                                info!("Updated addresses for node {}", id);
+                                                // Only this node and its peers need new configs
+                                                crate::websocket_state::broadcast_node_change(&node_manager, &[id.as_str()]).await;
+                                            }
+                                            Err(e) => {
+                                                error!("Failed to update addresses for node {}: {}", id, e);
+                                            }
+                                        }
+                                    } else {
+                                        debug!("Address list unchanged for node {}, skipping update", id);
+                                    }
+                                } else {
+                                    warn!("Cannot update addresses for unknown node: {}", id);
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to parse agent message: {}", e);
+                    let _ = tx.send(ServerMessage::Error {
+                        message: format!("cannot parse message: {}", e),
+                        code: Some(ErrorCode::ParseError),
+                        upgrade: None,
+                    }).await;
+                }
+            }
+        }
+    }
+
+    // Clean up
+    if let Some(id) = node_id {
+        // The agent may have reconnected before this socket timed out
+        if crate::websocket_state::unregister_agent_connection(&id, &tx).await {
+            context.status_history.record(&id, false).await;
+            info!("Agent {} at {} disconnected", id, client_ip);
+            
+            if going_offline && context.config_manager.get().agent.withdraw_on_shutdown && node_manager.withdraw(&id) {
+                info!("Withdrawing {} from its peers' configs until it returns", id);
+                crate::websocket_state::broadcast_node_change(&node_manager, &[id.as_str()]).await;
+            }
+        } else {
+            info!("Replaced connection of agent {} at {} closed", id, client_ip);
+        }
+    }
+
+    // Let the send task write the close frame, unless the connection is gone
+    match close.map(|frame| close_tx.send(frame)) {
+        Some(Ok(())) => {
+            if tokio::time::timeout(CLOSE_TIMEOUT, &mut send_task).await.is_err() {
+                debug!("Agent at {} did not take the close frame in time", client_ip);
+                send_task.abort();
+            }
+        }
+        _ => send_task.abort(),
+    }
+}
+
+/// An agent message from a JSON text frame, or a CBOR binary frame of
+/// agents started with --cbor; `None` for control frames
+fn decode_agent_message(
+    frame: Message,
+    cbor: &AtomicBool,
+) -> Option<std::result::Result<AgentMessage, String>> {
+    match frame {
+        Message::Text(text) => Some(serde_json::from_str(&text).map_err(|e| e.to_string())),
+        Message::Binary(bytes) => {
+            cbor.store(true, Ordering::Relaxed);
+            Some(ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// The complete configuration of `node`, as sent on registration and on
+/// `RequestFullConfig`, with the private key sealed to `sealed_to` if given
+/// and `nonce` echoed from the registration or pull it answers
+pub(crate) async fn full_config(node_manager: &NodeManager, context: &AppContext, node: &Node, sealed_to: Option<&crypto_box::PublicKey>, nonce: Option<&str>) -> Option<ServerMessage> {
+    let configs = node_manager.generate_configs().await;
+    let config = configs.get(&node.id)?;
+    let revision = node_manager.current_revision().await;
+    config_message(context, node, (node_manager.epoch(), revision), config, sealed_to, nonce).await
+}
+
+/// The configuration for a registering agent: the revision last pushed to
+/// it when it never acknowledged that one, e.g. because the server
+/// restarted before the agent reconnected, and the current one otherwise
+async fn registration_config(node_manager: &NodeManager, context: &AppContext, node: &Node, sealed_to: Option<&crypto_box::PublicKey>, nonce: Option<&str>) -> Option<ServerMessage> {
+    match node_manager.outbox().pending(&node.id).await {
+        Ok(Some((revision, config))) => {
+            info!("Redelivering unacknowledged config revision {} to {}", revision, node.id);
+            if let Err(e) = node_manager.outbox().record_attempt(&node.id).await {
+                warn!("Failed to count delivery of revision {} to {}: {}", revision, node.id, e);
+            }
+            config_message(context, node, (node_manager.epoch(), revision), &config, sealed_to, nonce).await
+        }
+        Ok(None) => full_config(node_manager, context, node, sealed_to, nonce).await,
+        Err(e) => {
+            warn!("Failed to read pending config of {}, sending the current one: {}", node.id, e);
+            full_config(node_manager, context, node, sealed_to, nonce).await
+        }
+    }
+}
+
+/// The current configuration of `node` for a dry run, which is not
+/// expected to be acknowledged
+async fn preview_config(node_manager: &NodeManager, context: &AppContext, node: &Node, sealed_to: Option<&crypto_box::PublicKey>, nonce: Option<&str>) -> Option<ServerMessage> {
+    let configs = node_manager.generate_configs().await;
+    let config = configs.get(&node.id)?;
+    let revision = node_manager.current_revision().await;
+    build_config_message(context, node, (node_manager.epoch(), revision), config, sealed_to, nonce)
+}
+
+async fn config_message(context: &AppContext, node: &Node, (epoch, revision): (&str, u64), config: &YggdrasilConfig, sealed_to: Option<&crypto_box::PublicKey>, nonce: Option<&str>) -> Option<ServerMessage> {
+    let message = build_config_message(context, node, (epoch, revision), config, sealed_to, nonce)?;
+    crate::websocket_state::record_expected_revision(&node.id, revision).await;
+    Some(message)
+}
+
+/// The config message for `revision` of `epoch`, see `ServerMessage::Config`
+fn build_config_message(context: &AppContext, node: &Node, (epoch, revision): (&str, u64), config: &YggdrasilConfig, sealed_to: Option<&crypto_box::PublicKey>, nonce: Option<&str>) -> Option<ServerMessage> {
+    let agent_token = Secret::new(crate::signing::agent_token(&context.signing_key, &node.id));
+    let (private_key, sealed_private_key, agent_token, sealed_agent_token) = match sealed_to {
+        Some(key) => match (crate::sealing::seal(key, node.private_key.expose()), crate::sealing::seal(key, agent_token.expose())) {
+            (Ok(sealed_key), Ok(sealed_token)) => (Secret::default(), Some(sealed_key), Secret::default(), Some(sealed_token)),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Failed to seal the private key of {}: {}", node.id, e);
+                return None;
+            }
+        },
+        None => (node.private_key.clone(), None, agent_token, None),
+    };
+    
+    Some(ServerMessage::Config {
+        revision,
+        epoch: epoch.to_string(),
+        nonce: nonce.map(str::to_string),
+        node_id: node.id.clone(),
+        private_key,
+        sealed_private_key,
+        agent_token,
+        sealed_agent_token,
+        listen: config.listen.clone(),
+        peers: config.peers.clone(),
+        allowed_public_keys: config.allowed_public_keys.clone(),
+        interface_peers: config.interface_peers.clone(),
+        if_name: Some(config.if_name.clone()),
+        extra_config: config.extra_config.clone(),
+        timing: Some(AgentTiming::from(&context.config_manager.get().agent)),
+    })
+}
+
+/// Whether an agent reporting `agent_version` and `protocol_version` meets
+/// the configured minimums; the error explains how to upgrade
+fn check_agent_version(config: &crate::config::AgentConfig, agent_version: Option<&str>, protocol_version: u32) -> Result<(), String> {
+    if protocol_version < config.min_protocol_version {
+        return Err(format!(
+            "agent protocol version {} is no longer supported, upgrade the agent to one speaking protocol {} or newer",
+            protocol_version, config.min_protocol_version
+        ));
+    }
+    
+    let Some(min_version) = config.min_version.as_deref().and_then(|v| semver::Version::parse(v).ok()) else {
+        return Ok(());
+    };
+    match agent_version.map(semver::Version::parse) {
+        Some(Ok(version)) if version >= min_version => Ok(()),
+        Some(Ok(version)) => Err(format!("agent version {} is older than the minimum {}, upgrade yggman-agent", version, min_version)),
+        _ => Err(format!("agent did not report a valid version, upgrade yggman-agent to {} or newer", min_version)),
+    }
+}
+
+/// The configured agent release, when it is newer than `agent_version` and
+/// published for the agent's target
+fn update_offer(config: &crate::config::AgentConfig, agent_version: Option<&str>, protocol_version: u32, target: Option<&str>) -> Option<ServerMessage> {
+    if protocol_version < UPDATE_PROTOCOL_VERSION {
+        return None;
+    }
+    let version = config.update_version.as_deref()?;
+    let newer = semver::Version::parse(version).ok()? > semver::Version::parse(agent_version?).ok()?;
+    if !newer {
+        return None;
+    }
+    
+    let target = target?;
+    let sha256 = config.update_sha256.get(target)?;
+    let url = config.update_url.as_deref()?
+        .replace("{version}", version)
+        .replace("{target}", target);
+    Some(ServerMessage::UpdateAvailable {
+        version: version.to_string(),
+        url,
+        sha256: sha256.to_lowercase(),
+    })
+}
@@ -0,0 +1,96 @@
+use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait, QueryOrder, Set};
+
+use crate::database::entities::network::{self as network_entity, Model as Network};
+use crate::error::AppError;
+
+/// Network every node belongs to unless told otherwise; legacy `/api/...`
+/// routes and agents without `--network` operate on it.
+pub use yggman_core::DEFAULT_NETWORK;
+
+pub struct NetworkManager {
+    db: DatabaseConnection,
+}
+
+impl NetworkManager {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+    
+    pub async fn initialize_defaults(&self) -> Result<(), AppError> {
+        if self.get_network(DEFAULT_NETWORK).await?.is_none() {
+            self.create_network(DEFAULT_NETWORK.to_string(), "Default".to_string(), String::new()).await?;
+            tracing::info!("Created default network");
+        }
+        Ok(())
+    }
+    
+    pub async fn get_network(&self, id: &str) -> Result<Option<Network>, AppError> {
+        network_entity::Entity::find_by_id(id)
+            .one(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+    
+    pub async fn get_all_networks(&self) -> Result<Vec<Network>, AppError> {
+        network_entity::Entity::find()
+            .order_by_asc(network_entity::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+    
+    pub async fn create_network(&self, id: String, name: String, description: String) -> Result<Network, AppError> {
+        if !is_valid_network_id(&id) {
+            return Err(AppError::Config(
+                "Invalid network id: use 1-64 lowercase letters, digits, '-' or '_'".to_string()
+            ));
+        }
+        
+        if self.get_network(&id).await?.is_some() {
+            return Err(AppError::Config(format!("Network {} already exists", id)));
+        }
+        
+        let _write = crate::database::queue_write().await;
+        network_entity::ActiveModel::new(id, name, description)
+            .insert(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+    
+    pub async fn update_network(&self, id: &str, name: String, description: String) -> Result<Network, AppError> {
+        let network = self.get_network(id).await?
+            .ok_or_else(|| AppError::Config("Network not found".to_string()))?;
+        
+        let _write = crate::database::queue_write().await;
+        let mut active: network_entity::ActiveModel = network.into();
+        active.name = Set(name);
+        active.description = Set(description);
+        active.update(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+    
+    pub async fn remove_network(&self, id: &str) -> Result<(), AppError> {
+        if id == DEFAULT_NETWORK {
+            return Err(AppError::Config("The default network cannot be removed".to_string()));
+        }
+        
+        let _write = crate::database::queue_write().await;
+        let result = network_entity::Entity::delete_by_id(id)
+            .exec(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        
+        if result.rows_affected == 0 {
+            return Err(AppError::Config("Network not found".to_string()));
+        }
+        
+        Ok(())
+    }
+}
+
+pub fn is_valid_network_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
@@ -0,0 +1,48 @@
+//! Lifecycle events of nodes. Creating, changing or deleting a node is
+//! recorded as an event and delivered through the notifier together with
+//! the node's external id and provider, so a controller mapping Kubernetes
+//! nodes or cloud instances to yggman nodes can follow along.
+
+use crate::core::context::AppContext;
+use crate::event_log::EventSeverity;
+use crate::yggdrasil::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeLifecycle {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl NodeLifecycle {
+    fn kind(self) -> &'static str {
+        match self {
+            NodeLifecycle::Created => "node_created",
+            NodeLifecycle::Updated => "node_updated",
+            NodeLifecycle::Deleted => "node_deleted",
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            NodeLifecycle::Created => "created",
+            NodeLifecycle::Updated => "updated",
+            NodeLifecycle::Deleted => "deleted",
+        }
+    }
+}
+
+pub async fn record(context: &AppContext, change: NodeLifecycle, node: &Node) {
+    let mut message = format!("Node {} {} in network {}", node.name, change.verb(), node.network);
+    if let Some(external_id) = &node.external_id {
+        message.push_str(&format!(" ({} {})", node.provider.as_deref().unwrap_or("external id"), external_id));
+    }
+    context.event_log.emit(change.kind(), EventSeverity::Info, Some(&node.id), &message).await;
+    context.notifier.send(&context.config_manager, change.kind(), serde_json::json!({
+        "node_id": node.id,
+        "name": node.name,
+        "network": node.network,
+        "external_id": node.external_id,
+        "provider": node.provider,
+    }));
+}
@@ -0,0 +1,822 @@
+use crate::yggdrasil::{ListenPolicy, Node, YggdrasilConfig};
+use crate::outbox::ConfigOutbox;
+use crate::settings_manager::SettingsManager;
+use crate::storage::NodeStore;
+use crate::redact::Secret;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use yggman_core::mesh::{
+    convert_listen_to_peer_with_address, generate_network_configs_for, listen_host_is_public, mesh_peers, peer_addresses,
+    ScheduledPeers,
+};
+
+pub use yggman_core::mesh::{generate_network_configs, is_public_address, ordered_pair, peer_uri_key};
+
+/// User-provided fields of a new node; keys and id are generated.
+#[derive(Debug, Clone, Default)]
+pub struct NodeSpec {
+    pub name: String,
+    pub listen: Vec<String>,
+    pub listen_policy: ListenPolicy,
+    pub addresses: Vec<String>,
+    pub tags: Vec<String>,
+    pub mtu: Option<u16>,
+    pub node_info: HashMap<String, serde_json::Value>,
+    pub if_name: Option<String>,
+    pub interface_peers: HashMap<String, Vec<String>>,
+    /// Public keys of devices outside yggman the node accepts as peers
+    pub extra_allowed_keys: Vec<String>,
+    pub extra_config: HashMap<String, serde_json::Value>,
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    pub contact: Option<String>,
+    pub external_id: Option<String>,
+    pub provider: Option<String>,
+}
+
+/// Optional per-node settings; `None` keeps the current value. An empty
+/// description, owner, contact, external id or provider clears it.
+#[derive(Debug, Clone, Default)]
+pub struct NodeOptions {
+    pub listen_policy: Option<ListenPolicy>,
+    pub mtu: Option<u16>,
+    pub node_info: Option<HashMap<String, serde_json::Value>>,
+    pub if_name: Option<String>,
+    pub interface_peers: Option<HashMap<String, Vec<String>>>,
+    pub extra_allowed_keys: Option<Vec<String>>,
+    pub extra_config: Option<HashMap<String, serde_json::Value>>,
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    pub contact: Option<String>,
+    pub external_id: Option<String>,
+    pub provider: Option<String>,
+}
+
+impl NodeOptions {
+    pub fn is_empty(&self) -> bool {
+        self.listen_policy.is_none() && self.mtu.is_none() && self.node_info.is_none() && self.if_name.is_none()
+            && self.interface_peers.is_none() && self.extra_allowed_keys.is_none() && self.extra_config.is_none()
+            && self.description.is_none() && self.owner.is_none() && self.contact.is_none()
+            && self.external_id.is_none() && self.provider.is_none()
+    }
+}
+
+/// Nodes of a network selected by a bulk update. At least one criterion
+/// is required so an empty filter never touches the whole network.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct NodeFilter {
+    /// Select every node of the network
+    pub all: bool,
+    pub ids: Vec<String>,
+    /// Nodes carrying all of these tags
+    pub tags: Vec<String>,
+}
+
+impl NodeFilter {
+    fn is_empty(&self) -> bool {
+        !self.all && self.ids.is_empty() && self.tags.is_empty()
+    }
+    
+    pub fn matches(&self, node: &Node) -> bool {
+        (self.ids.is_empty() || self.ids.contains(&node.id))
+            && self.tags.iter().all(|tag| node.tags.contains(tag))
+    }
+}
+
+/// Changes applied to every node matched by a bulk update; `None` and empty
+/// lists keep the current value
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct BulkChanges {
+    /// Replace the tags
+    pub tags: Option<Vec<String>>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    /// Replace the listen endpoints or templates
+    pub listen: Option<Vec<String>>,
+    pub mtu: Option<u16>,
+}
+
+impl BulkChanges {
+    fn is_empty(&self) -> bool {
+        self.tags.is_none() && self.add_tags.is_empty() && self.remove_tags.is_empty()
+            && self.listen.is_none() && self.mtu.is_none()
+    }
+}
+
+impl NodeSpec {
+    pub fn validate(&self) -> Result<(), crate::error::AppError> {
+        if let Some(if_name) = &self.if_name {
+            validate_if_name(if_name)?;
+        }
+        validate_allowed_keys(&self.extra_allowed_keys)?;
+        validate_extra_config(&self.extra_config)
+    }
+    
+    /// Spec for a copy of an existing node under a new name. Addresses,
+    /// external peers, extra allowed keys, external ids and keys are
+    /// per-host and not copied.
+    pub fn clone_of(node: &Node, name: String, addresses: Vec<String>) -> Self {
+        Self {
+            name,
+            listen: node.listen.clone(),
+            listen_policy: node.listen_policy,
+            addresses,
+            tags: node.tags.clone(),
+            mtu: node.mtu,
+            node_info: node.node_info.clone(),
+            if_name: node.if_name.clone(),
+            interface_peers: node.interface_peers.clone(),
+            extra_allowed_keys: Vec::new(),
+            extra_config: node.extra_config.clone(),
+            // Notes describe the original host, ownership carries over
+            description: None,
+            owner: node.owner.clone(),
+            contact: node.contact.clone(),
+            external_id: None,
+            provider: None,
+        }
+    }
+}
+
+pub struct NodeManager {
+    store: Arc<dyn NodeStore>,
+    settings_manager: SettingsManager,
+    outbox: ConfigOutbox,
+    /// Nodes whose agents announced a shutdown, which other nodes stop
+    /// dialing until the agent registers again
+    withdrawn: std::sync::RwLock<HashSet<String>>,
+    /// Random per server run. Revisions restart after a restore or in
+    /// ephemeral mode, so configs carry the run that numbered them.
+    epoch: String,
+}
+
+impl NodeManager {
+    pub fn new(store: Arc<dyn NodeStore>, settings_manager: SettingsManager) -> Self {
+        let outbox = ConfigOutbox::new(settings_manager.connection());
+        Self { store, settings_manager, outbox, withdrawn: Default::default(), epoch: uuid_simple() }
+    }
+    
+    /// The epoch configs and updates of this run are numbered in
+    pub fn epoch(&self) -> &str {
+        &self.epoch
+    }
+    
+    /// Leave `node_id` out of other nodes' peers; false if it already was
+    pub fn withdraw(&self, node_id: &str) -> bool {
+        self.withdrawn.write().unwrap().insert(node_id.to_string())
+    }
+    
+    /// Peer other nodes with `node_id` again; false if it was not withdrawn
+    pub fn reinstate(&self, node_id: &str) -> bool {
+        self.withdrawn.write().unwrap().remove(node_id)
+    }
+    
+    pub fn settings(&self) -> &SettingsManager {
+        &self.settings_manager
+    }
+    
+    /// Pushed revisions agents have not acknowledged yet
+    pub fn outbox(&self) -> &ConfigOutbox {
+        &self.outbox
+    }
+    
+    /// Hit rate of the node cache, when it is enabled
+    pub fn cache_stats(&self) -> Option<crate::storage::CacheStats> {
+        self.store.cache_stats()
+    }
+    
+    /// Read nodes from the store again on next use
+    pub fn invalidate_cache(&self) {
+        self.store.invalidate_cache();
+    }
+    
+    pub async fn add_node(&self, network: &str, spec: NodeSpec) -> Result<Node, crate::error::AppError> {
+        spec.validate()?;
+        let external_id = non_empty(spec.external_id);
+        let provider = non_empty(spec.provider);
+        self.check_external_id(network, None, provider.as_deref(), external_id.as_deref()).await?;
+        
+        let (public_key, private_key) = generate_keypair();
+        
+        let node = Node {
+            id: format!("node-{}", uuid_simple()),
+            name: spec.name,
+            public_key,
+            private_key,
+            listen: spec.listen,
+            listen_policy: spec.listen_policy,
+            addresses: spec.addresses,
+            external_peers: Vec::new(),
+            network: network.to_string(),
+            tags: spec.tags,
+            resolved_listen: Vec::new(),
+            mtu: spec.mtu,
+            node_info: spec.node_info,
+            if_name: spec.if_name.filter(|name| name != "auto"),
+            interface_peers: spec.interface_peers,
+            extra_allowed_keys: spec.extra_allowed_keys,
+            extra_config: spec.extra_config,
+            agent_version: None,
+            yggdrasil_version: None,
+            description: non_empty(spec.description),
+            owner: non_empty(spec.owner),
+            contact: non_empty(spec.contact),
+            external_id,
+            provider,
+        };
+        
+        self.store.insert(&node).await?;
+        
+        Ok(node)
+    }
+    
+    /// Put back a node removed earlier, keys and all; one kept without its
+    /// private key comes back with a new keypair
+    pub async fn restore_node(&self, mut node: Node) -> Result<(), crate::error::AppError> {
+        if self.store.get(&node.id).await?.is_some() {
+            return Err(crate::error::AppError::Config(format!("Node {} exists", node.id)));
+        }
+        if self.get_node_by_name(&node.network, &node.name).await.is_some() {
+            return Err(crate::error::AppError::Config(format!("A node named {} exists in network {}", node.name, node.network)));
+        }
+        if node.private_key.is_empty() {
+            (node.public_key, node.private_key) = generate_keypair();
+        }
+        self.store.insert(&node).await
+    }
+    
+    /// Replace what is set by hand on a node with `spec` and
+    /// `external_peers`, keeping its id, keys and what its agent reported
+    pub async fn replace_node(&self, node_id: &str, spec: NodeSpec, external_peers: Vec<String>) -> Result<(), crate::error::AppError> {
+        spec.validate()?;
+        
+        let mut node = self.require_node(node_id).await?;
+        if node.listen != spec.listen {
+            // Resolved endpoints belong to the old listen templates
+            node.resolved_listen.clear();
+        }
+        node.name = spec.name;
+        node.listen = spec.listen;
+        node.listen_policy = spec.listen_policy;
+        node.addresses = spec.addresses;
+        node.external_peers = external_peers;
+        node.tags = spec.tags;
+        node.mtu = spec.mtu;
+        node.node_info = spec.node_info;
+        node.if_name = spec.if_name.filter(|name| name != "auto");
+        node.interface_peers = spec.interface_peers;
+        node.extra_allowed_keys = spec.extra_allowed_keys;
+        node.extra_config = spec.extra_config;
+        node.description = non_empty(spec.description);
+        node.owner = non_empty(spec.owner);
+        node.contact = non_empty(spec.contact);
+        node.external_id = non_empty(spec.external_id);
+        node.provider = non_empty(spec.provider);
+        self.check_external_id(&node.network, Some(node_id), node.provider.as_deref(), node.external_id.as_deref()).await?;
+        
+        self.store.update(&[node]).await
+    }
+    
+    /// Refuse an external id that another node of `network` has from the
+    /// same provider, so it always maps to one node
+    async fn check_external_id(&self, network: &str, node_id: Option<&str>, provider: Option<&str>, external_id: Option<&str>) -> Result<(), crate::error::AppError> {
+        let Some(external_id) = external_id else {
+            return Ok(());
+        };
+        let taken = self.store.in_network(network).await?.into_iter().find(|other| {
+            Some(other.id.as_str()) != node_id && other.external_id.as_deref() == Some(external_id) && other.provider.as_deref() == provider
+        });
+        match taken {
+            Some(other) => Err(crate::error::AppError::Config(format!("Node {} already has external id {}", other.name, external_id))),
+            None => Ok(()),
+        }
+    }
+    
+    /// A stored node, or a "Node not found" error
+    async fn require_node(&self, node_id: &str) -> Result<Node, crate::error::AppError> {
+        self.store.get(node_id).await?
+            .ok_or_else(|| crate::error::AppError::Config("Node not found".to_string()))
+    }
+    
+    /// Update a node; `tags` of `None` keeps the current tags
+    pub async fn update_node(&self, node_id: &str, name: String, listen: Vec<String>, addresses: Vec<String>, tags: Option<Vec<String>>) -> Result<(), crate::error::AppError> {
+        let mut node = self.require_node(node_id).await?;
+        
+        if node.listen != listen {
+            // Resolved endpoints belong to the old listen templates
+            node.resolved_listen.clear();
+        }
+        node.name = name;
+        node.listen = listen;
+        node.addresses = addresses;
+        if let Some(tags) = tags {
+            node.tags = tags;
+        }
+        
+        self.store.update(&[node]).await
+    }
+    
+    /// Apply `changes` to the nodes of `network` matched by `filter` at
+    /// once, returning the ids of the updated nodes
+    pub async fn bulk_update(&self, network: &str, filter: &NodeFilter, changes: &BulkChanges) -> Result<Vec<String>, crate::error::AppError> {
+        if filter.is_empty() {
+            return Err(crate::error::AppError::Config("Filter selects no nodes; use \"all\": true to update the whole network".to_string()));
+        }
+        if changes.is_empty() {
+            return Err(crate::error::AppError::Config("No changes given".to_string()));
+        }
+        
+        let mut updated = Vec::new();
+        for mut node in self.store.in_network(network).await? {
+            if !filter.matches(&node) {
+                continue;
+            }
+            
+            if let Some(tags) = &changes.tags {
+                node.tags = tags.clone();
+            }
+            for tag in &changes.add_tags {
+                if !node.tags.contains(tag) {
+                    node.tags.push(tag.clone());
+                }
+            }
+            node.tags.retain(|tag| !changes.remove_tags.contains(tag));
+            if let Some(listen) = changes.listen.as_ref().filter(|listen| **listen != node.listen) {
+                node.listen = listen.clone();
+                // Resolved endpoints belong to the old listen templates
+                node.resolved_listen.clear();
+            }
+            if let Some(mtu) = changes.mtu {
+                node.mtu = Some(mtu);
+            }
+            updated.push(node);
+        }
+        
+        self.store.update(&updated).await?;
+        Ok(updated.into_iter().map(|node| node.id).collect())
+    }
+    
+    /// Rewrite the listen endpoints of a network's nodes following its
+    /// listen templates, e.g. after the templates changed, returning the ids
+    /// of the rewritten nodes. Pinned nodes are left alone; nodes without
+    /// listen endpoints follow the templates anyway.
+    pub async fn follow_listen_templates(&self, network: &str) -> Result<Vec<String>, crate::error::AppError> {
+        let templates = self.settings_manager.get_listen_templates(network).await?;
+        
+        let mut updated = Vec::new();
+        for mut node in self.store.in_network(network).await? {
+            if node.listen_policy == ListenPolicy::Pinned || node.listen.is_empty() {
+                continue;
+            }
+            let listen = templates.resolve(&node.tags);
+            if listen == node.listen {
+                continue;
+            }
+            node.listen = listen;
+            // Resolved endpoints belong to the old listen templates
+            node.resolved_listen.clear();
+            updated.push(node);
+        }
+        
+        self.store.update(&updated).await?;
+        Ok(updated.into_iter().map(|node| node.id).collect())
+    }
+    
+    /// Update MTU, NodeInfo and interface settings of a node
+    pub async fn update_node_options(&self, node_id: &str, options: NodeOptions) -> Result<(), crate::error::AppError> {
+        if let Some(if_name) = &options.if_name {
+            validate_if_name(if_name)?;
+        }
+        if let Some(extra_allowed_keys) = &options.extra_allowed_keys {
+            validate_allowed_keys(extra_allowed_keys)?;
+        }
+        if let Some(extra_config) = &options.extra_config {
+            validate_extra_config(extra_config)?;
+        }
+        
+        let mut node = self.require_node(node_id).await?;
+        if let Some(listen_policy) = options.listen_policy {
+            node.listen_policy = listen_policy;
+        }
+        if let Some(mtu) = options.mtu {
+            node.mtu = Some(mtu);
+        }
+        if let Some(node_info) = options.node_info {
+            node.node_info = node_info;
+        }
+        if let Some(if_name) = options.if_name {
+            node.if_name = Some(if_name).filter(|name| name != "auto");
+        }
+        if let Some(interface_peers) = options.interface_peers {
+            node.interface_peers = interface_peers;
+        }
+        if let Some(extra_allowed_keys) = options.extra_allowed_keys {
+            node.extra_allowed_keys = extra_allowed_keys;
+        }
+        if let Some(extra_config) = options.extra_config {
+            node.extra_config = extra_config;
+        }
+        if let Some(description) = options.description {
+            node.description = non_empty(Some(description));
+        }
+        if let Some(owner) = options.owner {
+            node.owner = non_empty(Some(owner));
+        }
+        if let Some(contact) = options.contact {
+            node.contact = non_empty(Some(contact));
+        }
+        if let Some(external_id) = options.external_id {
+            node.external_id = non_empty(Some(external_id));
+        }
+        if let Some(provider) = options.provider {
+            node.provider = non_empty(Some(provider));
+        }
+        self.check_external_id(&node.network, Some(node_id), node.provider.as_deref(), node.external_id.as_deref()).await?;
+        
+        self.store.update(&[node]).await
+    }
+    
+    pub async fn set_external_peers(&self, node_id: &str, external_peers: Vec<String>) -> Result<(), crate::error::AppError> {
+        let mut node = self.require_node(node_id).await?;
+        node.external_peers = external_peers;
+        self.store.update(&[node]).await
+    }
+    
+    /// Store the listen endpoints an agent resolved locally. Returns whether
+    /// they changed.
+    pub async fn set_resolved_listen(&self, node_id: &str, resolved_listen: Vec<String>) -> Result<bool, crate::error::AppError> {
+        let mut node = self.require_node(node_id).await?;
+        if node.resolved_listen == resolved_listen {
+            return Ok(false);
+        }
+        
+        node.resolved_listen = resolved_listen;
+        self.store.update(&[node]).await?;
+        Ok(true)
+    }
+    
+    /// Store the versions an agent reported. Returns whether they changed.
+    pub async fn set_versions(&self, node_id: &str, agent_version: Option<String>, yggdrasil_version: Option<String>) -> Result<bool, crate::error::AppError> {
+        let mut node = self.require_node(node_id).await?;
+        if node.agent_version == agent_version && node.yggdrasil_version == yggdrasil_version {
+            return Ok(false);
+        }
+        
+        node.agent_version = agent_version;
+        node.yggdrasil_version = yggdrasil_version;
+        self.store.update(&[node]).await?;
+        Ok(true)
+    }
+    
+    pub async fn remove_node(&self, node_id: &str) -> Result<(), crate::error::AppError> {
+        if !self.store.remove(node_id).await? {
+            return Err(crate::error::AppError::Config("Node not found".to_string()));
+        }
+        
+        if let Err(e) = self.outbox.remove(node_id).await {
+            tracing::warn!("Failed to drop pending config of removed node {}: {}", node_id, e);
+        }
+        Ok(())
+    }
+    
+    /// Nodes whose stored fields do not parse, see `NodeStore::unreadable_fields`
+    pub async fn unreadable_nodes(&self) -> Result<Vec<(String, Vec<&'static str>)>, crate::error::AppError> {
+        self.store.unreadable_fields().await
+    }
+    
+    /// Store a node again as it was read, replacing fields that did not
+    /// parse with the empty values they were read as
+    pub async fn rewrite_node(&self, node_id: &str) -> Result<(), crate::error::AppError> {
+        let node = self.require_node(node_id).await?;
+        self.store.update(&[node]).await
+    }
+    
+    pub async fn get_node_by_id(&self, node_id: &str) -> Option<Node> {
+        self.store.get(node_id).await.ok().flatten()
+    }
+    
+    pub async fn get_node_by_name(&self, network: &str, name: &str) -> Option<Node> {
+        self.get_nodes_in_network(network).await
+            .into_iter()
+            .find(|node| node.name == name)
+    }
+    
+    
+    pub async fn get_all_nodes(&self) -> Vec<Node> {
+        match self.store.all().await {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                tracing::error!("Failed to fetch nodes: {}", e);
+                Vec::new()
+            }
+        }
+    }
+    
+    pub async fn get_nodes_in_network(&self, network: &str) -> Vec<Node> {
+        match self.store.in_network(network).await {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                tracing::error!("Failed to fetch nodes of network {}: {}", network, e);
+                Vec::new()
+            }
+        }
+    }
+    
+    /// Revision of the configuration agents currently hold
+    pub async fn current_revision(&self) -> u64 {
+        self.settings_manager.get_config_revision().await.unwrap_or_else(|e| {
+            tracing::error!("Failed to read config revision: {}", e);
+            0
+        })
+    }
+    
+    /// Advance the config revision ahead of a broadcast. Callers serialize
+    /// broadcasts, so revisions reach each agent in increasing order.
+    pub async fn next_revision(&self) -> u64 {
+        let revision = self.current_revision().await + 1;
+        if let Err(e) = self.settings_manager.set_config_revision(revision).await {
+            tracing::error!("Failed to persist config revision {}: {}", revision, e);
+        }
+        revision
+    }
+    
+    /// Ids of nodes that have maintenance windows, none of them open now
+    pub async fn nodes_outside_maintenance_window(&self) -> HashSet<String> {
+        let now = chrono::Utc::now();
+        let mut closed = HashSet::new();
+        let mut windows_by_network = HashMap::new();
+        
+        for node in self.get_all_nodes().await {
+            if !windows_by_network.contains_key(&node.network) {
+                let windows = self.settings_manager.get_maintenance_windows(&node.network).await.unwrap_or_else(|e| {
+                    tracing::error!("Failed to load maintenance windows of network {}: {}", node.network, e);
+                    Vec::new()
+                });
+                windows_by_network.insert(node.network.clone(), windows);
+            }
+            
+            let windows: Vec<_> = windows_by_network[&node.network].iter().filter(|w| w.applies_to(&node)).collect();
+            if !windows.is_empty() && !windows.iter().any(|w| w.is_open(now)) {
+                closed.insert(node.id);
+            }
+        }
+        
+        closed
+    }
+    
+    /// Peers of the nodes in `network` under an open peer schedule, by node id
+    async fn scheduled_peers(&self, network: &str, nodes: &[Node]) -> HashMap<String, ScheduledPeers> {
+        let schedules = self.settings_manager.get_peer_schedules(network).await.unwrap_or_else(|e| {
+            tracing::error!("Failed to load peer schedules of network {}: {}", network, e);
+            Vec::new()
+        });
+        let now = chrono::Utc::now();
+        let mut scheduled: HashMap<String, ScheduledPeers> = HashMap::new();
+        for schedule in schedules.iter().filter(|s| s.is_open(now)) {
+            for node in nodes.iter().filter(|n| schedule.applies_to(n)) {
+                let peers = scheduled.entry(node.id.clone()).or_default();
+                for entry in &schedule.peers {
+                    if entry.contains("://") {
+                        peers.uris.push(entry.clone());
+                    } else if let Some(target) = nodes.iter().find(|n| n.id != node.id && (n.name == *entry || n.id == *entry)) {
+                        peers.nodes.insert(target.id.clone());
+                    } else {
+                        tracing::warn!("Scheduled peer {} of node {} is not a node in its network", entry, node.name);
+                    }
+                }
+            }
+        }
+        scheduled
+    }
+    
+    /// Fill in the listen endpoints of nodes without their own from the
+    /// network's (tag-resolved) listen template.
+    async fn apply_listen_templates(&self, network: &str, nodes: &mut [Node]) {
+        if nodes.iter().all(|n| !n.listen.is_empty()) {
+            return;
+        }
+        
+        match self.settings_manager.get_listen_templates(network).await {
+            Ok(templates) => {
+                for node in nodes.iter_mut().filter(|n| n.listen.is_empty()) {
+                    node.listen = templates.resolve(&node.tags);
+                }
+            }
+            Err(e) => tracing::error!("Failed to load listen templates of network {}: {}", network, e),
+        }
+    }
+    
+    /// Add the network's NodeInfo template to every node, rendered for the
+    /// node. Entries a node sets itself take precedence.
+    async fn apply_node_info_template(&self, network: &str, nodes: &mut [Node]) {
+        let template = match self.settings_manager.get_node_info_template(network).await {
+            Ok(template) => template,
+            Err(e) => {
+                tracing::error!("Failed to load NodeInfo template of network {}: {}", network, e);
+                return;
+            }
+        };
+        if template.0.is_empty() {
+            return;
+        }
+        
+        for node in nodes.iter_mut() {
+            for (key, value) in template.render(node) {
+                node.node_info.entry(key).or_insert(value);
+            }
+        }
+    }
+    
+    /// Border nodes of the federation partners, none when they cannot be read
+    async fn federated_nodes(&self) -> HashMap<String, crate::federation::PartnerNodes> {
+        self.settings_manager.get_federated_nodes().await.unwrap_or_else(|e| {
+            tracing::error!("Failed to load federated nodes: {}", e);
+            HashMap::new()
+        })
+    }
+    
+    /// Generate configs for every node. Nodes only peer with and allow
+    /// nodes from their own network, and border nodes the federation
+    /// partners' border nodes.
+    pub async fn generate_configs(&self) -> HashMap<String, YggdrasilConfig> {
+        let withdrawn = self.withdrawn.read().unwrap().clone();
+        let mut networks: HashMap<String, Vec<Node>> = HashMap::new();
+        for node in self.get_all_nodes().await {
+            networks.entry(node.network.clone()).or_default().push(node);
+        }
+        
+        let federated = self.federated_nodes().await;
+        let mut jobs = Vec::new();
+        for (network, mut nodes) in networks {
+            self.apply_listen_templates(&network, &mut nodes).await;
+            self.apply_node_info_template(&network, &mut nodes).await;
+            crate::federation::apply(&mut nodes, &federated);
+            let scheduled = self.scheduled_peers(&network, &nodes).await;
+            let all: Vec<usize> = (0..nodes.len()).collect();
+            jobs.push(GenerationJob { nodes, indices: all, scheduled });
+        }
+        
+        generate_blocking(jobs, withdrawn).await
+    }
+    
+    /// Configs of the nodes affected by a change to `changed`: the changed
+    /// nodes and their peers. Every node peers with and allows all others in
+    /// its network, so only networks containing a changed node are rebuilt.
+    pub async fn generate_affected_configs(&self, changed: &HashSet<String>) -> HashMap<String, YggdrasilConfig> {
+        let withdrawn = self.withdrawn.read().unwrap().clone();
+        let mut networks: HashMap<String, Vec<Node>> = HashMap::new();
+        for node in self.get_all_nodes().await {
+            networks.entry(node.network.clone()).or_default().push(node);
+        }
+        
+        let federated = self.federated_nodes().await;
+        let mut jobs = Vec::new();
+        for (network, mut nodes) in networks {
+            if !nodes.iter().any(|n| changed.contains(&n.id)) {
+                continue;
+            }
+            self.apply_listen_templates(&network, &mut nodes).await;
+            self.apply_node_info_template(&network, &mut nodes).await;
+            crate::federation::apply(&mut nodes, &federated);
+            let scheduled = self.scheduled_peers(&network, &nodes).await;
+            let affected: Vec<usize> = (0..nodes.len())
+                .filter(|&i| changed.contains(&nodes[i].id) || mesh_peers(&nodes, i).any(|peer| changed.contains(&peer.id)))
+                .collect();
+            jobs.push(GenerationJob { nodes, indices: affected, scheduled });
+        }
+        
+        generate_blocking(jobs, withdrawn).await
+    }
+    
+    /// Pairs of nodes in a network that should be peered, as derived from
+    /// the peers their generated configs dial. Each pair is ordered by id.
+    pub async fn intended_links(&self, network: &str) -> HashSet<(String, String)> {
+        let mut nodes = self.get_nodes_in_network(network).await;
+        self.apply_listen_templates(network, &mut nodes).await;
+        let ids_by_key: HashMap<String, String> = nodes.iter().map(|n| (n.public_key.clone(), n.id.clone())).collect();
+        
+        let mut links = HashSet::new();
+        let withdrawn = self.withdrawn.read().unwrap().clone();
+        let scheduled = self.scheduled_peers(network, &nodes).await;
+        let all: Vec<usize> = (0..nodes.len()).collect();
+        let job = GenerationJob { nodes, indices: all, scheduled };
+        for (node_id, config) in generate_blocking(vec![job], withdrawn).await {
+            for peer in &config.peers {
+                let Some(peer_id) = peer_uri_key(peer).and_then(|key| ids_by_key.get(key)) else {
+                    continue;
+                };
+                if *peer_id != node_id {
+                    links.insert(ordered_pair(&node_id, peer_id));
+                }
+            }
+        }
+        links
+    }
+    
+    /// Collect the publicly reachable peer URIs of every node in a network,
+    /// suitable for publishing in the yggdrasil public-peers list.
+    pub async fn get_public_peers(&self, network: &str) -> Vec<(Node, Vec<String>)> {
+        let mut nodes = self.get_nodes_in_network(network).await;
+        self.apply_listen_templates(network, &mut nodes).await;
+        let mut result = Vec::new();
+        
+        for node in nodes {
+            let mut uris = Vec::new();
+            for listen_addr in node.advertised_listen() {
+                if !listen_host_is_public(listen_addr) {
+                    continue;
+                }
+                for address in peer_addresses(listen_addr, &node.addresses).iter().filter(|a| is_public_address(a)) {
+                    if let Some(peer_addr) = convert_listen_to_peer_with_address(listen_addr, &node.public_key, address) {
+                        uris.push(peer_addr);
+                    }
+                }
+            }
+            
+            if !uris.is_empty() {
+                result.push((node, uris));
+            }
+        }
+        
+        result
+    }
+
+}
+
+/// The nodes of one network and which of them to generate configs for
+struct GenerationJob {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    scheduled: HashMap<String, ScheduledPeers>,
+}
+
+/// Generate the configs of `jobs` on the blocking pool: large meshes take
+/// long enough to stall the async workers serving agents and the API.
+async fn generate_blocking(jobs: Vec<GenerationJob>, withdrawn: HashSet<String>) -> HashMap<String, YggdrasilConfig> {
+    tokio::task::spawn_blocking(move || {
+        jobs.iter()
+            .flat_map(|job| generate_network_configs_for(&job.nodes, &job.indices, &withdrawn, &job.scheduled))
+            .collect()
+    })
+    .await
+    .expect("config generation panicked")
+}
+
+/// Trimmed text, `None` when blank
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// A new hex public key and Yggdrasil private key
+fn generate_keypair() -> (String, Secret<String>) {
+    let signing_key = SigningKey::from_bytes(&rand::random());
+    let verifying_key: VerifyingKey = signing_key.verifying_key();
+    
+    // Yggdrasil expects a 64-byte private key (32-byte seed + 32-byte public key)
+    let mut full_private_key = Vec::with_capacity(64);
+    full_private_key.extend_from_slice(&signing_key.to_bytes());
+    full_private_key.extend_from_slice(&verifying_key.to_bytes());
+    
+    (hex::encode(verifying_key.to_bytes()), hex::encode(full_private_key).into())
+}
+
+fn uuid_simple() -> String {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let bytes: Vec<u8> = (0..16).map(|_| rng.r#gen()).collect();
+    hex::encode(bytes)
+}
+
+/// Accept "auto", "none" or a name the kernel allows for an interface
+fn validate_if_name(if_name: &str) -> Result<(), crate::error::AppError> {
+    if if_name.is_empty() || if_name.len() > 15 || if_name.contains(|c: char| c == '/' || c.is_whitespace()) {
+        return Err(crate::error::AppError::Config(format!("Invalid interface name: {:?}", if_name)));
+    }
+    Ok(())
+}
+
+/// Reject allowed keys that are not hex-encoded ed25519 public keys
+fn validate_allowed_keys(keys: &[String]) -> Result<(), crate::error::AppError> {
+    match keys.iter().find(|key| key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit())) {
+        Some(key) => Err(crate::error::AppError::Config(format!("Invalid public key: {:?}", key))),
+        None => Ok(()),
+    }
+}
+
+/// Reject extra config setting keys yggman generates itself
+fn validate_extra_config(extra_config: &HashMap<String, serde_json::Value>) -> Result<(), crate::error::AppError> {
+    let mut conflicts: Vec<&str> = extra_config.keys()
+        .map(String::as_str)
+        .filter(|key| crate::yggdrasil::MANAGED_CONFIG_KEYS.contains(key))
+        .collect();
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    conflicts.sort();
+    Err(crate::error::AppError::Config(format!(
+        "extra_config sets keys managed by yggman: {}", conflicts.join(", ")
+    )))
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::config::ConfigManager;
+
+/// Delivers notifications to the webhooks in `[notifications]`
+pub struct Notifier {
+    client: reqwest::Client,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// POST `data` to every configured webhook as
+    /// `{"event": ..., "sent_at": ..., "data": ...}`. Delivery happens in
+    /// the background; failures are logged.
+    pub fn send(&self, config_manager: &ConfigManager, event: &str, data: serde_json::Value) {
+        let config = config_manager.get().notifications.clone();
+        if config.webhooks.is_empty() {
+            return;
+        }
+
+        let body = serde_json::json!({
+            "event": event,
+            "sent_at": chrono::Utc::now(),
+            "data": data,
+        });
+        for url in config.webhooks {
+            let request = self.client.post(&url)
+                .timeout(Duration::from_secs(config.timeout.max(1)))
+                .json(&body);
+            let event = event.to_string();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => tracing::debug!("Delivered {} notification to {}", event, url),
+                    Err(e) => tracing::warn!("Failed to deliver {} notification to {}: {}", event, url, e),
+                }
+            });
+        }
+    }
+}
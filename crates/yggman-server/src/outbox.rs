@@ -0,0 +1,127 @@
+//! Config revisions pushed to nodes whose agents have not acknowledged
+//! them yet, kept in the database so a server restart does not lose them.
+//! An agent is sent its pending revision again when it reconnects, and the
+//! entry only goes away once the agent reports back, making delivery
+//! at-least-once.
+
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+
+use crate::database::entities::config_outbox as outbox_entity;
+use crate::error::AppError;
+use crate::yggdrasil::YggdrasilConfig;
+
+/// A pending delivery, without the config itself
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OutboxEntry {
+    pub node_id: String,
+    pub revision: u64,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl From<outbox_entity::Model> for OutboxEntry {
+    fn from(model: outbox_entity::Model) -> Self {
+        Self {
+            node_id: model.node_id,
+            revision: model.revision as u64,
+            attempts: model.attempts as u32,
+            created_at: model.created_at,
+            last_attempt_at: model.last_attempt_at,
+        }
+    }
+}
+
+fn db_error(e: sea_orm::DbErr) -> AppError {
+    AppError::Config(format!("Database error: {}", e))
+}
+
+pub struct ConfigOutbox {
+    db: DatabaseConnection,
+}
+
+impl ConfigOutbox {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Store `revision` as pending for every node in `entries`, replacing
+    /// older pending revisions. The flag tells whether the config was sent
+    /// to a connected agent right away.
+    pub async fn enqueue(&self, revision: u64, entries: &[(&str, &YggdrasilConfig, bool)]) -> Result<(), AppError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let _write = crate::database::queue_write().await;
+        let txn = self.db.begin().await.map_err(db_error)?;
+        outbox_entity::Entity::delete_many()
+            .filter(outbox_entity::Column::NodeId.is_in(entries.iter().map(|(node_id, _, _)| *node_id)))
+            .exec(&txn)
+            .await
+            .map_err(db_error)?;
+        for (node_id, config, attempted) in entries {
+            let config = serde_json::to_string(config)
+                .map_err(|e| AppError::Config(format!("Failed to serialize config of node {}: {}", node_id, e)))?;
+            outbox_entity::ActiveModel::new(node_id.to_string(), revision as i64, config, *attempted)
+                .insert(&txn)
+                .await
+                .map_err(db_error)?;
+        }
+        txn.commit().await.map_err(db_error)
+    }
+
+    /// The revision and config still waiting for `node_id`'s agent
+    pub async fn pending(&self, node_id: &str) -> Result<Option<(u64, YggdrasilConfig)>, AppError> {
+        let Some(entry) = outbox_entity::Entity::find_by_id(node_id).one(&self.db).await.map_err(db_error)? else {
+            return Ok(None);
+        };
+        let config = serde_json::from_str(&entry.config)
+            .map_err(|e| AppError::Config(format!("Invalid pending config of node {}: {}", node_id, e)))?;
+        Ok(Some((entry.revision as u64, config)))
+    }
+
+    /// Count another delivery of `node_id`'s pending revision
+    pub async fn record_attempt(&self, node_id: &str) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        let Some(entry) = outbox_entity::Entity::find_by_id(node_id).one(&self.db).await.map_err(db_error)? else {
+            return Ok(());
+        };
+        let attempts = entry.attempts;
+        let mut active: outbox_entity::ActiveModel = entry.into();
+        active.attempts = Set(attempts + 1);
+        active.last_attempt_at = Set(Some(Utc::now()));
+        active.update(&self.db).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Drop `node_id`'s pending revision once its agent reported on
+    /// `revision` or a newer one
+    pub async fn acknowledge(&self, node_id: &str, revision: u64) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        outbox_entity::Entity::delete_many()
+            .filter(outbox_entity::Column::NodeId.eq(node_id))
+            .filter(outbox_entity::Column::Revision.lte(revision as i64))
+            .exec(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, node_id: &str) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        outbox_entity::Entity::delete_by_id(node_id).exec(&self.db).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Every pending delivery, oldest first
+    pub async fn list(&self) -> Result<Vec<OutboxEntry>, AppError> {
+        let entries = outbox_entity::Entity::find()
+            .order_by_asc(outbox_entity::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(entries.into_iter().map(OutboxEntry::from).collect())
+    }
+}
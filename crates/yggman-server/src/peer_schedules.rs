@@ -0,0 +1,93 @@
+//! Config pushes at the edges of peer schedules (`PeerSchedule`), so the
+//! matching nodes switch to their scheduled peers when a window opens and
+//! back to the mesh when it closes.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::Notify;
+
+use crate::core::context::AppContext;
+use crate::event_log::EventSeverity;
+use crate::node_manager::NodeManager;
+use crate::settings_manager::PeerSchedule;
+
+/// Longest sleep between checks, in case the clock jumps
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+static SCHEDULES_CHANGED: Notify = Notify::const_new();
+
+/// Have `run` look at the schedules again after they were edited
+pub fn reschedule() {
+    SCHEDULES_CHANGED.notify_one();
+}
+
+/// Every network's peer schedules
+async fn all_schedules(context: &AppContext) -> Vec<(String, PeerSchedule)> {
+    let networks = match context.network_manager.get_all_networks().await {
+        Ok(networks) => networks,
+        Err(e) => {
+            tracing::error!("Failed to list networks for peer schedules: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut schedules = Vec::new();
+    for network in networks {
+        match context.settings_manager.get_peer_schedules(&network.id).await {
+            Ok(found) => schedules.extend(found.into_iter().map(|schedule| (network.id.clone(), schedule))),
+            Err(e) => tracing::error!("Failed to load peer schedules of network {}: {}", network.id, e),
+        }
+    }
+    schedules
+}
+
+fn describe(network: &str, schedule: &PeerSchedule) -> String {
+    let selector = match (&schedule.node, &schedule.tag) {
+        (Some(node), Some(tag)) => format!("node {} with tag {}", node, tag),
+        (Some(node), None) => format!("node {}", node),
+        (None, Some(tag)) => format!("nodes tagged {}", tag),
+        (None, None) => "no nodes".to_string(),
+    };
+    format!("peer schedule \"{}\" of {} in network {}", schedule.schedule, selector, network)
+}
+
+/// Wake up at each opening and closing, and after edits, broadcasting when
+/// the set of open schedules changed. Configs are generated with the
+/// schedules open at the time, so nothing is pushed for the ones open at
+/// startup.
+pub async fn run(node_manager: Arc<NodeManager>, context: Arc<AppContext>) {
+    let mut open: Option<HashSet<(String, PeerSchedule)>> = None;
+    loop {
+        let now = Utc::now();
+        let schedules = all_schedules(&context).await;
+        let now_open: HashSet<(String, PeerSchedule)> = schedules.iter()
+            .filter(|(_, schedule)| schedule.is_open(now))
+            .cloned()
+            .collect();
+
+        if let Some(open) = open.as_ref().filter(|open| **open != now_open) {
+            for (network, schedule) in now_open.difference(open) {
+                let message = format!("Opened {}, its nodes peer with {}", describe(network, schedule), schedule.peers.join(", "));
+                context.event_log.emit("peer_schedule_opened", EventSeverity::Info, None, &message).await;
+            }
+            for (network, schedule) in open.difference(&now_open) {
+                let message = format!("Closed {}, its nodes rejoin the mesh", describe(network, schedule));
+                context.event_log.emit("peer_schedule_closed", EventSeverity::Info, None, &message).await;
+            }
+            crate::websocket_state::broadcast_configuration_update(&node_manager).await;
+        }
+        open = Some(now_open);
+
+        let wait = schedules.iter()
+            .filter_map(|(_, schedule)| schedule.next_change(now))
+            .min()
+            .and_then(|next| (next - now).to_std().ok())
+            .map_or(MAX_WAIT, |wait| wait.clamp(Duration::from_secs(1), MAX_WAIT));
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = SCHEDULES_CHANGED.notified() => {}
+        }
+    }
+}
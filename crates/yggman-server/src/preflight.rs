@@ -0,0 +1,191 @@
+//! Checks run at startup, before anything is served: the database schema,
+//! the stored settings, the TLS certificate and the signing key. A failed
+//! check stops the boot unless `server.start_degraded` is set, in
+//! which case the report is served on GET /api/system/preflight and turns
+//! /api/health and the dashboard degraded.
+
+use std::io::BufReader;
+
+use sea_orm::{DatabaseConnection, EntityTrait, PaginatorTrait};
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::database::connection::{self, SCHEMA_VERSION};
+use crate::network_manager::NetworkManager;
+use crate::settings_manager::SettingsManager;
+
+/// Certificates expiring sooner than this are reported as a warning
+const CERT_EXPIRY_WARNING_DAYS: i64 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    /// Stops the boot unless `server.start_degraded` is set
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Ok, message: message.into() }
+    }
+
+    fn warning(name: &'static str, message: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Warning, message: message.into() }
+    }
+
+    fn failed(name: &'static str, message: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Failed, message: message.into() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    /// The server started although a check failed
+    pub degraded: bool,
+}
+
+impl Report {
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|check| check.status == CheckStatus::Failed)
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Ok => tracing::info!("Preflight {}: {}", check.name, check.message),
+                CheckStatus::Warning => tracing::warn!("Preflight {}: {}", check.name, check.message),
+                CheckStatus::Failed => tracing::error!("Preflight {}: {}", check.name, check.message),
+            }
+        }
+    }
+}
+
+pub async fn run(
+    config: &AppConfig,
+    db: &DatabaseConnection,
+    settings_manager: &SettingsManager,
+    network_manager: &NetworkManager,
+) -> Report {
+    let checks = vec![
+        check_schema(db).await,
+        check_settings(settings_manager, network_manager).await,
+        check_tls(config),
+        check_signing_key(config, db, settings_manager).await,
+    ];
+    Report { checks, degraded: false }
+}
+
+async fn check_schema(db: &DatabaseConnection) -> Check {
+    const NAME: &str = "database_schema";
+    match connection::stored_schema_version(db).await {
+        Ok(Some(version)) if version > SCHEMA_VERSION => {
+            return Check::failed(NAME, format!(
+                "the database was migrated to schema version {} by a newer yggman, this build knows version {}",
+                version, SCHEMA_VERSION,
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => return Check::failed(NAME, e.to_string()),
+    }
+    match connection::verify_schema(db).await {
+        Ok(()) => Check::ok(NAME, format!("schema version {}", SCHEMA_VERSION)),
+        Err(e) => Check::failed(NAME, format!("unexpected table layout, {}", e)),
+    }
+}
+
+async fn check_settings(settings_manager: &SettingsManager, network_manager: &NetworkManager) -> Check {
+    const NAME: &str = "settings";
+    let networks: Vec<String> = match network_manager.get_all_networks().await {
+        Ok(networks) => networks.into_iter().map(|network| network.id).collect(),
+        Err(e) => return Check::failed(NAME, e.to_string()),
+    };
+    let errors = settings_manager.unreadable_settings(&networks).await;
+    if errors.is_empty() {
+        Check::ok(NAME, format!("settings of {} networks parse", networks.len()))
+    } else {
+        Check::failed(NAME, errors.join("; "))
+    }
+}
+
+fn check_tls(config: &AppConfig) -> Check {
+    const NAME: &str = "tls";
+    let tls = &config.server.tls;
+    if !tls.enabled {
+        return Check::ok(NAME, "disabled");
+    }
+    if tls.acme.enabled {
+        return Check::ok(NAME, "certificates are obtained over ACME");
+    }
+    let Some(cert_file) = &tls.cert_file else {
+        return Check::failed(NAME, "server.tls.cert_file is not set");
+    };
+    if let Some(key_file) = &tls.key_file {
+        if let Err(e) = std::fs::metadata(key_file) {
+            return Check::failed(NAME, format!("cannot read {}: {}", key_file, e));
+        }
+    }
+
+    let pem = match std::fs::read(cert_file) {
+        Ok(pem) => pem,
+        Err(e) => return Check::failed(NAME, format!("cannot read {}: {}", cert_file, e)),
+    };
+    let der = match rustls_pemfile::certs(&mut BufReader::new(pem.as_slice())).next() {
+        Some(Ok(der)) => der,
+        Some(Err(e)) => return Check::failed(NAME, format!("invalid certificate in {}: {}", cert_file, e)),
+        None => return Check::failed(NAME, format!("no certificate found in {}", cert_file)),
+    };
+    let cert = match x509_parser::parse_x509_certificate(der.as_ref()) {
+        Ok((_, cert)) => cert,
+        Err(e) => return Check::failed(NAME, format!("invalid certificate in {}: {}", cert_file, e)),
+    };
+
+    let validity = cert.validity();
+    let not_after = chrono::DateTime::from_timestamp(validity.not_after.timestamp(), 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+    if !validity.is_valid() {
+        return Check::failed(NAME, format!("the certificate in {} is not valid now, it expires {}", cert_file, not_after));
+    }
+    match validity.time_to_expiration() {
+        Some(left) if left.whole_days() < CERT_EXPIRY_WARNING_DAYS => {
+            Check::warning(NAME, format!("the certificate in {} expires in {} days, {}", cert_file, left.whole_days(), not_after))
+        }
+        _ => Check::ok(NAME, format!("the certificate in {} is valid until {}", cert_file, not_after)),
+    }
+}
+
+async fn check_signing_key(config: &AppConfig, db: &DatabaseConnection, settings_manager: &SettingsManager) -> Check {
+    const NAME: &str = "signing_key";
+    let key_file = config.server.signing_key_file.as_deref();
+    match settings_manager.has_signing_key(key_file).await {
+        // Loading an existing key creates nothing and fails on a corrupt one
+        Ok(true) => match settings_manager.get_or_create_signing_key(key_file).await {
+            Ok(_) => Check::ok(NAME, "present"),
+            Err(e) => Check::failed(NAME, e.to_string()),
+        },
+        Ok(false) => {
+            // Agents pin the key, so a new one locks out the existing nodes
+            let nodes = crate::database::entities::node::Entity::find().count(db).await.unwrap_or(0);
+            if nodes == 0 {
+                Check::ok(NAME, "missing, a new key will be generated")
+            } else {
+                Check::failed(NAME, format!(
+                    "missing although {} nodes exist; agents pinning the old key would reject a new one, restore it{}",
+                    nodes,
+                    key_file.map(|path| format!(" to {}", path)).unwrap_or_default(),
+                ))
+            }
+        }
+        Err(e) => Check::failed(NAME, e.to_string()),
+    }
+}
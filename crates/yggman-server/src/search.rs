@@ -0,0 +1,138 @@
+//! Finding managed nodes from what yggdrasilctl and friends print: a name,
+//! a public key or a prefix of it, a mesh address or subnet, or an underlay
+//! address, bare or as a peer URI.
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use crate::node_manager::NodeManager;
+use crate::yggdrasil::Node;
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchField {
+    Name,
+    PublicKey,
+    /// The node's 200::/7 address
+    YggdrasilAddress,
+    /// The /64 the node routes from 300::/7
+    YggdrasilSubnet,
+    /// A reported address or advertised listen endpoint
+    Address,
+}
+
+#[derive(serde::Serialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub name: String,
+    pub network: String,
+    pub public_key: String,
+    pub yggdrasil_address: Option<Ipv6Addr>,
+    pub addresses: Vec<String>,
+    /// Why the node matched
+    pub matched: Vec<MatchField>,
+}
+
+/// Nodes of every network matching `query`, ordered by name
+pub async fn search(node_manager: &NodeManager, query: &str, limit: usize) -> Vec<SearchHit> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let ip: Option<IpAddr> = query.parse().ok();
+
+    let mut hits: Vec<SearchHit> = node_manager.get_all_nodes().await.into_iter()
+        .filter_map(|node| {
+            let matched = matches(&node, &query, ip);
+            (!matched.is_empty()).then(|| SearchHit {
+                yggdrasil_address: node.yggdrasil_address(),
+                id: node.id,
+                name: node.name,
+                network: node.network,
+                public_key: node.public_key,
+                addresses: node.addresses,
+                matched,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    hits.truncate(limit);
+    hits
+}
+
+fn matches(node: &Node, query: &str, ip: Option<IpAddr>) -> Vec<MatchField> {
+    let mut matched = Vec::new();
+
+    if node.name.to_lowercase().contains(query) {
+        matched.push(MatchField::Name);
+    }
+    if query.bytes().all(|b| b.is_ascii_hexdigit()) && node.public_key.to_lowercase().starts_with(query) {
+        matched.push(MatchField::PublicKey);
+    }
+
+    if let Some(address) = node.yggdrasil_address() {
+        let subnet = subnet_of(address);
+        match ip {
+            Some(IpAddr::V6(ip)) => {
+                if ip == address {
+                    matched.push(MatchField::YggdrasilAddress);
+                } else if ip.octets()[..8] == subnet.octets()[..8] {
+                    matched.push(MatchField::YggdrasilSubnet);
+                }
+            }
+            Some(IpAddr::V4(_)) => {}
+            // A partial address, as far as it was copied
+            None if query.contains(':') => {
+                if address.to_string().starts_with(query) {
+                    matched.push(MatchField::YggdrasilAddress);
+                } else if subnet.to_string().starts_with(query) {
+                    matched.push(MatchField::YggdrasilSubnet);
+                }
+            }
+            None => {}
+        }
+    }
+
+    let listen_hosts = node.advertised_listen().iter().filter_map(|uri| uri_host(uri));
+    let underlay_match = node.addresses.iter().map(String::as_str).chain(listen_hosts).any(|host| {
+        let host = host.to_lowercase();
+        match (ip, host.parse::<IpAddr>()) {
+            // Compare parsed, as IPv6 addresses have several spellings
+            (Some(ip), Ok(host)) => ip == host,
+            _ => host.starts_with(query),
+        }
+    });
+    if underlay_match {
+        matched.push(MatchField::Address);
+    }
+
+    matched
+}
+
+/// Lowercase `query` and reduce URIs, bracketed or zoned addresses and
+/// prefix lengths to the bare host or address
+fn normalize(query: &str) -> String {
+    let query = query.trim();
+    let query = uri_host(query).unwrap_or(query);
+    let query = query.trim_start_matches('[').trim_end_matches(']');
+    let query = query.split(['%', '/']).next().unwrap_or(query);
+    query.to_lowercase()
+}
+
+/// Host part of a peer or listen URI such as `tls://[200::1]:443?key=...`
+fn uri_host(uri: &str) -> Option<&str> {
+    let (_, rest) = uri.split_once("://")?;
+    let authority = rest.split(['?', '/']).next()?;
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    Some(host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+/// First address of the /64 a node with `address` routes
+fn subnet_of(address: Ipv6Addr) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets[..8].copy_from_slice(&address.octets()[..8]);
+    octets[0] |= 0x01;
+    Ipv6Addr::from(octets)
+}
@@ -0,0 +1,713 @@
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::database::entities::settings::{Entity as SettingsEntity, ActiveModel};
+use crate::error::AppError;
+use crate::config::ConfigManager;
+use crate::federation::PartnerNodes;
+use crate::network_manager::DEFAULT_NETWORK;
+
+const LISTEN_TEMPLATE_KEY: &str = "listen_template";
+const LISTEN_TEMPLATE_RULES_KEY: &str = "listen_template_rules";
+const NODE_TEMPLATES_KEY: &str = "node_templates";
+const NODE_INFO_TEMPLATE_KEY: &str = "node_info_template";
+const IMPORTED_PUBLIC_PEERS_KEY: &str = "imported_public_peers";
+const KEY_ESCROW_POLICY_KEY: &str = "key_escrow_policy";
+const SIGNING_KEY_KEY: &str = "server_signing_key";
+const CONFIG_REVISION_KEY: &str = "config_revision";
+const MAINTENANCE_WINDOWS_KEY: &str = "maintenance_windows";
+const PEER_SCHEDULES_KEY: &str = "peer_schedules";
+const AUTO_BROADCAST_KEY: &str = "auto_broadcast";
+const ROLLBACK_POLICY_KEY: &str = "rollback_policy";
+const NODE_TIMING_KEY: &str = "node_timing";
+const FEDERATED_NODES_KEY: &str = "federated_nodes";
+
+/// Listen template applied to nodes carrying a tag. When several rules
+/// match, the highest priority wins; ties go to the rule listed first.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ListenTemplateRule {
+    pub tag: String,
+    #[serde(default)]
+    pub priority: i32,
+    pub template: Vec<String>,
+}
+
+/// A network's listen template together with its tag rules
+#[derive(Debug, Clone)]
+pub struct ListenTemplates {
+    pub default: Vec<String>,
+    pub rules: Vec<ListenTemplateRule>,
+}
+
+impl ListenTemplates {
+    pub fn resolve(&self, tags: &[String]) -> Vec<String> {
+        let mut best: Option<&ListenTemplateRule> = None;
+        for rule in self.rules.iter().filter(|r| tags.contains(&r.tag)) {
+            if best.is_none_or(|b| rule.priority > b.priority) {
+                best = Some(rule);
+            }
+        }
+        
+        best.map(|r| r.template.clone()).unwrap_or_else(|| self.default.clone())
+    }
+}
+
+/// Recurring period during which config pushes to matching nodes are
+/// allowed. Matches the node named `node` and/or nodes carrying `tag`;
+/// nodes without any window accept pushes at any time.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MaintenanceWindow {
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Cron expression for the window start in UTC, e.g. "0 2 * * Sun"
+    pub schedule: String,
+    pub duration_minutes: u64,
+}
+
+impl MaintenanceWindow {
+    pub fn applies_to(&self, node: &crate::yggdrasil::Node) -> bool {
+        window_applies_to(self.node.as_deref(), self.tag.as_deref(), node)
+    }
+    
+    pub fn parse_schedule(&self) -> Result<cron::Schedule, AppError> {
+        parse_cron(&self.schedule)
+            .map_err(|e| AppError::Config(format!("Invalid maintenance window schedule '{}': {}", self.schedule, e)))
+    }
+    
+    pub fn is_open(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let schedule = match self.parse_schedule() {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                // A broken window must not hold back updates forever
+                tracing::error!("{}", e);
+                return true;
+            }
+        };
+        
+        window_start(&schedule, self.duration_minutes, now).is_some()
+    }
+}
+
+/// Recurring period during which matching nodes peer with `peers` instead
+/// of the whole mesh, e.g. to send nightly backups through a relay with
+/// more bandwidth. Matches like a `MaintenanceWindow`. The rest of the mesh
+/// stops dialing those nodes meanwhile, so their only links are to `peers`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct PeerSchedule {
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Cron expression for the window start in UTC, e.g. "0 1 * * *"
+    pub schedule: String,
+    pub duration_minutes: u64,
+    /// Names or IDs of nodes in the network, or peer URIs
+    pub peers: Vec<String>,
+}
+
+impl PeerSchedule {
+    pub fn applies_to(&self, node: &crate::yggdrasil::Node) -> bool {
+        window_applies_to(self.node.as_deref(), self.tag.as_deref(), node)
+    }
+    
+    pub fn parse_schedule(&self) -> Result<cron::Schedule, AppError> {
+        parse_cron(&self.schedule)
+            .map_err(|e| AppError::Config(format!("Invalid peer schedule '{}': {}", self.schedule, e)))
+    }
+    
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.parse_schedule()?;
+        if self.node.is_none() && self.tag.is_none() {
+            return Err(AppError::Config(format!("Peer schedule '{}' needs a node or a tag", self.schedule)));
+        }
+        if self.peers.is_empty() {
+            return Err(AppError::Config(format!("Peer schedule '{}' has no peers", self.schedule)));
+        }
+        Ok(())
+    }
+    
+    /// A broken schedule never opens, leaving the mesh as it is
+    pub fn is_open(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.parse_schedule().ok().is_some_and(|schedule| window_start(&schedule, self.duration_minutes, now).is_some())
+    }
+    
+    /// When the window next opens or, while open, closes
+    pub fn next_change(&self, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+        let schedule = self.parse_schedule().ok()?;
+        let end = window_start(&schedule, self.duration_minutes, now)
+            .map(|start| start + chrono::Duration::minutes(self.duration_minutes as i64));
+        [schedule.after(&now).next(), end].into_iter().flatten().min()
+    }
+}
+
+fn window_applies_to(node_name: Option<&str>, tag: Option<&str>, node: &crate::yggdrasil::Node) -> bool {
+    let node_matches = node_name.is_none_or(|name| name == node.name);
+    let tag_matches = tag.is_none_or(|tag| node.tags.iter().any(|t| t == tag));
+    (node_name.is_some() || tag.is_some()) && node_matches && tag_matches
+}
+
+/// Accepts standard five-field cron expressions as well as the
+/// seconds-first six and seven-field form.
+fn parse_cron(expression: &str) -> Result<cron::Schedule, cron::error::Error> {
+    if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression).parse()
+    } else {
+        expression.parse()
+    }
+}
+
+/// Start of the window of `schedule` lasting `duration_minutes` that is
+/// open at `now`, if any
+fn window_start(schedule: &cron::Schedule, duration_minutes: u64, now: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+    let window_start = now - chrono::Duration::minutes(duration_minutes as i64);
+    schedule.after(&window_start).next().filter(|start| *start <= now)
+}
+
+/// When to roll back a revision that makes agents drop offline
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RollbackPolicy {
+    pub enabled: bool,
+    /// Share of the agents that received a revision which must go offline
+    /// to trigger a rollback
+    pub offline_threshold_percent: u32,
+    /// How long after a push agents going offline count against it
+    pub window_minutes: u64,
+    /// Pushes reaching fewer agents are never rolled back
+    pub min_agents: usize,
+}
+
+impl Default for RollbackPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            offline_threshold_percent: 50,
+            window_minutes: 5,
+            min_agents: 3,
+        }
+    }
+}
+
+/// How often agents check in and when they count as gone, in seconds.
+/// Replaces `agent.heartbeat_interval`, `agent.offline_threshold` and
+/// `stale_nodes.check_interval` of the config file once saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NodeTiming {
+    /// Between heartbeats and status reports of an agent
+    pub heartbeat_interval: u64,
+    /// Without any message from an agent before it is offline and its
+    /// connection closed
+    pub offline_threshold: u64,
+    /// Between sweeps for stale nodes
+    pub stale_check_interval: u64,
+}
+
+impl NodeTiming {
+    /// The timing `config` runs with
+    pub fn from_config(config: &crate::config::AppConfig) -> Self {
+        Self {
+            heartbeat_interval: config.agent.heartbeat_interval,
+            offline_threshold: config.agent.offline_threshold(),
+            stale_check_interval: config.stale_nodes.check_interval,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.heartbeat_interval == 0 {
+            return Err(AppError::Config("heartbeat_interval must be at least 1 second".to_string()));
+        }
+        if self.offline_threshold <= self.heartbeat_interval {
+            return Err(AppError::Config(format!(
+                "offline_threshold ({}s) must be longer than heartbeat_interval ({}s)",
+                self.offline_threshold, self.heartbeat_interval
+            )));
+        }
+        if self.stale_check_interval == 0 {
+            return Err(AppError::Config("stale_check_interval must be at least 1 second".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Preset fields for adding similar nodes in one call
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct NodeTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub mtu: Option<u16>,
+    #[serde(default)]
+    pub node_info: HashMap<String, serde_json::Value>,
+}
+
+/// Variables a `NodeInfoTemplate` may use
+pub const NODE_INFO_VARIABLES: &[&str] = &["node_name", "node_id", "network", "tags", "region"];
+/// Tags of this form give `{region}`, e.g. "region:eu-west"
+const REGION_TAG_PREFIX: &str = "region:";
+
+/// NodeInfo every node of a network publishes unless it sets the key
+/// itself. Strings, also nested in arrays and objects, may contain
+/// `{node_name}`, `{node_id}`, `{network}`, `{tags}` (comma separated) and
+/// `{region}`, filled in per node when its config is generated.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct NodeInfoTemplate(pub HashMap<String, serde_json::Value>);
+
+impl NodeInfoTemplate {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.0.contains_key("name") {
+            return Err(AppError::Config("NodeInfo \"name\" is always the node name and cannot be templated".to_string()));
+        }
+        let mut strings = Vec::new();
+        for value in self.0.values() {
+            collect_strings(value, &mut strings);
+        }
+        for text in strings {
+            if let Some(unknown) = placeholders(text).find(|name| !NODE_INFO_VARIABLES.contains(name)) {
+                return Err(AppError::Config(format!(
+                    "Unknown NodeInfo variable {{{}}}, expected one of {}",
+                    unknown,
+                    NODE_INFO_VARIABLES.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", "),
+                )));
+            }
+        }
+        Ok(())
+    }
+    
+    /// The template's entries for `node`, variables filled in
+    pub fn render(&self, node: &crate::yggdrasil::Node) -> HashMap<String, serde_json::Value> {
+        let region = node.tags.iter().find_map(|tag| tag.strip_prefix(REGION_TAG_PREFIX)).unwrap_or_default();
+        let values = [
+            ("node_name", node.name.clone()),
+            ("node_id", node.id.clone()),
+            ("network", node.network.clone()),
+            ("tags", node.tags.join(",")),
+            ("region", region.to_string()),
+        ];
+        self.0.iter()
+            .map(|(key, value)| (key.clone(), render_value(value, &values)))
+            .collect()
+    }
+}
+
+fn collect_strings<'a>(value: &'a serde_json::Value, strings: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(text) => strings.push(text),
+        serde_json::Value::Array(items) => items.iter().for_each(|item| collect_strings(item, strings)),
+        serde_json::Value::Object(fields) => fields.values().for_each(|field| collect_strings(field, strings)),
+        _ => {}
+    }
+}
+
+fn render_value(value: &serde_json::Value, values: &[(&str, String)]) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => {
+            let mut text = text.clone();
+            for (name, value) in values {
+                text = text.replace(&format!("{{{}}}", name), value);
+            }
+            serde_json::Value::String(text)
+        }
+        serde_json::Value::Array(items) => items.iter().map(|item| render_value(item, values)).collect(),
+        serde_json::Value::Object(fields) => fields.iter()
+            .map(|(key, field)| (key.clone(), render_value(field, values)))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Names between braces that look like variables; other braces are text
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+/// Whether node private keys can be read back through the audited, admin-only
+/// key export after creation; other API responses never carry them.
+/// `WriteOnly` keys are only ever sent to the node's own agent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEscrowPolicy {
+    #[default]
+    Exportable,
+    WriteOnly,
+}
+
+/// An entry imported from the public Yggdrasil peer list.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExternalPeer {
+    pub uri: String,
+    pub region: String,
+    pub response_ms: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct SettingsManager {
+    db: Arc<DatabaseConnection>,
+}
+
+impl SettingsManager {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db: Arc::new(db),
+        }
+    }
+    
+    /// The database the settings live in, for stores kept alongside them
+    pub fn connection(&self) -> DatabaseConnection {
+        self.db.as_ref().clone()
+    }
+    
+    /// Listen template of a network. Networks without their own template
+    /// inherit the default network's one.
+    pub async fn get_listen_template(&self, network: &str) -> Result<Vec<String>, AppError> {
+        if let Some(template) = self.get_json::<Vec<String>>(&scoped_key(network, LISTEN_TEMPLATE_KEY)).await? {
+            return Ok(template);
+        }
+        
+        match self.get_json::<Vec<String>>(LISTEN_TEMPLATE_KEY).await? {
+            Some(template) => Ok(template),
+            // Return default template if not found
+            None => Ok(vec!["tcp://0.0.0.0:9001".to_string()]),
+        }
+    }
+    
+    pub async fn set_listen_template(&self, network: &str, template: Vec<String>) -> Result<(), AppError> {
+        self.set_json(&scoped_key(network, LISTEN_TEMPLATE_KEY), &template).await?;
+        tracing::info!("Listen template of network {} saved to database: {:?}", network, template);
+        Ok(())
+    }
+    
+    pub async fn get_listen_template_rules(&self, network: &str) -> Result<Vec<ListenTemplateRule>, AppError> {
+        Ok(self.get_json(&scoped_key(network, LISTEN_TEMPLATE_RULES_KEY)).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_listen_template_rules(&self, network: &str, rules: &[ListenTemplateRule]) -> Result<(), AppError> {
+        self.set_json(&scoped_key(network, LISTEN_TEMPLATE_RULES_KEY), &rules).await?;
+        tracing::info!("Listen template rules of network {} saved to database", network);
+        Ok(())
+    }
+    
+    pub async fn get_listen_templates(&self, network: &str) -> Result<ListenTemplates, AppError> {
+        Ok(ListenTemplates {
+            default: self.get_listen_template(network).await?,
+            rules: self.get_listen_template_rules(network).await?,
+        })
+    }
+    
+    pub async fn get_node_templates(&self, network: &str) -> Result<Vec<NodeTemplate>, AppError> {
+        Ok(self.get_json(&scoped_key(network, NODE_TEMPLATES_KEY)).await?.unwrap_or_default())
+    }
+    
+    pub async fn get_node_template(&self, network: &str, name: &str) -> Result<Option<NodeTemplate>, AppError> {
+        Ok(self.get_node_templates(network).await?.into_iter().find(|t| t.name == name))
+    }
+    
+    pub async fn set_node_templates(&self, network: &str, templates: &[NodeTemplate]) -> Result<(), AppError> {
+        self.set_json(&scoped_key(network, NODE_TEMPLATES_KEY), &templates).await?;
+        tracing::info!("Node templates of network {} saved to database", network);
+        Ok(())
+    }
+    
+    /// NodeInfo template of a network. Networks without their own template
+    /// inherit the default network's one.
+    pub async fn get_node_info_template(&self, network: &str) -> Result<NodeInfoTemplate, AppError> {
+        if let Some(template) = self.get_json(&scoped_key(network, NODE_INFO_TEMPLATE_KEY)).await? {
+            return Ok(template);
+        }
+        Ok(self.get_json(NODE_INFO_TEMPLATE_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_node_info_template(&self, network: &str, template: &NodeInfoTemplate) -> Result<(), AppError> {
+        template.validate()?;
+        self.set_json(&scoped_key(network, NODE_INFO_TEMPLATE_KEY), template).await?;
+        tracing::info!("NodeInfo template of network {} saved to database", network);
+        Ok(())
+    }
+    
+    /// Drop all settings scoped to a network
+    pub async fn remove_network_settings(&self, network: &str) -> Result<(), AppError> {
+        if network == DEFAULT_NETWORK {
+            return Ok(());
+        }
+        
+        let _write = crate::database::queue_write().await;
+        SettingsEntity::delete_many()
+            .filter(crate::database::entities::settings::Column::Key.starts_with(format!("{}/", network)))
+            .exec(&*self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        
+        Ok(())
+    }
+    
+    pub async fn initialize_defaults(&self) -> Result<(), AppError> {
+        // Check if listen template exists, if not create default
+        if SettingsEntity::find()
+            .filter(crate::database::entities::settings::Column::Key.eq(LISTEN_TEMPLATE_KEY))
+            .one(&*self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?
+            .is_none()
+        {
+            let default_template = vec!["tcp://0.0.0.0:9001".to_string()];
+            self.set_listen_template(DEFAULT_NETWORK, default_template).await?;
+            tracing::info!("Initialized default listen template");
+        }
+        
+        Ok(())
+    }
+    
+    pub async fn get_imported_public_peers(&self) -> Result<Vec<ExternalPeer>, AppError> {
+        Ok(self.get_json(IMPORTED_PUBLIC_PEERS_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_imported_public_peers(&self, peers: &[ExternalPeer]) -> Result<(), AppError> {
+        self.set_json(IMPORTED_PUBLIC_PEERS_KEY, &peers).await
+    }
+    
+    pub async fn get_key_escrow_policy(&self) -> Result<KeyEscrowPolicy, AppError> {
+        Ok(self.get_json(KEY_ESCROW_POLICY_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_key_escrow_policy(&self, policy: KeyEscrowPolicy) -> Result<(), AppError> {
+        self.set_json(KEY_ESCROW_POLICY_KEY, &policy).await?;
+        tracing::info!("Key escrow policy set to {:?}", policy);
+        Ok(())
+    }
+    
+    pub async fn get_maintenance_windows(&self, network: &str) -> Result<Vec<MaintenanceWindow>, AppError> {
+        Ok(self.get_json(&scoped_key(network, MAINTENANCE_WINDOWS_KEY)).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_maintenance_windows(&self, network: &str, windows: &[MaintenanceWindow]) -> Result<(), AppError> {
+        for window in windows {
+            window.parse_schedule()?;
+        }
+        self.set_json(&scoped_key(network, MAINTENANCE_WINDOWS_KEY), &windows).await?;
+        tracing::info!("Maintenance windows of network {} saved to database", network);
+        Ok(())
+    }
+    
+    pub async fn get_peer_schedules(&self, network: &str) -> Result<Vec<PeerSchedule>, AppError> {
+        Ok(self.get_json(&scoped_key(network, PEER_SCHEDULES_KEY)).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_peer_schedules(&self, network: &str, schedules: &[PeerSchedule]) -> Result<(), AppError> {
+        for schedule in schedules {
+            schedule.validate()?;
+        }
+        self.set_json(&scoped_key(network, PEER_SCHEDULES_KEY), &schedules).await?;
+        tracing::info!("Peer schedules of network {} saved to database", network);
+        Ok(())
+    }
+    
+    /// Whether changes are pushed to agents as they happen, rather than
+    /// staged for an explicit broadcast
+    pub async fn get_auto_broadcast(&self) -> Result<bool, AppError> {
+        Ok(self.get_json(AUTO_BROADCAST_KEY).await?.unwrap_or(true))
+    }
+    
+    pub async fn set_auto_broadcast(&self, enabled: bool) -> Result<(), AppError> {
+        self.set_json(AUTO_BROADCAST_KEY, &enabled).await?;
+        tracing::info!("Automatic broadcast {}", if enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+    
+    pub async fn get_rollback_policy(&self) -> Result<RollbackPolicy, AppError> {
+        Ok(self.get_json(ROLLBACK_POLICY_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_rollback_policy(&self, policy: &RollbackPolicy) -> Result<(), AppError> {
+        self.set_json(ROLLBACK_POLICY_KEY, policy).await
+    }
+    
+    /// Timing saved over the API, `None` while the config file's applies
+    pub async fn get_node_timing(&self) -> Result<Option<NodeTiming>, AppError> {
+        self.get_json(NODE_TIMING_KEY).await
+    }
+    
+    pub async fn set_node_timing(&self, timing: &NodeTiming) -> Result<(), AppError> {
+        timing.validate()?;
+        self.set_json(NODE_TIMING_KEY, timing).await?;
+        tracing::info!("Node timing saved to database: {:?}", timing);
+        Ok(())
+    }
+    
+    /// Border nodes of the federation partners, by partner name
+    pub async fn get_federated_nodes(&self) -> Result<HashMap<String, PartnerNodes>, AppError> {
+        Ok(self.get_json(FEDERATED_NODES_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_federated_nodes(&self, nodes: &HashMap<String, PartnerNodes>) -> Result<(), AppError> {
+        self.set_json(FEDERATED_NODES_KEY, nodes).await
+    }
+    
+    /// Revision of the configuration last pushed to agents
+    pub async fn get_config_revision(&self) -> Result<u64, AppError> {
+        Ok(self.get_json(CONFIG_REVISION_KEY).await?.unwrap_or_default())
+    }
+    
+    pub async fn set_config_revision(&self, revision: u64) -> Result<(), AppError> {
+        self.set_json(CONFIG_REVISION_KEY, &revision).await
+    }
+    
+    /// Control-plane key used to sign messages to agents. Kept in
+    /// `key_file` when configured, otherwise in the settings table; created
+    /// on first use either way.
+    pub async fn get_or_create_signing_key(&self, key_file: Option<&str>) -> Result<ed25519_dalek::SigningKey, AppError> {
+        let seed_hex = match key_file {
+            Some(path) if std::path::Path::new(path).exists() => Some(std::fs::read_to_string(path)?.trim().to_string()),
+            Some(_) => None,
+            None => self.get_json::<String>(SIGNING_KEY_KEY).await?,
+        };
+        
+        if let Some(seed_hex) = seed_hex {
+            let seed: [u8; 32] = hex::decode(&seed_hex)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| AppError::Config("Invalid server signing key".to_string()))?;
+            return Ok(ed25519_dalek::SigningKey::from_bytes(&seed));
+        }
+        
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+        let seed_hex = hex::encode(signing_key.to_bytes());
+        match key_file {
+            Some(path) => write_private_file(path, &seed_hex)?,
+            None => self.set_json(SIGNING_KEY_KEY, &seed_hex).await?,
+        }
+        tracing::info!("Generated new server signing key");
+        
+        Ok(signing_key)
+    }
+    
+    /// Whether a signing key exists, which `get_or_create_signing_key`
+    /// would otherwise create
+    pub async fn has_signing_key(&self, key_file: Option<&str>) -> Result<bool, AppError> {
+        match key_file {
+            Some(path) => Ok(std::path::Path::new(path).exists()),
+            None => Ok(self.get_json::<String>(SIGNING_KEY_KEY).await?.is_some()),
+        }
+    }
+    
+    /// Errors of the stored settings of `networks` and the server-wide ones
+    /// that do not parse
+    pub async fn unreadable_settings(&self, networks: &[String]) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut note = |result: Result<(), AppError>| {
+            if let Err(e) = result {
+                errors.push(e.to_string());
+            }
+        };
+        for network in networks {
+            note(self.get_listen_template(network).await.map(drop));
+            note(self.get_listen_template_rules(network).await.map(drop));
+            note(self.get_node_templates(network).await.map(drop));
+            note(self.get_node_info_template(network).await.map(drop));
+            note(self.get_maintenance_windows(network).await.map(drop));
+            note(self.get_peer_schedules(network).await.map(drop));
+        }
+        note(self.get_imported_public_peers().await.map(drop));
+        note(self.get_key_escrow_policy().await.map(drop));
+        note(self.get_auto_broadcast().await.map(drop));
+        note(self.get_rollback_policy().await.map(drop));
+        note(self.get_node_timing().await.map(drop));
+        note(self.get_federated_nodes().await.map(drop));
+        note(self.get_config_revision().await.map(drop));
+        errors
+    }
+    
+    async fn get_json<T>(&self, key: &str) -> Result<Option<T>, AppError>
+    where
+        T: for<'de> serde::Deserialize<'de>
+    {
+        let setting = SettingsEntity::find()
+            .filter(crate::database::entities::settings::Column::Key.eq(key))
+            .one(&*self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        
+        match setting {
+            Some(setting) => setting.parse_json_value::<T>()
+                .map(Some)
+                .map_err(|e| AppError::Config(format!("Failed to parse setting {}: {}", key, e))),
+            None => Ok(None),
+        }
+    }
+    
+    async fn set_json(&self, key: &str, value: &impl serde::Serialize) -> Result<(), AppError> {
+        // Held across the lookup so concurrent first writes of a key do not both insert
+        let _write = crate::database::queue_write().await;
+        let existing = SettingsEntity::find()
+            .filter(crate::database::entities::settings::Column::Key.eq(key))
+            .one(&*self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        
+        if let Some(existing_setting) = existing {
+            let mut active_model: ActiveModel = existing_setting.into();
+            active_model.update_value(value)
+                .map_err(|e| AppError::Config(format!("Failed to serialize setting {}: {}", key, e)))?;
+            
+            SettingsEntity::update(active_model)
+                .exec(&*self.db)
+                .await
+                .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        } else {
+            let active_model = ActiveModel::new(key.to_string(), value)
+                .map_err(|e| AppError::Config(format!("Failed to serialize setting {}: {}", key, e)))?;
+            
+            SettingsEntity::insert(active_model)
+                .exec(&*self.db)
+                .await
+                .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        }
+        
+        Ok(())
+    }
+    
+    pub async fn load_settings_to_config(&self, config_manager: &ConfigManager) -> Result<(), AppError> {
+        // Load listen template from database and update config
+        let template = self.get_listen_template(DEFAULT_NETWORK).await?;
+        config_manager.update_listen_template(template);
+        if let Some(timing) = self.get_node_timing().await? {
+            config_manager.update_node_timing(&timing);
+        }
+        tracing::info!("Loaded settings from database to config");
+        Ok(())
+    }
+}
+/// Settings of the default network keep their bare key for backward
+/// compatibility; other networks prefix the key with the network id.
+fn scoped_key(network: &str, key: &str) -> String {
+    if network == DEFAULT_NETWORK {
+        key.to_string()
+    } else {
+        format!("{}/{}", network, key)
+    }
+}
+
+/// Write a secret to a file readable only by its owner
+fn write_private_file(path: &str, contents: &str) -> Result<(), AppError> {
+    use std::io::Write;
+    
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
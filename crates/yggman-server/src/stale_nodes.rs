@@ -0,0 +1,287 @@
+//! Cleanup of nodes whose agents stay away, per `[stale_nodes]`. A node
+//! whose agent has not connected for `after_days` is flagged; if it is
+//! still away `grace_days` later, it is drained (left out of its peers'
+//! configs) or deleted, keeping a copy that can be restored (without its
+//! private key under the write_only key escrow policy, so a restored node
+//! gets a new one). Each step is
+//! recorded as an event and delivered through the notifier, and a node
+//! whose agent connects again is no longer stale.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, QueryOrder, Set};
+
+use crate::config::StaleNodeAction;
+use crate::core::context::AppContext;
+use crate::database::entities::stale_node as stale_entity;
+use crate::error::AppError;
+use crate::event_log::EventSeverity;
+use crate::node_manager::NodeManager;
+use crate::settings_manager::KeyEscrowPolicy;
+use crate::yggdrasil::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleState {
+    Flagged,
+    Drained,
+    Deleted,
+}
+
+impl StaleState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StaleState::Flagged => "flagged",
+            StaleState::Drained => "drained",
+            StaleState::Deleted => "deleted",
+        }
+    }
+
+    fn parse(state: &str) -> Self {
+        match state {
+            "drained" => StaleState::Drained,
+            "deleted" => StaleState::Deleted,
+            _ => StaleState::Flagged,
+        }
+    }
+}
+
+/// A stale node as listed through the API, without the deleted copy
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StaleNode {
+    pub node_id: String,
+    pub network: String,
+    pub name: String,
+    /// When its agent was last connected
+    pub last_seen: DateTime<Utc>,
+    pub flagged_at: DateTime<Utc>,
+    pub state: StaleState,
+    /// When the node was drained or deleted
+    pub acted_at: Option<DateTime<Utc>>,
+}
+
+impl From<stale_entity::Model> for StaleNode {
+    fn from(model: stale_entity::Model) -> Self {
+        Self {
+            node_id: model.node_id,
+            network: model.network,
+            name: model.name,
+            last_seen: model.last_seen,
+            flagged_at: model.flagged_at,
+            state: StaleState::parse(&model.state),
+            acted_at: model.acted_at,
+        }
+    }
+}
+
+fn db_error(e: sea_orm::DbErr) -> AppError {
+    AppError::Config(format!("Database error: {}", e))
+}
+
+pub struct StaleNodes {
+    db: DatabaseConnection,
+}
+
+impl StaleNodes {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Longest away first
+    pub async fn list(&self) -> Result<Vec<StaleNode>, AppError> {
+        let nodes = stale_entity::Entity::find()
+            .order_by_asc(stale_entity::Column::LastSeen)
+            .all(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(nodes.into_iter().map(StaleNode::from).collect())
+    }
+
+    async fn all(&self) -> Result<HashMap<String, stale_entity::Model>, AppError> {
+        let nodes = stale_entity::Entity::find().all(&self.db).await.map_err(db_error)?;
+        Ok(nodes.into_iter().map(|node| (node.node_id.clone(), node)).collect())
+    }
+
+    async fn flag(&self, node: &Node, last_seen: DateTime<Utc>) -> Result<StaleNode, AppError> {
+        let _write = crate::database::queue_write().await;
+        let model = stale_entity::ActiveModel::new(node.id.clone(), node.network.clone(), node.name.clone(), last_seen)
+            .insert(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(model.into())
+    }
+
+    async fn mark(&self, model: stale_entity::Model, state: StaleState, node: Option<String>) -> Result<StaleNode, AppError> {
+        let _write = crate::database::queue_write().await;
+        let mut active: stale_entity::ActiveModel = model.into();
+        active.state = Set(state.as_str().to_string());
+        active.acted_at = Set(Some(Utc::now()));
+        active.node = Set(node);
+        let model = active.update(&self.db).await.map_err(db_error)?;
+        Ok(model.into())
+    }
+
+    /// Forget `node_id`; returns whether it was stale
+    async fn clear(&self, node_id: &str) -> Result<bool, AppError> {
+        let _write = crate::database::queue_write().await;
+        let result = stale_entity::Entity::delete_by_id(node_id)
+            .exec(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Put a drained node back into its peers' configs, or a deleted one
+    /// back into the inventory; its absence is counted anew from now.
+    /// `None` when `node_id` is not drained or deleted.
+    pub async fn restore(&self, node_manager: &NodeManager, context: &AppContext, node_id: &str) -> Result<Option<StaleNode>, AppError> {
+        let Some(model) = stale_entity::Entity::find_by_id(node_id).one(&self.db).await.map_err(db_error)? else {
+            return Ok(None);
+        };
+        match StaleState::parse(&model.state) {
+            StaleState::Flagged => return Ok(None),
+            StaleState::Drained => {
+                node_manager.reinstate(node_id);
+            }
+            StaleState::Deleted => {
+                let node: Node = serde_json::from_str(model.node.as_deref().unwrap_or_default())?;
+                node_manager.restore_node(node).await?;
+            }
+        }
+        self.clear(node_id).await?;
+        context.status_history.record(node_id, false).await;
+        Ok(Some(model.into()))
+    }
+
+    /// Flag, drain or delete the nodes of `node_manager` as configured,
+    /// and push the configs that changed, even when a step failed after
+    /// them. Deleted nodes' keys are revoked at once.
+    pub async fn sweep(&self, node_manager: &Arc<NodeManager>, context: &AppContext) -> Result<(), AppError> {
+        let mut changes = SweepChanges::default();
+        let result = self.sweep_nodes(node_manager, context, &mut changes).await;
+        if changes.deleted {
+            crate::websocket_state::broadcast_urgent_configuration_update(node_manager).await;
+        } else if changes.peers {
+            crate::websocket_state::broadcast_configuration_update(node_manager).await;
+        }
+        result
+    }
+
+    async fn sweep_nodes(&self, node_manager: &NodeManager, context: &AppContext, changes: &mut SweepChanges) -> Result<(), AppError> {
+        let config = context.config_manager.get().stale_nodes.clone();
+        let mut stale = self.all().await?;
+        if config.after_days == 0 && stale.is_empty() {
+            return Ok(());
+        }
+
+        let connected = crate::websocket_state::get_connected_node_ids().await;
+        let history = context.status_history.latest().await?;
+        let now = Utc::now();
+
+        for node in node_manager.get_all_nodes().await {
+            let exempt = node.tags.iter().any(|tag| config.exempt_tags.contains(tag));
+            let last_seen = match history.get(&node.id) {
+                _ if connected.contains(&node.id) => None,
+                Some(change) if !change.online => Some(change.created_at),
+                Some(_) => None,
+                None => {
+                    // Nodes that never connected count as away from now on
+                    context.status_history.record(&node.id, false).await;
+                    None
+                }
+            };
+            let away = last_seen.filter(|last_seen| config.after_days > 0 && !exempt && (now - *last_seen).num_days() >= config.after_days as i64);
+
+            let Some(last_seen) = away else {
+                // Back, exempted, or the policy was relaxed
+                if stale.remove(&node.id).is_some() {
+                    self.clear(&node.id).await?;
+                    changes.peers |= node_manager.reinstate(&node.id);
+                    let message = format!("Node {} is no longer stale", node.name);
+                    context.event_log.emit("node_stale_cleared", EventSeverity::Info, Some(&node.id), &message).await;
+                    context.notifier.send(&context.config_manager, "node_stale_cleared", serde_json::json!({ "node_id": node.id, "name": node.name }));
+                }
+                continue;
+            };
+
+            let Some(model) = stale.remove(&node.id) else {
+                let flagged = self.flag(&node, last_seen).await?;
+                let message = format!("Node {} has not connected for {} days", node.name, (now - last_seen).num_days());
+                context.event_log.emit("node_stale", EventSeverity::Warning, Some(&node.id), &message).await;
+                context.notifier.send(&context.config_manager, "node_stale", serde_json::to_value(&flagged).unwrap_or_default());
+                continue;
+            };
+
+            match StaleState::parse(&model.state) {
+                // Withdrawals are not persisted, so redo them after a restart
+                StaleState::Drained => changes.peers |= node_manager.withdraw(&node.id),
+                StaleState::Flagged if (now - model.flagged_at).num_days() >= config.grace_days as i64 => match config.action {
+                    StaleNodeAction::Flag => {}
+                    StaleNodeAction::Drain => {
+                        changes.peers |= node_manager.withdraw(&node.id);
+                        let drained = self.mark(model, StaleState::Drained, None).await?;
+                        let message = format!("Drained stale node {}, its peers stop dialing it", node.name);
+                        context.event_log.emit("node_drained", EventSeverity::Warning, Some(&node.id), &message).await;
+                        context.notifier.send(&context.config_manager, "node_drained", serde_json::to_value(&drained).unwrap_or_default());
+                    }
+                    StaleNodeAction::Delete => {
+                        // The copy keeps the key only where the key export could hand it out
+                        let copy = match context.settings_manager.get_key_escrow_policy().await? {
+                            KeyEscrowPolicy::Exportable => serde_json::to_string(&node.with_private_key())?,
+                            KeyEscrowPolicy::WriteOnly => serde_json::to_string(&node)?,
+                        };
+                        let deleted = self.mark(model, StaleState::Deleted, Some(copy)).await?;
+                        node_manager.remove_node(&node.id).await?;
+                        changes.deleted = true;
+                        let message = format!("Deleted stale node {}, restorable through /api/stale-nodes/{}/restore", node.name, node.id);
+                        context.event_log.emit("node_deleted", EventSeverity::Warning, Some(&node.id), &message).await;
+                        context.notifier.send(&context.config_manager, "node_deleted", serde_json::to_value(&deleted).unwrap_or_default());
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        // Flags of nodes removed by hand; deleted copies stay restorable
+        for (node_id, model) in stale {
+            if StaleState::parse(&model.state) != StaleState::Deleted {
+                self.clear(&node_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What a sweep changed in peers' configs, up to a failed step
+#[derive(Default)]
+struct SweepChanges {
+    /// Nodes were drained or reinstated
+    peers: bool,
+    deleted: bool,
+}
+
+/// Longest wait before a changed `check_interval` is noticed
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Sweep every `check_interval` seconds, pushing new configs to the
+/// remaining nodes when drained or deleted nodes leave their peers
+pub async fn run(node_manager: Arc<NodeManager>, context: Arc<AppContext>) {
+    let mut last_sweep: Option<tokio::time::Instant> = None;
+    loop {
+        // Read every round, as the interval can change through the settings
+        let check_interval = Duration::from_secs(context.config_manager.get().stale_nodes.check_interval.max(1));
+        let wait = last_sweep.map_or(Duration::ZERO, |at| check_interval.saturating_sub(at.elapsed()));
+        if !wait.is_zero() {
+            tokio::time::sleep(wait.min(MAX_WAIT)).await;
+            continue;
+        }
+        last_sweep = Some(tokio::time::Instant::now());
+        if let Err(e) = context.stale_nodes.sweep(&node_manager, &context).await {
+            tracing::error!("Failed to check for stale nodes: {}", e);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+
+use crate::database::entities::status_history::{self as status_entity, Model as StatusChange};
+use crate::error::AppError;
+
+/// Windows availability is reported over, with their labels
+const WINDOWS: &[(&str, i64)] = &[("24h", 1), ("7d", 7), ("30d", 30)];
+
+/// Share of a window a node was online
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WindowAvailability {
+    pub window: &'static str,
+    /// `None` when nothing is known about the node in this window
+    pub uptime_percent: Option<f64>,
+    pub online_seconds: i64,
+    /// Part of the window covered by history; less than the window for
+    /// nodes added during it
+    pub tracked_seconds: i64,
+    /// Times the node went offline within the window
+    pub disconnects: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Availability {
+    pub node_id: String,
+    pub online: bool,
+    pub last_change: Option<DateTime<Utc>>,
+    pub windows: Vec<WindowAvailability>,
+}
+
+/// Persisted online/offline transitions of every node's agent
+pub struct StatusHistory {
+    db: DatabaseConnection,
+}
+
+impl StatusHistory {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Record that `node_id` came online or went offline. Failures are
+    /// logged, a lost transition must not drop the agent's connection.
+    pub async fn record(&self, node_id: &str, online: bool) {
+        let _write = crate::database::queue_write().await;
+        if let Err(e) = status_entity::ActiveModel::new(node_id.to_string(), online).insert(&self.db).await {
+            tracing::error!("Failed to record status of node {}: {}", node_id, e);
+        }
+    }
+
+    /// The most recent transition of every node with any history
+    pub async fn latest(&self) -> Result<HashMap<String, StatusChange>, AppError> {
+        let ids: Vec<i64> = status_entity::Entity::find()
+            .select_only()
+            .column_as(status_entity::Column::Id.max(), "id")
+            .group_by(status_entity::Column::NodeId)
+            .into_tuple()
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        let changes = status_entity::Entity::find()
+            .filter(status_entity::Column::Id.is_in(ids))
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+
+        Ok(changes.into_iter().map(|change| (change.node_id.clone(), change)).collect())
+    }
+
+    /// Close the intervals of nodes that were online when the server last
+    /// stopped; their agents reconnect and are recorded online again
+    pub async fn close_open_intervals(&self) -> Result<(), AppError> {
+        for (node_id, change) in self.latest().await? {
+            if change.online {
+                self.record(&node_id, false).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Uptime of `node_id` over the last 24 hours, 7 days and 30 days
+    pub async fn availability(&self, node_id: &str) -> Result<Availability, AppError> {
+        let now = Utc::now();
+        let oldest = now - Duration::days(WINDOWS.iter().map(|(_, days)| *days).max().unwrap_or(30));
+
+        let before = status_entity::Entity::find()
+            .filter(status_entity::Column::NodeId.eq(node_id))
+            .filter(status_entity::Column::CreatedAt.lt(oldest))
+            .order_by_desc(status_entity::Column::CreatedAt)
+            .one(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        let changes = status_entity::Entity::find()
+            .filter(status_entity::Column::NodeId.eq(node_id))
+            .filter(status_entity::Column::CreatedAt.gte(oldest))
+            .order_by_asc(status_entity::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+
+        let history: Vec<StatusChange> = before.into_iter().chain(changes).collect();
+        let windows = WINDOWS.iter()
+            .map(|(label, days)| window_availability(label, &history, now - Duration::days(*days), now))
+            .collect();
+
+        Ok(Availability {
+            node_id: node_id.to_string(),
+            online: crate::websocket_state::get_connected_node_ids().await.contains(node_id),
+            last_change: history.last().map(|change| change.created_at),
+            windows,
+        })
+    }
+}
+
+/// Sum the online time in `history`, oldest first, between `start` and `end`
+fn window_availability(label: &'static str, history: &[StatusChange], start: DateTime<Utc>, end: DateTime<Utc>) -> WindowAvailability {
+    let mut online_seconds = 0;
+    let mut tracked_seconds = 0;
+    let mut disconnects = 0;
+
+    for (i, change) in history.iter().enumerate() {
+        let from = change.created_at.max(start);
+        let to = history.get(i + 1).map_or(end, |next| next.created_at).min(end);
+        if change.created_at >= start && !change.online {
+            disconnects += 1;
+        }
+        if to <= from {
+            continue;
+        }
+        let seconds = (to - from).num_seconds();
+        tracked_seconds += seconds;
+        if change.online {
+            online_seconds += seconds;
+        }
+    }
+
+    WindowAvailability {
+        window: label,
+        uptime_percent: (tracked_seconds > 0)
+            .then(|| (online_seconds as f64 * 10000.0 / tracked_seconds as f64).round() / 100.0),
+        online_seconds,
+        tracked_seconds,
+        disconnects,
+    }
+}
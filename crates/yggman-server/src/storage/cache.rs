@@ -0,0 +1,128 @@
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use super::NodeStore;
+use crate::error::Result;
+use crate::yggdrasil::Node;
+
+/// Counters of the node cache since startup
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CacheStats {
+    pub ttl_seconds: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// Share of reads served from the cache, 0 to 1
+    pub hit_rate: f64,
+}
+
+/// Read-through cache of the full node list in front of another store.
+/// Writes through this store invalidate it; `ttl` bounds how long writes
+/// by other yggman instances sharing the database go unnoticed.
+pub struct CachedNodeStore {
+    inner: Arc<dyn NodeStore>,
+    ttl: Duration,
+    nodes: RwLock<Option<(Instant, Arc<Vec<Node>>)>>,
+    /// Bumped on every write, so a load that raced a write is not cached
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl CachedNodeStore {
+    pub fn new(inner: Arc<dyn NodeStore>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            nodes: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    fn cached(&self) -> Option<Arc<Vec<Node>>> {
+        let nodes = self.nodes.read().unwrap();
+        let (loaded_at, nodes) = nodes.as_ref()?;
+        (loaded_at.elapsed() < self.ttl).then(|| nodes.clone())
+    }
+
+    async fn load(&self) -> Result<Arc<Vec<Node>>> {
+        if let Some(nodes) = self.cached() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(nodes);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let nodes = Arc::new(self.inner.all().await?);
+        let mut cache = self.nodes.write().unwrap();
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cache = Some((Instant::now(), nodes.clone()));
+        }
+        Ok(nodes)
+    }
+
+    fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *self.nodes.write().unwrap() = None;
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl NodeStore for CachedNodeStore {
+    async fn all(&self) -> Result<Vec<Node>> {
+        Ok(self.load().await?.as_ref().clone())
+    }
+
+    async fn in_network(&self, network: &str) -> Result<Vec<Node>> {
+        Ok(self.load().await?.iter().filter(|node| node.network == network).cloned().collect())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Node>> {
+        Ok(self.load().await?.iter().find(|node| node.id == id).cloned())
+    }
+
+    async fn insert(&self, node: &Node) -> Result<()> {
+        let result = self.inner.insert(node).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update(&self, nodes: &[Node]) -> Result<()> {
+        let result = self.inner.update(nodes).await;
+        self.invalidate();
+        result
+    }
+
+    async fn remove(&self, id: &str) -> Result<bool> {
+        let result = self.inner.remove(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn unreadable_fields(&self) -> Result<Vec<(String, Vec<&'static str>)>> {
+        self.inner.unreadable_fields().await
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        Some(CacheStats {
+            ttl_seconds: self.ttl.as_secs(),
+            hits,
+            misses,
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            hit_rate: if hits + misses == 0 { 0.0 } else { hits as f64 / (hits + misses) as f64 },
+        })
+    }
+
+    fn invalidate_cache(&self) {
+        self.invalidate();
+    }
+}
@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, NotSet, QueryFilter, TransactionTrait};
+
+use crate::database::entities::node as node_entity;
+use crate::error::{AppError, Result};
+use crate::yggdrasil::Node;
+
+use super::NodeStore;
+
+fn db_error(e: sea_orm::DbErr) -> AppError {
+    AppError::Config(format!("Database error: {}", e))
+}
+
+/// Nodes in the `nodes` table of the configured database
+pub struct DatabaseNodeStore {
+    db: DatabaseConnection,
+}
+
+impl DatabaseNodeStore {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl NodeStore for DatabaseNodeStore {
+    async fn all(&self) -> Result<Vec<Node>> {
+        let models = node_entity::Entity::find().all(&self.db).await.map_err(db_error)?;
+        Ok(models.into_iter().map(Node::from).collect())
+    }
+
+    async fn in_network(&self, network: &str) -> Result<Vec<Node>> {
+        let models = node_entity::Entity::find()
+            .filter(node_entity::Column::Network.eq(network))
+            .all(&self.db)
+            .await
+            .map_err(db_error)?;
+        Ok(models.into_iter().map(Node::from).collect())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Node>> {
+        let model = node_entity::Entity::find_by_id(id).one(&self.db).await.map_err(db_error)?;
+        Ok(model.map(Node::from))
+    }
+
+    async fn insert(&self, node: &Node) -> Result<()> {
+        let _write = crate::database::queue_write().await;
+        node_entity::ActiveModel::from(node).insert(&self.db).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn update(&self, nodes: &[Node]) -> Result<()> {
+        let _write = crate::database::queue_write().await;
+        let txn = self.db.begin().await.map_err(db_error)?;
+        for node in nodes {
+            let mut active_model = node_entity::ActiveModel::from(node);
+            active_model.created_at = NotSet;
+            active_model.update(&txn).await.map_err(db_error)?;
+        }
+        txn.commit().await.map_err(db_error)
+    }
+
+    async fn remove(&self, id: &str) -> Result<bool> {
+        let _write = crate::database::queue_write().await;
+        let result = node_entity::Entity::delete_by_id(id).exec(&self.db).await.map_err(db_error)?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn unreadable_fields(&self) -> Result<Vec<(String, Vec<&'static str>)>> {
+        let models = node_entity::Entity::find().all(&self.db).await.map_err(db_error)?;
+        Ok(models.into_iter()
+            .map(|model| (model.unreadable_fields(), model.id))
+            .filter(|(fields, _)| !fields.is_empty())
+            .map(|(fields, id)| (id, fields))
+            .collect())
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+
+use crate::error::{AppError, Result};
+use crate::yggdrasil::Node;
+
+use super::NodeStore;
+
+#[derive(Deserialize)]
+struct NodesFile {
+    nodes: Vec<Node>,
+}
+
+/// Nodes kept in memory and written to a JSON file on every change. The
+/// file holds private keys, so it is created readable by its owner only.
+pub struct FileNodeStore {
+    path: String,
+    nodes: RwLock<Vec<Node>>,
+}
+
+impl FileNodeStore {
+    /// Load `path`, starting empty when it does not exist yet
+    pub async fn open(path: &str) -> Result<Self> {
+        let nodes = match tokio::fs::read_to_string(path).await {
+            Ok(content) => serde_json::from_str::<NodesFile>(&content)
+                .map_err(|e| AppError::Config(format!("Invalid node file {}: {}", path, e)))?
+                .nodes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            nodes: RwLock::new(nodes),
+        })
+    }
+
+    /// Replace the file atomically with `nodes`
+    async fn save(&self, nodes: &[Node]) -> Result<()> {
+        let nodes: Vec<_> = nodes.iter().map(Node::with_private_key).collect();
+        let contents = serde_json::to_string_pretty(&serde_json::json!({ "nodes": nodes }))?;
+        let temp_path = format!("{}.tmp", self.path);
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&temp_path).await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NodeStore for FileNodeStore {
+    async fn all(&self) -> Result<Vec<Node>> {
+        Ok(self.nodes.read().await.clone())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Node>> {
+        Ok(self.nodes.read().await.iter().find(|node| node.id == id).cloned())
+    }
+
+    async fn insert(&self, node: &Node) -> Result<()> {
+        let mut nodes = self.nodes.write().await;
+        if nodes.iter().any(|existing| existing.id == node.id) {
+            return Err(AppError::Config(format!("Node {} already exists", node.id)));
+        }
+
+        let mut updated = nodes.clone();
+        updated.push(node.clone());
+        self.save(&updated).await?;
+        *nodes = updated;
+        Ok(())
+    }
+
+    async fn update(&self, changed: &[Node]) -> Result<()> {
+        let mut nodes = self.nodes.write().await;
+        let mut updated = nodes.clone();
+        for node in changed {
+            let existing = updated.iter_mut()
+                .find(|existing| existing.id == node.id)
+                .ok_or_else(|| AppError::Config("Node not found".to_string()))?;
+            *existing = node.clone();
+        }
+
+        self.save(&updated).await?;
+        *nodes = updated;
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<bool> {
+        let mut nodes = self.nodes.write().await;
+        if !nodes.iter().any(|node| node.id == id) {
+            return Ok(false);
+        }
+
+        let updated: Vec<Node> = nodes.iter().filter(|node| node.id != id).cloned().collect();
+        self.save(&updated).await?;
+        *nodes = updated;
+        Ok(true)
+    }
+}
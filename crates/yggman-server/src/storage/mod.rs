@@ -0,0 +1,70 @@
+//! Persistence of nodes behind a trait, so small deployments can keep them
+//! in a plain file instead of the database.
+
+mod cache;
+mod database;
+mod file;
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+
+use crate::config::{StorageBackend, StorageConfig};
+use crate::error::Result;
+use crate::yggdrasil::Node;
+
+pub use cache::{CacheStats, CachedNodeStore};
+pub use database::DatabaseNodeStore;
+pub use file::FileNodeStore;
+
+#[async_trait]
+pub trait NodeStore: Send + Sync {
+    async fn all(&self) -> Result<Vec<Node>>;
+
+    async fn in_network(&self, network: &str) -> Result<Vec<Node>> {
+        let mut nodes = self.all().await?;
+        nodes.retain(|node| node.network == network);
+        Ok(nodes)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Node>>;
+
+    async fn insert(&self, node: &Node) -> Result<()>;
+
+    /// Replace stored nodes by id, either all of them or none
+    async fn update(&self, nodes: &[Node]) -> Result<()>;
+
+    /// Returns whether the node existed
+    async fn remove(&self, id: &str) -> Result<bool>;
+
+    /// Ids of nodes with stored fields that do not parse and were read as
+    /// empty, with the names of those fields. Stores that keep whole nodes
+    /// have none.
+    async fn unreadable_fields(&self) -> Result<Vec<(String, Vec<&'static str>)>> {
+        Ok(Vec::new())
+    }
+
+    /// Hit counters, for stores that cache
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// Forget cached nodes, e.g. after the backing store was edited by hand
+    fn invalidate_cache(&self) {}
+}
+
+/// The node store selected by the `[storage]` config section
+pub async fn open(config: &StorageConfig, db: DatabaseConnection) -> Result<Arc<dyn NodeStore>> {
+    let store: Arc<dyn NodeStore> = match config.backend {
+        StorageBackend::Database => Arc::new(DatabaseNodeStore::new(db)),
+        StorageBackend::File => {
+            tracing::info!("Storing nodes in {}", config.path);
+            Arc::new(FileNodeStore::open(&config.path).await?)
+        }
+    };
+    if config.cache_ttl == 0 {
+        return Ok(store);
+    }
+    tracing::info!("Caching the node list for up to {}s", config.cache_ttl);
+    Ok(Arc::new(CachedNodeStore::new(store, std::time::Duration::from_secs(config.cache_ttl))))
+}
@@ -0,0 +1,83 @@
+//! Running as a systemd service: listening sockets passed by socket
+//! activation, and readiness, stopping and watchdog notifications. All of
+//! it does nothing when the server was not started by systemd.
+
+/// Listening sockets systemd passed in LISTEN_FDS
+#[derive(Default)]
+pub struct ActivatedSockets {
+    /// Used instead of binding `server.port`
+    pub tcp: Option<std::net::TcpListener>,
+    /// Used instead of binding `server.unix_socket`
+    #[cfg(unix)]
+    pub unix: Option<std::os::unix::net::UnixListener>,
+}
+
+/// Take the sockets passed by socket activation, the first TCP and the
+/// first unix stream listener. Later calls find none.
+#[cfg(unix)]
+pub fn activated_sockets() -> ActivatedSockets {
+    let mut fds = listenfd::ListenFd::from_env();
+    let mut sockets = ActivatedSockets::default();
+    for index in 0..fds.len() {
+        if sockets.tcp.is_none() {
+            if let Ok(Some(listener)) = fds.take_tcp_listener(index) {
+                tracing::info!("Using TCP socket {} passed by systemd", index);
+                sockets.tcp = Some(listener);
+                continue;
+            }
+        }
+        if sockets.unix.is_none() {
+            if let Ok(Some(listener)) = fds.take_unix_listener(index) {
+                tracing::info!("Using unix socket {} passed by systemd", index);
+                sockets.unix = Some(listener);
+                continue;
+            }
+        }
+        tracing::warn!("Ignoring socket {} passed by systemd, only one TCP and one unix stream listener are used", index);
+    }
+    sockets
+}
+
+#[cfg(not(unix))]
+pub fn activated_sockets() -> ActivatedSockets {
+    ActivatedSockets::default()
+}
+
+/// Tell systemd startup finished, for `Type=notify` services
+pub fn notify_ready() {
+    #[cfg(unix)]
+    notify(sd_notify::NotifyState::Ready);
+}
+
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    notify(sd_notify::NotifyState::Stopping);
+}
+
+/// With `WatchdogSec=` set, send keepalives at half the interval from the
+/// async runtime, so systemd restarts the server when the runtime stalls
+pub fn spawn_watchdog() {
+    #[cfg(unix)]
+    {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) || usec == 0 {
+            return;
+        }
+        let period = std::time::Duration::from_micros(usec / 2);
+        tracing::info!("Sending systemd watchdog keepalives every {:?}", period);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                notify(sd_notify::NotifyState::Watchdog);
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+fn notify(state: sd_notify::NotifyState) {
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        tracing::warn!("Failed to notify systemd: {}", e);
+    }
+}
@@ -0,0 +1,114 @@
+//! Intended versus established links between the nodes of a network, as
+//! reported by their agents.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::node_manager::{ordered_pair, NodeManager};
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    /// Intended and established
+    Ok,
+    /// Intended but neither side reports a session
+    Missing,
+    /// Established although yggman never configured it
+    Unexpected,
+    /// Intended, but neither agent reported its live peers
+    Unknown,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConnectivityNode {
+    pub id: String,
+    pub name: String,
+    pub connected: bool,
+    /// Whether the agent reported its established peers
+    pub reporting: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConnectivityLink {
+    pub a: String,
+    pub b: String,
+    pub status: LinkStatus,
+}
+
+#[derive(serde::Serialize, Default)]
+pub struct ConnectivitySummary {
+    pub ok: usize,
+    pub missing: usize,
+    pub unexpected: usize,
+    pub unknown: usize,
+}
+
+#[derive(serde::Serialize)]
+pub struct Connectivity {
+    pub nodes: Vec<ConnectivityNode>,
+    pub links: Vec<ConnectivityLink>,
+    /// Link status by node id pair, filled in both directions
+    pub matrix: BTreeMap<String, BTreeMap<String, LinkStatus>>,
+    pub summary: ConnectivitySummary,
+}
+
+pub async fn connectivity(node_manager: &NodeManager, network: &str) -> Connectivity {
+    let nodes = node_manager.get_nodes_in_network(network).await;
+    let intended = node_manager.intended_links(network).await;
+    let connected_ids = crate::websocket_state::get_connected_node_ids().await;
+    let ids_by_key: HashMap<&str, &str> = nodes.iter().map(|n| (n.public_key.as_str(), n.id.as_str())).collect();
+
+    // Links established according to either end, and the nodes that reported
+    let mut established = HashSet::new();
+    let mut reporting = HashSet::new();
+    let mut connectivity_nodes = Vec::new();
+    for node in &nodes {
+        let status = crate::websocket_state::get_agent_status(&node.id).await;
+        let peers = status.and_then(|s| s.established_peers);
+        if let Some(peers) = &peers {
+            reporting.insert(node.id.clone());
+            for key in peers {
+                if let Some(peer_id) = ids_by_key.get(key.as_str()).filter(|id| **id != node.id) {
+                    established.insert(ordered_pair(&node.id, peer_id));
+                }
+            }
+        }
+        connectivity_nodes.push(ConnectivityNode {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            connected: connected_ids.contains(&node.id),
+            reporting: peers.is_some(),
+        });
+    }
+
+    let mut pairs: Vec<&(String, String)> = intended.union(&established).collect();
+    pairs.sort();
+
+    let mut links = Vec::new();
+    let mut matrix: BTreeMap<String, BTreeMap<String, LinkStatus>> = BTreeMap::new();
+    let mut summary = ConnectivitySummary::default();
+    for (a, b) in pairs {
+        let pair = (a.clone(), b.clone());
+        let status = match (intended.contains(&pair), established.contains(&pair)) {
+            (true, true) => LinkStatus::Ok,
+            (false, _) => LinkStatus::Unexpected,
+            (true, false) if reporting.contains(a) || reporting.contains(b) => LinkStatus::Missing,
+            (true, false) => LinkStatus::Unknown,
+        };
+        match status {
+            LinkStatus::Ok => summary.ok += 1,
+            LinkStatus::Missing => summary.missing += 1,
+            LinkStatus::Unexpected => summary.unexpected += 1,
+            LinkStatus::Unknown => summary.unknown += 1,
+        }
+        matrix.entry(a.clone()).or_default().insert(b.clone(), status);
+        matrix.entry(b.clone()).or_default().insert(a.clone(), status);
+        links.push(ConnectivityLink { a: a.clone(), b: b.clone(), status });
+    }
+
+    Connectivity {
+        nodes: connectivity_nodes,
+        links,
+        matrix,
+        summary,
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+
+use crate::config::TrafficConfig;
+use crate::database::entities::traffic_stats::{self as traffic_entity, Model as TrafficBucket};
+use crate::error::AppError;
+
+pub use yggman_proto::protocol::PeerCounters;
+
+/// Resolution detailed buckets are merged into
+const HOUR: i64 = 3600;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TrafficPoint {
+    pub at: DateTime<Utc>,
+    /// Width of the bucket; hourly for data older than the detailed retention
+    pub seconds: i64,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PeerSeries {
+    pub key: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub points: Vec<TrafficPoint>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TrafficSeries {
+    pub node_id: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub peers: Vec<PeerSeries>,
+}
+
+/// Last (rx, tx) counters reported per peer key
+type PeerTotals = HashMap<String, (u64, u64)>;
+
+/// Per-peer traffic of every node, stored as byte counts per time bucket
+pub struct TrafficStats {
+    db: DatabaseConnection,
+    /// Last counters reported per node and peer, to turn them into deltas
+    last: Mutex<HashMap<String, PeerTotals>>,
+}
+
+impl TrafficStats {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Add the traffic since `node_id`'s previous report to the current
+    /// bucket. The first report after a server start only sets the baseline.
+    pub async fn record(&self, node_id: &str, peers: &[PeerCounters], bucket_seconds: u64) {
+        let deltas: Vec<(String, u64, u64)> = {
+            let mut last = self.last.lock().unwrap();
+            let known = last.contains_key(node_id);
+            let previous = last.insert(
+                node_id.to_string(),
+                peers.iter().map(|peer| (peer.key.clone(), (peer.rx_bytes, peer.tx_bytes))).collect(),
+            );
+            if !known {
+                return;
+            }
+            let previous = previous.unwrap_or_default();
+            peers.iter()
+                .map(|peer| {
+                    // Counters restart with each session, a peer seen for the
+                    // first time or with smaller counters reconnected
+                    let (rx, tx) = match previous.get(&peer.key) {
+                        Some(&(rx, tx)) if peer.rx_bytes >= rx && peer.tx_bytes >= tx => (peer.rx_bytes - rx, peer.tx_bytes - tx),
+                        _ => (peer.rx_bytes, peer.tx_bytes),
+                    };
+                    (peer.key.clone(), rx, tx)
+                })
+                .filter(|(_, rx, tx)| *rx > 0 || *tx > 0)
+                .collect()
+        };
+
+        let resolution = bucket_seconds.max(1) as i64;
+        let bucket_start = bucket_of(Utc::now(), resolution);
+        for (peer_key, rx, tx) in deltas {
+            if let Err(e) = self.add(node_id, &peer_key, bucket_start, resolution, rx as i64, tx as i64).await {
+                tracing::error!("Failed to store traffic of node {}: {}", node_id, e);
+            }
+        }
+    }
+
+    async fn add(&self, node_id: &str, peer_key: &str, bucket_start: DateTime<Utc>, resolution: i64, rx_bytes: i64, tx_bytes: i64) -> Result<(), AppError> {
+        let _write = crate::database::queue_write().await;
+        let existing = traffic_entity::Entity::find()
+            .filter(traffic_entity::Column::NodeId.eq(node_id))
+            .filter(traffic_entity::Column::PeerKey.eq(peer_key))
+            .filter(traffic_entity::Column::BucketStart.eq(bucket_start))
+            .filter(traffic_entity::Column::Resolution.eq(resolution))
+            .one(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+
+        let result = match existing {
+            Some(bucket) => {
+                let mut active: traffic_entity::ActiveModel = bucket.clone().into();
+                active.rx_bytes = Set(bucket.rx_bytes + rx_bytes);
+                active.tx_bytes = Set(bucket.tx_bytes + tx_bytes);
+                active.update(&self.db).await.map(|_| ())
+            }
+            None => traffic_entity::ActiveModel::new(node_id.to_string(), peer_key.to_string(), bucket_start, resolution, rx_bytes, tx_bytes)
+                .insert(&self.db)
+                .await
+                .map(|_| ()),
+        };
+        result.map_err(|e| AppError::Config(format!("Database error: {}", e)))
+    }
+
+    /// Merge detailed buckets past their retention into hourly ones and
+    /// delete buckets past the overall retention
+    pub async fn compact(&self, config: &TrafficConfig) -> Result<(), AppError> {
+        let now = Utc::now();
+        // Aligned to the hour, so hourly and detailed buckets never overlap
+        let detail_cutoff = bucket_of(now - Duration::hours(config.detail_retention_hours as i64), HOUR);
+
+        let detailed = traffic_entity::Entity::find()
+            .filter(traffic_entity::Column::Resolution.lt(HOUR))
+            .filter(traffic_entity::Column::BucketStart.lt(detail_cutoff))
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+
+        if !detailed.is_empty() {
+            let mut hourly: BTreeMap<(String, String, DateTime<Utc>), (i64, i64)> = BTreeMap::new();
+            for bucket in &detailed {
+                let totals = hourly
+                    .entry((bucket.node_id.clone(), bucket.peer_key.clone(), bucket_of(bucket.bucket_start, HOUR)))
+                    .or_default();
+                totals.0 += bucket.rx_bytes;
+                totals.1 += bucket.tx_bytes;
+            }
+            for ((node_id, peer_key, bucket_start), (rx, tx)) in hourly {
+                self.add(&node_id, &peer_key, bucket_start, HOUR, rx, tx).await?;
+            }
+            let _write = crate::database::queue_write().await;
+            traffic_entity::Entity::delete_many()
+                .filter(traffic_entity::Column::Id.is_in(detailed.iter().map(|bucket| bucket.id)))
+                .exec(&self.db)
+                .await
+                .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+            tracing::debug!("Merged {} traffic buckets into hourly ones", detailed.len());
+        }
+
+        let _write = crate::database::queue_write().await;
+        traffic_entity::Entity::delete_many()
+            .filter(traffic_entity::Column::BucketStart.lt(now - Duration::days(config.retention_days as i64)))
+            .exec(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+        Ok(())
+    }
+
+    /// Traffic of `node_id` since `from`, per peer and oldest first,
+    /// optionally limited to one peer
+    pub async fn series(&self, node_id: &str, from: DateTime<Utc>, peer_key: Option<&str>) -> Result<TrafficSeries, AppError> {
+        let mut query = traffic_entity::Entity::find()
+            .filter(traffic_entity::Column::NodeId.eq(node_id))
+            .filter(traffic_entity::Column::BucketStart.gte(bucket_of(from, HOUR)));
+        if let Some(peer_key) = peer_key {
+            query = query.filter(traffic_entity::Column::PeerKey.eq(peer_key));
+        }
+        let buckets: Vec<TrafficBucket> = query
+            .order_by_asc(traffic_entity::Column::BucketStart)
+            .all(&self.db)
+            .await
+            .map_err(|e| AppError::Config(format!("Database error: {}", e)))?;
+
+        let mut peers: BTreeMap<String, PeerSeries> = BTreeMap::new();
+        for bucket in buckets {
+            let series = peers.entry(bucket.peer_key.clone()).or_insert_with(|| PeerSeries {
+                key: bucket.peer_key.clone(),
+                rx_bytes: 0,
+                tx_bytes: 0,
+                points: Vec::new(),
+            });
+            series.rx_bytes += bucket.rx_bytes;
+            series.tx_bytes += bucket.tx_bytes;
+            series.points.push(TrafficPoint {
+                at: bucket.bucket_start,
+                seconds: bucket.resolution,
+                rx_bytes: bucket.rx_bytes,
+                tx_bytes: bucket.tx_bytes,
+            });
+        }
+
+        Ok(TrafficSeries {
+            node_id: node_id.to_string(),
+            from,
+            to: Utc::now(),
+            peers: peers.into_values().collect(),
+        })
+    }
+}
+
+/// Start of the `seconds` wide bucket containing `at`
+fn bucket_of(at: DateTime<Utc>, seconds: i64) -> DateTime<Utc> {
+    let timestamp = at.timestamp();
+    DateTime::from_timestamp(timestamp - timestamp.rem_euclid(seconds), 0).unwrap_or(at)
+}
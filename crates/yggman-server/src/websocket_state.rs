@@ -0,0 +1,697 @@
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::modules::websocket::{AgentTiming, ServerMessage, TIMING_PROTOCOL_VERSION};
+use crate::node_manager::NodeManager;
+use crate::yggdrasil::YggdrasilConfig;
+
+/// Latest ConfigApplied report of an agent
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConfigAck {
+    pub revision: u64,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Configuration an agent last reported as present in its Yggdrasil config
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AgentStatus {
+    pub revision: u64,
+    pub listen: Vec<String>,
+    pub peers: Vec<String>,
+    pub allowed_public_keys: Vec<String>,
+    /// Public keys of peers the node has a live session with, when the
+    /// agent can query its Yggdrasil daemon
+    pub established_peers: Option<Vec<String>>,
+    /// The managed sections of the node's config file were edited by hand
+    /// since the agent last wrote them
+    pub drift: bool,
+    /// The last error the server answered the agent with, as it reported it
+    pub last_server_error: Option<yggman_proto::protocol::ServerErrorReport>,
+    pub reported_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Firewall ports an agent opened with --manage-firewall
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FirewallStatus {
+    pub backend: String,
+    pub open_ports: Vec<String>,
+    pub error: Option<String>,
+    pub reported_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Change in an agent's connection or reported state, for live subscribers
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeStatusEvent {
+    pub node_id: String,
+    pub connected: bool,
+    /// Revision the agent last reported as applied, when known
+    pub revision: Option<u64>,
+}
+
+type ConnectionMap = Arc<RwLock<HashMap<String, Sender<ServerMessage>>>>;
+
+/// Configuration pushed under one revision, kept so a bad revision can be
+/// rolled back to the one before it
+#[derive(Debug, Clone)]
+pub struct RevisionRecord {
+    pub revision: u64,
+    pub configs: HashMap<String, YggdrasilConfig>,
+    /// Agents that were online and received this revision
+    pub delivered_to: HashSet<String>,
+    pub pushed_at: tokio::time::Instant,
+    pub rollback: bool,
+}
+
+const MAX_REVISION_HISTORY: usize = 10;
+
+/// Agents a push sends to at once; one slow agent only holds up its own slot
+const BROADCAST_CONCURRENCY: usize = 64;
+
+/// An agent whose queue stays full this long misses the push, and gets the
+/// revision from the outbox or its next full config instead
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    static ref AGENT_CONNECTIONS: ConnectionMap = Arc::new(RwLock::new(HashMap::new()));
+    // Protocol version each connected agent registered with
+    static ref AGENT_PROTOCOLS: Arc<RwLock<HashMap<String, u32>>> = Arc::new(RwLock::new(HashMap::new()));
+    // Held for the whole of a push, so agents get revisions in order while
+    // registrations only wait for the snapshot of AGENT_CONNECTIONS
+    static ref BROADCAST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    // Nodes whose latest update waits for their maintenance window
+    static ref DEFERRED_UPDATES: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::new()));
+    static ref CONFIG_ACKS: Arc<RwLock<HashMap<String, ConfigAck>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref AGENT_STATUS: Arc<RwLock<HashMap<String, AgentStatus>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref FIREWALL_STATUS: Arc<RwLock<HashMap<String, FirewallStatus>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref NODE_STATUS_EVENTS: tokio::sync::broadcast::Sender<NodeStatusEvent> = tokio::sync::broadcast::channel(256).0;
+    static ref REVISION_EVENTS: tokio::sync::broadcast::Sender<u64> = tokio::sync::broadcast::channel(16).0;
+    // Revision each agent was last sent, which it should report
+    static ref EXPECTED_REVISIONS: Arc<RwLock<HashMap<String, u64>>> = Arc::new(RwLock::new(HashMap::new()));
+    // Newest first
+    static ref REVISION_HISTORY: Arc<RwLock<VecDeque<RevisionRecord>>> = Arc::new(RwLock::new(VecDeque::new()));
+    // Set while a canary rollout proves a revision, to whether automatic
+    // broadcasts were held back since
+    static ref CANARY_HOLD: std::sync::Mutex<Option<bool>> = std::sync::Mutex::new(None);
+}
+
+/// Held by tests that connect agents or push revisions, which share the
+/// state above
+#[cfg(test)]
+pub(crate) static TEST_PUSHES: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+pub async fn register_agent_connection(node_id: String, tx: tokio::sync::mpsc::Sender<ServerMessage>, protocol_version: u32) {
+    // A registering agent receives its full config right away
+    DEFERRED_UPDATES.write().await.remove(&node_id);
+    AGENT_PROTOCOLS.write().await.insert(node_id.clone(), protocol_version);
+    
+    let mut connections = AGENT_CONNECTIONS.write().await;
+    connections.insert(node_id.clone(), tx);
+    info!("Registered agent connection for node: {}", node_id);
+    publish_node_status(&node_id, true, None);
+}
+
+/// Forget the connection sending through `tx`. Returns false, leaving the
+/// node connected, when a newer connection of its agent took over since.
+pub async fn unregister_agent_connection(node_id: &str, tx: &tokio::sync::mpsc::Sender<ServerMessage>) -> bool {
+    {
+        let mut connections = AGENT_CONNECTIONS.write().await;
+        if connections.get(node_id).is_some_and(|current| !current.same_channel(tx)) {
+            return false;
+        }
+        connections.remove(node_id);
+    }
+    AGENT_PROTOCOLS.write().await.remove(node_id);
+    info!("Unregistered agent connection for node: {}", node_id);
+    publish_node_status(node_id, false, None);
+    true
+}
+
+/// Drop the connection of an agent, telling it why; it registers again
+/// when it reconnects. Returns whether it was connected.
+pub async fn disconnect_agent(node_id: &str, reason: &str) -> bool {
+    let Some(tx) = AGENT_CONNECTIONS.write().await.remove(node_id) else {
+        return false;
+    };
+    // The send task ends once the error is out and the channel is closed
+    let _ = tx.send(ServerMessage::Error {
+        message: reason.to_string(),
+        code: Some(crate::modules::websocket::ErrorCode::Unauthorized),
+        upgrade: None,
+    }).await;
+    info!("Disconnected agent of node {}: {}", node_id, reason);
+    publish_node_status(node_id, false, None);
+    true
+}
+
+/// Receive node status changes as they happen
+pub fn subscribe_node_status() -> tokio::sync::broadcast::Receiver<NodeStatusEvent> {
+    NODE_STATUS_EVENTS.subscribe()
+}
+
+/// Receive the number of every revision pushed, e.g. to refresh views of
+/// the topology
+pub fn subscribe_revisions() -> tokio::sync::broadcast::Receiver<u64> {
+    REVISION_EVENTS.subscribe()
+}
+
+fn publish_node_status(node_id: &str, connected: bool, revision: Option<u64>) {
+    // No subscribers is not an error
+    let _ = NODE_STATUS_EVENTS.send(NodeStatusEvent {
+        node_id: node_id.to_string(),
+        connected,
+        revision,
+    });
+}
+
+pub async fn get_deferred_updates() -> Vec<String> {
+    DEFERRED_UPDATES.read().await.iter().cloned().collect()
+}
+
+/// Push the current configuration to connected agents. Agents outside
+/// their maintenance window get it once the window opens. Does nothing but
+/// stage the change while automatic broadcasts are disabled.
+pub async fn broadcast_configuration_update(node_manager: &Arc<NodeManager>) {
+    if auto_broadcast_enabled(node_manager).await {
+        broadcast_new_revision(node_manager, None, false).await;
+    }
+}
+
+/// Push the configs affected by a change to `node_ids` alone. Only the
+/// changed nodes and their peers are regenerated, and only agents whose
+/// config differs from the last revision receive the new one; the rest
+/// stay on the revision they have. Falls back to a full broadcast when
+/// nothing was pushed since startup to compare against.
+pub async fn broadcast_node_change(node_manager: &Arc<NodeManager>, node_ids: &[&str]) {
+    if !auto_broadcast_enabled(node_manager).await {
+        return;
+    }
+    
+    let broadcast = BROADCAST_LOCK.lock().await;
+    let Some(mut configs) = REVISION_HISTORY.read().await.front().map(|record| record.configs.clone()) else {
+        drop(broadcast);
+        broadcast_new_revision(node_manager, None, false).await;
+        return;
+    };
+    
+    let changed: HashSet<String> = node_ids.iter().map(|id| id.to_string()).collect();
+    let affected = node_manager.generate_affected_configs(&changed).await;
+    let targets: HashSet<String> = affected.iter()
+        .filter(|(node_id, config)| configs.get(*node_id) != Some(config))
+        .map(|(node_id, _)| node_id.clone())
+        .collect();
+    if targets.is_empty() {
+        info!("Change to {} leaves every config as it was, nothing to push", node_ids.join(", "));
+        return;
+    }
+    
+    // The record keeps the full picture so a rollback covers every node
+    configs.extend(affected);
+    let revision = node_manager.next_revision().await;
+    let delivered_to = send_configuration(node_manager, &configs, revision, Some(&targets), false).await;
+    record_revision(RevisionRecord {
+        revision,
+        configs,
+        delivered_to,
+        pushed_at: tokio::time::Instant::now(),
+        rollback: false,
+    }).await;
+}
+
+async fn auto_broadcast_enabled(node_manager: &NodeManager) -> bool {
+    let enabled = match node_manager.settings().get_auto_broadcast().await {
+        Ok(true) => true,
+        Ok(false) => {
+            info!("Automatic broadcast disabled, change staged for the next broadcast");
+            false
+        }
+        Err(e) => {
+            warn!("Failed to read auto broadcast setting, broadcasting anyway: {}", e);
+            true
+        }
+    };
+    if let Some(held) = CANARY_HOLD.lock().unwrap().as_mut().filter(|_| enabled) {
+        info!("Canary rollout in progress, change held until it finishes");
+        *held = true;
+        return false;
+    }
+    enabled
+}
+
+/// Hold automatic broadcasts back while a canary rollout runs, so that
+/// agents outside the canary only get configs once it passed. False when
+/// another rollout already holds them.
+pub fn hold_automatic_broadcasts() -> bool {
+    let mut hold = CANARY_HOLD.lock().unwrap();
+    if hold.is_some() {
+        return false;
+    }
+    *hold = Some(false);
+    true
+}
+
+/// End a hold; whether an automatic broadcast was held back during it
+pub fn release_automatic_broadcasts() -> bool {
+    CANARY_HOLD.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Push the current configuration to all connected agents right away,
+/// ignoring maintenance windows. For changes that must not wait, such as
+/// revoking a node's key.
+pub async fn broadcast_urgent_configuration_update(node_manager: &Arc<NodeManager>) {
+    broadcast_new_revision(node_manager, None, true).await;
+}
+
+/// Generate configs under a new revision and push them to `targets`, or to
+/// every connected agent when `None`. Returns what was pushed so staged
+/// rollouts can deliver the same revision to the remaining agents later.
+pub async fn broadcast_new_revision(
+    node_manager: &Arc<NodeManager>,
+    targets: Option<&HashSet<String>>,
+    urgent: bool,
+) -> (HashMap<String, YggdrasilConfig>, u64) {
+    let _broadcast = BROADCAST_LOCK.lock().await;
+    let configs = node_manager.generate_configs().await;
+    let revision = node_manager.next_revision().await;
+    
+    let delivered_to = send_configuration(node_manager, &configs, revision, targets, urgent).await;
+    record_revision(RevisionRecord {
+        revision,
+        configs: configs.clone(),
+        delivered_to,
+        pushed_at: tokio::time::Instant::now(),
+        rollback: false,
+    }).await;
+    (configs, revision)
+}
+
+/// Push earlier configs under a new revision to the connected agents they
+/// cover; agents never accept older revisions, so a rollback moves forward.
+pub async fn push_rollback(node_manager: &Arc<NodeManager>, configs: &HashMap<String, YggdrasilConfig>) -> u64 {
+    let _broadcast = BROADCAST_LOCK.lock().await;
+    let revision = node_manager.next_revision().await;
+    let targets: HashSet<String> = AGENT_CONNECTIONS.read().await.keys().filter(|id| configs.contains_key(*id)).cloned().collect();
+    
+    let delivered_to = send_configuration(node_manager, configs, revision, Some(&targets), true).await;
+    record_revision(RevisionRecord {
+        revision,
+        configs: configs.clone(),
+        delivered_to,
+        pushed_at: tokio::time::Instant::now(),
+        rollback: true,
+    }).await;
+    revision
+}
+
+async fn record_revision(record: RevisionRecord) {
+    // No subscribers is not an error
+    let _ = REVISION_EVENTS.send(record.revision);
+    let mut history = REVISION_HISTORY.write().await;
+    history.push_front(record);
+    history.truncate(MAX_REVISION_HISTORY);
+}
+
+/// Revisions pushed since startup, newest first
+pub async fn get_revision_history() -> Vec<RevisionRecord> {
+    REVISION_HISTORY.read().await.iter().cloned().collect()
+}
+
+/// Push `configs` to `targets` under the already pushed `revision`,
+/// recording them with it so a rollback covers those agents too
+pub async fn push_configuration(
+    node_manager: &Arc<NodeManager>,
+    configs: &HashMap<String, YggdrasilConfig>,
+    revision: u64,
+    targets: &HashSet<String>,
+    urgent: bool,
+) {
+    let _broadcast = BROADCAST_LOCK.lock().await;
+    let delivered_to = send_configuration(node_manager, configs, revision, Some(targets), urgent).await;
+    
+    if let Some(record) = REVISION_HISTORY.write().await.iter_mut().find(|r| r.revision == revision) {
+        record.configs.extend(configs.iter().filter(|(node_id, _)| targets.contains(*node_id)).map(|(node_id, config)| (node_id.clone(), config.clone())));
+        record.delivered_to.extend(delivered_to);
+    }
+}
+
+async fn send_configuration(
+    node_manager: &Arc<NodeManager>,
+    configs: &HashMap<String, YggdrasilConfig>,
+    revision: u64,
+    targets: Option<&HashSet<String>>,
+    urgent: bool,
+) -> HashSet<String> {
+    let closed_windows = if urgent {
+        HashSet::new()
+    } else {
+        node_manager.nodes_outside_maintenance_window().await
+    };
+    let connections: Vec<(String, Sender<ServerMessage>)> = AGENT_CONNECTIONS.read().await.iter()
+        .filter(|(node_id, _)| targets.is_none_or(|t| t.contains(*node_id)))
+        .map(|(node_id, tx)| (node_id.clone(), tx.clone()))
+        .collect();
+    
+    info!("Broadcasting configuration revision {} to {} connected agents", revision, targets.map_or(connections.len(), |t| t.len()));
+    
+    // What each agent gets; deleted nodes get an empty config, to disconnect gracefully
+    let mut sends = Vec::new();
+    {
+        let mut deferred = DEFERRED_UPDATES.write().await;
+        let mut expected = EXPECTED_REVISIONS.write().await;
+        for (node_id, tx) in connections {
+            if closed_windows.contains(&node_id) && configs.contains_key(&node_id) {
+                info!("Deferring update for node {} until its maintenance window opens", node_id);
+                deferred.insert(node_id.clone());
+                expected.insert(node_id, revision);
+                continue;
+            }
+            deferred.remove(&node_id);
+            
+            let update = update_message(node_manager, &node_id, revision, configs.get(&node_id));
+            sends.push((node_id, tx, update));
+        }
+    }
+    
+    let results: Vec<_> = futures::stream::iter(sends)
+        .map(|(node_id, tx, update)| async move {
+            let result = tokio::time::timeout(SEND_TIMEOUT, tx.send(update)).await;
+            (node_id, tx, result)
+        })
+        .buffer_unordered(BROADCAST_CONCURRENCY)
+        .collect()
+        .await;
+    
+    let mut failed_connections = Vec::new();
+    let mut delivered_to = HashSet::new();
+    for (node_id, tx, result) in results {
+        let deleted = !configs.contains_key(&node_id);
+        match result {
+            Ok(Ok(())) if deleted => {
+                info!("Sent final empty config to deleted node {}", node_id);
+                failed_connections.push((node_id, tx));
+            }
+            Ok(Ok(())) => {
+                delivered_to.insert(node_id);
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to send update to node {}: {}", node_id, e);
+                failed_connections.push((node_id, tx));
+            }
+            Err(_) => warn!("Agent of node {} is not keeping up, skipped revision {}", node_id, revision),
+        }
+    }
+    {
+        let mut expected = EXPECTED_REVISIONS.write().await;
+        for node_id in &delivered_to {
+            expected.insert(node_id.clone(), revision);
+        }
+    }
+    
+    // Kept until acknowledged, including for agents that are offline now
+    let pending: Vec<(&str, &YggdrasilConfig, bool)> = configs.iter()
+        .filter(|(node_id, _)| targets.is_none_or(|t| t.contains(*node_id)))
+        .map(|(node_id, config)| (node_id.as_str(), config, delivered_to.contains(node_id)))
+        .collect();
+    if let Err(e) = node_manager.outbox().enqueue(revision, &pending).await {
+        warn!("Failed to persist pending deliveries of revision {}: {}", revision, e);
+    }
+    
+    remove_connections(failed_connections).await;
+    delivered_to
+}
+
+/// Send changed intervals to the connected agents that understand them;
+/// older agents pick them up when they reconnect
+pub async fn broadcast_timing(timing: AgentTiming) {
+    let protocols = AGENT_PROTOCOLS.read().await.clone();
+    let connections: Vec<(String, Sender<ServerMessage>)> = AGENT_CONNECTIONS.read().await.iter()
+        .filter(|(node_id, _)| protocols.get(*node_id).is_some_and(|version| *version >= TIMING_PROTOCOL_VERSION))
+        .map(|(node_id, tx)| (node_id.clone(), tx.clone()))
+        .collect();
+    
+    info!("Sending new timing to {} connected agents", connections.len());
+    futures::stream::iter(connections)
+        .for_each_concurrent(BROADCAST_CONCURRENCY, |(node_id, tx)| {
+            let message = ServerMessage::Timing { timing: timing.clone() };
+            async move {
+                match tokio::time::timeout(SEND_TIMEOUT, tx.send(message)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("Failed to send timing to node {}: {}", node_id, e),
+                    Err(_) => warn!("Agent of node {} is not keeping up, timing not sent", node_id),
+                }
+            }
+        })
+        .await;
+}
+
+/// Forget failed connections, unless their agent registered again meanwhile
+async fn remove_connections(failed: Vec<(String, Sender<ServerMessage>)>) {
+    if failed.is_empty() {
+        return;
+    }
+    let mut connections = AGENT_CONNECTIONS.write().await;
+    for (node_id, tx) in failed {
+        if connections.get(&node_id).is_some_and(|current| current.same_channel(&tx)) {
+            connections.remove(&node_id);
+            info!("Removed failed connection for node: {}", node_id);
+        }
+    }
+}
+
+/// Revision of the config last sent to `node_id`. Agents skipped by
+/// incremental pushes stay on older revisions without falling behind.
+pub async fn get_expected_revision(node_id: &str) -> Option<u64> {
+    EXPECTED_REVISIONS.read().await.get(node_id).copied()
+}
+
+pub async fn record_expected_revision(node_id: &str, revision: u64) {
+    EXPECTED_REVISIONS.write().await.insert(node_id.to_string(), revision);
+}
+
+pub async fn get_connected_node_ids() -> HashSet<String> {
+    AGENT_CONNECTIONS.read().await.keys().cloned().collect()
+}
+
+pub async fn record_config_ack(node_id: &str, ack: ConfigAck) {
+    CONFIG_ACKS.write().await.insert(node_id.to_string(), ack);
+}
+
+pub async fn get_config_ack(node_id: &str) -> Option<ConfigAck> {
+    CONFIG_ACKS.read().await.get(node_id).cloned()
+}
+
+pub async fn record_agent_status(node_id: &str, status: AgentStatus) {
+    let changed = AGENT_STATUS.read().await.get(node_id).is_none_or(|last| last.revision != status.revision);
+    if changed {
+        publish_node_status(node_id, true, Some(status.revision));
+    }
+    AGENT_STATUS.write().await.insert(node_id.to_string(), status);
+}
+
+pub async fn get_agent_status(node_id: &str) -> Option<AgentStatus> {
+    AGENT_STATUS.read().await.get(node_id).cloned()
+}
+
+pub async fn record_firewall_status(node_id: &str, status: FirewallStatus) {
+    FIREWALL_STATUS.write().await.insert(node_id.to_string(), status);
+}
+
+pub async fn get_firewall_status(node_id: &str) -> Option<FirewallStatus> {
+    FIREWALL_STATUS.read().await.get(node_id).cloned()
+}
+
+/// Deliver deferred updates as maintenance windows open. Runs forever.
+pub async fn run_deferred_delivery(node_manager: Arc<NodeManager>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        
+        if DEFERRED_UPDATES.read().await.is_empty() {
+            continue;
+        }
+        
+        // Under the broadcast lock so deliveries never overtake broadcasts
+        let _broadcast = BROADCAST_LOCK.lock().await;
+        let closed_windows = node_manager.nodes_outside_maintenance_window().await;
+        // Taken off the list at once, so registrations need not wait for the sends
+        let ready: Vec<String> = {
+            let mut deferred = DEFERRED_UPDATES.write().await;
+            let ready: Vec<String> = deferred.iter().filter(|id| !closed_windows.contains(*id)).cloned().collect();
+            for node_id in &ready {
+                deferred.remove(node_id);
+            }
+            ready
+        };
+        if ready.is_empty() {
+            continue;
+        }
+        
+        let configs = node_manager.generate_configs().await;
+        let revision = node_manager.current_revision().await;
+        let sends: Vec<(String, Sender<ServerMessage>, ServerMessage)> = {
+            let connections = AGENT_CONNECTIONS.read().await;
+            ready.into_iter()
+                .filter_map(|node_id| {
+                    let tx = connections.get(&node_id)?.clone();
+                    let update = update_message(&node_manager, &node_id, revision, Some(configs.get(&node_id)?));
+                    Some((node_id, tx, update))
+                })
+                .collect()
+        };
+        
+        let results: Vec<_> = futures::stream::iter(sends)
+            .map(|(node_id, tx, update)| async move {
+                let result = tokio::time::timeout(SEND_TIMEOUT, tx.send(update)).await;
+                (node_id, result)
+            })
+            .buffer_unordered(BROADCAST_CONCURRENCY)
+            .collect()
+            .await;
+        
+        let mut delivered_to = HashSet::new();
+        let mut sent = Vec::new();
+        for (node_id, result) in results {
+            match result {
+                Ok(Ok(())) => {
+                    info!("Delivered deferred update to node {}", node_id);
+                    delivered_to.insert(node_id.clone());
+                }
+                Ok(Err(e)) => warn!("Failed to deliver deferred update to node {}: {}", node_id, e),
+                Err(_) => warn!("Agent of node {} is not keeping up, deferred update not delivered", node_id),
+            }
+            sent.push(node_id);
+        }
+        for node_id in &delivered_to {
+            record_expected_revision(node_id, revision).await;
+        }
+        
+        // Undelivered ones wait in the outbox for the agent's next registration
+        let pending: Vec<(&str, &YggdrasilConfig, bool)> = sent.iter()
+            .filter_map(|node_id| Some((node_id.as_str(), configs.get(node_id)?, delivered_to.contains(node_id))))
+            .collect();
+        if let Err(e) = node_manager.outbox().enqueue(revision, &pending).await {
+            warn!("Failed to persist deferred deliveries of revision {}: {}", revision, e);
+        }
+        if let Some(record) = REVISION_HISTORY.write().await.iter_mut().find(|r| r.revision == revision) {
+            record.delivered_to.extend(delivered_to);
+        }
+    }
+}
+
+/// The update carrying `config`, or an empty one for a deleted node
+fn update_message(node_manager: &NodeManager, node_id: &str, revision: u64, config: Option<&YggdrasilConfig>) -> ServerMessage {
+    match config {
+        Some(config) => ServerMessage::Update {
+            revision,
+            epoch: node_manager.epoch().to_string(),
+            node_id: node_id.to_string(),
+            listen: config.listen.clone(),
+            peers: config.peers.clone(),
+            allowed_public_keys: config.allowed_public_keys.clone(),
+            interface_peers: config.interface_peers.clone(),
+            if_name: Some(config.if_name.clone()),
+            extra_config: config.extra_config.clone(),
+        },
+        None => ServerMessage::Update {
+            revision,
+            epoch: node_manager.epoch().to_string(),
+            node_id: node_id.to_string(),
+            listen: vec![],
+            peers: vec![],
+            allowed_public_keys: vec![],
+            interface_peers: HashMap::new(),
+            if_name: None,
+            extra_config: HashMap::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::app::Application;
+    use crate::node_manager::{NodeOptions, NodeSpec};
+    use tokio::sync::mpsc::Receiver;
+
+    async fn add_node(node_manager: &NodeManager, network: &str, name: &str, address: &str) -> String {
+        let spec = NodeSpec {
+            name: name.to_string(),
+            listen: vec!["tcp://0.0.0.0:9001".to_string()],
+            addresses: vec![address.to_string()],
+            ..Default::default()
+        };
+        node_manager.add_node(network, spec).await.unwrap().id
+    }
+
+    async fn connect(node_id: &str) -> (Sender<ServerMessage>, Receiver<ServerMessage>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        register_agent_connection(node_id.to_string(), tx.clone(), yggman_proto::protocol::PROTOCOL_VERSION).await;
+        (tx, rx)
+    }
+
+    /// Revisions of the updates an agent received since the last call
+    fn received(rx: &mut Receiver<ServerMessage>) -> Vec<u64> {
+        std::iter::from_fn(|| rx.try_recv().ok()).filter_map(|message| message.revision()).collect()
+    }
+
+    #[tokio::test]
+    async fn node_change_pushes_only_to_affected_agents() {
+        let _pushes = TEST_PUSHES.lock().await;
+        let app = Application::for_tests().await;
+        let node_manager = app.node_manager();
+        app.context().network_manager.create_network("lab".to_string(), "Lab".to_string(), String::new()).await.unwrap();
+        let a = add_node(&node_manager, "default", "a", "192.0.2.1").await;
+        let b = add_node(&node_manager, "default", "b", "192.0.2.2").await;
+        let other = add_node(&node_manager, "lab", "other", "192.0.2.3").await;
+        let mut agents = Vec::new();
+        for id in [&a, &b, &other] {
+            agents.push((id.clone(), connect(id).await));
+        }
+
+        let (_, first) = broadcast_new_revision(&node_manager, None, true).await;
+        for (_, (_, rx)) in &mut agents {
+            assert_eq!(received(rx), [first]);
+        }
+
+        // Extra config only shows up in the node's own config
+        let options = NodeOptions {
+            extra_config: Some(HashMap::from([("LogLookups".to_string(), serde_json::json!(true))])),
+            ..Default::default()
+        };
+        node_manager.update_node_options(&a, options).await.unwrap();
+        broadcast_node_change(&node_manager, &[a.as_str()]).await;
+        let second = node_manager.current_revision().await;
+        assert!(second > first);
+        let revisions: Vec<Vec<u64>> = agents.iter_mut().map(|(_, (_, rx))| received(rx)).collect();
+        assert_eq!(revisions, [vec![second], vec![], vec![]]);
+
+        // The record still covers every node, for rollbacks
+        let record = get_revision_history().await.into_iter().next().unwrap();
+        assert_eq!(record.revision, second);
+        assert_eq!(record.configs.len(), 3);
+        assert_eq!(record.delivered_to, HashSet::from([a.clone()]));
+
+        // Nothing differs from the last revision, nothing is pushed
+        broadcast_node_change(&node_manager, &[a.as_str()]).await;
+        assert_eq!(node_manager.current_revision().await, second);
+        assert!(agents.iter_mut().all(|(_, (_, rx))| received(rx).is_empty()));
+
+        // A new listen port changes the peers dialling the node too, but
+        // not those of another network
+        node_manager.update_node(&a, "a".to_string(), vec!["tcp://0.0.0.0:9002".to_string()], vec!["192.0.2.1".to_string()], None).await.unwrap();
+        broadcast_node_change(&node_manager, &[a.as_str()]).await;
+        let third = node_manager.current_revision().await;
+        assert!(third > second);
+        let revisions: Vec<Vec<u64>> = agents.iter_mut().map(|(_, (_, rx))| received(rx)).collect();
+        assert_eq!(revisions, [vec![third], vec![third], vec![]]);
+
+        for (id, (tx, _)) in &agents {
+            unregister_agent_connection(id, tx).await;
+        }
+    }
+}
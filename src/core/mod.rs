@@ -1,3 +0,0 @@
-pub mod app;
-pub mod context;
-pub mod module;
\ No newline at end of file
@@ -1,3 +0,0 @@
-pub mod example;
-pub mod web;
-pub mod websocket;
\ No newline at end of file